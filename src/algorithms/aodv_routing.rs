@@ -0,0 +1,398 @@
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* Ad hoc On-Demand Distance Vector (AODV) routing.
+* Routes are only discovered when needed: a packet without a route is dropped
+* and a route request (RREQ) is flooded on the next step. The destination (or a
+* node with a fresh enough route) answers with a route reply (RREP) along the
+* reverse path. Link breaks are reported upstream with route errors (RERR).
+* Every control message travels one hop per simulation step.
+*/
+
+#[derive(Clone)]
+enum Message {
+	Rreq { origin: ID, origin_seq: u32, dest: ID, dest_seq: u32, rreq_id: u32, hops: u32 },
+	Rrep { origin: ID, dest: ID, dest_seq: u32, hops: u32 },
+	Rerr { dests: Vec<(ID, u32)> }
+}
+
+impl Message {
+	// estimated size on the wire
+	fn size(&self) -> usize {
+		match self {
+			Message::Rreq { .. } => 6 * 4,
+			Message::Rrep { .. } => 4 * 4,
+			Message::Rerr { dests } => 4 + dests.len() * 8
+		}
+	}
+}
+
+struct Transmission {
+	from: ID,
+	to: ID,
+	message: Message
+}
+
+#[derive(Clone)]
+struct Route {
+	next: ID,
+	hops: u32,
+	seq: u32,
+	expires: u32,
+	valid: bool
+}
+
+#[derive(Clone)]
+struct Node {
+	seq: u32,
+	rreq_id: u32,
+	neighbors: Vec<ID>,
+	routes: HashMap<ID, Route>,
+	// seen route requests (origin, rreq_id) => time
+	seen: HashMap<(ID, u32), u32>,
+	// destination => time of last route request
+	discovering: HashMap<ID, u32>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			seq: 0,
+			rreq_id: 0,
+			neighbors: vec![],
+			routes: HashMap::new(),
+			seen: HashMap::new(),
+			discovering: HashMap::new()
+		}
+	}
+
+	fn get_route(&self, dest: ID) -> Option<&Route> {
+		self.routes.get(&dest).filter(|route| route.valid)
+	}
+
+	// Update route if the information is fresher or shorter
+	fn update_route(&mut self, dest: ID, next: ID, hops: u32, seq: u32, expires: u32) {
+		let is_better = match self.routes.get(&dest) {
+			Some(route) => !route.valid || seq > route.seq || (seq == route.seq && hops < route.hops),
+			None => true
+		};
+
+		if is_better {
+			self.routes.insert(dest, Route { next, hops, seq, expires, valid: true });
+		} else if let Some(route) = self.routes.get_mut(&dest) {
+			if route.next == next {
+				route.expires = u32::max(route.expires, expires);
+			}
+		}
+	}
+}
+
+pub struct AodvRouting {
+	nodes: Vec<Node>,
+	inflight: Vec<Transmission>,
+	// route misses from route(), handled on the next step
+	pending: Mutex<Vec<(ID, ID)>>,
+	time: u32,
+	route_timeout: u32,
+	rreq_wait: u32,
+	messages_sent: u64
+}
+
+impl Default for AodvRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl AodvRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			inflight: vec![],
+			pending: Mutex::new(vec![]),
+			time: 0,
+			route_timeout: 30,
+			rreq_wait: 10,
+			messages_sent: 0
+		}
+	}
+
+	fn broadcast(&mut self, out: &mut Vec<Transmission>, from: ID, message: Message) {
+		for to in &self.nodes[from as usize].neighbors {
+			out.push(Transmission { from, to: *to, message: message.clone() });
+			self.messages_sent += 1;
+		}
+	}
+
+	fn unicast(&mut self, out: &mut Vec<Transmission>, from: ID, to: ID, message: Message) {
+		out.push(Transmission { from, to, message });
+		self.messages_sent += 1;
+	}
+
+	fn send_rreq(&mut self, out: &mut Vec<Transmission>, origin: ID, dest: ID) {
+		let time = self.time;
+		let node = &mut self.nodes[origin as usize];
+
+		if let Some(started) = node.discovering.get(&dest) {
+			if (started + self.rreq_wait) > time {
+				// discovery still in progress
+				return;
+			}
+		}
+
+		node.discovering.insert(dest, time);
+		node.seq += 1;
+		node.rreq_id += 1;
+		node.seen.insert((origin, node.rreq_id), time);
+
+		let message = Message::Rreq {
+			origin,
+			origin_seq: node.seq,
+			dest,
+			dest_seq: node.routes.get(&dest).map_or(0, |route| route.seq),
+			rreq_id: node.rreq_id,
+			hops: 0
+		};
+
+		self.broadcast(out, origin, message);
+	}
+
+	fn receive(&mut self, out: &mut Vec<Transmission>, from: ID, to: ID, message: Message) {
+		let time = self.time;
+		let expires = time + self.route_timeout;
+
+		match message {
+			Message::Rreq { origin, origin_seq, dest, dest_seq, rreq_id, hops } => {
+				let node = &mut self.nodes[to as usize];
+				if node.seen.contains_key(&(origin, rreq_id)) {
+					return;
+				}
+				node.seen.insert((origin, rreq_id), time);
+
+				// reverse route to the originator
+				node.update_route(origin, from, hops + 1, origin_seq, expires);
+
+				if to == dest {
+					node.seq = u32::max(node.seq, dest_seq);
+					let reply = Message::Rrep { origin, dest, dest_seq: node.seq, hops: 0 };
+					self.unicast(out, to, from, reply);
+				} else if let Some(route) = node.get_route(dest).filter(|route| dest_seq > 0 && route.seq >= dest_seq) {
+					let reply = Message::Rrep { origin, dest, dest_seq: route.seq, hops: route.hops };
+					self.unicast(out, to, from, reply);
+				} else {
+					let request = Message::Rreq { origin, origin_seq, dest, dest_seq, rreq_id, hops: hops + 1 };
+					self.broadcast(out, to, request);
+				}
+			},
+			Message::Rrep { origin, dest, dest_seq, hops } => {
+				let node = &mut self.nodes[to as usize];
+
+				// forward route to the destination
+				node.update_route(dest, from, hops + 1, dest_seq, expires);
+
+				if to == origin {
+					node.discovering.remove(&dest);
+				} else if let Some(next) = node.get_route(origin).map(|route| route.next) {
+					let reply = Message::Rrep { origin, dest, dest_seq, hops: hops + 1 };
+					self.unicast(out, to, next, reply);
+				}
+			},
+			Message::Rerr { dests } => {
+				let node = &mut self.nodes[to as usize];
+				let mut lost = vec![];
+
+				for (dest, seq) in dests {
+					if let Some(route) = node.routes.get_mut(&dest) {
+						if route.valid && route.next == from {
+							route.valid = false;
+							route.seq = u32::max(route.seq, seq);
+							lost.push((dest, route.seq));
+						}
+					}
+				}
+
+				if !lost.is_empty() {
+					self.broadcast(out, to, Message::Rerr { dests: lost });
+				}
+			}
+		}
+	}
+}
+
+impl RoutingAlgorithm for AodvRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let node = &self.nodes[id as usize];
+			let valid = node.routes.values().filter(|route| route.valid).count();
+			write!(out, "{} ({})", id, valid)?;
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "AODV Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Reactive routing. Routes are discovered on demand by flooding route requests ",
+					"and answered by route replies along the reverse path."
+				))?;
+			},
+			"overhead" => {
+				write!(out, "{} messages", self.messages_sent)?;
+			},
+			"route_timeout" => {
+				write!(out, "{}", self.route_timeout)?;
+			},
+			"rreq_wait" => {
+				write!(out, "{}", self.rreq_wait)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"route_timeout" => {
+				if let Ok(timeout) = value.parse() {
+					self.route_timeout = timeout;
+				} else {
+					println!("invalid route_timeout value");
+				}
+			},
+			"rreq_wait" => {
+				if let Ok(wait) = value.parse() {
+					self.rreq_wait = wait;
+				} else {
+					println!("invalid rreq_wait value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn is_converged(&self) -> Option<bool> {
+		// reactive, stable when no route discovery is going on
+		let pending = self.pending.lock().map_or(true, |pending| pending.is_empty());
+		Some(pending && self.inflight.is_empty())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let bytes = size_of::<Node>()
+			+ node.routes.len() * size_of::<(ID, Route)>()
+			+ node.seen.len() * size_of::<((ID, u32), u32)>()
+			+ node.discovering.len() * size_of::<(ID, u32)>()
+			+ node.neighbors.len() * size_of::<ID>();
+		Some((node.routes.len(), bytes))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.inflight.clear();
+		self.pending.lock().unwrap().clear();
+		self.time = 0;
+		self.messages_sent = 0;
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.time += 1;
+		let time = self.time;
+		let expires = time + self.route_timeout;
+		let mut out = vec![];
+
+		// detect link changes (hello messages / link layer feedback)
+		for id in 0..self.nodes.len() {
+			// unidirectional links are ignored
+			let neighbors : Vec<ID> = io.node_links(id as ID).iter()
+				.filter(|link| io.has_link(link.to, id as ID))
+				.map(|link| link.to).collect();
+			let node = &mut self.nodes[id];
+			let mut lost = vec![];
+
+			for (dest, route) in node.routes.iter_mut() {
+				if route.valid && !neighbors.contains(&route.next) {
+					route.valid = false;
+					route.seq += 1;
+					lost.push((*dest, route.seq));
+				}
+			}
+
+			for neighbor in &neighbors {
+				let seq = node.routes.get(neighbor).map_or(0, |route| route.seq);
+				node.update_route(*neighbor, *neighbor, 1, seq, expires);
+			}
+
+			node.neighbors = neighbors;
+
+			if !lost.is_empty() {
+				self.broadcast(&mut out, id as ID, Message::Rerr { dests: lost });
+			}
+		}
+
+		// start route discoveries
+		let pending : Vec<(ID, ID)> = self.pending.lock().unwrap().drain(..).collect();
+		for (origin, dest) in pending {
+			if (origin as usize) < self.nodes.len() && (dest as usize) < self.nodes.len() {
+				self.send_rreq(&mut out, origin, dest);
+			}
+		}
+
+		// deliver messages sent on the previous step
+		let inflight = std::mem::take(&mut self.inflight);
+		for transmission in inflight {
+			if (transmission.to as usize) < self.nodes.len()
+					&& self.nodes[transmission.from as usize].neighbors.contains(&transmission.to) {
+				self.receive(&mut out, transmission.from, transmission.to, transmission.message);
+			}
+		}
+
+		// expire routes and old request ids
+		let seen_timeout = 2 * self.rreq_wait;
+		for node in &mut self.nodes {
+			for route in node.routes.values_mut() {
+				if route.valid && route.expires < time {
+					route.valid = false;
+				}
+			}
+			node.seen.retain(|_, t| (*t + seen_timeout) >= time);
+		}
+
+		for transmission in &out {
+			io.send(1, transmission.message.size() as u64);
+		}
+
+		self.inflight = out;
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let node = &self.nodes[packet.receiver as usize];
+		if let Some(route) = node.get_route(packet.destination) {
+			Some(route.next)
+		} else {
+			// packet is dropped, start route discovery on the next step
+			if let Ok(mut pending) = self.pending.lock() {
+				pending.push((packet.receiver, packet.destination));
+			}
+			None
+		}
+	}
+}
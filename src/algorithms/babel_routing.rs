@@ -0,0 +1,368 @@
+
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+
+use crate::graph::{ID, Link};
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* Babel like distance vector routing (RFC 8966).
+* Link costs are either hop count or ETX based. Loops are avoided by the
+* feasibility condition on the (seqno, metric) pairs advertised so far.
+* Route selection uses a smoothed metric and hysteresis to reduce flapping.
+* Starving nodes request a new sequence number from the destination; such
+* requests are passed to the destination directly on the next step.
+*/
+
+const INFINITY : u32 = 0xFFFF;
+const HOP_COST : u32 = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Metric {
+	HopCount,
+	Etx
+}
+
+#[derive(Clone)]
+struct Entry {
+	seq: u32,
+	// metric as advertised by the neighbor
+	adv_metric: u32,
+	// advertised metric + link cost
+	metric: u32,
+	last_updated: u32
+}
+
+#[derive(Clone)]
+struct Selected {
+	next: ID,
+	seq: u32,
+	metric: u32,
+	smoothed: u32
+}
+
+#[derive(Clone)]
+struct Node {
+	seq: u32,
+	// link costs to neighbors
+	costs: HashMap<ID, u32>,
+	// destination => neighbor => entry
+	entries: HashMap<ID, HashMap<ID, Entry>>,
+	selected: HashMap<ID, Selected>,
+	// feasibility distances: destination => (seq, metric)
+	source_table: HashMap<ID, (u32, u32)>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			seq: 0,
+			costs: HashMap::new(),
+			entries: HashMap::new(),
+			selected: HashMap::new(),
+			source_table: HashMap::new()
+		}
+	}
+
+	fn is_feasible(&self, dest: ID, seq: u32, adv_metric: u32) -> bool {
+		if adv_metric >= INFINITY {
+			return false;
+		}
+		match self.source_table.get(&dest) {
+			Some((fd_seq, fd_metric)) => seq > *fd_seq || (seq == *fd_seq && adv_metric < *fd_metric),
+			None => true
+		}
+	}
+
+	// Select routes, returns destinations without feasible route and number of route changes
+	fn select_routes(&mut self, hysteresis: u32) -> (Vec<ID>, u32) {
+		let mut starving = vec![];
+		let mut changes = 0;
+		let dests : Vec<ID> = self.entries.keys().cloned().collect();
+
+		for dest in dests {
+			let mut best : Option<(ID, Entry)> = None;
+			let mut current : Option<Entry> = None;
+			let current_next = self.selected.get(&dest).map(|s| s.next);
+
+			for (neighbor, entry) in &self.entries[&dest] {
+				if !self.is_feasible(dest, entry.seq, entry.adv_metric) && Some(*neighbor) != current_next {
+					continue;
+				}
+				if entry.metric >= INFINITY {
+					continue;
+				}
+				if Some(*neighbor) == current_next {
+					current = Some(entry.clone());
+				}
+				let is_better = match &best {
+					Some((id, b)) => entry.metric < b.metric || (entry.metric == b.metric && neighbor < id),
+					None => true
+				};
+				if is_better {
+					best = Some((*neighbor, entry.clone()));
+				}
+			}
+
+			let choice = match (current, best) {
+				(Some(current), Some((next, best))) => {
+					let smoothed = self.selected[&dest].smoothed;
+					// hysteresis: only switch on a clear improvement
+					if best.metric + hysteresis < u32::min(smoothed, current.metric) {
+						Some((next, best))
+					} else {
+						Some((current_next.unwrap(), current))
+					}
+				},
+				(None, best) => best,
+				(Some(current), None) => Some((current_next.unwrap(), current))
+			};
+
+			match choice {
+				Some((next, entry)) => {
+					if current_next != Some(next) {
+						changes += 1;
+					}
+					let smoothed = match self.selected.get(&dest) {
+						Some(s) if s.next == next => (3 * s.smoothed + entry.metric) / 4,
+						_ => entry.metric
+					};
+					self.selected.insert(dest, Selected { next, seq: entry.seq, metric: entry.metric, smoothed });
+
+					// update feasibility distance
+					let fd = self.source_table.entry(dest).or_insert((entry.seq, entry.metric));
+					if entry.seq > fd.0 || (entry.seq == fd.0 && entry.metric < fd.1) {
+						*fd = (entry.seq, entry.metric);
+					}
+				},
+				None => {
+					if self.selected.remove(&dest).is_some() {
+						changes += 1;
+					}
+					if !self.entries[&dest].is_empty() {
+						starving.push(dest);
+					}
+				}
+			}
+		}
+
+		(starving, changes)
+	}
+}
+
+pub struct BabelRouting {
+	nodes: Vec<Node>,
+	time: u32,
+	metric: Metric,
+	hysteresis: u32,
+	timeout: u32,
+	route_changes: u64,
+	messages_sent: u64
+}
+
+impl Default for BabelRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl BabelRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			time: 0,
+			metric: Metric::Etx,
+			hysteresis: HOP_COST / 2,
+			timeout: 5,
+			route_changes: 0,
+			messages_sent: 0
+		}
+	}
+
+	fn link_cost(&self, io: &Io, link: &Link) -> u32 {
+		let etx = match io.etx(link.from, link.to) {
+			Some(etx) => etx,
+			None => return INFINITY
+		};
+
+		match self.metric {
+			Metric::HopCount => HOP_COST,
+			Metric::Etx => {
+				if etx.is_finite() {
+					u32::min((HOP_COST as f32 * etx) as u32, INFINITY)
+				} else {
+					INFINITY
+				}
+			}
+		}
+	}
+}
+
+impl RoutingAlgorithm for BabelRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let node = &self.nodes[id as usize];
+			write!(out, "{} ({})", id, node.selected.len())?;
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Babel Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Distance vector routing with feasibility conditions to avoid loops, ",
+					"hop count or ETX metrics and hysteresis against route flapping."
+				))?;
+			},
+			"metric" => {
+				write!(out, "{}", if self.metric == Metric::Etx { "etx" } else { "hop" })?;
+			},
+			"hysteresis" => {
+				write!(out, "{}", self.hysteresis)?;
+			},
+			"timeout" => {
+				write!(out, "{}", self.timeout)?;
+			},
+			"route_changes" => {
+				write!(out, "{}", self.route_changes)?;
+			},
+			"overhead" => {
+				write!(out, "{} messages", self.messages_sent)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"metric" => {
+				match value {
+					"hop" => { self.metric = Metric::HopCount; },
+					"etx" => { self.metric = Metric::Etx; },
+					_ => { println!("invalid metric value, use hop or etx"); }
+				}
+			},
+			"hysteresis" => {
+				if let Ok(hysteresis) = value.parse() {
+					self.hysteresis = hysteresis;
+				} else {
+					println!("invalid hysteresis value");
+				}
+			},
+			"timeout" => {
+				if let Ok(timeout) = value.parse() {
+					self.timeout = timeout;
+				} else {
+					println!("invalid timeout value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.entries.values()
+			.map(|entries| size_of::<(ID, HashMap<ID, Entry>)>() + entries.len() * size_of::<(ID, Entry)>())
+			.sum::<usize>();
+		let bytes = size_of::<Node>() + entries
+			+ node.costs.len() * size_of::<(ID, u32)>()
+			+ node.selected.len() * size_of::<(ID, Selected)>()
+			+ node.source_table.len() * size_of::<(ID, (u32, u32))>();
+		Some((node.selected.len(), bytes))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.time = 0;
+		self.route_changes = 0;
+		self.messages_sent = 0;
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.time += 1;
+		let time = self.time;
+
+		// measure link costs (hello/IHU)
+		for id in 0..self.nodes.len() as ID {
+			let mut costs = HashMap::new();
+			for link in io.node_links(id) {
+				costs.insert(link.to, self.link_cost(io, link));
+			}
+			self.nodes[id as usize].costs = costs;
+		}
+
+		// updates from the previous state
+		let updates : Vec<Vec<(ID, u32, u32)>> = self.nodes.iter().enumerate().map(|(id, node)| {
+			let mut update = vec![(id as ID, node.seq, 0)];
+			for (dest, selected) in &node.selected {
+				update.push((*dest, selected.seq, selected.metric));
+			}
+			update
+		}).collect();
+
+		for (from, update) in updates.iter().enumerate() {
+			let from = from as ID;
+			for link in io.node_links(from) {
+				let to = link.to;
+				self.messages_sent += 1;
+				io.send(1, (update.len() * size_of::<(ID, u32, u32)>()) as u64);
+				let node = &mut self.nodes[to as usize];
+				let cost = *node.costs.get(&from).unwrap_or(&INFINITY);
+				for (dest, seq, adv_metric) in update {
+					if *dest == to {
+						continue;
+					}
+					node.entries.entry(*dest).or_default().insert(from, Entry {
+						seq: *seq,
+						adv_metric: *adv_metric,
+						metric: u32::min(adv_metric + cost, INFINITY),
+						last_updated: time
+					});
+				}
+			}
+		}
+
+		// route selection
+		let timeout = self.timeout;
+		let mut requests = HashSet::new();
+		for node in &mut self.nodes {
+			for entries in node.entries.values_mut() {
+				entries.retain(|_, e| (e.last_updated + timeout) >= time);
+			}
+			let (starving, changes) = node.select_routes(self.hysteresis);
+			node.entries.retain(|_, entries| !entries.is_empty());
+			self.route_changes += changes as u64;
+			requests.extend(starving);
+		}
+
+		// seqno requests
+		for dest in requests {
+			if let Some(node) = self.nodes.get_mut(dest as usize) {
+				node.seq += 1;
+				self.messages_sent += 1;
+				io.send(1, size_of::<(ID, u32)>() as u64);
+			}
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		self.nodes[packet.receiver as usize].selected
+			.get(&packet.destination)
+			.map(|selected| selected.next)
+	}
+}
@@ -0,0 +1,387 @@
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::utils::random;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* B.A.T.M.A.N. IV style routing.
+* Every node periodically broadcasts an originator message (OGM). OGMs are
+* rebroadcasted with the transmit quality (TQ) of the path they took.
+* Every node keeps a sliding window of the last received sequence numbers
+* for each originator and neighbor. The neighbor with the best average TQ
+* over the most recent OGMs is used as next hop towards the originator.
+* Links drop OGMs with a probability according to their link quality.
+*/
+
+const TQ_MAX : u32 = 255;
+const TTL_MAX : u8 = 50;
+const TQ_AVG_WINDOW_SIZE : usize = 5;
+
+#[derive(Clone)]
+struct Ogm {
+	originator: ID,
+	seq: u32,
+	tq: u32,
+	ttl: u8
+}
+
+struct Transmission {
+	from: ID,
+	to: ID,
+	ogm: Ogm
+}
+
+// sliding window of received sequence numbers and TQ values
+#[derive(Clone)]
+struct Window {
+	last_seq: u32,
+	entries: Vec<(u32, u32)> // (seq, tq)
+}
+
+impl Window {
+	fn new() -> Self {
+		Self { last_seq: 0, entries: vec![] }
+	}
+
+	fn add(&mut self, seq: u32, tq: u32, size: u32) {
+		self.last_seq = u32::max(self.last_seq, seq);
+		let oldest = self.last_seq.saturating_sub(size);
+		self.entries.retain(|(s, _)| *s > oldest && *s != seq);
+		if seq > oldest {
+			self.entries.push((seq, tq));
+		}
+	}
+
+	// move window forward without a new packet
+	fn slide(&mut self, last_seq: u32, size: u32) {
+		self.last_seq = u32::max(self.last_seq, last_seq);
+		let oldest = self.last_seq.saturating_sub(size);
+		self.entries.retain(|(s, _)| *s > oldest);
+	}
+
+	fn count(&self) -> u32 {
+		self.entries.len() as u32
+	}
+
+	// average TQ of the most recent packets
+	fn tq_avg(&self) -> u32 {
+		let mut recent : Vec<&(u32, u32)> = self.entries.iter().collect();
+		recent.sort_by_key(|(seq, _)| std::cmp::Reverse(*seq));
+		recent.truncate(TQ_AVG_WINDOW_SIZE);
+		if recent.is_empty() {
+			0
+		} else {
+			recent.iter().map(|(_, tq)| tq).sum::<u32>() / recent.len() as u32
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Originator {
+	routers: HashMap<ID, Window>,
+	best: Option<ID>,
+	last_forwarded: u32,
+	last_updated: u32
+}
+
+impl Originator {
+	fn new() -> Self {
+		Self {
+			routers: HashMap::new(),
+			best: None,
+			last_forwarded: 0,
+			last_updated: 0
+		}
+	}
+
+	fn last_seq(&self) -> u32 {
+		self.routers.values().map(|w| w.last_seq).max().unwrap_or(0)
+	}
+
+	fn update_best(&mut self, window_size: u32) {
+		let last_seq = self.last_seq();
+		for window in self.routers.values_mut() {
+			window.slide(last_seq, window_size);
+		}
+		self.routers.retain(|_, w| w.count() > 0);
+
+		// routers without echos (e.g. unidirectional links) have a TQ of 0
+		self.best = self.routers.iter()
+			.filter(|(_, w)| w.tq_avg() > 0)
+			.max_by_key(|(id, w)| (w.tq_avg(), std::cmp::Reverse(**id)))
+			.map(|(id, _)| *id);
+	}
+
+	fn tq(&self) -> u32 {
+		self.best.and_then(|id| self.routers.get(&id)).map_or(0, |w| w.tq_avg())
+	}
+}
+
+#[derive(Clone)]
+struct Node {
+	seq: u32,
+	originators: HashMap<ID, Originator>,
+	// echos of our own OGMs per neighbor
+	echos: HashMap<ID, Window>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			seq: 0,
+			originators: HashMap::new(),
+			echos: HashMap::new()
+		}
+	}
+
+	fn local_tq(&self, neighbor: ID, window_size: u32) -> u32 {
+		self.echos.get(&neighbor).map_or(0, |w| (TQ_MAX * w.count()) / u32::max(window_size, 1))
+	}
+}
+
+pub struct BatmanRouting {
+	nodes: Vec<Node>,
+	inflight: Vec<Transmission>,
+	time: u32,
+	window_size: u32,
+	hop_penalty: u32,
+	timeout: u32,
+	messages_sent: u64
+}
+
+impl Default for BatmanRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl BatmanRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			inflight: vec![],
+			time: 0,
+			window_size: 64,
+			hop_penalty: 15,
+			timeout: 200,
+			messages_sent: 0
+		}
+	}
+
+	fn broadcast(&mut self, io: &Io, out: &mut Vec<Transmission>, from: ID, ogm: &Ogm) {
+		for link in io.node_links(from) {
+			self.messages_sent += 1;
+			io.send(1, size_of::<Ogm>() as u64);
+			// lossy link
+			if random::<f32>() <= link.delivery_ratio() {
+				out.push(Transmission { from, to: link.to, ogm: ogm.clone() });
+			}
+		}
+	}
+
+	fn receive(&mut self, io: &Io, out: &mut Vec<Transmission>, from: ID, to: ID, ogm: Ogm) {
+		let window_size = self.window_size;
+		let time = self.time;
+		let node = &mut self.nodes[to as usize];
+
+		if ogm.originator == to {
+			// echo of our own OGM from a direct neighbor
+			if ogm.ttl == (TTL_MAX - 1) {
+				node.echos.entry(from).or_insert_with(Window::new).add(ogm.seq, TQ_MAX, window_size);
+			}
+			return;
+		}
+
+		// local link quality is measured by own OGM echos
+		let local_tq = node.local_tq(from, window_size);
+		let tq = (ogm.tq * local_tq / TQ_MAX) * (TQ_MAX - self.hop_penalty) / TQ_MAX;
+
+		let orig = node.originators.entry(ogm.originator).or_insert_with(Originator::new);
+		if (ogm.seq + window_size) <= orig.last_seq() {
+			// too old
+			return;
+		}
+
+		orig.routers.entry(from).or_insert_with(Window::new).add(ogm.seq, tq, window_size);
+		orig.last_updated = time;
+		orig.update_best(window_size);
+
+		// rebroadcast own neighbors OGMs (needed for echos) and OGMs received via the best next hop
+		let rebroadcast = ogm.seq > orig.last_forwarded
+			&& ogm.ttl > 1
+			&& (ogm.originator == from || orig.best == Some(from));
+
+		if rebroadcast {
+			orig.last_forwarded = ogm.seq;
+			let ogm = Ogm {
+				originator: ogm.originator,
+				seq: ogm.seq,
+				tq: orig.tq(),
+				ttl: ogm.ttl - 1
+			};
+			self.broadcast(io, out, to, &ogm);
+		}
+	}
+
+	fn write_originators(&self, id: ID, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if let Some(node) = self.nodes.get(id as usize) {
+			let mut ids : Vec<&ID> = node.originators.keys().collect();
+			ids.sort();
+			writeln!(out, "originator  next_hop  tq  last_seq")?;
+			for oid in ids {
+				let orig = &node.originators[oid];
+				if let Some(best) = orig.best {
+					writeln!(out, "{}  {}  {}  {}", oid, best, orig.tq(), orig.last_seq())?;
+				}
+			}
+		} else {
+			write!(out, "invalid node id: {}", id)?;
+		}
+		Ok(())
+	}
+}
+
+impl RoutingAlgorithm for BatmanRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				let node = &self.nodes[id as usize];
+				write!(out, "{} ({})", id, node.originators.len())?;
+			},
+			"originators" => {
+				self.write_originators(id, out)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "B.A.T.M.A.N. Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Nodes broadcast originator messages. The next hop towards an originator ",
+					"is the neighbor with the best transmit quality over a sliding window."
+				))?;
+			},
+			"overhead" => {
+				write!(out, "{} messages", self.messages_sent)?;
+			},
+			"window_size" => {
+				write!(out, "{}", self.window_size)?;
+			},
+			"hop_penalty" => {
+				write!(out, "{}", self.hop_penalty)?;
+			},
+			"timeout" => {
+				write!(out, "{}", self.timeout)?;
+			},
+			_ => {
+				// originators.<node_id>
+				if let Some(id) = key.strip_prefix("originators.").and_then(|id| id.parse::<ID>().ok()) {
+					self.write_originators(id, out)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"window_size" => {
+				if let Ok(size) = value.parse::<u32>() {
+					self.window_size = u32::max(size, 1);
+				} else {
+					println!("invalid window_size value");
+				}
+			},
+			"hop_penalty" => {
+				if let Ok(penalty) = value.parse::<u32>() {
+					self.hop_penalty = u32::min(penalty, TQ_MAX);
+				} else {
+					println!("invalid hop_penalty value");
+				}
+			},
+			"timeout" => {
+				if let Ok(timeout) = value.parse() {
+					self.timeout = timeout;
+				} else {
+					println!("invalid timeout value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let window = |window: &Window| size_of::<(ID, Window)>() + window.entries.len() * size_of::<(u32, u32)>();
+		let originators = node.originators.values()
+			.map(|originator| size_of::<(ID, Originator)>() + originator.routers.values().map(window).sum::<usize>())
+			.sum::<usize>();
+		let bytes = size_of::<Node>() + originators + node.echos.values().map(window).sum::<usize>();
+		Some((node.originators.len(), bytes))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.inflight.clear();
+		self.time = 0;
+		self.messages_sent = 0;
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.time += 1;
+		let mut out = vec![];
+
+		// deliver OGMs sent on the previous step
+		let inflight = std::mem::take(&mut self.inflight);
+		for transmission in inflight {
+			if (transmission.to as usize) < self.nodes.len() {
+				self.receive(io, &mut out, transmission.from, transmission.to, transmission.ogm);
+			}
+		}
+
+		// broadcast own OGMs
+		for id in 0..self.nodes.len() {
+			let node = &mut self.nodes[id];
+			node.seq += 1;
+			let seq = node.seq;
+			for window in node.echos.values_mut() {
+				window.slide(seq, self.window_size);
+			}
+			let ogm = Ogm { originator: id as ID, seq, tq: TQ_MAX, ttl: TTL_MAX };
+			self.broadcast(io, &mut out, id as ID, &ogm);
+		}
+
+		// purge originators we have not heard of for a while
+		let time = self.time;
+		let timeout = self.timeout;
+		for node in &mut self.nodes {
+			node.originators.retain(|_, o| (o.last_updated + timeout) >= time);
+		}
+
+		self.inflight = out;
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		self.nodes[packet.receiver as usize].originators
+			.get(&packet.destination)
+			.and_then(|orig| orig.best)
+	}
+}
@@ -1,7 +1,7 @@
 
-use std::usize;
 
 use crate::graph::ID;
+use crate::utils::random;
 use crate::sim::{Io, RoutingAlgorithm};
 
 
@@ -31,6 +31,12 @@ pub struct DistanceEnumeration {
 	nodes: Vec<Node>
 }
 
+impl Default for DistanceEnumeration {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl DistanceEnumeration {
 	pub fn new() -> Self {
 		Self {
@@ -41,7 +47,7 @@ impl DistanceEnumeration {
 
 impl RoutingAlgorithm for DistanceEnumeration
 {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"name" => {
 				let node = &self.nodes[id as usize];
@@ -56,7 +62,7 @@ impl RoutingAlgorithm for DistanceEnumeration
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"description" => {
 				write!(out, "{}",
@@ -77,7 +83,7 @@ impl RoutingAlgorithm for DistanceEnumeration
 
 		// Assign random numbers
 		for i in 0..len {
-			self.nodes[i].num = rand::random::<u32>();
+			self.nodes[i].num = random::<u32>();
 		}
 	}
 
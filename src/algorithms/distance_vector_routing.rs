@@ -1,20 +1,27 @@
 
+use std::mem::size_of;
+
 use crate::utils::*;
 use crate::graph::*;
 use crate::sim::{Io, RoutingAlgorithm, TestPacket};
 
 
+/*
+* Bellman-Ford / RIP style distance vector routing.
+* Every node sends its whole routing table to all neighbors on every step.
+*/
+
 // management packet
 struct Packet {
 	from: ID,
 	to: ID,
 	origin: ID,
-	hops: u32
+	cost: u32
 }
 
 impl Packet {
-	fn new(from: ID, to: ID, origin: ID, hops: u32) -> Self {
-		Self { from, to, origin, hops }
+	fn new(from: ID, to: ID, origin: ID, cost: u32) -> Self {
+		Self { from, to, origin, cost }
 	}
 }
 
@@ -22,7 +29,7 @@ impl Packet {
 struct Entry {
 	id: ID, // destination id
 	next: ID, // next hop id
-	hops: u32,
+	cost: u32,
 	last_updated: u32
 }
 
@@ -43,113 +50,231 @@ impl Node {
 		Self { entries: vec![] }
 	}
 
-	fn timeout_entries(&mut self, time: u32) {
+	fn timeout_entries(&mut self, time: u32, timeout: u32) {
 		// remove old entries
-		vec_filter(&mut self.entries, |ref e| (e.last_updated + 5) >= time);
+		vec_filter(&mut self.entries, |e| (e.last_updated + timeout) >= time);
 	}
 
-	fn route(&self, packet: &TestPacket) -> Option<ID> {
+	fn route(&self, packet: &TestPacket, infinity: u32) -> Option<ID> {
 		let id = packet.destination;
-		for neighbor in &self.entries {
-			if neighbor.id == id {
-				return Some(neighbor.next);
+		for entry in &self.entries {
+			if entry.id == id && entry.cost < infinity {
+				return Some(entry.next);
 			}
 		}
 		None
 	}
 
-	fn update_table(&mut self, packet: &Packet, time: u32) {
+	// Returns true if the table was changed
+	fn update_table(&mut self, packet: &Packet, time: u32, infinity: u32) -> bool {
 		// dismiss info about own node
 		if packet.origin == packet.to {
-			return;
+			return false;
 		}
 
-		// update exiting entry
-		for neighbor in &mut self.entries {
-			if neighbor.id == packet.origin {
-				if packet.hops <= neighbor.hops {
-					neighbor.next = packet.from;
-					neighbor.last_updated = time;
+		let cost = u32::min(packet.cost, infinity);
+
+		// update existing entry
+		for entry in &mut self.entries {
+			if entry.id == packet.origin {
+				if entry.next == packet.from {
+					// always believe the current next hop (allows count-to-infinity)
+					let changed = entry.cost != cost;
+					entry.cost = cost;
+					entry.last_updated = time;
+					return changed;
+				} else if cost < entry.cost {
+					entry.next = packet.from;
+					entry.cost = cost;
+					entry.last_updated = time;
+					return true;
 				}
-				return;
+				return false;
 			}
 		}
 
+		if cost >= infinity {
+			return false;
+		}
+
 		// add new entry
 		self.entries.push(Entry {
 			id: packet.origin,
-			hops: packet.hops,
 			next: packet.from,
+			cost,
 			last_updated: time
 		});
+
+		true
 	}
 }
 
 pub struct DistanceVectorRouting {
 	nodes: Vec<Node>,
-	time: u32
+	time: u32,
+	infinity: u32,
+	timeout: u32,
+	split_horizon: bool,
+	changes: u32
+}
+
+impl Default for DistanceVectorRouting {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl DistanceVectorRouting {
 	pub fn new() -> Self {
 		Self {
 			nodes: vec![],
-			time: 0
+			time: 0,
+			infinity: 16,
+			timeout: 5,
+			split_horizon: false,
+			changes: 0
 		}
 	}
 }
 
 impl RoutingAlgorithm for DistanceVectorRouting
 {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"name" => {
 				let node = &self.nodes[id as usize];
 				write!(out, "{} ({})", id, node.entries.len())?;
 			},
+			"routes" => {
+				writeln!(out, "destination  next_hop  cost")?;
+				for entry in &self.nodes[id as usize].entries {
+					writeln!(out, "{}  {}  {}", entry.id, entry.next, entry.cost)?;
+				}
+			},
 			_ => {}
 		}
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"name" => {
-				write!(out, "Distance Vector Algorithm")?;
+				write!(out, "Distance Vector Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Every node keeps a table of (destination, next hop, cost) ",
+					"and sends it to all neighbors on every step (Bellman-Ford)."
+				))?;
+			},
+			"infinity" => {
+				write!(out, "{}", self.infinity)?;
+			},
+			"timeout" => {
+				write!(out, "{}", self.timeout)?;
+			},
+			"split_horizon" => {
+				write!(out, "{}", self.split_horizon)?;
+			},
+			"changes" => {
+				// routing table changes during the last step
+				write!(out, "{}", self.changes)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"infinity" => {
+				if let Ok(infinity) = value.parse::<u32>() {
+					self.infinity = u32::max(infinity, 1);
+				} else {
+					println!("invalid infinity value");
+				}
+			},
+			"timeout" => {
+				if let Ok(timeout) = value.parse() {
+					self.timeout = timeout;
+				} else {
+					println!("invalid timeout value");
+				}
+			},
+			"split_horizon" => {
+				if let Ok(split_horizon) = value.parse() {
+					self.split_horizon = split_horizon;
+				} else {
+					println!("invalid split_horizon value");
+				}
 			},
 			_ => {}
 		}
 		Ok(())
 	}
 
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn is_converged(&self) -> Option<bool> {
+		// no routing table changes during the last step
+		Some(self.time > 0 && self.changes == 0)
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.entries.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Entry>()))
+	}
+
 	fn reset(&mut self, len: usize) {
 		self.nodes = vec![Node::new(); len];
 		self.time = 0;
+		self.changes = 0;
 	}
 
 	fn step(&mut self, io: &mut Io) {
 		self.time += 1;
+		self.changes = 0;
 
 		// fade out old entries
 		for node in &mut self.nodes {
-			node.timeout_entries(self.time);
+			node.timeout_entries(self.time, self.timeout);
 		}
 
 		// For each link...
 		let mut nodes = self.nodes.clone();
-		for (from, to) in io.link_iter() {
+		for from in 0..io.nodes_count() as ID {
 			let src = &self.nodes[from as usize];
-			let dst = &mut nodes[to as usize];
-			// way faster approach, but mixes old and new states!!
-			//let (src, dst) = index_two_mut(&mut self.nodes, from as usize, to as usize);
+			for link in io.node_links(from) {
+				let to = link.to;
+				// the neighbor forwards packets over the reverse link
+				let cost = match io.get_link(to, from) {
+					Some(reverse) => reverse.cost() as u32,
+					None => continue
+				};
+				let dst = &mut nodes[to as usize];
 
-			// Send own info to neighbor
-			dst.update_table(&Packet::new(from, to, from, 1), self.time);
+				// one update with own info and routing table
+				io.send(1, ((1 + src.entries.len()) * size_of::<(ID, u32)>()) as u64);
+
+				// Send own info to neighbor
+				if dst.update_table(&Packet::new(from, to, from, cost), self.time, self.infinity) {
+					self.changes += 1;
+				}
 
-			// Forward new information to neighbor
-			for entry in &src.entries {
-				if entry.last_updated + 1 == self.time {
-					dst.update_table(&Packet::new(from, to, entry.id, entry.hops + 1), self.time);
+				// Send routing table to neighbor
+				for entry in &src.entries {
+					if self.split_horizon && entry.next == to {
+						continue;
+					}
+					let packet = Packet::new(from, to, entry.id, entry.cost.saturating_add(cost));
+					if dst.update_table(&packet, self.time, self.infinity) {
+						self.changes += 1;
+					}
 				}
 			}
 		}
@@ -159,6 +284,6 @@ impl RoutingAlgorithm for DistanceVectorRouting
 	}
 
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
-		self.nodes[packet.receiver as usize].route(packet)
+		self.nodes[packet.receiver as usize].route(packet, self.infinity)
 	}
 }
@@ -1,5 +1,5 @@
-use std::f32;
-use std::u32;
+use std::mem::size_of;
+use std::collections::VecDeque;
 
 use crate::utils::*;
 use crate::graph::*;
@@ -32,12 +32,14 @@ pub struct Vars {
 }
 
 // A virtual machine to run small programs with the availability of some environment varibles
+// (the vector components of two stack slots are combined by index)
+#[allow(clippy::needless_range_loop)]
 pub fn run_program(prog: &[u32], vars: &Vars) -> Option<[f32; 3]> {
 	let mut st = [[0.0, 0.0, 0.0]; 32];
 	let mut sp = 0;
 
-	for ip in 0..prog.len() {
-		match prog[ip] {
+	for &op in prog {
+		match op {
 			NOP => {
 			},
 			VAR_ONES => {
@@ -70,7 +72,7 @@ pub fn run_program(prog: &[u32], vars: &Vars) -> Option<[f32; 3]> {
 				}
 				sp -= 1;
 				for i in 0..3 {
-					st[sp - 1][i] = st[sp - 1][i] + st[sp][i];
+					st[sp - 1][i] += st[sp][i];
 				}
 			},
 			EXP => {
@@ -88,7 +90,7 @@ pub fn run_program(prog: &[u32], vars: &Vars) -> Option<[f32; 3]> {
 				}
 				sp -= 1;
 				for i in 0..3 {
-					st[sp - 1][i] = st[sp - 1][i] - st[sp][i];
+					st[sp - 1][i] -= st[sp][i];
 				}
 			},
 			VMUL => {
@@ -110,7 +112,7 @@ pub fn run_program(prog: &[u32], vars: &Vars) -> Option<[f32; 3]> {
 				}
 				sp -= 1;
 				for i in 0..3 {
-					st[sp - 1][i] = st[sp - 1][i] * st[sp][i];
+					st[sp - 1][i] *= st[sp][i];
 				}
 			},
 			DIV => {
@@ -118,7 +120,7 @@ pub fn run_program(prog: &[u32], vars: &Vars) -> Option<[f32; 3]> {
 					return None;
 				}
 				for i in 0..3 {
-					st[sp - 2][i] = st[sp - 2][i] / st[sp - 1][i];
+					st[sp - 2][i] /= st[sp - 1][i];
 				}
 				sp -= 1;
 			},
@@ -205,12 +207,169 @@ impl Node {
 			// Do anything or ignore?
 		}
 	}
+
+	fn route(&self, dst: &Vec3) -> Option<ID> {
+		let mut d_next = f32::INFINITY;
+		let mut n_next = None;
+
+		for neighbor in &self.neighbors {
+			let d = neighbor.pos.distance(dst);
+			if d < d_next {
+				d_next = d;
+				n_next = Some(neighbor.id);
+			}
+		}
+
+		n_next
+	}
+}
+
+// one round of position updates, every node runs the program for each neighbor
+fn embed(nodes: &mut [Node], links: &[(ID, ID)], program: &[u32]) {
+	// clear neighbor table and backup pos
+	for node in nodes.iter_mut() {
+		node.pos_old = node.pos;
+		node.neighbors.clear();
+	}
+
+	// rebuild neighbor table
+	for &(from, to) in links {
+		let pos = nodes[from as usize].pos_old;
+		nodes[to as usize].neighbors.push(Neighbor{id: from, pos});
+	}
+
+	for &(from, to) in links {
+		let pos = nodes[from as usize].pos_old;
+		nodes[to as usize].run(program, pos);
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Fitness {
+	// inverse of the mean stretch of the delivered packets
+	Stretch,
+	// fraction of delivered packets
+	Delivery,
+	// delivery rate divided by the mean stretch
+	Combined
+}
+
+impl Fitness {
+	fn name(&self) -> &'static str {
+		match self {
+			Fitness::Stretch => "stretch",
+			Fitness::Delivery => "delivery",
+			Fitness::Combined => "combined"
+		}
+	}
+
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"stretch" => Some(Fitness::Stretch),
+			"delivery" => Some(Fitness::Delivery),
+			"combined" => Some(Fitness::Combined),
+			_ => None
+		}
+	}
+
+	fn value(&self, delivered: u32, packets: u32, stretch_sum: f32) -> f32 {
+		if delivered == 0 {
+			return 0.0;
+		}
+		let delivery = delivered as f32 / packets as f32;
+		let stretch = stretch_sum / delivered as f32;
+		match self {
+			Fitness::Stretch => 1.0 / stretch,
+			Fitness::Delivery => delivery,
+			Fitness::Combined => delivery / stretch
+		}
+	}
+}
+
+// genetic search settings
+#[derive(Clone, Copy)]
+struct Params {
+	// programs per generation
+	population: usize,
+	// chance of a symbol to be replaced
+	mutation_rate: f32,
+	// chance of a child to be a mix of two parents
+	crossover_rate: f32,
+	// symbols per program (the program stack has 32 entries)
+	program_length: usize,
+	// embedding rounds before a program is evaluated
+	rounds: u32,
+	// test packets per program
+	samples: u32,
+	fitness: Fitness
+}
+
+impl Params {
+	fn new() -> Self {
+		Self {
+			population: 20,
+			mutation_rate: 0.05,
+			crossover_rate: 0.7,
+			program_length: 12,
+			rounds: 10,
+			samples: 50,
+			fitness: Fitness::Combined
+		}
+	}
+}
+
+fn random_program(len: usize) -> Vec<u32> {
+	(0..len).map(|_| random_range(0, MAX_SYMBOLS)).collect()
+}
+
+// hop count of the shortest path
+fn shortest_hops(nodes: &[Node], source: ID, target: ID) -> Option<u32> {
+	let mut hops = vec![u32::MAX; nodes.len()];
+	let mut queue = VecDeque::new();
+	hops[source as usize] = 0;
+	queue.push_back(source);
+
+	while let Some(id) = queue.pop_front() {
+		if id == target {
+			return Some(hops[id as usize]);
+		}
+		for neighbor in &nodes[id as usize].neighbors {
+			if hops[neighbor.id as usize] == u32::MAX {
+				hops[neighbor.id as usize] = hops[id as usize] + 1;
+				queue.push_back(neighbor.id);
+			}
+		}
+	}
+	None
+}
+
+// hop count of the greedy path
+fn greedy_hops(nodes: &[Node], source: ID, target: ID) -> Option<u32> {
+	let dst = nodes[target as usize].pos;
+	let mut current = source;
+	for hops in 0..nodes.len() as u32 {
+		if current == target {
+			return Some(hops);
+		}
+		current = nodes[current as usize].route(&dst)?;
+	}
+	None
 }
 
 pub struct GeneticRouting {
 	nodes: Vec<Node>,
 	program: Vec<u32>,
-	time: u32
+	time: u32,
+	params: Params,
+	population: Vec<Vec<u32>>,
+	generation: u32,
+	best_fitness: f32
+}
+
+impl Default for GeneticRouting {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl GeneticRouting {
@@ -218,94 +377,268 @@ impl GeneticRouting {
 		Self {
 			nodes: vec![],
 			program: vec![],
-			time: 0
+			time: 0,
+			params: Params::new(),
+			population: vec![],
+			generation: 0,
+			best_fitness: 0.0
 		}
 	}
 
 	pub fn set_program(&mut self, program: &[u32]) {
 		self.program = program.to_vec();
 	}
+
+	fn init_population(&mut self) {
+		let len = self.params.program_length;
+		self.population = (0..self.params.population).map(|_| random_program(len)).collect();
+		self.generation = 0;
+		self.best_fitness = 0.0;
+	}
+
+	fn evaluate(&self, program: &[u32], start: &[Node], links: &[(ID, ID)], pairs: &[(ID, ID, u32)]) -> f32 {
+		let mut nodes = start.to_vec();
+		for _ in 0..self.params.rounds {
+			embed(&mut nodes, links, program);
+		}
+
+		let mut delivered = 0;
+		let mut stretch_sum = 0.0;
+		for &(source, target, shortest) in pairs {
+			if let Some(hops) = greedy_hops(&nodes, source, target) {
+				delivered += 1;
+				stretch_sum += hops as f32 / shortest as f32;
+			}
+		}
+
+		self.params.fitness.value(delivered, pairs.len() as u32, stretch_sum)
+	}
+
+	// pick the fitter of three random programs
+	fn select<'a>(&'a self, fitness: &[f32]) -> &'a [u32] {
+		let mut best = random_range(0, fitness.len());
+		for _ in 0..2 {
+			let i = random_range(0, fitness.len());
+			if fitness[i] > fitness[best] {
+				best = i;
+			}
+		}
+		&self.population[best]
+	}
+
+	// evaluate the population on the current topology and breed the next generation
+	fn evolve(&mut self, links: &[(ID, ID)]) {
+		let len = self.nodes.len();
+		if len < 2 || links.is_empty() {
+			return;
+		}
+
+		if self.population.is_empty() {
+			self.init_population();
+		}
+
+		// all programs start from the same positions and route the same packets
+		let mut start = vec![Node::new(); len];
+		for node in &mut start {
+			node.pos = Vec3::random_unit();
+		}
+		embed(&mut start, links, &[]);
+
+		let mut pairs = vec![];
+		for _ in 0..self.params.samples {
+			let source = random_range(0, len) as ID;
+			let target = random_range(0, len) as ID;
+			if source != target {
+				if let Some(hops) = shortest_hops(&start, source, target) {
+					pairs.push((source, target, hops));
+				}
+			}
+		}
+
+		if pairs.is_empty() {
+			return;
+		}
+
+		let fitness : Vec<f32> = self.population.iter()
+			.map(|program| self.evaluate(program, &start, links, &pairs))
+			.collect();
+
+		let mut best = 0;
+		for i in 1..fitness.len() {
+			if fitness[i] > fitness[best] {
+				best = i;
+			}
+		}
+
+		// the best program survives unchanged
+		let mut next = vec![self.population[best].clone()];
+		while next.len() < self.params.population {
+			let mut child = self.select(&fitness).to_vec();
+			if random::<f32>() < self.params.crossover_rate {
+				let other = self.select(&fitness);
+				let cut = random_range(0, child.len() + 1);
+				child[cut..].copy_from_slice(&other[cut..]);
+			}
+			for symbol in &mut child {
+				if random::<f32>() < self.params.mutation_rate {
+					*symbol = random_range(0, MAX_SYMBOLS);
+				}
+			}
+			next.push(child);
+		}
+
+		self.program = self.population[best].clone();
+		self.best_fitness = fitness[best];
+		self.population = next;
+		self.generation += 1;
+	}
 }
 
 impl RoutingAlgorithm for GeneticRouting
 {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
-		match key {
-			"name" => {
-				let pos = self.nodes[id as usize].pos;
-				write!(out, "{:.1}/{:.1}/{:.1}", pos.x(), pos.y(), pos.z())?;
-			},
-			_ => {}
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let pos = self.nodes[id as usize].pos;
+			write!(out, "{:.1}/{:.1}/{:.1}", pos.x(), pos.y(), pos.z())?;
 		}
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"description" => {
 				write!(out, "{}", concat!(
 					"Greedy Routing on virtual coordinates generated ",
-					"by applying spring forces to links."
+					"by programs found with a genetic search."
 				))?;
 			},
 			"name" => {
-				write!(out, "Sprint Routing")?;
+				write!(out, "Genetic Routing")?;
+			},
+			"parameters" => {
+				writeln!(out, "population <n>          programs per generation")?;
+				writeln!(out, "mutation_rate <0..1>    chance of a symbol to be replaced")?;
+				writeln!(out, "crossover_rate <0..1>   chance of a child to be a mix of two parents")?;
+				writeln!(out, "program_length <1..32>  symbols per program, restarts the search")?;
+				writeln!(out, "rounds <n>              embedding rounds before a program is evaluated")?;
+				writeln!(out, "samples <n>             test packets per program")?;
+				writeln!(out, "fitness <name>          stretch, delivery or combined")?;
+			},
+			"population" => {
+				write!(out, "{}", self.params.population)?;
+			},
+			"mutation_rate" => {
+				write!(out, "{}", self.params.mutation_rate)?;
+			},
+			"crossover_rate" => {
+				write!(out, "{}", self.params.crossover_rate)?;
+			},
+			"program_length" => {
+				write!(out, "{}", self.params.program_length)?;
+			},
+			"rounds" => {
+				write!(out, "{}", self.params.rounds)?;
+			},
+			"samples" => {
+				write!(out, "{}", self.params.samples)?;
+			},
+			"fitness" => {
+				write!(out, "{}", self.params.fitness.name())?;
+			},
+			"generation" => {
+				write!(out, "{}", self.generation)?;
+			},
+			"best_fitness" => {
+				write!(out, "{:.4} (generation {})", self.best_fitness, self.generation)?;
+			},
+			"program" => {
+				let symbols : Vec<String> = self.program.iter().map(|s| s.to_string()).collect();
+				write!(out, "{}", symbols.join(" "))?;
 			},
 			_ => {}
 		}
 		Ok(())
 	}
 
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"population" => match value.parse::<usize>() {
+				Ok(population) if population >= 2 => {
+					self.params.population = population;
+					let len = self.params.program_length;
+					self.population.resize_with(population, || random_program(len));
+				},
+				_ => println!("invalid population value, expected at least 2")
+			},
+			"mutation_rate" => match value.parse::<f32>() {
+				Ok(rate) if (0.0..=1.0).contains(&rate) => self.params.mutation_rate = rate,
+				_ => println!("invalid mutation_rate value, expected 0..1")
+			},
+			"crossover_rate" => match value.parse::<f32>() {
+				Ok(rate) if (0.0..=1.0).contains(&rate) => self.params.crossover_rate = rate,
+				_ => println!("invalid crossover_rate value, expected 0..1")
+			},
+			"program_length" => match value.parse::<usize>() {
+				Ok(len) if (1..=32).contains(&len) => {
+					self.params.program_length = len;
+					self.init_population();
+				},
+				_ => println!("invalid program_length value, expected 1..32")
+			},
+			"rounds" => match value.parse::<u32>() {
+				Ok(rounds) if rounds > 0 => self.params.rounds = rounds,
+				_ => println!("invalid rounds value")
+			},
+			"samples" => match value.parse::<u32>() {
+				Ok(samples) if samples > 0 => self.params.samples = samples,
+				_ => println!("invalid samples value")
+			},
+			"fitness" => match Fitness::parse(value) {
+				Some(fitness) => self.params.fitness = fitness,
+				None => println!("invalid fitness value, expected stretch, delivery or combined")
+			},
+			_ => {
+				print_unknown_key(key);
+			}
+		}
+		Ok(())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>()))
+	}
+
 	fn reset(&mut self, len: usize) {
 		self.nodes = vec![Node::new(); len];
 		for node in &mut self.nodes {
 			node.pos = Vec3::random_unit();
 		}
 		self.time = 0;
+		self.init_population();
 	}
 
 	fn step(&mut self, io: &mut Io) {
 		self.time += 1;
 
-		// clear neighbor table and backup pos
-		for node in &mut self.nodes {
-			node.pos_old = node.pos;
-			node.neighbors.clear();
-		}
+		// only bidirectional links can be used to send packets back
+		let links : Vec<(ID, ID)> = io.link_iter()
+			.filter(|&(from, to)| io.has_link(to, from))
+			.collect();
 
-/*
-		for node in &mut self.nodes {
-			node.calculate_mean()
-		}
-*/
-		// rebuild neighbor table
-		for (from, to) in io.link_iter() {
-			let to_node = &mut self.nodes[to as usize];
-			to_node.neighbors.push(Neighbor{id: from, pos: to_node.pos_old});
-		}
+		self.evolve(&links);
 
 		// simulate broadcast traffic
-		for (from, to) in io.link_iter() {
-			let pos = self.nodes[from as usize].pos_old;
-			self.nodes[to as usize].run(&self.program, pos);
+		for _ in &links {
+			io.send(1, size_of::<(ID, Vec3)>() as u64);
 		}
+		embed(&mut self.nodes, &links, &self.program);
 	}
 
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
 		let node = &self.nodes[packet.receiver as usize];
 		let dst = self.nodes[packet.destination as usize].pos;
-
-		let mut d_next = f32::INFINITY;
-		let mut n_next = None;
-
-		for neighbor in &node.neighbors {
-			let d = neighbor.pos.distance(&dst);
-			if d < d_next {
-				d_next = d;
-				n_next = Some(neighbor.id);
-			}
-		}
-
-		n_next
+		node.route(&dst)
 	}
 }
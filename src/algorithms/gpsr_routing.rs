@@ -0,0 +1,256 @@
+
+use std::mem::size_of;
+
+use crate::utils::*;
+use crate::graph::*;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* Greedy Perimeter Stateless Routing (GPSR) on the geo positions of the nodes.
+* Packets are forwarded to the neighbor closest to the destination. At a local
+* minimum the packet is routed around the face of the planarized (Gabriel) graph
+* using the right-hand rule.
+*
+* The test packet carries no GPSR header, so the perimeter mode is derived per hop:
+* a packet that was passed on from a node closer to the destination is considered
+* to be in perimeter mode. It returns to greedy mode as soon as progress is possible.
+*/
+
+#[derive(Clone)]
+struct Neighbor {
+	id: ID,
+	pos: [f32; 3],
+	last_updated: u32
+}
+
+impl PartialEq for Neighbor {
+	fn eq(&self, other: &Neighbor) -> bool {
+		self.id == other.id
+	}
+}
+
+#[derive(Clone)]
+struct Node {
+	pos: Option<[f32; 3]>,
+	neighbors: Vec<Neighbor>,
+	// neighbors on the planarized graph
+	planar: Vec<ID>,
+}
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+	((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn angle(from: &[f32; 3], to: &[f32; 3]) -> f32 {
+	(to[1] - from[1]).atan2(to[0] - from[0])
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			pos: None,
+			neighbors: vec![],
+			planar: vec![],
+		}
+	}
+
+	fn timeout_entries(&mut self, time: u32) {
+		vec_filter(&mut self.neighbors, |e| (e.last_updated + 5) >= time);
+	}
+
+	fn update(&mut self, from_id: ID, from_pos: [f32; 3], time: u32) {
+		vec_add_entry(&mut self.neighbors,
+			&Neighbor {
+				id: from_id,
+				pos: from_pos,
+				last_updated: time
+			}
+		);
+	}
+
+	// Gabriel graph: remove link u-v if another neighbor w lies
+	// within the circle with the diameter u-v
+	fn planarize(&mut self) {
+		self.planar.clear();
+
+		let pos = match self.pos {
+			Some(pos) => pos,
+			None => return
+		};
+
+		for v in &self.neighbors {
+			let m = [(pos[0] + v.pos[0]) / 2.0, (pos[1] + v.pos[1]) / 2.0, 0.0];
+			let r = distance(&pos, &v.pos) / 2.0;
+			let witness = self.neighbors.iter().any(|w| {
+				w.id != v.id && distance(&m, &w.pos) < r
+			});
+			if !witness {
+				self.planar.push(v.id);
+			}
+		}
+	}
+
+	fn get_neighbor(&self, id: ID) -> Option<&Neighbor> {
+		self.neighbors.iter().find(|n| n.id == id)
+	}
+
+	fn greedy(&self, pos: &[f32; 3], dst_pos: &[f32; 3]) -> Option<ID> {
+		let mut d_next = distance(pos, dst_pos);
+		let mut n_next = None;
+
+		for v in &self.neighbors {
+			let d = distance(&v.pos, dst_pos);
+			if d < d_next {
+				d_next = d;
+				n_next = Some(v.id);
+			}
+		}
+
+		n_next
+	}
+
+	// right-hand rule: next planar edge counterclockwise from the reference angle
+	fn perimeter(&self, pos: &[f32; 3], reference: f32, exclude: ID) -> Option<ID> {
+		let mut best_delta = f32::INFINITY;
+		let mut best = None;
+
+		for id in &self.planar {
+			if let Some(v) = self.get_neighbor(*id) {
+				let mut delta = angle(pos, &v.pos) - reference;
+				while delta <= 0.0 {
+					delta += 2.0 * std::f32::consts::PI;
+				}
+				// only go back if there is no other way
+				if v.id == exclude {
+					delta = 2.0 * std::f32::consts::PI + 1.0;
+				}
+				if delta < best_delta {
+					best_delta = delta;
+					best = Some(v.id);
+				}
+			}
+		}
+
+		best
+	}
+}
+
+pub struct GpsrRouting {
+	nodes: Vec<Node>,
+	time: u32
+}
+
+impl Default for GpsrRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl GpsrRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			time: 0
+		}
+	}
+}
+
+impl RoutingAlgorithm for GpsrRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let node = &self.nodes[id as usize];
+			write!(out, "{} ({}/{})", id, node.planar.len(), node.neighbors.len())?;
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "GPSR Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Greedy forwarding on geo positions with ",
+					"perimeter routing on a planarized graph as fallback."
+				))?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>() + node.planar.len() * size_of::<ID>()))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.time = 0;
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.time += 1;
+
+		// fade out old entries and update own position
+		for (id, node) in self.nodes.iter_mut().enumerate() {
+			node.pos = io.position(id as ID);
+			node.timeout_entries(self.time);
+		}
+
+		// simulate beacons
+		for (from, to) in io.link_iter() {
+			// only bidirectional links can be used to send packets back
+			if !io.has_link(to, from) {
+				continue;
+			}
+			if let Some(pos) = self.nodes[from as usize].pos {
+				io.send(1, size_of::<(ID, [f32; 3])>() as u64);
+				self.nodes[to as usize].update(from, pos, self.time);
+			}
+		}
+
+		for node in &mut self.nodes {
+			node.planarize();
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let node = &self.nodes[packet.receiver as usize];
+
+		// we pretend to know the destination location
+		let (pos, dst_pos) = match (node.pos, self.nodes[packet.destination as usize].pos) {
+			(Some(pos), Some(dst_pos)) => (pos, dst_pos),
+			_ => return None
+		};
+
+		if let Some(next) = node.greedy(&pos, &dst_pos) {
+			return Some(next);
+		}
+
+		// local minimum => perimeter mode
+		let prev = node.get_neighbor(packet.transmitter);
+		let reference = match prev {
+			Some(prev) if distance(&prev.pos, &dst_pos) < distance(&pos, &dst_pos) => {
+				// we are already in perimeter mode, continue from incoming edge
+				angle(&pos, &prev.pos)
+			},
+			_ => {
+				// enter perimeter mode, start from the line to the destination
+				angle(&pos, &dst_pos)
+			}
+		};
+
+		node.perimeter(&pos, reference, packet.transmitter)
+	}
+}
@@ -0,0 +1,317 @@
+
+use std::collections::{HashSet, VecDeque};
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+use crate::utils::{random, random_range};
+
+
+/*
+* Kademlia style routing over the physical topology (like cjdns).
+* Every node has a random 32 bit key. A node keeps up to k peers for
+* every bucket (peers with the first differing key bit at the bucket
+* index), the physically closest ones, together with a source route.
+* Announcements are flooded, so every node knows its closest peers.
+* A packet is sent to the peer with the smallest XOR distance to the
+* key of the destination (overlay hop) along the source route to that
+* peer, which continues the same way until the destination is reached.
+* Every overlay hop at least halves the XOR distance.
+*/
+
+const KEY_BITS : usize = 32;
+
+#[derive(Clone)]
+struct Peer {
+	id: ID,
+	// source route without the node itself, ends with the peer
+	path: Vec<ID>
+}
+
+#[derive(Clone)]
+struct Node {
+	key: u32,
+	buckets: Vec<Vec<Peer>>
+}
+
+impl Node {
+	fn new(key: u32) -> Self {
+		Self {
+			key,
+			buckets: vec![vec![]; KEY_BITS]
+		}
+	}
+
+	fn peer_count(&self) -> usize {
+		self.buckets.iter().map(|bucket| bucket.len()).sum()
+	}
+}
+
+fn bucket_index(a: u32, b: u32) -> usize {
+	KEY_BITS - 1 - (a ^ b).leading_zeros() as usize
+}
+
+// overlay and underlay hops of the sampled routes
+#[derive(Clone, Copy, Default)]
+struct Stretch {
+	samples: usize,
+	failed: usize,
+	overlay_hops: f32,
+	underlay_hops: f32,
+	stretch: f32
+}
+
+pub struct KademliaRouting {
+	nodes: Vec<Node>,
+	// peers per bucket
+	bucket_size: usize,
+	// routes sampled per step for the stretch
+	samples: usize,
+	stretch: Stretch
+}
+
+// hops and parent of all nodes reachable from the source
+fn bfs(io: &Io, source: ID) -> (Vec<Option<u32>>, Vec<ID>, Vec<ID>) {
+	let len = io.nodes_count();
+	let mut hops = vec![None; len];
+	let mut parents = vec![source; len];
+	let mut order = vec![];
+	let mut queue = VecDeque::new();
+	hops[source as usize] = Some(0);
+	queue.push_back(source);
+	while let Some(u) = queue.pop_front() {
+		let h = hops[u as usize].unwrap_or(0);
+		order.push(u);
+		for link in io.node_links(u) {
+			if hops[link.to as usize].is_none() {
+				hops[link.to as usize] = Some(h + 1);
+				parents[link.to as usize] = u;
+				queue.push_back(link.to);
+			}
+		}
+	}
+	(hops, parents, order)
+}
+
+impl Default for KademliaRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl KademliaRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			bucket_size: 4,
+			samples: 100,
+			stretch: Stretch::default()
+		}
+	}
+
+	// physical path of the overlay route and the number of overlay hops
+	fn overlay_route(&self, source: ID, destination: ID) -> Option<(Vec<ID>, usize)> {
+		let key = self.nodes.get(destination as usize)?.key;
+		let mut current = source;
+		let mut path = vec![source];
+		let mut overlay_hops = 0;
+
+		while current != destination {
+			let node = self.nodes.get(current as usize)?;
+			let peer = node.buckets[bucket_index(node.key, key)].iter()
+				.min_by_key(|peer| (self.nodes[peer.id as usize].key ^ key, peer.path.len()))?;
+			path.extend(&peer.path);
+			current = peer.id;
+			overlay_hops += 1;
+		}
+
+		Some((path, overlay_hops))
+	}
+
+	fn update_stretch(&mut self, io: &Io) {
+		let len = self.nodes.len();
+		let mut stretch = Stretch::default();
+		if len < 2 {
+			self.stretch = stretch;
+			return;
+		}
+
+		for _ in 0..self.samples {
+			let source = random_range(0, len) as ID;
+			let destination = random_range(0, len) as ID;
+			if source == destination {
+				continue;
+			}
+			let shortest = match bfs(io, source).0[destination as usize] {
+				Some(hops) => hops,
+				None => continue
+			};
+			stretch.samples += 1;
+			match self.overlay_route(source, destination) {
+				Some((path, overlay_hops)) => {
+					let underlay_hops = (path.len() - 1) as f32;
+					stretch.overlay_hops += overlay_hops as f32;
+					stretch.underlay_hops += underlay_hops;
+					stretch.stretch += underlay_hops / shortest as f32;
+				},
+				None => {
+					stretch.failed += 1;
+				}
+			}
+		}
+
+		let routed = (stretch.samples - stretch.failed).max(1) as f32;
+		stretch.overlay_hops /= routed;
+		stretch.underlay_hops /= routed;
+		stretch.stretch /= routed;
+		self.stretch = stretch;
+	}
+}
+
+impl RoutingAlgorithm for KademliaRouting {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		let node = match self.nodes.get(id as usize) {
+			Some(node) => node,
+			None => return Ok(())
+		};
+
+		match key {
+			"name" | "label" | "key" => {
+				write!(out, "{:08x}", node.key)?;
+			},
+			"buckets" => {
+				let sizes : Vec<String> = node.buckets.iter().map(|bucket| bucket.len().to_string()).collect();
+				write!(out, "{}", sizes.join(","))?;
+			},
+			"peers" => {
+				let peers : Vec<String> = node.buckets.iter().flatten()
+					.map(|peer| format!("{} ({} hops)", peer.id, peer.path.len()))
+					.collect();
+				write!(out, "{}", peers.join(", "))?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Kademlia Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Nodes have random keys and know k peers per key bucket with source routes. ",
+					"Packets are routed greedily in XOR space over the peers."
+				))?;
+			},
+			"parameters" => {
+				writeln!(out, "bucket_size <k>  peers per bucket")?;
+				writeln!(out, "samples <n>      routes sampled per step for the overlay and underlay stretch")?;
+			},
+			"bucket_size" => {
+				write!(out, "{}", self.bucket_size)?;
+			},
+			"samples" => {
+				write!(out, "{}", self.samples)?;
+			},
+			"stretch" => {
+				let s = &self.stretch;
+				write!(out, "samples: {}, failed: {}, overlay hops: {:.2}, underlay hops: {:.2}, underlay stretch: {:.3}",
+					s.samples, s.failed, s.overlay_hops, s.underlay_hops, s.stretch)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"bucket_size" => {
+				match value.parse::<usize>() {
+					Ok(k) if k > 0 => self.bucket_size = k,
+					_ => println!("invalid bucket_size value")
+				}
+			},
+			"samples" => {
+				if let Ok(samples) = value.parse::<usize>() {
+					self.samples = samples;
+				} else {
+					println!("invalid samples value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let hops : usize = node.buckets.iter().flatten().map(|peer| peer.path.len()).sum();
+		Some((node.peer_count(), size_of::<Node>() + node.peer_count() * size_of::<Peer>() + hops * size_of::<ID>()))
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new(node.key);
+		}
+	}
+
+	fn reset(&mut self, len: usize) {
+		// unique keys
+		let mut keys = HashSet::new();
+		self.nodes = (0..len).map(|_| {
+			loop {
+				let key = random::<u32>();
+				if keys.insert(key) {
+					return Node::new(key);
+				}
+			}
+		}).collect();
+		self.stretch = Stretch::default();
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		if io.nodes_count() != self.nodes.len() {
+			return;
+		}
+
+		let link_count = io.link_iter().count() as u64;
+		for id in 0..self.nodes.len() {
+			// the announcement of every node is sent over every link
+			io.send(link_count, link_count * (size_of::<ID>() + size_of::<u32>()) as u64);
+
+			let (_, parents, order) = bfs(io, id as ID);
+			let key = self.nodes[id].key;
+			let mut buckets = vec![vec![]; KEY_BITS];
+			// closest nodes first
+			for other in order.into_iter().skip(1) {
+				let bucket : &mut Vec<Peer> = &mut buckets[bucket_index(key, self.nodes[other as usize].key)];
+				if bucket.len() < self.bucket_size {
+					let mut path = vec![other];
+					let mut current = other;
+					while parents[current as usize] != id as ID {
+						current = parents[current as usize];
+						path.push(current);
+					}
+					path.reverse();
+					bucket.push(Peer { id: other, path });
+				}
+			}
+			self.nodes[id].buckets = buckets;
+		}
+
+		self.update_stretch(io);
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		// the overlay route is deterministic, find the position of the packet on it
+		let (path, _) = self.overlay_route(packet.source, packet.destination)?;
+		for i in 0..path.len().saturating_sub(1) {
+			if path[i] == packet.receiver && (if i == 0 { packet.transmitter == packet.receiver } else { path[i - 1] == packet.transmitter }) {
+				return Some(path[i + 1]);
+			}
+		}
+		None
+	}
+}
@@ -0,0 +1,420 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+use crate::utils::random_range;
+
+
+/*
+* Landmark routing (Tsuchiya).
+* A few nodes are landmarks. Every node knows the next hop to all
+* landmarks and to all nodes within a small radius (distance vector).
+* The address of a node is its nearest landmark and the hops to all
+* landmarks. Every node also announces itself along the path to its
+* nearest landmark, so the nodes on that path know the way back down.
+* Packets go to the landmark of the destination until a node knows
+* the destination within its radius or on the path down.
+* The state per node only grows with the number of landmarks and
+* the size of the neighborhood, not with the network size.
+*/
+
+#[derive(Clone, Copy)]
+struct Route {
+	next: ID,
+	hops: u32
+}
+
+#[derive(Clone, Copy)]
+struct Member {
+	route: Route,
+	// landmark the node belongs to
+	home: ID
+}
+
+#[derive(Clone)]
+struct Node {
+	// routes to landmarks
+	landmarks: HashMap<ID, Route>,
+	// routes to nodes within the radius
+	local: HashMap<ID, Route>,
+	// routes down to the nodes that announce themselves through this node
+	members: HashMap<ID, Member>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			landmarks: HashMap::new(),
+			local: HashMap::new(),
+			members: HashMap::new()
+		}
+	}
+
+	// nearest landmark, the lowest id on equal distance
+	fn home(&self) -> Option<(ID, u32)> {
+		self.landmarks.iter()
+			.map(|(id, route)| (*id, route.hops))
+			.min_by_key(|(id, hops)| (*hops, *id))
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LandmarkStrategy {
+	Random,
+	// nodes with the most neighbors
+	MaxDegree,
+	// add landmarks until every node has one within the radius
+	Cover
+}
+
+impl LandmarkStrategy {
+	fn name(&self) -> &'static str {
+		match self {
+			LandmarkStrategy::Random => "random",
+			LandmarkStrategy::MaxDegree => "max-degree",
+			LandmarkStrategy::Cover => "cover"
+		}
+	}
+
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"random" => Some(LandmarkStrategy::Random),
+			"max-degree" => Some(LandmarkStrategy::MaxDegree),
+			"cover" => Some(LandmarkStrategy::Cover),
+			_ => None
+		}
+	}
+}
+
+// nodes within the given hops
+fn ball(io: &Io, id: ID, radius: u32) -> Vec<ID> {
+	let mut hops = HashMap::new();
+	let mut queue = VecDeque::new();
+	hops.insert(id, 0);
+	queue.push_back(id);
+	while let Some(u) = queue.pop_front() {
+		let h = hops[&u];
+		if h == radius {
+			continue;
+		}
+		for link in io.node_links(u) {
+			if let std::collections::hash_map::Entry::Vacant(e) = hops.entry(link.to) {
+				e.insert(h + 1);
+				queue.push_back(link.to);
+			}
+		}
+	}
+	hops.keys().cloned().collect()
+}
+
+fn select_landmarks(io: &Io, strategy: LandmarkStrategy, count: usize, radius: u32) -> Vec<ID> {
+	let len = io.nodes_count();
+	let mut ids : Vec<ID> = (0..len as ID).collect();
+
+	// random order, also for the other strategies on equal degree
+	for i in 0..len {
+		let j = random_range(i, len);
+		ids.swap(i, j);
+	}
+
+	match strategy {
+		LandmarkStrategy::Random => {
+			ids.truncate(count);
+			ids
+		},
+		LandmarkStrategy::MaxDegree => {
+			ids.sort_by_key(|&id| usize::MAX - io.node_links(id).len());
+			ids.truncate(count);
+			ids
+		},
+		LandmarkStrategy::Cover => {
+			let mut covered = vec![false; len];
+			let mut landmarks = vec![];
+			for id in ids {
+				if !covered[id as usize] {
+					for other in ball(io, id, radius) {
+						covered[other as usize] = true;
+					}
+					landmarks.push(id);
+				}
+			}
+			landmarks
+		}
+	}
+}
+
+pub struct LandmarkRouting {
+	nodes: Vec<Node>,
+	strategy: LandmarkStrategy,
+	// number of landmarks, 0 for the square root of the node count
+	count: usize,
+	radius: u32,
+	// selected on the next step if empty
+	landmarks: Vec<ID>
+}
+
+impl Default for LandmarkRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl LandmarkRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			strategy: LandmarkStrategy::Random,
+			count: 0,
+			radius: 2,
+			landmarks: vec![]
+		}
+	}
+
+	fn landmark_count(&self) -> usize {
+		if self.count > 0 {
+			self.count
+		} else {
+			((self.nodes.len() as f32).sqrt().ceil() as usize).max(1)
+		}
+	}
+
+	fn clear_routes(&mut self) {
+		for node in &mut self.nodes {
+			*node = Node::new();
+		}
+	}
+
+	// share of the nodes with a landmark within the radius
+	fn coverage(&self) -> f32 {
+		let covered = self.nodes.iter()
+			.filter(|node| node.home().is_some_and(|(_, hops)| hops <= self.radius))
+			.count();
+		100.0 * covered as f32 / self.nodes.len().max(1) as f32
+	}
+}
+
+impl RoutingAlgorithm for LandmarkRouting {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		let node = match self.nodes.get(id as usize) {
+			Some(node) => node,
+			None => return Ok(())
+		};
+
+		match key {
+			"name" => {
+				match node.home() {
+					Some((landmark, _)) => write!(out, "{} ({})", id, landmark)?,
+					None => write!(out, "{}", id)?
+				}
+			},
+			"label" | "landmark" => {
+				if let Some((landmark, _)) = node.home() {
+					write!(out, "{}", landmark)?;
+				}
+			},
+			"address" => {
+				let hops : Vec<String> = self.landmarks.iter()
+					.map(|l| node.landmarks.get(l).map_or("-".to_string(), |r| r.hops.to_string()))
+					.collect();
+				match node.home() {
+					Some((landmark, _)) => write!(out, "{}:[{}]", landmark, hops.join(","))?,
+					None => write!(out, "none")?
+				}
+			},
+			"local" => {
+				write!(out, "{}", node.local.len())?;
+			},
+			"members" => {
+				write!(out, "{}", node.members.len())?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Landmark Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Nodes know the routes to a few landmarks and to the nodes within a radius. ",
+					"Packets are sent towards the nearest landmark of the destination until the destination is within the radius."
+				))?;
+			},
+			"parameters" => {
+				writeln!(out, "landmark_strategy <name>  random, max-degree or cover (every node within radius of a landmark)")?;
+				writeln!(out, "landmarks <n>             number of landmarks, 0 for the square root of the node count")?;
+				writeln!(out, "radius <hops>             nodes know the routes to all nodes within this radius")?;
+			},
+			"landmark_strategy" => {
+				write!(out, "{}", self.strategy.name())?;
+			},
+			"landmarks" => {
+				let ids : Vec<String> = self.landmarks.iter().map(|id| id.to_string()).collect();
+				write!(out, "{} ({})", self.landmarks.len(), ids.join(","))?;
+			},
+			"radius" => {
+				write!(out, "{}", self.radius)?;
+			},
+			"coverage" => {
+				write!(out, "{:.1}%", self.coverage())?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"landmark_strategy" => match LandmarkStrategy::parse(value) {
+				Some(strategy) => {
+					self.strategy = strategy;
+					self.landmarks.clear();
+					self.clear_routes();
+				},
+				None => println!("invalid landmark_strategy value, expected random, max-degree or cover")
+			},
+			"landmarks" => {
+				if let Ok(count) = value.parse::<usize>() {
+					self.count = count;
+					self.landmarks.clear();
+					self.clear_routes();
+				} else {
+					println!("invalid landmarks value");
+				}
+			},
+			"radius" => {
+				if let Ok(radius) = value.parse::<u32>() {
+					self.radius = radius;
+					if self.strategy == LandmarkStrategy::Cover {
+						self.landmarks.clear();
+					}
+					self.clear_routes();
+				} else {
+					println!("invalid radius value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.landmarks.len() + node.local.len() + node.members.len();
+		let bytes = (node.landmarks.len() + node.local.len()) * size_of::<(ID, Route)>()
+			+ node.members.len() * size_of::<(ID, Member)>();
+		Some((entries, size_of::<Node>() + bytes))
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.landmarks.clear();
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		if self.landmarks.is_empty() && io.nodes_count() == self.nodes.len() {
+			self.landmarks = select_landmarks(io, self.strategy, self.landmark_count(), self.radius);
+		}
+
+		// every node sends its tables to all neighbors (as hops of the last step)
+		let mut nodes = vec![Node::new(); self.nodes.len()];
+		for &landmark in &self.landmarks {
+			if let Some(node) = nodes.get_mut(landmark as usize) {
+				node.landmarks.insert(landmark, Route { next: landmark, hops: 0 });
+			}
+		}
+
+		let max_hops = self.nodes.len() as u32;
+		for (from, to) in io.link_iter() {
+			// routes need links in both directions
+			if !io.has_link(to, from) {
+				continue;
+			}
+
+			let sender = &self.nodes[from as usize];
+			let receiver = &mut nodes[to as usize];
+			let entries = sender.landmarks.len() + sender.local.len() + sender.members.len() + 1;
+			io.send(1, (entries * (size_of::<ID>() + size_of::<u32>())) as u64);
+
+			let update = |table: &mut HashMap<ID, Route>, id: ID, hops: u32| {
+				let better = match table.get(&id) {
+					Some(route) => (hops, from) < (route.hops, route.next),
+					None => true
+				};
+				if better {
+					table.insert(id, Route { next: from, hops });
+				}
+			};
+
+			for (id, route) in &sender.landmarks {
+				if *id != to && route.hops < max_hops {
+					update(&mut receiver.landmarks, *id, route.hops + 1);
+				}
+			}
+
+			update(&mut receiver.local, from, 1);
+			for (id, route) in &sender.local {
+				if *id != to && route.hops < self.radius {
+					update(&mut receiver.local, *id, route.hops + 1);
+				}
+			}
+
+			// announcements towards the landmark of the sender
+			let up = |home: ID| home != from && sender.landmarks.get(&home).is_some_and(|route| route.next == to);
+			let mut announce = |id: ID, home: ID, hops: u32| {
+				let better = match receiver.members.get(&id) {
+					Some(member) => (hops, from) < (member.route.hops, member.route.next),
+					None => true
+				};
+				if better {
+					receiver.members.insert(id, Member { route: Route { next: from, hops }, home });
+				}
+			};
+
+			if let Some((home, _)) = sender.home() {
+				if up(home) {
+					announce(from, home, 1);
+				}
+			}
+			for (id, member) in &sender.members {
+				if *id != to && up(member.home) {
+					announce(*id, member.home, member.route.hops + 1);
+				}
+			}
+		}
+
+		self.nodes = nodes;
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let node = self.nodes.get(packet.receiver as usize)?;
+
+		// destination within the radius
+		if let Some(route) = node.local.get(&packet.destination) {
+			return Some(route.next);
+		}
+
+		// on the path down from the landmark
+		if let Some(member) = node.members.get(&packet.destination) {
+			return Some(member.route.next);
+		}
+
+		// towards the landmark in the address of the destination
+		let (landmark, _) = self.nodes.get(packet.destination as usize)?.home()?;
+		if landmark == packet.receiver {
+			return None;
+		}
+		node.landmarks.get(&landmark).map(|route| route.next)
+	}
+}
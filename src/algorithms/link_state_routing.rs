@@ -0,0 +1,295 @@
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* OSPF like link state routing.
+* Nodes flood link state advertisements (LSA) of their direct links
+* and calculate the shortest paths on their local topology database.
+*/
+
+#[derive(Clone, PartialEq)]
+struct Lsa {
+	origin: ID,
+	seq: u32,
+	// (neighbor, cost)
+	links: Vec<(ID, u32)>,
+}
+
+impl Lsa {
+	// estimated size on the wire
+	fn size(&self) -> usize {
+		4 + 4 + self.links.len() * 6
+	}
+}
+
+#[derive(Clone)]
+struct Entry {
+	lsa: Lsa,
+	last_updated: u32
+}
+
+#[derive(Clone)]
+struct Node {
+	seq: u32,
+	links: Vec<(ID, u32)>,
+	database: HashMap<ID, Entry>,
+	// LSAs to flood on the next step
+	outbox: Vec<Lsa>,
+	next_hops: HashMap<ID, ID>,
+	changed: bool
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			seq: 0,
+			links: vec![],
+			database: HashMap::new(),
+			outbox: vec![],
+			next_hops: HashMap::new(),
+			changed: false
+		}
+	}
+
+	fn originate(&mut self, id: ID, time: u32) {
+		self.seq += 1;
+		let lsa = Lsa { origin: id, seq: self.seq, links: self.links.clone() };
+		self.database.insert(id, Entry { lsa: lsa.clone(), last_updated: time });
+		self.outbox.push(lsa);
+		self.changed = true;
+	}
+
+	fn receive(&mut self, lsa: &Lsa, time: u32) {
+		let is_newer = match self.database.get(&lsa.origin) {
+			Some(entry) => lsa.seq > entry.lsa.seq,
+			None => true
+		};
+
+		if is_newer {
+			self.database.insert(lsa.origin, Entry { lsa: lsa.clone(), last_updated: time });
+			self.outbox.push(lsa.clone());
+			self.changed = true;
+		}
+	}
+
+	fn timeout_entries(&mut self, time: u32, max_age: u32) {
+		let len = self.database.len();
+		self.database.retain(|_, e| (e.last_updated + max_age) >= time);
+		if len != self.database.len() {
+			self.changed = true;
+		}
+	}
+
+	// shortest path first calculation on the topology database
+	fn calculate_routes(&mut self, id: ID) {
+		let mut dists = HashMap::<ID, u32>::new();
+		let mut heap = BinaryHeap::new();
+
+		self.next_hops.clear();
+		dists.insert(id, 0);
+		heap.push(Reverse((0u32, id, id)));
+
+		while let Some(Reverse((dist, u, first_hop))) = heap.pop() {
+			if dist > *dists.get(&u).unwrap_or(&u32::MAX) {
+				continue;
+			}
+
+			if u != id {
+				self.next_hops.insert(u, first_hop);
+			}
+
+			if let Some(entry) = self.database.get(&u) {
+				for (v, cost) in &entry.lsa.links {
+					let alt = dist + cost;
+					if alt < *dists.get(v).unwrap_or(&u32::MAX) {
+						dists.insert(*v, alt);
+						let hop = if u == id { *v } else { first_hop };
+						heap.push(Reverse((alt, *v, hop)));
+					}
+				}
+			}
+		}
+
+		self.changed = false;
+	}
+}
+
+pub struct LinkStateRouting {
+	nodes: Vec<Node>,
+	time: u32,
+	refresh_interval: u32,
+	max_age: u32,
+	messages_sent: u64,
+	bytes_sent: u64,
+	messages_step: u64
+}
+
+impl Default for LinkStateRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl LinkStateRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			time: 0,
+			refresh_interval: 10,
+			max_age: 30,
+			messages_sent: 0,
+			bytes_sent: 0,
+			messages_step: 0
+		}
+	}
+}
+
+impl RoutingAlgorithm for LinkStateRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let node = &self.nodes[id as usize];
+			write!(out, "{} ({})", id, node.database.len())?;
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Link State Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Nodes flood link state advertisements through the network ",
+					"and run a shortest path calculation on their topology database."
+				))?;
+			},
+			"overhead" => {
+				write!(out, "{} messages, {} bytes", self.messages_sent, self.bytes_sent)?;
+			},
+			"overhead_step" => {
+				write!(out, "{}", self.messages_step)?;
+			},
+			"refresh_interval" => {
+				write!(out, "{}", self.refresh_interval)?;
+			},
+			"max_age" => {
+				write!(out, "{}", self.max_age)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"refresh_interval" => {
+				if let Ok(interval) = value.parse::<u32>() {
+					let interval = u32::max(interval, 1);
+					// LSAs would expire between refreshes
+					if interval < self.max_age {
+						self.refresh_interval = interval;
+					} else {
+						println!("refresh_interval must be smaller than max_age ({})", self.max_age);
+					}
+				} else {
+					println!("invalid refresh_interval value");
+				}
+			},
+			"max_age" => {
+				if let Ok(max_age) = value.parse::<u32>() {
+					if max_age > self.refresh_interval {
+						self.max_age = max_age;
+					} else {
+						println!("max_age must be larger than refresh_interval ({})", self.refresh_interval);
+					}
+				} else {
+					println!("invalid max_age value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let database = node.database.values()
+			.map(|entry| size_of::<(ID, Entry)>() + entry.lsa.links.len() * size_of::<(ID, u32)>())
+			.sum::<usize>();
+		let bytes = size_of::<Node>() + database
+			+ node.links.len() * size_of::<(ID, u32)>()
+			+ node.next_hops.len() * size_of::<(ID, ID)>();
+		Some((node.database.len(), bytes))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.time = 0;
+		self.messages_sent = 0;
+		self.bytes_sent = 0;
+		self.messages_step = 0;
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.time += 1;
+		self.messages_step = 0;
+
+		// detect link changes and refresh own LSA
+		for id in 0..self.nodes.len() {
+			let links : Vec<(ID, u32)> = io.node_links(id as ID).iter()
+				.map(|link| (link.to, link.cost() as u32)).collect();
+			let node = &mut self.nodes[id];
+			node.timeout_entries(self.time, self.max_age);
+			if node.links != links || self.time.is_multiple_of(self.refresh_interval) {
+				node.links = links;
+				node.originate(id as ID, self.time);
+			}
+		}
+
+		// flood LSAs to all neighbors
+		let outboxes : Vec<Vec<Lsa>> = self.nodes.iter_mut()
+			.map(|node| std::mem::take(&mut node.outbox)).collect();
+
+		for (from, outbox) in outboxes.iter().enumerate() {
+			if outbox.is_empty() {
+				continue;
+			}
+			for link in io.node_links(from as ID) {
+				let node = &mut self.nodes[link.to as usize];
+				for lsa in outbox {
+					self.messages_step += 1;
+					self.bytes_sent += lsa.size() as u64;
+					io.send(1, lsa.size() as u64);
+					node.receive(lsa, self.time);
+				}
+			}
+		}
+
+		self.messages_sent += self.messages_step;
+
+		// update routing tables
+		for (id, node) in self.nodes.iter_mut().enumerate() {
+			if node.changed {
+				node.calculate_routes(id as ID);
+			}
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		self.nodes[packet.receiver as usize].next_hops.get(&packet.destination).cloned()
+	}
+}
@@ -1,7 +1,7 @@
 
-use std::usize;
 
 use crate::graph::ID;
+use crate::utils::random;
 use crate::sim::{Io, RoutingAlgorithm};
 
 
@@ -28,6 +28,12 @@ pub struct MaxNumConsensus {
 	nodes: Vec<Node>
 }
 
+impl Default for MaxNumConsensus {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl MaxNumConsensus {
 	pub fn new() -> Self {
 		Self {
@@ -38,23 +44,17 @@ impl MaxNumConsensus {
 
 impl RoutingAlgorithm for MaxNumConsensus
 {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
-		match key {
-			"name" => {
-				let node = &self.nodes[id as usize];
-				write!(out, "{} ({})", id, node.num)?;
-			},
-			_ => {}
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let node = &self.nodes[id as usize];
+			write!(out, "{} ({})", id, node.num)?;
 		}
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
-		match key {
-			"name" => {
-				write!(out, "Maximum Number Consensus")?;
-			},
-			_ => {}
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			write!(out, "Maximum Number Consensus")?;
 		}
 		Ok(())
 	}
@@ -64,7 +64,7 @@ impl RoutingAlgorithm for MaxNumConsensus
 
 		// Assign random numbers
 		for i in 0..len {
-			self.nodes[i].num = rand::random::<u32>();
+			self.nodes[i].num = random::<u32>();
 		}
 	}
 
@@ -0,0 +1,370 @@
+
+use std::collections::{HashMap, VecDeque};
+
+use serde_json::Value;
+
+use crate::algorithms::create_algorithm;
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+use crate::utils::{get_str, MyError};
+
+
+/*
+* Different routing algorithms (or parameter sets) on different nodes.
+* Every group of nodes runs its own algorithm instance, which only sees
+* the links between the nodes of the group (see Io::subgraph).
+* Between groups, packets are routed over border links: the group path
+* is the shortest path in the graph of adjacent groups, and inside a
+* group a packet is routed to the border node of the next group by the
+* algorithm of the group (the destination is replaced by the border node).
+*/
+
+pub const NAME : &str = "mixed";
+
+struct Group {
+	name: String,
+	algorithm_name: String,
+	algorithm: Box<dyn RoutingAlgorithm>
+}
+
+pub struct MixedRouting {
+	groups: Vec<Group>,
+	// group index of every node
+	node_groups: Vec<usize>,
+	// (group, next group) => border link
+	borders: HashMap<(usize, usize), (ID, ID)>,
+	// next group on the path of every group to every other group
+	next_groups: Vec<Vec<Option<usize>>>
+}
+
+impl MixedRouting {
+	// all nodes run the given algorithm at first
+	pub fn with_default(algorithm_name: &str, algorithm: Box<dyn RoutingAlgorithm>, len: usize) -> Self {
+		Self {
+			groups: vec![Group {
+				name: algorithm_name.to_string(),
+				algorithm_name: algorithm_name.to_string(),
+				algorithm
+			}],
+			node_groups: vec![0; len],
+			borders: HashMap::new(),
+			next_groups: vec![]
+		}
+	}
+
+	fn node_group(&self, id: ID) -> Option<&Group> {
+		self.groups.get(*self.node_groups.get(id as usize)?)
+	}
+
+	fn find_group(&self, name: &str) -> Option<usize> {
+		self.groups.iter().position(|group| group.name == name)
+	}
+
+	// move a node to a group, the group is created if it does not exist
+	fn assign(&mut self, id: ID, algorithm_name: &str, group_name: &str) -> Result<(), MyError> {
+		if id as usize >= self.node_groups.len() {
+			return Err(MyError::new(format!("Invalid node id: {}", id)));
+		}
+
+		let index = match self.find_group(group_name) {
+			Some(index) => {
+				if self.groups[index].algorithm_name != algorithm_name {
+					return Err(MyError::new(format!("Group {} runs {}", group_name, self.groups[index].algorithm_name)));
+				}
+				index
+			},
+			None => {
+				let mut algorithm = create_algorithm(algorithm_name)
+					.ok_or_else(|| MyError::new(format!("Unknown algorithm: {}", algorithm_name)))?;
+				algorithm.reset(self.node_groups.len());
+				self.groups.push(Group {
+					name: group_name.to_string(),
+					algorithm_name: algorithm_name.to_string(),
+					algorithm
+				});
+				self.groups.len() - 1
+			}
+		};
+
+		self.node_groups[id as usize] = index;
+		self.remove_empty_groups();
+		Ok(())
+	}
+
+	fn remove_empty_groups(&mut self) {
+		let mut counts = vec![0; self.groups.len()];
+		for group in &self.node_groups {
+			counts[*group] += 1;
+		}
+
+		// new index of every group
+		let mut map = vec![0; self.groups.len()];
+		let mut index = 0;
+		// the first group is kept for new nodes
+		for (i, count) in counts.iter().enumerate() {
+			map[i] = index;
+			if *count > 0 || i == 0 {
+				index += 1;
+			}
+		}
+
+		let mut i = 0;
+		self.groups.retain(|_| { i += 1; counts[i - 1] > 0 || i == 1 });
+		for group in &mut self.node_groups {
+			*group = map[*group];
+		}
+	}
+
+	// border links and paths between the groups
+	fn update_borders(&mut self, io: &Io) {
+		let len = self.groups.len();
+		self.borders.clear();
+		for (from, to) in io.link_iter() {
+			let (a, b) = (self.node_groups[from as usize], self.node_groups[to as usize]);
+			if a != b && io.has_link(to, from) {
+				// lowest ids are used on several border links
+				let border = self.borders.entry((a, b)).or_insert((from, to));
+				if (from, to) < *border {
+					*border = (from, to);
+				}
+			}
+		}
+
+		self.next_groups = vec![vec![None; len]; len];
+		for source in 0..len {
+			let mut first_hops = vec![None; len];
+			let mut queue = VecDeque::new();
+			first_hops[source] = Some(source);
+			queue.push_back(source);
+			while let Some(group) = queue.pop_front() {
+				for next in 0..len {
+					if first_hops[next].is_none() && self.borders.contains_key(&(group, next)) {
+						first_hops[next] = if group == source { Some(next) } else { first_hops[group] };
+						queue.push_back(next);
+					}
+				}
+			}
+			self.next_groups[source] = first_hops;
+		}
+	}
+
+	// packet with the border node as destination if the destination is in another group
+	fn local_packet(&self, packet: &TestPacket) -> Option<(usize, TestPacket)> {
+		let group = *self.node_groups.get(packet.receiver as usize)?;
+		let target = *self.node_groups.get(packet.destination as usize)?;
+		let destination = if group == target {
+			packet.destination
+		} else {
+			let next = self.next_groups.get(group)?.get(target).cloned()??;
+			self.borders.get(&(group, next))?.0
+		};
+		// packets from other groups start again at the receiver
+		if self.node_groups.get(packet.source as usize) == Some(&group) {
+			Some((group, TestPacket::new(packet.transmitter, packet.receiver, packet.source, destination)))
+		} else {
+			Some((group, TestPacket::new(packet.receiver, packet.receiver, packet.receiver, destination)))
+		}
+	}
+
+	// next hop over the border link if the packet is at the border node
+	fn border_hop(&self, packet: &TestPacket, local: &TestPacket) -> Option<ID> {
+		if local.destination != packet.destination && local.destination == packet.receiver {
+			let group = self.node_groups[packet.receiver as usize];
+			let target = self.node_groups[packet.destination as usize];
+			let next = self.next_groups[group][target]?;
+			return self.borders.get(&(group, next)).map(|(_, to)| *to);
+		}
+		None
+	}
+}
+
+impl RoutingAlgorithm for MixedRouting {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if let Some(group) = self.node_group(id) {
+			if key == "group" {
+				write!(out, "{} ({})", group.name, group.algorithm_name)?;
+			} else {
+				group.algorithm.get_node(id, key, out)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Mixed Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Groups of nodes run different routing algorithms. ",
+					"Packets to other groups are routed to the border node of the next group."
+				))?;
+			},
+			"parameters" => {
+				writeln!(out, "<group>.<key>  parameter of the algorithm of a group")?;
+			},
+			"groups" => {
+				let mut counts = vec![0; self.groups.len()];
+				for group in &self.node_groups {
+					counts[*group] += 1;
+				}
+				let groups : Vec<String> = self.groups.iter().zip(counts.iter())
+					.map(|(group, count)| format!("{} ({}): {} nodes", group.name, group.algorithm_name, count))
+					.collect();
+				write!(out, "{}", groups.join(", "))?;
+			},
+			"borders" => {
+				write!(out, "{}", self.borders.len())?;
+			},
+			_ => {
+				if let Some((index, key)) = key.split_once('.').and_then(|(name, key)| Some((self.find_group(name)?, key))) {
+					self.groups[index].algorithm.get(key, out)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key.split_once('.').and_then(|(name, key)| Some((self.find_group(name)?, key))) {
+			Some((index, key)) => self.groups[index].algorithm.set(key, value)?,
+			None => println!("unknown key, expected <group>.<key>")
+		}
+		Ok(())
+	}
+
+	fn set_node(&mut self, id: ID, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		if key == "algorithm" {
+			// <algorithm> [<group>]
+			let mut parts = value.split_whitespace();
+			if let Some(algorithm_name) = parts.next() {
+				let group_name = parts.next().unwrap_or(algorithm_name);
+				if let Err(e) = self.assign(id, algorithm_name, group_name) {
+					println!("invalid algorithm value: {}", e);
+				}
+			}
+		} else if let Some(index) = self.node_groups.get(id as usize).cloned() {
+			self.groups[index].algorithm.set_node(id, key, value)?;
+		}
+		Ok(())
+	}
+
+	fn save_state(&self) -> Option<Value> {
+		let groups : Vec<Value> = self.groups.iter().map(|group| {
+			serde_json::json!({
+				"name": group.name,
+				"algorithm": group.algorithm_name,
+				"state": group.algorithm.save_state()
+			})
+		}).collect();
+		Some(serde_json::json!({
+			"groups": groups,
+			"node_groups": self.node_groups
+		}))
+	}
+
+	fn load_state(&mut self, state: &Value) -> Result<(), MyError> {
+		let len = self.node_groups.len();
+		let mut groups = vec![];
+		for group in state.get("groups").and_then(Value::as_array).map_or(&[][..], |v| v.as_slice()) {
+			let name = get_str(group, "name").unwrap_or("");
+			let algorithm_name = get_str(group, "algorithm").unwrap_or("");
+			let mut algorithm = create_algorithm(algorithm_name)
+				.ok_or_else(|| MyError::new(format!("Unknown algorithm: {}", algorithm_name)))?;
+			algorithm.reset(len);
+			if let Some(state) = group.get("state").filter(|v| !v.is_null()) {
+				algorithm.load_state(state)?;
+			}
+			groups.push(Group { name: name.to_string(), algorithm_name: algorithm_name.to_string(), algorithm });
+		}
+
+		let node_groups : Vec<usize> = state.get("node_groups").and_then(Value::as_array).map_or(vec![], |v| {
+			v.iter().map(|group| group.as_u64().unwrap_or(0) as usize).collect()
+		});
+		if groups.is_empty() || node_groups.len() != len || node_groups.iter().any(|group| *group >= groups.len()) {
+			return Err(MyError::new("Invalid state of mixed routing".to_string()));
+		}
+
+		self.groups = groups;
+		self.node_groups = node_groups;
+		Ok(())
+	}
+
+	fn is_converged(&self) -> Option<bool> {
+		let mut converged = Some(true);
+		for group in &self.groups {
+			match group.algorithm.is_converged() {
+				Some(false) => return Some(false),
+				None => converged = None,
+				Some(true) => {}
+			}
+		}
+		converged
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		self.node_group(id)?.algorithm.state_size(id)
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(index) = self.node_groups.get(id as usize).cloned() {
+			self.groups[index].algorithm.reset_node(id);
+		}
+	}
+
+	fn reset(&mut self, len: usize) {
+		// new nodes join the first group
+		self.node_groups.resize(len, 0);
+		self.remove_empty_groups();
+		for group in &mut self.groups {
+			group.algorithm.reset(len);
+		}
+		self.borders.clear();
+		self.next_groups.clear();
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		if io.nodes_count() != self.node_groups.len() {
+			return;
+		}
+
+		for (index, group) in self.groups.iter_mut().enumerate() {
+			let members : Vec<bool> = self.node_groups.iter().map(|g| *g == index).collect();
+			let graph = io.subgraph(&members);
+			let mut group_io = Io::new(&graph, io.locations());
+			group.algorithm.step(&mut group_io);
+			let (messages, bytes) = group_io.sent();
+			io.send(messages, bytes);
+		}
+
+		self.update_borders(io);
+	}
+
+	fn distance(&self, from: ID, to: ID) -> Option<f32> {
+		let group = self.node_groups.get(from as usize)?;
+		if self.node_groups.get(to as usize)? == group {
+			self.groups[*group].algorithm.distance(from, to)
+		} else {
+			None
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let (group, local) = self.local_packet(packet)?;
+		if let Some(next) = self.border_hop(packet, &local) {
+			return Some(next);
+		}
+		self.groups[group].algorithm.route(&local)
+	}
+
+	fn route_multi(&self, packet: &TestPacket) -> Vec<ID> {
+		match self.local_packet(packet) {
+			Some((group, local)) => match self.border_hop(packet, &local) {
+				Some(next) => vec![next],
+				None => self.groups[group].algorithm.route_multi(&local)
+			},
+			None => vec![]
+		}
+	}
+}
@@ -6,5 +6,104 @@ pub mod distance_vector_routing;
 pub mod max_num_consensus;
 pub mod distance_enumeration;
 pub mod spanning_tree_routing;
-//pub mod babel_routing;
+pub mod landmark_routing;
+pub mod kademlia_routing;
+pub mod tree_embedding_routing;
+pub mod mixed_routing;
+pub mod babel_routing;
 pub mod genetic_routing;
+pub mod shortest_path_routing;
+pub mod link_state_routing;
+pub mod gpsr_routing;
+pub mod aodv_routing;
+pub mod batman_routing;
+pub mod plugin_routing;
+pub mod wasm_routing;
+
+use std::sync::{Arc, Mutex};
+use crate::sim::RoutingAlgorithm;
+use crate::utils::MyError;
+use mixed_routing::MixedRouting;
+use plugin_routing::{Plugin, PluginRouting};
+use wasm_routing::{WasmModule, WasmRouting};
+
+
+pub type AlgorithmFactory = fn() -> Box<dyn RoutingAlgorithm>;
+
+/*
+* All selectable routing algorithms.
+* Add new algorithms here to make them available via the "algo" command.
+*/
+pub const ALGORITHMS: &[(&str, AlgorithmFactory)] = &[
+	("random", || Box::new(random_routing::RandomRouting::new())),
+	("vivaldi", || Box::new(vivaldi_routing::VivaldiRouting::new())),
+	("spring", || Box::new(spring_routing::SpringRouting::new())),
+	("genetic", || Box::new(genetic_routing::GeneticRouting::new())),
+	("tree", || Box::new(spanning_tree_routing::SpanningTreeRouting::new())),
+	("landmark", || Box::new(landmark_routing::LandmarkRouting::new())),
+	("kademlia", || Box::new(kademlia_routing::KademliaRouting::new())),
+	("embedding", || Box::new(tree_embedding_routing::TreeEmbeddingRouting::new())),
+	("dijkstra", || Box::new(shortest_path_routing::ShortestPathRouting::new())),
+	("distance_vector", || Box::new(distance_vector_routing::DistanceVectorRouting::new())),
+	("link_state", || Box::new(link_state_routing::LinkStateRouting::new())),
+	("gpsr", || Box::new(gpsr_routing::GpsrRouting::new())),
+	("aodv", || Box::new(aodv_routing::AodvRouting::new())),
+	("batman", || Box::new(batman_routing::BatmanRouting::new())),
+	("babel", || Box::new(babel_routing::BabelRouting::new())),
+];
+
+type LoadedFactory = Arc<dyn Fn() -> Box<dyn RoutingAlgorithm> + Send + Sync>;
+
+// algorithms loaded at runtime with "algo load <path>" and "algo wasm <path>"
+static LOADED : Mutex<Vec<(String, LoadedFactory)>> = Mutex::new(Vec::new());
+
+// make a loaded algorithm available by its name, replaces an algorithm of the same name
+fn register_loaded(name: &str, factory: LoadedFactory) -> Result<(), MyError> {
+	if ALGORITHMS.iter().any(|(algo_name, _)| *algo_name == name) || name == mixed_routing::NAME {
+		return Err(MyError::new(format!("Name is already used by a builtin algorithm: {}", name)));
+	}
+
+	let mut loaded = LOADED.lock().unwrap();
+	loaded.retain(|(n, _)| n != name);
+	loaded.push((name.to_string(), factory));
+	Ok(())
+}
+
+// load a plugin (shared library), returns the name
+pub fn load_plugin(path: &str) -> Result<String, MyError> {
+	let plugin = Plugin::load(path)?;
+	let name = plugin.name().to_string();
+	register_loaded(&name, Arc::new(move || Box::new(PluginRouting::new(plugin.clone()))))?;
+	Ok(name)
+}
+
+// load a WebAssembly module, returns the name
+pub fn load_wasm(path: &str) -> Result<String, MyError> {
+	let module = WasmModule::load(path)?;
+	let name = module.name().to_string();
+	register_loaded(&name, Arc::new(move || Box::new(WasmRouting::new(module.clone()))))?;
+	Ok(name)
+}
+
+pub fn create_algorithm(name: &str) -> Option<Box<dyn RoutingAlgorithm>> {
+	// groups are added with "algo_node", all nodes start with random routing
+	if name == mixed_routing::NAME {
+		return Some(Box::new(MixedRouting::with_default("random", Box::new(random_routing::RandomRouting::new()), 0)));
+	}
+
+	for (algo_name, factory) in ALGORITHMS {
+		if *algo_name == name {
+			return Some(factory());
+		}
+	}
+
+	let loaded = LOADED.lock().unwrap();
+	let (_, factory) = loaded.iter().find(|(n, _)| n == name)?;
+	Some(factory())
+}
+
+pub fn algorithm_names() -> Vec<String> {
+	let mut names : Vec<String> = ALGORITHMS.iter().map(|(name, _)| name.to_string()).collect();
+	names.extend(LOADED.lock().unwrap().iter().map(|(name, _)| name.clone()));
+	names
+}
@@ -0,0 +1,154 @@
+
+use std::os::raw::c_void;
+use std::path::Path;
+use std::sync::Arc;
+use libloading::Library;
+
+use crate::graph::ID;
+use crate::utils::MyError;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+ * Routing algorithms from shared libraries (cdylib) with a C ABI:
+ *
+ * uint32_t mesh_plugin_abi_version(void);
+ * void *mesh_plugin_create(void);
+ * void mesh_plugin_destroy(void *state);
+ * void mesh_plugin_reset(void *state, uint32_t node_count);
+ * void mesh_plugin_step(void *state, const uint32_t *links, size_t link_count);
+ * int64_t mesh_plugin_route(const void *state, uint32_t transmitter, uint32_t receiver,
+ *     uint32_t source, uint32_t destination);
+ *
+ * The links are passed as from/to pairs. mesh_plugin_route returns
+ * the next hop or -1 and must be thread safe, tests route in parallel.
+ */
+
+pub const PLUGIN_ABI_VERSION : u32 = 1;
+
+type VersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn() -> *mut c_void;
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+type ResetFn = unsafe extern "C" fn(*mut c_void, u32);
+type StepFn = unsafe extern "C" fn(*mut c_void, *const u32, usize);
+type RouteFn = unsafe extern "C" fn(*const c_void, u32, u32, u32, u32) -> i64;
+
+#[derive(Clone)]
+pub struct Plugin {
+	name: String,
+	path: String,
+	// the functions are valid as long as the library is loaded
+	_library: Arc<Library>,
+	create: CreateFn,
+	destroy: DestroyFn,
+	reset: ResetFn,
+	step: StepFn,
+	route: RouteFn
+}
+
+impl Plugin {
+	pub fn load(path: &str) -> Result<Self, MyError> {
+		let error = |e: libloading::Error| MyError::new(e.to_string());
+
+		unsafe {
+			let library = Library::new(path).map_err(error)?;
+
+			let version = (*library.get::<VersionFn>(b"mesh_plugin_abi_version").map_err(error)?)();
+			if version != PLUGIN_ABI_VERSION {
+				return Err(MyError::new(format!("{}: unsupported plugin ABI version {} (expected {})",
+					path, version, PLUGIN_ABI_VERSION)));
+			}
+
+			let create = *library.get::<CreateFn>(b"mesh_plugin_create").map_err(error)?;
+			let destroy = *library.get::<DestroyFn>(b"mesh_plugin_destroy").map_err(error)?;
+			let reset = *library.get::<ResetFn>(b"mesh_plugin_reset").map_err(error)?;
+			let step = *library.get::<StepFn>(b"mesh_plugin_step").map_err(error)?;
+			let route = *library.get::<RouteFn>(b"mesh_plugin_route").map_err(error)?;
+
+			// libfoo.so => foo
+			let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or(path);
+			let name = stem.trim_start_matches("lib").to_string();
+
+			Ok(Self {
+				name,
+				path: path.to_string(),
+				_library: Arc::new(library),
+				create,
+				destroy,
+				reset,
+				step,
+				route
+			})
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+}
+
+pub struct PluginRouting {
+	plugin: Plugin,
+	state: *mut c_void,
+	links: Vec<u32>
+}
+
+// the plugin state is only accessed through the plugin functions
+unsafe impl Send for PluginRouting {}
+unsafe impl Sync for PluginRouting {}
+
+impl PluginRouting {
+	pub fn new(plugin: Plugin) -> Self {
+		let state = unsafe { (plugin.create)() };
+		Self {
+			plugin,
+			state,
+			links: vec![]
+		}
+	}
+}
+
+impl Drop for PluginRouting {
+	fn drop(&mut self) {
+		unsafe { (self.plugin.destroy)(self.state) };
+	}
+}
+
+impl RoutingAlgorithm for PluginRouting {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Plugin {}", self.plugin.name)?;
+			},
+			"description" => {
+				write!(out, "Routing algorithm loaded from {}.", self.plugin.path)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset(&mut self, len: usize) {
+		unsafe { (self.plugin.reset)(self.state, len as u32) };
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		self.links.clear();
+		for (from, to) in io.link_iter() {
+			self.links.push(from);
+			self.links.push(to);
+		}
+		unsafe { (self.plugin.step)(self.state, self.links.as_ptr(), self.links.len() / 2) };
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let next = unsafe {
+			(self.plugin.route)(self.state, packet.transmitter, packet.receiver, packet.source, packet.destination)
+		};
+		if next < 0 {
+			None
+		} else {
+			Some(next as ID)
+		}
+	}
+}
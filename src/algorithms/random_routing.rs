@@ -1,5 +1,5 @@
 
-use std::usize;
+use std::mem::size_of;
 use crate::graph::*;
 use crate::utils::*;
 use crate::sim::{Io, RoutingAlgorithm, TestPacket};
@@ -11,12 +11,6 @@ struct Neighbor {
 	last_updated: u32
 }
 
-impl Neighbor {
-	fn new(id: ID, last_updated: u32) -> Self {
-		Self {id: id, last_updated: last_updated}
-	}
-}
-
 impl PartialEq for Neighbor {
     fn eq(&self, other: &Neighbor) -> bool {
         self.id == other.id
@@ -36,7 +30,7 @@ impl Node {
 	}
 
 	fn step(&mut self, time: u32) {
-		vec_filter(&mut self.neighbors, |ref e| (e.last_updated + 5) >= time);
+		vec_filter(&mut self.neighbors, |e| (e.last_updated + 5) >= time);
 	}
 
 	fn update(&mut self, from_id: ID, time: u32) {
@@ -54,6 +48,12 @@ pub struct RandomRouting {
 	time: u32,
 }
 
+impl Default for RandomRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl RandomRouting {
 	pub fn new() -> Self {
 		Self {
@@ -65,7 +65,7 @@ impl RandomRouting {
 
 impl RoutingAlgorithm for RandomRouting
 {
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"name" => {
 				write!(out, "Random Routing")?;
@@ -78,6 +78,18 @@ impl RoutingAlgorithm for RandomRouting
 		Ok(())
 	}
 
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>()))
+	}
+
 	fn reset(&mut self, len: usize) {
 		self.nodes = vec![Node::new(); len];
 		self.time = 0;
@@ -93,6 +105,11 @@ impl RoutingAlgorithm for RandomRouting
 
 		// simulate broadcast traffic
 		for (from, to) in io.link_iter() {
+			// only bidirectional links can be used to send packets back
+			if !io.has_link(to, from) {
+				continue;
+			}
+			io.send(1, size_of::<ID>() as u64);
 			self.nodes[to as usize].update(from, self.time);
 		}
 	}
@@ -100,7 +117,7 @@ impl RoutingAlgorithm for RandomRouting
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
 		let from = packet.receiver;
 		let neighbors = &self.nodes[from as usize].neighbors;
-		let rnd = rand::random::<usize>();
+		let rnd = random::<usize>();
 		let len = neighbors.len();
 
 		if len > 0 {
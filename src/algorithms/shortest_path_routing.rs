@@ -0,0 +1,140 @@
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+* Routing along the shortest paths with global knowledge of the topology.
+* Not a real routing protocol, but an optimal baseline to compare stretch against.
+*/
+
+const NO_HOP : ID = ID::MAX;
+
+#[derive(Clone)]
+struct Node {
+	// next hop for each destination
+	next_hops: Vec<ID>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			next_hops: vec![]
+		}
+	}
+
+	fn reachable(&self) -> usize {
+		self.next_hops.iter().filter(|next| **next != NO_HOP).count()
+	}
+}
+
+pub struct ShortestPathRouting {
+	nodes: Vec<Node>
+}
+
+impl Default for ShortestPathRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ShortestPathRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![]
+		}
+	}
+
+	// Dijkstra from source, remembering the first hop taken for each target
+	fn calculate_next_hops(io: &Io, source: ID) -> Vec<ID> {
+		let len = io.nodes_count();
+		let mut dists = vec![u32::MAX; len];
+		let mut next_hops = vec![NO_HOP; len];
+		let mut heap = BinaryHeap::new();
+
+		dists[source as usize] = 0;
+		heap.push(Reverse((0u32, source)));
+
+		while let Some(Reverse((dist, u))) = heap.pop() {
+			if dist > dists[u as usize] {
+				continue;
+			}
+
+			for link in io.node_links(u) {
+				let v = link.to as usize;
+				let alt = dist + link.cost() as u32;
+				if alt < dists[v] {
+					dists[v] = alt;
+					next_hops[v] = if u == source { link.to } else { next_hops[u as usize] };
+					heap.push(Reverse((alt, link.to)));
+				}
+			}
+		}
+
+		next_hops[source as usize] = NO_HOP;
+		next_hops
+	}
+}
+
+impl RoutingAlgorithm for ShortestPathRouting
+{
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			write!(out, "{} ({})", id, self.nodes[id as usize].reachable())?;
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Shortest Path Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Route along the shortest paths using global knowledge of the topology. ",
+					"Serves as an optimal baseline for other algorithms."
+				))?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn is_converged(&self) -> Option<bool> {
+		// all paths are calculated on every step
+		Some(true)
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.next_hops.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<ID>()))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		// recalculate all paths since the topology might have changed
+		let len = io.nodes_count();
+		self.nodes = (0..len as ID).map(|id| Node {
+			next_hops: Self::calculate_next_hops(io, id)
+		}).collect();
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let next = self.nodes.get(packet.receiver as usize)
+			.and_then(|node| node.next_hops.get(packet.destination as usize));
+
+		match next {
+			Some(&next) if next != NO_HOP => Some(next),
+			_ => None
+		}
+	}
+}
@@ -1,49 +1,46 @@
 
-use std::usize;
+use std::mem::size_of;
+use std::collections::VecDeque;
 
 use crate::graph::ID;
 use crate::sim::{Io, TestPacket, RoutingAlgorithm};
-use crate::utils::vec_filter;
+use crate::utils::{random, random_range, print_unknown_key, vec_filter};
 
 /*
 * Routing on top of an Spanning Tree.
+* Several trees can be built at the same time, each with its own root.
+* The root of a tree announces the priority 0, all other nodes 1.
 */
 
 #[derive(Clone, PartialEq, Debug)]
 struct Path {
 	id: u32, // todo: remove
+	// priority of the root, lower is better
+	prio: u32,
 	path: Vec<u32>,
 }
 
 impl Path {
-	fn new() -> Self {
-		Self {id: 0, path: Vec::new()}
-	}
-
-	fn with(id: u32) -> Self {
-		Self {id: id, path: vec![id]}
+	fn with(id: u32, prio: u32) -> Self {
+		Self {id, prio, path: vec![id]}
 	}
 
 	fn len(&self) -> usize {
 		self.path.len()
 	}
-
-	fn push(&mut self, id: u32) {
-		self.path.push(id);
-	}
 }
 
 #[derive(Clone)]
 struct Packet {
 	sender_id: u32,
-	path: Path,
+	paths: Vec<Path>,
 }
 
 impl Packet {
 	fn new() -> Self {
 		Packet {
 			sender_id: 0,
-			path: Path::new(),
+			paths: Vec::new(),
 		}
 	}
 }
@@ -59,44 +56,33 @@ struct Neighbor {
 #[derive(Clone)]
 struct Node {
 	id: u32,
-	path: Path,
+	// path to the root of each tree
+	paths: Vec<Path>,
 	time: u32,
 
 	// the entry with the smallest id is the root
 	neighbors: Vec<Neighbor>
 }
 
-fn has_duplicates(path: &Vec<u32>) -> bool {
-	let len = path.len();
-	for i in 0..len {
-		for j in 0..len {
-			if i != j && path[i] == path[j] {
-				return true;
-			}
-		}
-	}
-	false
-}
-
 impl Node {
 	fn new() -> Self {
 		Self {
 			id: 0,
-			path: Path::new(),
+			paths: Vec::new(),
 			time: 0,
 			neighbors: Vec::new(),
 		}
 	}
 
-	fn init(&mut self, id: u32, time: u32) {
+	fn init(&mut self, id: u32, time: u32, prios: &[u32]) {
 		self.id = id;
-		self.path = Path::with(id);
+		self.paths = prios.iter().map(|&prio| Path::with(id, prio)).collect();
 		self.time = time;
 		self.neighbors.clear();
 	}
 
 	fn is_better(p1: &Path, p2: &Path) -> bool {
-		(p1.id < p2.id) || (p1.id == p2.id && p1.len() < p2.len())
+		(p1.prio, p1.id) < (p2.prio, p2.id) || (p1.prio == p2.prio && p1.id == p2.id && p1.len() < p2.len())
 	}
 
 	fn tick(&mut self) -> Packet {
@@ -104,11 +90,11 @@ impl Node {
 
 		// timeout old entries
 		let time = self.time;
-		vec_filter(&mut self.neighbors, |ref e| (e.last_updated + 5) >= time);
+		vec_filter(&mut self.neighbors, |e| (e.last_updated + 5) >= time);
 
 		Packet {
 			sender_id: self.id,
-			path: self.path.clone()
+			paths: self.paths.clone()
 		}
 	}
 
@@ -124,35 +110,32 @@ impl Node {
 			neighbors.push(Neighbor{id: packet.sender_id, last_updated: time});
 		}
 
-		fn is_loop(path: &[u32], id: u32) -> bool {
-			for pid in path {
-				if *pid == id {
-					return true;
-				}
+		for (path, other) in self.paths.iter_mut().zip(&packet.paths) {
+			if Self::is_better(other, path) {
+				*path = other.clone();
+				path.path.push(self.id);
 			}
-			false
-		}
-
-		if Self::is_better(&packet.path, &self.path) {
-			self.path = packet.path.clone();
-			self.path.path.push(self.id);
 		}
 
 		update_neighbor(&mut self.neighbors, packet, self.time);
 	}
 
-	fn route(&self, dpath: &Path, destination: ID) -> Option<ID> {
-		println!("self.id: {}, path: {:?}, dpath: {:?}", self.id, self.path, dpath);
-
-		fn get_common_len(p1: &Path, p2: &Path) -> usize {
-			let len = usize::min(p1.len(), p2.len());
-			for i in 0..len {
-				if p1.path[i] != p2.path[i] {
-					return i;
-				}
-			}
-			len
+	// hops along the tree to the destination
+	fn tree_distance(path: &Path, dpath: &Path) -> Option<usize> {
+		if path.id != dpath.id {
+			return None;
+		}
+		let common_len = get_common_len(path, dpath);
+		if common_len == 0 {
+			None
+		} else {
+			Some(path.len() + dpath.len() - 2 * common_len)
 		}
+	}
+
+	fn route(&self, tree: usize, dpath: &Path, destination: ID) -> Option<ID> {
+		let path = &self.paths[tree];
+		println!("self.id: {}, path: {:?}, dpath: {:?}", self.id, path, dpath);
 
 		// destination is this node
 		if destination == self.id {
@@ -169,21 +152,21 @@ impl Node {
 		}
 
 		// different tree!
-		if dpath.id != self.path.id {
-			println!("different root! {} {}", dpath.id, self.path.id);
+		if dpath.id != path.id {
+			println!("different root! {} {}", dpath.id, path.id);
 			return None;
 		}
 
-		let common_len = get_common_len(&self.path, &dpath);
+		let common_len = get_common_len(path, dpath);
 
 		if common_len == 0 {
 			println!("common_len is zero");
-			return None;
-		} else if common_len < self.path.len() {
+			None
+		} else if common_len < path.len() {
 			// move up the tree
-			let next = self.path.path[self.path.len() - 2];
+			let next = path.path[path.len() - 2];
 			println!("up the tree: {}", next);
-			return Some(next);
+			Some(next)
 		} else {
 			// move down the tree
 			let neighbor_id = dpath.path[common_len];
@@ -197,34 +180,185 @@ impl Node {
 			}
 
 			println!("no neighbor found");
-			return None;
+			None
 		}
 	}
 }
 
+fn get_common_len(p1: &Path, p2: &Path) -> usize {
+	let len = usize::min(p1.len(), p2.len());
+	for i in 0..len {
+		if p1.path[i] != p2.path[i] {
+			return i;
+		}
+	}
+	len
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RootStrategy {
+	Random,
+	// nodes with the most neighbors
+	MaxDegree,
+	// nodes with the smallest sum of hops to all other nodes
+	Centroid,
+	// set by "set root <id>[,<id>..]"
+	Explicit
+}
+
+impl RootStrategy {
+	fn name(&self) -> &'static str {
+		match self {
+			RootStrategy::Random => "random",
+			RootStrategy::MaxDegree => "max-degree",
+			RootStrategy::Centroid => "centroid",
+			RootStrategy::Explicit => "explicit"
+		}
+	}
+
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"random" => Some(RootStrategy::Random),
+			"max-degree" => Some(RootStrategy::MaxDegree),
+			"centroid" => Some(RootStrategy::Centroid),
+			_ => None
+		}
+	}
+}
+
+// number of reachable nodes and the sum of hops to them
+fn hop_sum(io: &Io, source: ID) -> (usize, u64) {
+	let mut hops = vec![u32::MAX; io.nodes_count()];
+	let mut queue = VecDeque::new();
+	let mut reached = 0;
+	let mut sum = 0;
+	hops[source as usize] = 0;
+	queue.push_back(source);
+
+	while let Some(id) = queue.pop_front() {
+		reached += 1;
+		sum += hops[id as usize] as u64;
+		for link in io.node_links(id) {
+			if hops[link.to as usize] == u32::MAX {
+				hops[link.to as usize] = hops[id as usize] + 1;
+				queue.push_back(link.to);
+			}
+		}
+	}
+	(reached, sum)
+}
+
+// the best root candidates
+fn select_roots(io: &Io, strategy: RootStrategy, count: usize) -> Vec<ID> {
+	let len = io.nodes_count();
+	let mut ids : Vec<ID> = (0..len as ID).collect();
+	match strategy {
+		RootStrategy::Random | RootStrategy::Explicit => {
+			for i in 0..usize::min(count, len) {
+				let j = random_range(i, len);
+				ids.swap(i, j);
+			}
+		},
+		RootStrategy::MaxDegree => {
+			ids.sort_by_key(|&id| usize::MAX - io.node_links(id).len());
+		},
+		RootStrategy::Centroid => {
+			// nodes of the largest component first
+			let sums : Vec<(usize, u64)> = ids.iter().map(|&id| hop_sum(io, id)).collect();
+			ids.sort_by_key(|&id| {
+				let (reached, sum) = sums[id as usize];
+				(usize::MAX - reached, sum)
+			});
+		}
+	}
+	ids.truncate(count);
+	ids
+}
+
 pub struct SpanningTreeRouting {
 	nodes: Vec<Node>,
-	packets: Vec<Packet> // store packet that a node will send to it's neighbors separately, this will avoid cloning the nodes array on every step
+	packets: Vec<Packet>, // store packet that a node will send to it's neighbors separately, this will avoid cloning the nodes array on every step
+	strategy: RootStrategy,
+	trees: usize,
+	// root of each tree, selected on the next step if empty
+	roots: Vec<ID>
+}
+
+impl Default for SpanningTreeRouting {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl SpanningTreeRouting {
 	pub fn new() -> Self {
 		Self {
 			nodes: Vec::new(),
-			packets: Vec::new()
+			packets: Vec::new(),
+			strategy: RootStrategy::Random,
+			trees: 1,
+			roots: Vec::new()
 		}
 	}
+
+	fn init_nodes(&mut self) {
+		for i in 0..self.nodes.len() {
+			let prios : Vec<u32> = (0..self.trees)
+				.map(|t| if self.roots.get(t) == Some(&(i as ID)) { 0 } else { 1 })
+				.collect();
+			let time = random::<u16>() as u32;
+			self.nodes[i].init(i as u32, time, &prios);
+		}
+	}
+
+	// depth of the nodes in the tree of the selected root
+	fn depths(&self, tree: usize) -> Vec<usize> {
+		let root = match self.roots.get(tree) {
+			Some(&root) => root,
+			None => return vec![]
+		};
+		self.nodes.iter()
+			.map(|node| &node.paths[tree])
+			.filter(|path| path.id == root)
+			.map(|path| path.len() - 1)
+			.collect()
+	}
 }
 
 impl RoutingAlgorithm for SpanningTreeRouting {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		let node = &self.nodes[id as usize];
+		let path = &node.paths[0];
 		match key {
 			"name" => {
 				write!(out, "{}", node.id)?;
 			},
 			"label" => {
-				write!(out, "{},{}", node.neighbors.len(), node.path.len())?;
+				write!(out, "{},{}", node.neighbors.len(), path.len())?;
+			},
+			"root" => {
+				let roots : Vec<String> = node.paths.iter().map(|p| p.id.to_string()).collect();
+				write!(out, "{}", roots.join(","))?;
+			},
+			"depth" => {
+				let depths : Vec<String> = node.paths.iter().map(|p| (p.len() - 1).to_string()).collect();
+				write!(out, "{}", depths.join(","))?;
+			},
+			"parent" => {
+				let len = path.len();
+				if len >= 2 {
+					write!(out, "{}", path.path[len - 2])?;
+				} else {
+					write!(out, "none")?;
+				}
+			},
+			"path" => {
+				let path : Vec<String> = path.path.iter().map(|id| id.to_string()).collect();
+				write!(out, "{}", path.join(","))?;
+			},
+			"neighbors" => {
+				let neighbors : Vec<String> = node.neighbors.iter().map(|n| n.id.to_string()).collect();
+				write!(out, "{}", neighbors.join(","))?;
 			},
 			/*
 			"color" => {
@@ -235,7 +369,7 @@ impl RoutingAlgorithm for SpanningTreeRouting {
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"description" => {
 				write!(out, "{}",
@@ -245,51 +379,123 @@ impl RoutingAlgorithm for SpanningTreeRouting {
 			"name" => {
 				write!(out, "Spanning Tree Routing")?;
 			},
+			"parameters" => {
+				writeln!(out, "root_strategy <name>   random, max-degree or centroid")?;
+				writeln!(out, "root <id>[,<id>..]     explicit root of each tree")?;
+				writeln!(out, "trees <n>              number of trees, packets follow the shortest tree path")?;
+			},
+			"root_strategy" => {
+				write!(out, "{}", self.strategy.name())?;
+			},
+			"trees" => {
+				write!(out, "{}", self.trees)?;
+			},
+			"root" | "roots" => {
+				let roots : Vec<String> = self.roots.iter().map(|id| id.to_string()).collect();
+				write!(out, "{}", roots.join(","))?;
+			},
+			"depth" => {
+				for tree in 0..self.trees {
+					let depths = self.depths(tree);
+					let root = self.roots.get(tree).map_or("none".to_string(), |id| id.to_string());
+					if depths.is_empty() {
+						writeln!(out, "tree {}: root {}, nodes: 0", tree, root)?;
+					} else {
+						let avg = depths.iter().sum::<usize>() as f32 / depths.len() as f32;
+						let max = depths.iter().max().unwrap_or(&0);
+						writeln!(out, "tree {}: root {}, nodes: {}, depth avg: {:.2}, max: {}",
+							tree, root, depths.len(), avg, max)?;
+					}
+				}
+			},
 			_ => {}
 		}
 		Ok(())
 	}
 
-	fn reset(&mut self, len: usize) {
-		self.nodes = vec![Node::new(); len];
-		self.packets = vec![Packet::new(); len];
-
-		fn contains(nodes: &Vec<Node>, id: u32) -> bool {
-			for i in 0..nodes.len() {
-				if nodes[i].id == id {
-					return true;
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"root_strategy" => match RootStrategy::parse(value) {
+				Some(strategy) => {
+					self.strategy = strategy;
+					self.roots.clear();
+					self.init_nodes();
+				},
+				None => println!("invalid root_strategy value, expected random, max-degree or centroid")
+			},
+			"root" => {
+				let roots : Result<Vec<ID>, _> = value.split(',').map(|id| id.parse::<ID>()).collect();
+				match roots {
+					Ok(ref roots) if roots.iter().all(|&id| (id as usize) < self.nodes.len()) => {
+						self.strategy = RootStrategy::Explicit;
+						self.trees = roots.len();
+						self.roots = roots.clone();
+						self.init_nodes();
+					},
+					_ => println!("invalid root value, expected node ids separated by commas")
 				}
+			},
+			"trees" => match value.parse::<usize>() {
+				Ok(trees) if trees > 0 => {
+					if self.strategy == RootStrategy::Explicit {
+						self.strategy = RootStrategy::Random;
+					}
+					self.trees = trees;
+					self.roots.clear();
+					self.init_nodes();
+				},
+				_ => println!("invalid trees value")
+			},
+			_ => {
+				print_unknown_key(key);
 			}
-			false
 		}
+		Ok(())
+	}
 
-		fn unique_rnd_id(nodes: &Vec<Node>) -> u32 {
-			loop {
-				let id = rand::random::<u32>() % (nodes.len() as u32 * 2);
-				if !contains(&nodes, id) {
-					return id;
-				}
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		let path_len : usize = node.paths.iter().map(|path| path.len()).sum();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>() + path_len * size_of::<u32>()))
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		self.packets = vec![Packet::new(); len];
+
+		// explicit roots are kept if they still exist
+		if self.strategy != RootStrategy::Explicit || self.roots.iter().any(|&id| id as usize >= len) {
+			if self.strategy == RootStrategy::Explicit {
+				self.strategy = RootStrategy::Random;
 			}
+			self.roots.clear();
 		}
 
-		// Assign random numbers.
-		// Avoid edges case for now when the ids are not unique
-		for i in 0..len {
-			//let id = unique_rnd_id(&self.nodes);
-			let time = rand::random::<u16>() as u32;
-			self.nodes[i].init(i as u32, time);
-		}
+		self.init_nodes();
 	}
 
 	fn step(&mut self, io: &mut Io) {
+		// select the roots with the current topology
+		if self.roots.len() < self.trees && io.nodes_count() == self.nodes.len() {
+			self.roots = select_roots(io, self.strategy, self.trees);
+			self.init_nodes();
+		}
+
 		// keep state
 		for i in 0..self.nodes.len() {
 			self.packets[i] = self.nodes[i].tick();
 		}
 
 		for (from, to) in io.link_iter() {
+			// only bidirectional links can be used to send packets back
+			if !io.has_link(to, from) {
+				continue;
+			}
 			let packet = &self.packets[from as usize];
-			self.nodes[to as usize].update(&packet);
+			let path_len : usize = packet.paths.iter().map(|path| 1 + path.path.len()).sum();
+			io.send(1, (size_of::<u32>() * (1 + path_len)) as u64);
+			self.nodes[to as usize].update(packet);
 		}
 	}
 
@@ -300,8 +506,43 @@ pub fn new(transmitter: ID, receiver: ID, source: ID, destination: ID) -> Self {
 */
 
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
-		let dpath = &self.nodes[packet.destination as usize].path;
-		self.nodes[packet.receiver as usize].route(dpath, packet.destination)
+		let node = &self.nodes[packet.receiver as usize];
+		let dpaths = &self.nodes[packet.destination as usize].paths;
+
+		// the tree with the shortest path to the destination, the distance shrinks every hop
+		let mut tree = 0;
+		let mut d_next = usize::MAX;
+		for (i, (path, dpath)) in node.paths.iter().zip(dpaths).enumerate() {
+			if let Some(d) = Node::tree_distance(path, dpath) {
+				if d < d_next {
+					d_next = d;
+					tree = i;
+				}
+			}
+		}
+
+		node.route(tree, &dpaths[tree], packet.destination)
+	}
+
+	// the next hop of every tree, shortest tree path first
+	fn route_multi(&self, packet: &TestPacket) -> Vec<ID> {
+		let node = &self.nodes[packet.receiver as usize];
+		let dpaths = &self.nodes[packet.destination as usize].paths;
+
+		let mut trees : Vec<(usize, usize)> = node.paths.iter().zip(dpaths).enumerate()
+			.filter_map(|(i, (path, dpath))| Node::tree_distance(path, dpath).map(|d| (d, i)))
+			.collect();
+		trees.sort_unstable();
+
+		let mut nexts = vec![];
+		for (_, tree) in trees {
+			if let Some(next) = node.route(tree, &dpaths[tree], packet.destination) {
+				if !nexts.contains(&next) {
+					nexts.push(next);
+				}
+			}
+		}
+		nexts
 	}
 
 }
@@ -1,5 +1,5 @@
-use std::f32;
-use std::u32;
+use std::mem::size_of;
+use serde_json::{json, Value};
 
 use crate::utils::*;
 use crate::graph::*;
@@ -10,6 +10,8 @@ use crate::sim::{Io, RoutingAlgorithm, TestPacket};
 struct Neighbor {
 	id: ID,
 	pos: Vec3,
+	// link quality (0..1)
+	quality: f32,
 	last_updated: u32
 }
 
@@ -20,6 +22,33 @@ impl PartialEq for Neighbor {
 	}
 }
 
+// spring embedding settings
+#[derive(Clone, Copy)]
+struct Params {
+	// spring constant, fraction of the length error corrected per update
+	spring: f32,
+	// length of a relaxed spring
+	length: f32,
+	// fraction of the movement that is dropped
+	damping: f32,
+	// position updates per step
+	substeps: u32,
+	// weight neighbors by link quality
+	quality_weight: bool
+}
+
+impl Params {
+	fn new() -> Self {
+		Self {
+			spring: 0.25,
+			length: 1.5,
+			damping: 0.0,
+			substeps: 1,
+			quality_weight: false
+		}
+	}
+}
+
 #[derive(Clone)]
 struct Node {
 	pos: Vec3,
@@ -27,6 +56,15 @@ struct Node {
 	neighbors: Vec<Neighbor>
 }
 
+fn vec3_to_json(v: &Vec3) -> Value {
+	json!([v.x(), v.y(), v.z()])
+}
+
+fn vec3_from_json(value: &Value) -> Vec3 {
+	let get = |i: usize| value.get(i).and_then(Value::as_f64).unwrap_or(0.0) as f32;
+	Vec3::new(get(0), get(1), get(2))
+}
+
 impl Node {
 	fn new() -> Self {
 		Self {
@@ -37,7 +75,7 @@ impl Node {
 	}
 
 	fn timeout_entries(&mut self, time: u32) {
-		vec_filter(&mut self.neighbors, |ref e| (e.last_updated + 5) >= time);
+		vec_filter(&mut self.neighbors, |e| (e.last_updated + 5) >= time);
 	}
 
 	fn route(&self, _packet: &TestPacket, dst_pos: &Vec3) -> Option<ID> {
@@ -55,7 +93,7 @@ impl Node {
 		n_next
 	}
 
-	fn update(&mut self, from_id: ID, from_pos: Vec3, time: u32) {
+	fn update(&mut self, from_id: ID, from_pos: Vec3, quality: f32, time: u32, params: &Params) {
 		/*
 		fn center(ns: &Vec<Neighbor>) -> Vec3 {
 			let mut pos = Vec3::new0();
@@ -72,12 +110,21 @@ impl Node {
 			&Neighbor {
 				id: from_id,
 				pos: from_pos,
+				quality,
 				last_updated: time
 			}
 		);
 
-		fn update(dist: f32, local: Vec3, remote: Vec3) -> Vec3 {
-			let sensitivity = 0.25;
+		self.relax(params);
+	}
+
+	// move to the weighted mean of the positions the springs pull to
+	fn relax(&mut self, params: &Params) {
+		if self.neighbors.is_empty() {
+			return;
+		}
+
+		fn update(dist: f32, local: Vec3, remote: Vec3, sensitivity: f32) -> Vec3 {
 			let err = dist - local.distance(&remote);
 			//let direction_of_err = local.direction(&remote);
 			let direction_of_err = if local == remote {
@@ -86,48 +133,59 @@ impl Node {
 				local.direction(&remote)
 			};
 			let scaled_direction = direction_of_err * err;
-			return local + (scaled_direction * sensitivity);
+			local + (scaled_direction * sensitivity)
 		}
 
 		// normally called every step when all neigbors packets have arrived
 		// all nodes also are randomized 0..1000
 		let mut pos = Vec3::new(0.0, 0.0, 0.0);
+		let mut weights = 0.0;
 		for e in &self.neighbors {
-			pos += update(1.5, self.pos, e.pos);
+			let weight = if params.quality_weight { e.quality } else { 1.0 };
+			pos += update(params.length, self.pos, e.pos, params.spring) * weight;
+			weights += weight;
 		}
 
-		self.pos = pos * (1.0 / self.neighbors.len() as f32);
+		if weights > 0.0 {
+			let target = pos * (1.0 / weights);
+			self.pos = self.pos + (target - self.pos) * (1.0 - params.damping);
+		}
 	}
 }
 
 pub struct SpringRouting {
 	nodes: Vec<Node>,
-	time: u32
+	time: u32,
+	params: Params
+}
+
+impl Default for SpringRouting {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl SpringRouting {
 	pub fn new() -> Self {
 		Self {
 			nodes: vec![],
-			time: 0
+			time: 0,
+			params: Params::new()
 		}
 	}
 }
 
 impl RoutingAlgorithm for SpringRouting
 {
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
-		match key {
-			"name" => {
-				let pos = &self.nodes[id as usize].pos;
-				write!(out, "{:.1}/{:.1}/{:.1}", pos.x(), pos.y(), pos.z())?;
-			},
-			_ => {}
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "name" {
+			let pos = &self.nodes[id as usize].pos;
+			write!(out, "{:.1}/{:.1}/{:.1}", pos.x(), pos.y(), pos.z())?;
 		}
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"description" => {
 				write!(out, "{}", concat!(
@@ -138,16 +196,138 @@ impl RoutingAlgorithm for SpringRouting
 			"name" => {
 				write!(out, "Spring Routing")?;
 			},
+			"parameters" => {
+				writeln!(out, "spring <0..1>          spring constant, fraction of the length error corrected per update")?;
+				writeln!(out, "length <length>        length of a relaxed spring")?;
+				writeln!(out, "damping <0..1>         fraction of the movement that is dropped")?;
+				writeln!(out, "substeps <n>           position updates per simulation step")?;
+				writeln!(out, "quality_weight <bool>  weight neighbors by link quality")?;
+			},
+			"spring" => {
+				write!(out, "{}", self.params.spring)?;
+			},
+			"length" => {
+				write!(out, "{}", self.params.length)?;
+			},
+			"damping" => {
+				write!(out, "{}", self.params.damping)?;
+			},
+			"substeps" => {
+				write!(out, "{}", self.params.substeps)?;
+			},
+			"quality_weight" => {
+				write!(out, "{}", self.params.quality_weight)?;
+			},
+			"mean_error" => {
+				// relative difference of the neighbor distances to the relaxed spring length
+				let mut sum = 0.0;
+				let mut count = 0;
+				for node in &self.nodes {
+					for neighbor in &node.neighbors {
+						sum += (node.pos.distance(&neighbor.pos) - self.params.length).abs() / self.params.length;
+						count += 1;
+					}
+				}
+				if count > 0 {
+					write!(out, "{}", sum / count as f32)?;
+				}
+			},
 			_ => {}
 		}
 		Ok(())
 	}
 
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		let params = &mut self.params;
+		match key {
+			"spring" => match value.parse::<f32>() {
+				Ok(spring) if spring > 0.0 && spring <= 1.0 => params.spring = spring,
+				_ => println!("invalid spring value, expected 0 < spring <= 1")
+			},
+			"length" => match value.parse::<f32>() {
+				Ok(length) if length > 0.0 => params.length = length,
+				_ => println!("invalid length value")
+			},
+			"damping" => match value.parse::<f32>() {
+				Ok(damping) if (0.0..1.0).contains(&damping) => params.damping = damping,
+				_ => println!("invalid damping value, expected 0 <= damping < 1")
+			},
+			"substeps" => match value.parse::<u32>() {
+				Ok(substeps) if substeps > 0 => params.substeps = substeps,
+				_ => println!("invalid substeps value")
+			},
+			"quality_weight" => match value.parse::<bool>() {
+				Ok(quality_weight) => params.quality_weight = quality_weight,
+				_ => println!("invalid quality_weight value, expected true or false")
+			},
+			_ => {
+				print_unknown_key(key);
+			}
+		}
+		Ok(())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>()))
+	}
+
 	fn reset(&mut self, len: usize) {
 		self.nodes = vec![Node::new(); len];
 		self.time = 0;
 	}
 
+	fn save_state(&self) -> Option<Value> {
+		let nodes : Vec<Value> = self.nodes.iter().map(|node| {
+			let neighbors : Vec<Value> = node.neighbors.iter().map(|n| json!({
+				"id": n.id, "pos": vec3_to_json(&n.pos), "quality": n.quality, "last_updated": n.last_updated
+			})).collect();
+			json!({ "pos": vec3_to_json(&node.pos), "pos_old": vec3_to_json(&node.pos_old), "neighbors": neighbors })
+		}).collect();
+
+		let params = &self.params;
+		Some(json!({
+			"time": self.time, "nodes": nodes,
+			"spring": params.spring, "length": params.length, "damping": params.damping,
+			"substeps": params.substeps, "quality_weight": params.quality_weight
+		}))
+	}
+
+	fn load_state(&mut self, state: &Value) -> Result<(), MyError> {
+		let nodes = get_array(state, "nodes")
+			.ok_or_else(|| MyError::new("spring state: nodes missing".to_string()))?;
+		if nodes.len() != self.nodes.len() {
+			return Err(MyError::new(format!("spring state: expected {} nodes, got {}", self.nodes.len(), nodes.len())));
+		}
+
+		for (node, value) in self.nodes.iter_mut().zip(nodes) {
+			node.pos = vec3_from_json(&value["pos"]);
+			node.pos_old = vec3_from_json(&value["pos_old"]);
+			node.neighbors = get_array(value, "neighbors").map_or(vec![], |neighbors| {
+				neighbors.iter().map(|n| Neighbor {
+					id: get_u64(n, "id").unwrap_or(0) as ID,
+					pos: vec3_from_json(&n["pos"]),
+					quality: get_f64(n, "quality").unwrap_or(1.0) as f32,
+					last_updated: get_u64(n, "last_updated").unwrap_or(0) as u32
+				}).collect()
+			});
+		}
+
+		self.time = get_u64(state, "time").unwrap_or(0) as u32;
+
+		let defaults = Params::new();
+		let get = |key: &str, default: f32| get_f64(state, key).map_or(default, |v| v as f32);
+		self.params = Params {
+			spring: get("spring", defaults.spring),
+			length: get("length", defaults.length),
+			damping: get("damping", defaults.damping),
+			substeps: get_u64(state, "substeps").map_or(defaults.substeps, |v| (v as u32).max(1)),
+			quality_weight: state.get("quality_weight").and_then(Value::as_bool).unwrap_or(defaults.quality_weight)
+		};
+		Ok(())
+	}
+
 	fn step(&mut self, io: &mut Io) {
 		self.time += 1;
 /*
@@ -164,9 +344,28 @@ impl RoutingAlgorithm for SpringRouting
 
 		// simulate broadcast traffic
 		for (from, to) in io.link_iter() {
+			// only bidirectional links can be used to send packets back
+			if !io.has_link(to, from) {
+				continue;
+			}
+			io.send(1, size_of::<(ID, Vec3)>() as u64);
 			let pos = self.nodes[from as usize].pos_old;
-			self.nodes[to as usize].update(from, pos, self.time);
+			let quality = io.get_link(from, to).map_or(0.0, |link| link.quality() as f32 / u16::MAX as f32);
+			self.nodes[to as usize].update(from, pos, quality, self.time, &self.params);
 		}
+
+		// further updates with the known neighbor positions
+		for _ in 1..self.params.substeps {
+			for node in &mut self.nodes {
+				node.relax(&self.params);
+			}
+		}
+	}
+
+	fn distance(&self, from: ID, to: ID) -> Option<f32> {
+		let a = self.nodes.get(from as usize)?;
+		let b = self.nodes.get(to as usize)?;
+		Some(a.pos.distance(&b.pos))
 	}
 
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
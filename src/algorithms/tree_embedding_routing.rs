@@ -0,0 +1,279 @@
+
+use std::mem::size_of;
+
+use crate::graph::ID;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+use crate::utils::random_range;
+
+
+/*
+* Greedy routing on a tree embedding (PIE).
+* Nodes build a shortest path tree from a root. The coordinate of a
+* node is the coordinate of its parent plus the index of the link at
+* the parent (the port number), the root has the empty coordinate.
+* The distance of two coordinates is their distance in the tree.
+* Packets are sent to the neighbor with the smallest distance to the
+* destination. The parent or a child on the tree path always makes
+* progress, so delivery is guaranteed once the tree is built, while
+* links outside the tree are used as shortcuts.
+*/
+
+#[derive(Clone)]
+struct Node {
+	parent: Option<ID>,
+	// hops to the root
+	hops: u32,
+	coordinate: Vec<u32>,
+	// neighbors with links in both directions
+	neighbors: Vec<ID>
+}
+
+impl Node {
+	fn new() -> Self {
+		Self {
+			parent: None,
+			hops: u32::MAX,
+			coordinate: vec![],
+			neighbors: vec![]
+		}
+	}
+
+	fn has_coordinate(&self) -> bool {
+		self.hops != u32::MAX
+	}
+}
+
+// tree distance of two coordinates
+fn tree_distance(a: &[u32], b: &[u32]) -> usize {
+	let common = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+	a.len() + b.len() - 2 * common
+}
+
+fn format_coordinate(coordinate: &[u32]) -> String {
+	let parts : Vec<String> = coordinate.iter().map(|i| i.to_string()).collect();
+	format!("({})", parts.join("."))
+}
+
+pub struct TreeEmbeddingRouting {
+	nodes: Vec<Node>,
+	// selected on the next step if not set
+	root: Option<ID>,
+	// use links outside the tree
+	shortcuts: bool
+}
+
+impl Default for TreeEmbeddingRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl TreeEmbeddingRouting {
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![],
+			root: None,
+			shortcuts: true
+		}
+	}
+
+	fn clear_coordinates(&mut self) {
+		for node in &mut self.nodes {
+			*node = Node::new();
+		}
+	}
+
+	fn next_hop(&self, id: ID, destination: ID) -> Option<ID> {
+		let node = self.nodes.get(id as usize)?;
+		let target = self.nodes.get(destination as usize)?;
+		if !node.has_coordinate() || !target.has_coordinate() {
+			return None;
+		}
+
+		let mut best = (tree_distance(&node.coordinate, &target.coordinate), None);
+		for &neighbor in &node.neighbors {
+			let other = &self.nodes[neighbor as usize];
+			if !other.has_coordinate() {
+				continue;
+			}
+			// without shortcuts only the parent and children
+			if !self.shortcuts && node.parent != Some(neighbor) && other.parent != Some(id) {
+				continue;
+			}
+			let distance = tree_distance(&other.coordinate, &target.coordinate);
+			if distance < best.0 || (distance == best.0 && best.1.is_some_and(|b| neighbor < b)) {
+				best = (distance, Some(neighbor));
+			}
+		}
+		best.1
+	}
+}
+
+impl RoutingAlgorithm for TreeEmbeddingRouting {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		let node = match self.nodes.get(id as usize) {
+			Some(node) => node,
+			None => return Ok(())
+		};
+
+		match key {
+			"name" | "label" | "coordinate" if node.has_coordinate() => {
+				write!(out, "{}", format_coordinate(&node.coordinate))?;
+			},
+			"parent" => {
+				if let Some(parent) = node.parent {
+					write!(out, "{}", parent)?;
+				}
+			},
+			"depth" if node.has_coordinate() => {
+				write!(out, "{}", node.hops)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "Tree Embedding Routing")?;
+			},
+			"description" => {
+				write!(out, "{}", concat!(
+					"Nodes get coordinates of a spanning tree (parent coordinate and port number). ",
+					"Packets are sent greedily to the neighbor with the smallest tree distance to the destination."
+				))?;
+			},
+			"parameters" => {
+				writeln!(out, "root <id>         root of the tree, random if not set")?;
+				writeln!(out, "shortcuts <bool>  use links outside the tree")?;
+			},
+			"root" => {
+				if let Some(root) = self.root {
+					write!(out, "{}", root)?;
+				}
+			},
+			"shortcuts" => {
+				write!(out, "{}", self.shortcuts)?;
+			},
+			"depth" => {
+				let embedded = self.nodes.iter().filter(|node| node.has_coordinate()).count();
+				let depth = self.nodes.iter().filter(|node| node.has_coordinate()).map(|node| node.hops).max().unwrap_or(0);
+				write!(out, "nodes: {}/{}, depth: {}", embedded, self.nodes.len(), depth)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"root" => {
+				match value.parse::<ID>() {
+					Ok(root) if (root as usize) < self.nodes.len() => {
+						self.root = Some(root);
+						self.clear_coordinates();
+					},
+					_ => println!("invalid root value")
+				}
+			},
+			"shortcuts" => {
+				if let Ok(shortcuts) = value.parse::<bool>() {
+					self.shortcuts = shortcuts;
+				} else {
+					println!("invalid shortcuts value, expected true or false");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		// coordinates of the neighbors
+		let entries = node.neighbors.len();
+		let coordinates : usize = node.neighbors.iter()
+			.map(|n| self.nodes[*n as usize].coordinate.len())
+			.sum();
+		Some((entries, size_of::<Node>() + entries * size_of::<ID>() + (node.coordinate.len() + coordinates) * size_of::<u32>()))
+	}
+
+	fn reset_node(&mut self, id: ID) {
+		if let Some(node) = self.nodes.get_mut(id as usize) {
+			*node = Node::new();
+		}
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.nodes = vec![Node::new(); len];
+		if self.root.is_some_and(|root| root as usize >= len) {
+			self.root = None;
+		}
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		let len = self.nodes.len();
+		if len == 0 || io.nodes_count() != len {
+			return;
+		}
+
+		let root = match self.root {
+			Some(root) => root,
+			None => {
+				let root = random_range(0, len) as ID;
+				self.root = Some(root);
+				root
+			}
+		};
+
+		// every node sends its coordinate to its neighbors
+		let mut nodes = vec![Node::new(); len];
+		for (id, node) in nodes.iter_mut().enumerate() {
+			let id = id as ID;
+			node.neighbors = io.node_links(id).iter()
+				.map(|link| link.to)
+				.filter(|to| io.has_link(*to, id))
+				.collect();
+
+			let old = &self.nodes[id as usize];
+			io.send(node.neighbors.len() as u64, (node.neighbors.len() * (size_of::<u32>() * (old.coordinate.len() + 1))) as u64);
+
+			if id == root {
+				node.hops = 0;
+				continue;
+			}
+
+			// neighbor closest to the root, lowest id on equal hops
+			let parent = node.neighbors.iter()
+				.filter(|n| self.nodes[**n as usize].has_coordinate())
+				.min_by_key(|n| (self.nodes[**n as usize].hops, **n));
+
+			if let Some(&parent) = parent {
+				let p = &self.nodes[parent as usize];
+				// port number of the link at the parent
+				let port = io.node_links(parent).iter().position(|link| link.to == id).unwrap_or(0) as u32;
+				node.parent = Some(parent);
+				node.hops = p.hops + 1;
+				node.coordinate = p.coordinate.clone();
+				node.coordinate.push(port);
+			}
+		}
+
+		self.nodes = nodes;
+	}
+
+	fn distance(&self, from: ID, to: ID) -> Option<f32> {
+		let a = self.nodes.get(from as usize)?;
+		let b = self.nodes.get(to as usize)?;
+		if a.has_coordinate() && b.has_coordinate() {
+			Some(tree_distance(&a.coordinate, &b.coordinate) as f32)
+		} else {
+			None
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		self.next_hop(packet.receiver, packet.destination)
+	}
+}
@@ -1,10 +1,10 @@
-use std::f32;
-use std::u32;
+use std::mem::size_of;
+use serde_json::{json, Value};
 
 use crate::utils::*;
 use crate::graph::*;
 use crate::sim::{Io, TestPacket, RoutingAlgorithm};
-use std::ops::{AddAssign, DivAssign, Index, Add, Mul, Div, Sub, Deref};
+use std::ops::{AddAssign, DivAssign, Index, Add, Mul, Div, Sub};
 
 
 #[derive(Clone, Copy, PartialEq)]
@@ -49,10 +49,6 @@ impl VVec {
 		self.scalar_mul(1.0 / self.length())
 	}
 
-	pub fn angle(&self, v: &VVec) -> f32 {
-		(self.unit() * v.unit()).acos()
-	}
-
 	pub fn vec_mul(&self, v: &VVec) -> f32 {
 		let mut ret = 0.0;
 		for i in 0..self.data.len() {
@@ -62,7 +58,7 @@ impl VVec {
 	}
 
 	pub fn scalar_mul(&self, s: f32) -> VVec {
-		let mut ret = self.clone();
+		let mut ret = *self;
 		for i in 0..ret.data.len() {
 			ret.data[i] *= s
 		}
@@ -77,10 +73,6 @@ impl VVec {
 		self.vec_sub(v).length()
 	}
 
-	pub fn direction(&self, v: &VVec) -> VVec {
-		self.vec_sub(v).unit()
-	}
-
 	pub fn is_near_null(&self, eta: f32) -> bool {
 		for n in &self.data {
 			if (*n >= eta) && (*n <= -eta) {
@@ -90,31 +82,19 @@ impl VVec {
 		true
 	}
 
-	pub fn is_finite(&self) -> bool {
-		for n in &self.data {
-			if !n.is_finite() {
-				return false;
-			}
-		}
-		true
+	pub fn as_slice(&self) -> &[f32; 8] {
+		&self.data
 	}
 
-	pub fn to_finite(&self) -> Self {
-		let mut ret = Self::new_nan();
-		let mut i = 0;
-		for n in &self.data {
-			if n.is_finite() {
-				ret.data[i] = *n;
-				i += 1;
-			}
+	// only keep the first dim dimensions
+	pub fn truncate(&self, dim: usize) -> VVec {
+		let mut ret = *self;
+		for i in dim..ret.data.len() {
+			ret.data[i] = 0.0;
 		}
 		ret
 	}
 
-	pub fn as_slice(&self) -> &[f32; 8] {
-		&self.data
-	}
-
 	pub fn random_unit() -> VVec {
 		VVec::random_in_area(1.0).unit()
 	}
@@ -123,7 +103,7 @@ impl VVec {
 	pub fn random_in_area(r: f32) -> VVec {
 		let mut ret = VVec::new();
 		for i in 0..ret.data.len() {
-			ret.data[i] = (2.0 * rand::random::<f32>() - 1.0) * r;
+			ret.data[i] = (2.0 * random::<f32>() - 1.0) * r;
 		}
 		ret
 	}
@@ -193,6 +173,7 @@ impl Div<f32> for VVec {
 struct Neighbor {
 	id: ID,
 	pos: VVec,
+	height: f32,
 	error: f32,
 	last_updated: u32
 }
@@ -203,41 +184,59 @@ impl PartialEq for Neighbor {
 	}
 }
 
+fn vvec_to_json(v: &VVec) -> Value {
+	json!(&v.data[..])
+}
+
+// missing or null values (NaN) are read as NaN
+fn vvec_from_json(value: &Value) -> VVec {
+	let mut v = VVec::new_nan();
+	if let Some(values) = value.as_array() {
+		for (d, value) in v.data.iter_mut().zip(values) {
+			*d = value.as_f64().map_or(f32::NAN, |x| x as f32);
+		}
+	}
+	v
+}
+
 #[derive(Clone)]
 struct Node {
 	pos: VVec,
+	// height above the euclidean space (access link delay)
+	height: f32,
 	error: f32,
 	pos_old: VVec,
+	height_old: f32,
 	neighbors: Vec<Neighbor>,
 }
 
+// distance of height vectors, heights are zero if not used
+fn height_distance(a: &VVec, a_height: f32, b: &VVec, b_height: f32) -> f32 {
+	a.distance(b) + a_height + b_height
+}
+
 impl Node {
 	fn new() -> Self {
 		Self {
 			pos: VVec::new(),
+			height: 0.0,
 			error: 0.0,
 			pos_old: VVec::new(),
+			height_old: 0.0,
 			neighbors: vec![],
 		}
 	}
 
-	fn reset(&mut self) {
-		self.pos = VVec::new();
-		self.error = 0.0;
-		self.pos_old = VVec::new();
-		self.neighbors.clear();
-	}
-
 	fn timeout_entries(&mut self, time: u32) {
-		vec_filter(&mut self.neighbors, |ref e| (e.last_updated + 5) >= time);
+		vec_filter(&mut self.neighbors, |e| (e.last_updated + 5) >= time);
 	}
 
-	fn route(&self, packet: &TestPacket, dst_pos: &VVec) -> Option<ID> {
+	fn route(&self, _packet: &TestPacket, dst_pos: &VVec, dst_height: f32) -> Option<ID> {
 		let mut d_next = f32::INFINITY;
 		let mut n_next = None;
 
 		for v in &self.neighbors {
-			let d = v.pos.distance(dst_pos);
+			let d = height_distance(&v.pos, v.height, dst_pos, dst_height);
 			if d < d_next {
 				d_next = d;
 				n_next = Some(v.id);
@@ -247,21 +246,24 @@ impl Node {
 		n_next
 	}
 
-	fn update(&mut self, from_id: ID, from_pos: VVec, from_error: f32, time: u32, rtt: f32) {
+	#[allow(clippy::too_many_arguments)]
+	fn update(&mut self, from_id: ID, from_pos: VVec, from_height: f32, from_error: f32, time: u32, rtt: f32,
+			dim: usize, use_height: bool) {
 		vec_add_entry(&mut self.neighbors,
 			&Neighbor {
 				id: from_id,
 				pos: from_pos,
+				height: from_height,
 				error: from_error,
 				last_updated: time
 			}
 		);
 
-		self.vivaldi_update(&from_pos, from_error, rtt);
+		self.vivaldi_update(&from_pos, from_height, from_error, rtt, dim, use_height);
 	}
 
-	fn cut_old_pos(&mut self) {
-		let n = std::cmp::min(std::cmp::max(self.neighbors.len(), 1), 8);
+	fn cut_old_pos(&mut self, dim: usize) {
+		let n = std::cmp::min(std::cmp::max(self.neighbors.len(), 1), dim);
 		println!("n: {}, neigh: {}", n, self.neighbors.len());
 		for i in n..8 {
 			self.pos_old.data[i] = 0.0;
@@ -278,7 +280,7 @@ impl Node {
 	}
 
 	// Vivaldi algorithm
-	fn vivaldi_update(&mut self, pos: &VVec, error: f32, rtt: f32) {
+	fn vivaldi_update(&mut self, pos: &VVec, height: f32, error: f32, rtt: f32, dim: usize, use_height: bool) {
 		//let rtt = self.rtt;
 		let ce = 0.25;
 		let cc = 0.25;
@@ -293,8 +295,15 @@ impl Node {
 		// x_i - x_j
 		let ab = self.pos - *pos;
 
+		// height vectors: [x_i - x_j, h_i + h_j] with length |x_i - x_j| + h_i + h_j
+		let (ab_height, ab_length) = if use_height {
+			(self.height + height, ab.length() + self.height + height)
+		} else {
+			(0.0, ab.length())
+		};
+
 		// rtt - |x_i - x_j|
-		let re = rtt - ab.length();
+		let re = rtt - ab_length;
 
 		// e_s = ||x_i - x_j| - rtt| / rtt
 		let es = re.abs() / rtt;
@@ -308,7 +317,7 @@ impl Node {
 		// Choose random direction if both positions are identical
 		let direction = if ab.is_near_null(0.01) {
 			//println!("random direction");
-			VVec::random_unit()
+			VVec::random_unit().truncate(dim)
 		} else {
 			ab
 		};
@@ -316,27 +325,57 @@ impl Node {
 		//println!("old pos: {}, {} {} {}", self.pos, direction, w, re);
 
 		// x_i = x_i + ∂ * (rtt - |x_i - x_j|) * u(x_i - x_j)
-		self.pos = self.pos + direction * (d * re);
+		if use_height && ab_length > 0.0 {
+			// u([x_i - x_j, h_i + h_j]) has a euclidean and a height part, heights stay positive
+			let unit = if ab.is_near_null(0.01) { direction } else { ab / ab_length };
+			self.pos += unit * (d * re);
+			self.height = (self.height + ab_height / ab_length * d * re).max(0.0);
+		} else {
+			self.pos += direction * (d * re);
+		}
+		self.pos = self.pos.truncate(dim);
 		//println!("new pos: {}", self.pos);
 	}
 }
 
+// coordinate dimensions supported by VVec
+const MAX_DIM : usize = 8;
+
 pub struct VivaldiRouting {
 	nodes: Vec<Node>,
 	time: u32,
-	rtt: f32
+	rtt: f32,
+	// used coordinate dimensions
+	dim: usize,
+	// use height vectors
+	height: bool
 }
 
 //https://pdos.csail.mit.edu/papers/vivaldi:sigcomm/paper.pdf
+impl Default for VivaldiRouting {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl VivaldiRouting {
 	pub fn new() -> Self {
 		Self {
 			nodes: vec![],
 			time: 0,
-			rtt: 1.5
+			rtt: 1.5,
+			dim: MAX_DIM,
+			height: false
 		}
 	}
 
+	// mean and max relative error estimate of all nodes
+	fn get_error(&self) -> (f32, f32) {
+		let sum : f32 = self.nodes.iter().map(|n| n.error).sum();
+		let max = self.nodes.iter().map(|n| n.error).fold(0.0, f32::max);
+		(sum / self.nodes.len() as f32, max)
+	}
+
 	// get median distance change
 	pub fn get_convergence(&self) -> f32 {
 		let mut d = 0.0;
@@ -352,23 +391,129 @@ impl VivaldiRouting {
 
 impl RoutingAlgorithm for VivaldiRouting
 {
+	fn state_size(&self, id: ID) -> Option<(usize, usize)> {
+		let node = self.nodes.get(id as usize)?;
+		let entries = node.neighbors.len();
+		Some((entries, size_of::<Node>() + entries * size_of::<Neighbor>()))
+	}
+
 	fn reset(&mut self, len: usize) {
 		self.nodes = vec![Node::new(); len];
 		self.time = 0;
 	}
 
-	fn get_node(&self, id: ID, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get_node(&self, id: ID, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"name" => {
 				let pos = self.nodes[id as usize].pos;
 				write!(out, "{:.1}/{:.1}/{:.1}", pos[0], pos[1], pos[2])?;
 			},
+			"pos" => {
+				let pos : Vec<String> = self.nodes[id as usize].pos.as_slice().iter().map(|v| v.to_string()).collect();
+				write!(out, "{}", pos.join(","))?;
+			},
+			"error" => {
+				write!(out, "{}", self.nodes[id as usize].error)?;
+			},
+			"height" => {
+				write!(out, "{}", self.nodes[id as usize].height)?;
+			},
+			"neighbors" => {
+				for neighbor in &self.nodes[id as usize].neighbors {
+					writeln!(out, "{}: error {}, last updated {}", neighbor.id, neighbor.error, neighbor.last_updated)?;
+				}
+			},
 			_ => {}
 		}
 		Ok(())
 	}
 
-	fn get(&self, key: &str, out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn save_state(&self) -> Option<Value> {
+		let nodes : Vec<Value> = self.nodes.iter().map(|node| {
+			let neighbors : Vec<Value> = node.neighbors.iter().map(|n| json!({
+				"id": n.id, "pos": vvec_to_json(&n.pos), "height": n.height, "error": n.error, "last_updated": n.last_updated
+			})).collect();
+			json!({
+				"pos": vvec_to_json(&node.pos), "pos_old": vvec_to_json(&node.pos_old),
+				"height": node.height, "height_old": node.height_old,
+				"error": node.error, "neighbors": neighbors
+			})
+		}).collect();
+
+		Some(json!({ "time": self.time, "rtt": self.rtt, "dim": self.dim, "height": self.height, "nodes": nodes }))
+	}
+
+	fn load_state(&mut self, state: &Value) -> Result<(), MyError> {
+		let nodes = get_array(state, "nodes")
+			.ok_or_else(|| MyError::new("vivaldi state: nodes missing".to_string()))?;
+		if nodes.len() != self.nodes.len() {
+			return Err(MyError::new(format!("vivaldi state: expected {} nodes, got {}", self.nodes.len(), nodes.len())));
+		}
+
+		for (node, value) in self.nodes.iter_mut().zip(nodes) {
+			node.pos = vvec_from_json(&value["pos"]);
+			node.pos_old = vvec_from_json(&value["pos_old"]);
+			node.height = get_f64(value, "height").unwrap_or(0.0) as f32;
+			node.height_old = get_f64(value, "height_old").unwrap_or(0.0) as f32;
+			node.error = get_f64(value, "error").unwrap_or(0.0) as f32;
+			node.neighbors = get_array(value, "neighbors").map_or(vec![], |neighbors| {
+				neighbors.iter().map(|n| Neighbor {
+					id: get_u64(n, "id").unwrap_or(0) as ID,
+					pos: vvec_from_json(&n["pos"]),
+					height: get_f64(n, "height").unwrap_or(0.0) as f32,
+					error: get_f64(n, "error").unwrap_or(0.0) as f32,
+					last_updated: get_u64(n, "last_updated").unwrap_or(0) as u32
+				}).collect()
+			});
+		}
+
+		self.time = get_u64(state, "time").unwrap_or(0) as u32;
+		self.rtt = get_f64(state, "rtt").unwrap_or(self.rtt as f64) as f32;
+		self.dim = get_u64(state, "dim").map_or(MAX_DIM, |d| (d as usize).clamp(1, MAX_DIM));
+		self.height = state.get("height").and_then(Value::as_bool).unwrap_or(false);
+		Ok(())
+	}
+
+	fn set_node(&mut self, id: ID, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		let node = &mut self.nodes[id as usize];
+		match key {
+			"pos" => {
+				let values : Result<Vec<f32>, _> = value.split(',').map(|v| v.parse::<f32>()).collect();
+				match values {
+					Ok(ref values) if values.len() <= node.pos.data.len() => {
+						node.pos = VVec::new();
+						node.pos.data[..values.len()].copy_from_slice(values);
+					},
+					_ => {
+						println!("invalid pos value, use a comma separated list of up to {} numbers", node.pos.data.len());
+					}
+				}
+			},
+			"error" => {
+				if let Ok(error) = value.parse() {
+					node.error = error;
+				} else {
+					println!("invalid error value");
+				}
+			},
+			"height" => {
+				match value.parse::<f32>() {
+					Ok(height) if height >= 0.0 => {
+						node.height = height;
+					},
+					_ => {
+						println!("invalid height value");
+					}
+				}
+			},
+			_ => {
+				print_unknown_key(key);
+			}
+		}
+		Ok(())
+	}
+
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		match key {
 			"rtt" => {
 				write!(out, "{}", self.rtt)?;
@@ -382,6 +527,24 @@ impl RoutingAlgorithm for VivaldiRouting
 			"convergence" => {
 				write!(out, "{}", self.get_convergence())?;
 			},
+			"parameters" => {
+				writeln!(out, "rtt <rtt>              round trip time of a link")?;
+				writeln!(out, "dim <1..8>             coordinate dimensions")?;
+				writeln!(out, "height <bool>          use height vectors")?;
+			},
+			"dim" => {
+				write!(out, "{}", self.dim)?;
+			},
+			"height" => {
+				write!(out, "{}", self.height)?;
+			},
+			"error" => {
+				let (mean, max) = self.get_error();
+				write!(out, "mean: {}, max: {}", mean, max)?;
+			},
+			"mean_error" => {
+				write!(out, "{}", self.get_error().0)?;
+			},
 			_ => {}
 		}
 		Ok(())
@@ -396,6 +559,33 @@ impl RoutingAlgorithm for VivaldiRouting
 					println!("invalid rtt value");
 				}
 			},
+			"dim" => {
+				match value.parse::<usize>() {
+					Ok(dim) if (1..=MAX_DIM).contains(&dim) => {
+						self.dim = dim;
+						for node in &mut self.nodes {
+							node.pos = node.pos.truncate(dim);
+							node.pos_old = node.pos_old.truncate(dim);
+						}
+					},
+					_ => {
+						println!("invalid dim value, expected 1 to {}", MAX_DIM);
+					}
+				}
+			},
+			"height" => {
+				if let Ok(height) = value.parse::<bool>() {
+					self.height = height;
+					if !height {
+						for node in &mut self.nodes {
+							node.height = 0.0;
+							node.height_old = 0.0;
+						}
+					}
+				} else {
+					println!("invalid height value, expected true or false");
+				}
+			},
 			_ => {}
 		}
 		Ok(())
@@ -407,20 +597,33 @@ impl RoutingAlgorithm for VivaldiRouting
 		// fade out old entries
 		for node in &mut self.nodes {
 			node.pos_old = node.pos;
+			node.height_old = node.height;
 			node.timeout_entries(self.time);
-			node.cut_old_pos();
+			node.cut_old_pos(self.dim);
 		}
 
 		// simulate broadcast traffic
 		for (from, to) in io.link_iter() {
+			// only bidirectional links can be used to send packets back
+			if !io.has_link(to, from) {
+				continue;
+			}
+			io.send(1, size_of::<(ID, VVec)>() as u64);
 			let pos_old = self.nodes[from as usize].pos_old;
-			self.nodes[to as usize].update(from, pos_old, 1.0, self.time, self.rtt);
+			let height_old = self.nodes[from as usize].height_old;
+			self.nodes[to as usize].update(from, pos_old, height_old, 1.0, self.time, self.rtt, self.dim, self.height);
 		}
 	}
 
+	fn distance(&self, from: ID, to: ID) -> Option<f32> {
+		let a = self.nodes.get(from as usize)?;
+		let b = self.nodes.get(to as usize)?;
+		Some(height_distance(&a.pos, a.height, &b.pos, b.height))
+	}
+
 	fn route(&self, packet: &TestPacket) -> Option<ID> {
 		// we pretend to know the destination locator instead of the id
-		let dst_pos = &self.nodes[packet.destination as usize].pos;
-		self.nodes[packet.receiver as usize].route(packet, dst_pos)
+		let dst = &self.nodes[packet.destination as usize];
+		self.nodes[packet.receiver as usize].route(packet, &dst.pos, dst.height)
 	}
 }
@@ -0,0 +1,275 @@
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::graph::ID;
+use crate::utils::MyError;
+use crate::sim::{Io, RoutingAlgorithm, TestPacket};
+
+
+/*
+ * Routing algorithms compiled to WebAssembly, run in a sandbox.
+ * The module exports its memory and these functions:
+ *
+ * alloc(size: i32) -> i32
+ *   buffer in the guest memory for the inbox
+ * init(node_count: i32)
+ * step(inbox: i32, inbox_len: i32) -> i64
+ *   takes the messages received in this step, returns the messages
+ *   to send as (pointer << 32 | length)
+ * route(transmitter: i32, receiver: i32, source: i32, destination: i32) -> i32
+ *   next hop or -1
+ *
+ * A message is from (u32), to (u32), payload length (u32) and the
+ * payload, little endian. Messages to 0xffffffff go to all neighbors.
+ * Messages are only delivered over existing links and account for
+ * the overhead. Every call has an instruction (fuel) limit, the
+ * instructions used are reported for comparison.
+ */
+
+pub const BROADCAST : u32 = u32::MAX;
+
+// instruction limit per call
+const STEP_FUEL : u64 = 10_000_000_000;
+const ROUTE_FUEL : u64 = 10_000_000;
+
+#[derive(Clone)]
+pub struct WasmModule {
+	name: String,
+	path: String,
+	engine: Engine,
+	module: Arc<Module>
+}
+
+impl WasmModule {
+	pub fn load(path: &str) -> Result<Self, MyError> {
+		let data = std::fs::read(path).map_err(|e| MyError::new(format!("{}: {}", path, e)))?;
+
+		let mut config = Config::default();
+		config.consume_fuel(true);
+		let engine = Engine::new(&config);
+		let module = Module::new(&engine, &data[..])
+			.map_err(|e| MyError::new(format!("{}: {}", path, e)))?;
+
+		let name = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or(path).to_string();
+
+		Ok(Self {
+			name,
+			path: path.to_string(),
+			engine,
+			module: Arc::new(module)
+		})
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+}
+
+struct Guest {
+	store: Store<()>,
+	memory: Memory,
+	alloc: TypedFunc<i32, i32>,
+	step: TypedFunc<(i32, i32), i64>,
+	route: TypedFunc<(i32, i32, i32, i32), i32>,
+	// instructions used
+	step_fuel: u64,
+	route_fuel: u64,
+	route_calls: u64
+}
+
+impl Guest {
+	fn new(module: &WasmModule, node_count: usize) -> Result<Self, wasmi::Error> {
+		let mut store = Store::new(&module.engine, ());
+		store.set_fuel(STEP_FUEL)?;
+
+		let linker = <Linker<()>>::new(&module.engine);
+		let instance = linker.instantiate(&mut store, &module.module)?.start(&mut store)?;
+
+		let memory = instance.get_memory(&store, "memory")
+			.ok_or_else(|| wasmi::Error::new("memory export missing"))?;
+		let init = instance.get_typed_func::<i32, ()>(&store, "init")?;
+		let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+		let step = instance.get_typed_func::<(i32, i32), i64>(&store, "step")?;
+		let route = instance.get_typed_func::<(i32, i32, i32, i32), i32>(&store, "route")?;
+
+		init.call(&mut store, node_count as i32)?;
+
+		Ok(Self {
+			store,
+			memory,
+			alloc,
+			step,
+			route,
+			step_fuel: 0,
+			route_fuel: 0,
+			route_calls: 0
+		})
+	}
+
+	fn step(&mut self, inbox: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+		self.store.set_fuel(STEP_FUEL)?;
+
+		let ptr = self.alloc.call(&mut self.store, inbox.len() as i32)?;
+		self.memory.write(&mut self.store, ptr as u32 as usize, inbox)
+			.map_err(|e| wasmi::Error::new(e.to_string()))?;
+		let ret = self.step.call(&mut self.store, (ptr, inbox.len() as i32))? as u64;
+
+		let mut outbox = vec![0u8; (ret & 0xffff_ffff) as usize];
+		self.memory.read(&self.store, (ret >> 32) as usize, &mut outbox)
+			.map_err(|e| wasmi::Error::new(e.to_string()))?;
+
+		self.step_fuel += STEP_FUEL - self.store.get_fuel()?;
+		Ok(outbox)
+	}
+
+	fn route(&mut self, packet: &TestPacket) -> Result<i32, wasmi::Error> {
+		self.store.set_fuel(ROUTE_FUEL)?;
+		let next = self.route.call(&mut self.store, (packet.transmitter as i32, packet.receiver as i32,
+			packet.source as i32, packet.destination as i32))?;
+		self.route_fuel += ROUTE_FUEL - self.store.get_fuel()?;
+		self.route_calls += 1;
+		Ok(next)
+	}
+}
+
+fn encode_message(buf: &mut Vec<u8>, from: ID, to: ID, payload: &[u8]) {
+	buf.extend_from_slice(&from.to_le_bytes());
+	buf.extend_from_slice(&to.to_le_bytes());
+	buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+	buf.extend_from_slice(payload);
+}
+
+fn decode_messages(mut buf: &[u8]) -> Option<Vec<(ID, ID, Vec<u8>)>> {
+	let read_u32 = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+	let mut messages = vec![];
+	while !buf.is_empty() {
+		if buf.len() < 12 {
+			return None;
+		}
+		let from = read_u32(&buf[0..]);
+		let to = read_u32(&buf[4..]);
+		let len = read_u32(&buf[8..]) as usize;
+		let payload = buf.get(12..12 + len)?;
+		messages.push((from, to, payload.to_vec()));
+		buf = &buf[12 + len..];
+	}
+	Some(messages)
+}
+
+pub struct WasmRouting {
+	module: WasmModule,
+	// route is called from several threads
+	guest: Option<Mutex<Guest>>,
+	// messages sent in the last step
+	outbox: Vec<(ID, ID, Vec<u8>)>,
+	error: Option<String>
+}
+
+impl WasmRouting {
+	pub fn new(module: WasmModule) -> Self {
+		Self {
+			module,
+			guest: None,
+			outbox: vec![],
+			error: None
+		}
+	}
+
+	// the guest is stopped after an error
+	fn fail(&mut self, err: &str) {
+		println!("{}: {}", self.module.name, err);
+		self.error = Some(err.to_string());
+		self.guest = None;
+	}
+}
+
+impl RoutingAlgorithm for WasmRouting {
+	fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"name" => {
+				write!(out, "WASM {}", self.module.name)?;
+			},
+			"description" => {
+				write!(out, "Routing algorithm loaded from {}.", self.module.path)?;
+			},
+			"fuel" => {
+				if let Some(guest) = self.guest.as_ref().and_then(|g| g.lock().ok()) {
+					let per_route = guest.route_fuel.checked_div(guest.route_calls).unwrap_or(0);
+					write!(out, "step: {}, route: {} ({} per call)", guest.step_fuel, guest.route_fuel, per_route)?;
+				}
+			},
+			"error" => {
+				write!(out, "{}", self.error.as_ref().map_or("none", |e| e.as_str()))?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn reset(&mut self, len: usize) {
+		self.outbox.clear();
+		self.error = None;
+		match Guest::new(&self.module, len) {
+			Ok(guest) => {
+				self.guest = Some(Mutex::new(guest));
+			},
+			Err(e) => {
+				self.fail(&e.to_string());
+			}
+		}
+	}
+
+	fn step(&mut self, io: &mut Io) {
+		let node_count = io.nodes_count() as ID;
+
+		// deliver the messages of the last step
+		let mut inbox = vec![];
+		for (from, to, payload) in self.outbox.drain(..) {
+			// the node might have been removed
+			if from >= node_count {
+				continue;
+			}
+			if to == BROADCAST {
+				for link in io.node_links(from) {
+					encode_message(&mut inbox, from, link.to, &payload);
+					io.send(1, payload.len() as u64);
+				}
+			} else if io.has_link(from, to) {
+				encode_message(&mut inbox, from, to, &payload);
+				io.send(1, payload.len() as u64);
+			}
+		}
+
+		let result = match &self.guest {
+			Some(guest) => guest.lock().unwrap().step(&inbox),
+			None => return
+		};
+
+		match result {
+			Ok(outbox) => match decode_messages(&outbox) {
+				Some(messages) => {
+					// nodes can only send as themselves
+					self.outbox = messages.into_iter()
+						.filter(|(from, _, _)| *from < node_count)
+						.collect();
+				},
+				None => {
+					self.fail("invalid outbox");
+				}
+			},
+			Err(e) => {
+				self.fail(&e.to_string());
+			}
+		}
+	}
+
+	fn route(&self, packet: &TestPacket) -> Option<ID> {
+		let guest = self.guest.as_ref()?;
+		match guest.lock().unwrap().route(packet) {
+			Ok(next) if next >= 0 => Some(next as ID),
+			_ => None
+		}
+	}
+}
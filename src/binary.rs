@@ -0,0 +1,185 @@
+
+use std::io::{BufReader, Read, Write};
+
+use crate::graph::{Graph, Link, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::progress::{is_cancelled, Progress};
+use crate::utils::MyError;
+
+
+/*
+ * Compact binary graph format (.mgb) for very large topologies.
+ * All numbers are little endian:
+ *   magic "MGB", version (u8), node count (u32), link count (u64)
+ *   links: from (u32), to (u32), quality (u16), flags (u8)
+ *     flags & 1: cost (u16), bandwidth, latency, loss (f32)
+ *     flags & 2: channel (u8)
+ *   location count (u32), locations: id (u32), x, y, z (f32, in km)
+ *   metadata count (u32), metadata: id (u32), length (u32), JSON (bytes)
+ * Links are stored per direction, so the graph is restored exactly.
+ */
+
+const MAGIC : &[u8; 3] = b"MGB";
+const VERSION : u8 = 1;
+const HAS_ATTRIBUTES : u8 = 1;
+const HAS_CHANNEL : u8 = 2;
+
+struct Reader<R: Read> {
+	inner: R
+}
+
+impl<R: Read> Reader<R> {
+	fn bytes<const N: usize>(&mut self) -> Result<[u8; N], MyError> {
+		let mut buf = [0u8; N];
+		self.inner.read_exact(&mut buf).map_err(|_| MyError::new("Unexpected end of file".to_string()))?;
+		Ok(buf)
+	}
+
+	fn u8(&mut self) -> Result<u8, MyError> {
+		Ok(self.bytes::<1>()?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, MyError> {
+		Ok(u16::from_le_bytes(self.bytes()?))
+	}
+
+	fn u32(&mut self) -> Result<u32, MyError> {
+		Ok(u32::from_le_bytes(self.bytes()?))
+	}
+
+	fn u64(&mut self) -> Result<u64, MyError> {
+		Ok(u64::from_le_bytes(self.bytes()?))
+	}
+
+	fn f32(&mut self) -> Result<f32, MyError> {
+		Ok(f32::from_le_bytes(self.bytes()?))
+	}
+}
+
+// read a binary graph, the nodes are added after the existing nodes
+pub fn read_binary(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, input: impl Read,
+		show_progress: bool) -> Result<(), MyError> {
+	let mut r = Reader { inner: BufReader::new(input) };
+
+	if &r.bytes::<3>()? != MAGIC {
+		return Err(MyError::new("Not a binary graph file".to_string()));
+	}
+	let version = r.u8()?;
+	if version != VERSION {
+		return Err(MyError::new(format!("Unsupported binary graph version: {}", version)));
+	}
+
+	let base = graph.node_count() as ID;
+	let node_count = r.u32()?;
+	let link_count = r.u64()? as usize;
+	let check = |id: u32| if id < node_count {
+		Ok(base + id)
+	} else {
+		Err(MyError::new(format!("Invalid node id: {}", id)))
+	};
+
+	let mut links = graph.links().to_vec();
+	links.reserve(link_count);
+	let mut progress = Progress::new();
+	for i in 0..link_count {
+		if i % 100_000 == 0 {
+			if is_cancelled() {
+				return Err(MyError::new("Import cancelled".to_string()));
+			}
+			if show_progress {
+				progress.update(link_count, i);
+			}
+		}
+		let from = check(r.u32()?)?;
+		let to = check(r.u32()?)?;
+		let mut link = Link::new(from, to, r.u16()?);
+		let flags = r.u8()?;
+		if flags & HAS_ATTRIBUTES != 0 {
+			link.set_attributes(r.u16()?, r.f32()?, r.f32()?, r.f32()?);
+		}
+		if flags & HAS_CHANNEL != 0 {
+			link.set_channel(r.u8()?);
+		}
+		links.push(link);
+	}
+	if show_progress {
+		progress.update(link_count, link_count);
+	}
+
+	for _ in 0..r.u32()? {
+		let id = check(r.u32()?)?;
+		let pos = [r.f32()?, r.f32()?, r.f32()?];
+		if let Some(loc) = loc.as_mut() {
+			loc.insert(id, pos);
+		}
+	}
+
+	for _ in 0..r.u32()? {
+		let id = check(r.u32()?)?;
+		let mut data = vec![0u8; r.u32()? as usize];
+		r.inner.read_exact(&mut data).map_err(|_| MyError::new("Unexpected end of file".to_string()))?;
+		let data = String::from_utf8(data).map_err(|_| MyError::new(format!("Invalid metadata of node {}", id)))?;
+		if let Some(meta) = meta.as_mut() {
+			meta.insert(id, data);
+		}
+	}
+
+	*graph = Graph::from_links(base as usize + node_count as usize, links);
+	Ok(())
+}
+
+pub fn write_binary(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>, w: &mut impl Write) -> Result<(), MyError> {
+	let node_count = graph.node_count() as ID;
+
+	w.write_all(MAGIC)?;
+	w.write_all(&[VERSION])?;
+	w.write_all(&(node_count as u32).to_le_bytes())?;
+	w.write_all(&(graph.link_count() as u64).to_le_bytes())?;
+
+	for link in graph.links() {
+		let mut flags = 0;
+		if !link.has_default_attributes() {
+			flags |= HAS_ATTRIBUTES;
+		}
+		if link.channel() != 0 {
+			flags |= HAS_CHANNEL;
+		}
+		w.write_all(&link.from.to_le_bytes())?;
+		w.write_all(&link.to.to_le_bytes())?;
+		w.write_all(&link.quality().to_le_bytes())?;
+		w.write_all(&[flags])?;
+		if flags & HAS_ATTRIBUTES != 0 {
+			w.write_all(&link.cost().to_le_bytes())?;
+			w.write_all(&link.bandwidth().to_le_bytes())?;
+			w.write_all(&link.latency().to_le_bytes())?;
+			w.write_all(&link.loss().to_le_bytes())?;
+		}
+		if flags & HAS_CHANNEL != 0 {
+			w.write_all(&[link.channel()])?;
+		}
+	}
+
+	let positions : Vec<(ID, &[f32; 3])> = (0..node_count)
+		.filter_map(|id| Some((id, locations?.get_position(id)?)))
+		.collect();
+	w.write_all(&(positions.len() as u32).to_le_bytes())?;
+	for (id, pos) in positions {
+		w.write_all(&id.to_le_bytes())?;
+		for v in pos {
+			w.write_all(&v.to_le_bytes())?;
+		}
+	}
+
+	let data : Vec<(ID, &String)> = (0..node_count)
+		.filter_map(|id| Some((id, meta?.data.get(&id)?)))
+		.collect();
+	w.write_all(&(data.len() as u32).to_le_bytes())?;
+	for (id, data) in data {
+		w.write_all(&id.to_le_bytes())?;
+		w.write_all(&(data.len() as u32).to_le_bytes())?;
+		w.write_all(data.as_bytes())?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,191 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, ID};
+use crate::sim::{RoutingAlgorithm, TestPacket};
+use crate::traffic::Traffic;
+use crate::utils::{with_rng, MyError};
+
+
+/*
+ * Throughput estimation for a set of flows. Every flow follows the
+ * route of the routing algorithm and flows over the same link share
+ * its capacity. Rates are max-min fair (progressive filling): all
+ * rates grow in proportion to the flow weights until a link is full,
+ * the flows over that link are fixed and the others continue.
+ */
+
+pub struct Flow {
+	pub source: ID,
+	pub destination: ID,
+	pub weight: f32,
+	// links of the route, empty if not routable
+	pub path: Vec<(ID, ID)>,
+	// in MBit/s
+	pub rate: f32,
+	// link that limits the rate
+	pub bottleneck: Option<(ID, ID)>
+}
+
+// route of the algorithm as list of links
+fn route_links(graph: &Graph, algorithm: &dyn RoutingAlgorithm, source: ID, destination: ID) -> Option<Vec<(ID, ID)>> {
+	let mut packet = TestPacket::new(source, source, source, destination);
+	let mut links = vec![];
+	for _ in 0..graph.node_count() {
+		let next = algorithm.route(&packet)?;
+		if !graph.has_link(packet.receiver, next) {
+			return None;
+		}
+		links.push((packet.receiver, next));
+		if next == destination {
+			return Some(links);
+		}
+		packet.transmitter = packet.receiver;
+		packet.receiver = next;
+	}
+	None
+}
+
+// flows of a traffic matrix or sampled from the traffic pattern
+pub fn create_flows(traffic: &Traffic, node_count: usize, count: usize) -> Vec<(ID, ID, f32)> {
+	if let Traffic::Matrix(pairs, _) = traffic {
+		return pairs.iter()
+			.filter(|(s, d, _)| (*s as usize) < node_count && (*d as usize) < node_count && s != d)
+			.cloned().collect();
+	}
+
+	let mut flows = vec![];
+	if node_count < 2 {
+		return flows;
+	}
+	// give up on patterns that only produce invalid pairs
+	for _ in 0..(count * 100) {
+		if flows.len() >= count {
+			break;
+		}
+		let (source, destination) = with_rng(|rng| traffic.sample(rng, node_count));
+		if source != destination && (source as usize) < node_count && (destination as usize) < node_count {
+			flows.push((source, destination, 1.0));
+		}
+	}
+	flows
+}
+
+pub fn max_min_fair(graph: &Graph, algorithm: &dyn RoutingAlgorithm, flows: &[(ID, ID, f32)],
+		capacities: &HashMap<(ID, ID), f32>) -> Vec<Flow> {
+	let mut flows : Vec<Flow> = flows.iter().map(|(source, destination, weight)| {
+		Flow {
+			source: *source,
+			destination: *destination,
+			weight: *weight,
+			path: route_links(graph, algorithm, *source, *destination).unwrap_or_default(),
+			rate: 0.0,
+			bottleneck: None
+		}
+	}).collect();
+
+	// unroutable flows stay at zero
+	let mut active : Vec<usize> = (0..flows.len()).filter(|i| !flows[*i].path.is_empty()).collect();
+	// remaining capacity of every used link
+	let mut remaining = HashMap::<(ID, ID), f32>::new();
+	for flow in &flows {
+		for link in &flow.path {
+			remaining.insert(*link, capacities.get(link).cloned().unwrap_or(0.0));
+		}
+	}
+
+	while !active.is_empty() {
+		// weights of the active flows per link (a route can use a link twice)
+		let mut weights = HashMap::<(ID, ID), f32>::new();
+		for i in &active {
+			for link in &flows[*i].path {
+				*weights.entry(*link).or_insert(0.0) += flows[*i].weight;
+			}
+		}
+
+		// increase until the first link is full
+		let mut bottleneck = None;
+		let mut increase = f32::INFINITY;
+		for (link, weight) in &weights {
+			let step = remaining[link].max(0.0) / weight;
+			if step < increase {
+				increase = step;
+				bottleneck = Some(*link);
+			}
+		}
+
+		let bottleneck = match bottleneck {
+			Some(link) => link,
+			None => break
+		};
+
+		for (link, weight) in &weights {
+			*remaining.get_mut(link).unwrap() -= increase * weight;
+		}
+
+		active.retain(|i| {
+			let flow = &mut flows[*i];
+			flow.rate += increase * flow.weight;
+			if flow.path.contains(&bottleneck) {
+				flow.bottleneck = Some(bottleneck);
+				false
+			} else {
+				true
+			}
+		});
+	}
+
+	flows
+}
+
+// entries listed in text output
+const MAX_LISTED : usize = 20;
+
+pub fn print_flows(out: &mut dyn std::fmt::Write, flows: &[Flow], json: bool) -> Result<(), MyError> {
+	let rates : Vec<f32> = flows.iter().map(|flow| flow.rate).collect();
+	let total : f32 = rates.iter().sum();
+	let routed = flows.iter().filter(|flow| !flow.path.is_empty()).count();
+	let min = rates.iter().cloned().fold(f32::INFINITY, f32::min);
+	let mean = if flows.is_empty() { 0.0 } else { total / flows.len() as f32 };
+	let squares : f32 = rates.iter().map(|r| r * r).sum();
+	// Jain's fairness index
+	let fairness = if squares > 0.0 { total * total / (flows.len() as f32 * squares) } else { 0.0 };
+
+	if json {
+		let list : Vec<serde_json::Value> = flows.iter().map(|flow| {
+			serde_json::json!({
+				"source": flow.source,
+				"destination": flow.destination,
+				"weight": flow.weight,
+				"hops": flow.path.len(),
+				"rate": flow.rate,
+				"bottleneck": flow.bottleneck.map(|(from, to)| vec![from, to])
+			})
+		}).collect();
+		writeln!(out, "{}", serde_json::json!({
+			"flows": list,
+			"routed": routed,
+			"total": total,
+			"min": if flows.is_empty() { 0.0 } else { min },
+			"mean": mean,
+			"fairness": fairness
+		}))?;
+		return Ok(());
+	}
+
+	let mut sorted : Vec<&Flow> = flows.iter().collect();
+	sorted.sort_by(|a, b| a.rate.partial_cmp(&b.rate).unwrap_or(std::cmp::Ordering::Equal));
+	for flow in sorted.iter().take(MAX_LISTED) {
+		match flow.bottleneck {
+			Some((from, to)) => writeln!(out, "{} > {}: {:.3} MBit/s, hops: {}, bottleneck: {} > {}",
+				flow.source, flow.destination, flow.rate, flow.path.len(), from, to)?,
+			None => writeln!(out, "{} > {}: not routable", flow.source, flow.destination)?
+		}
+	}
+	if flows.len() > MAX_LISTED {
+		writeln!(out, "({} flows with higher rates not shown)", flows.len() - MAX_LISTED)?;
+	}
+	writeln!(out, "flows: {}, routed: {}, total: {:.3} MBit/s, min: {:.3}, mean: {:.3}, fairness: {:.3}",
+		flows.len(), routed, total, if flows.is_empty() { 0.0 } else { min }, mean, fairness)?;
+	Ok(())
+}
@@ -0,0 +1,216 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::utils::MyError;
+
+
+/*
+ * Channel assignment and interference of links.
+ * Both directions of a link use the same channel. Links on the
+ * same channel interfere when they share a node or an endpoint of
+ * one link is a neighbor of an endpoint of the other (two hop
+ * interference). With an interference range, links interfere when
+ * any of their endpoints are closer than the range (in km).
+ * Interfering links on the same channel share the bandwidth.
+ */
+
+#[derive(Clone)]
+pub struct Interference {
+	// interference range in km, two hop interference if not set
+	range: Option<f32>
+}
+
+// links without direction, a < b
+fn link_pairs(graph: &Graph) -> Vec<(ID, ID)> {
+	let mut pairs : Vec<(ID, ID)> = graph.links().iter()
+		.map(|link| (link.from.min(link.to), link.from.max(link.to)))
+		.collect();
+	pairs.sort_unstable();
+	pairs.dedup();
+	pairs
+}
+
+impl Default for Interference {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Interference {
+	pub fn new() -> Self {
+		Self { range: None }
+	}
+
+	pub fn set_range(&mut self, range: Option<f32>) {
+		self.range = range;
+	}
+
+	pub fn get_range(&self) -> String {
+		match self.range {
+			Some(range) => format!("{} km", range),
+			None => "two hops".to_string()
+		}
+	}
+
+	// interfering links of every link pair
+	fn conflicts(&self, graph: &Graph, locations: &Locations, pairs: &[(ID, ID)]) -> Vec<Vec<usize>> {
+		let mut conflicts = vec![vec![]; pairs.len()];
+
+		if let Some(range) = self.range {
+			let positions : Vec<Option<[[f32; 3]; 2]>> = pairs.iter().map(|(a, b)| {
+				Some([*locations.get_position(*a)?, *locations.get_position(*b)?])
+			}).collect();
+
+			let distance = |p: &[f32; 3], q: &[f32; 3]| {
+				((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt()
+			};
+
+			for i in 0..pairs.len() {
+				for j in (i + 1)..pairs.len() {
+					let interfere = match (&positions[i], &positions[j]) {
+						(Some(p), Some(q)) => p.iter().any(|p| q.iter().any(|q| distance(p, q) <= range)),
+						// links without positions only interfere when they share a node
+						_ => pairs[i].0 == pairs[j].0 || pairs[i].0 == pairs[j].1
+							|| pairs[i].1 == pairs[j].0 || pairs[i].1 == pairs[j].1
+					};
+					if interfere {
+						conflicts[i].push(j);
+						conflicts[j].push(i);
+					}
+				}
+			}
+		} else {
+			// link pairs at every node
+			let mut incident = vec![vec![]; graph.node_count()];
+			for (i, (a, b)) in pairs.iter().enumerate() {
+				incident[*a as usize].push(i);
+				incident[*b as usize].push(i);
+			}
+
+			for (i, (a, b)) in pairs.iter().enumerate() {
+				let mut nodes = vec![*a, *b];
+				nodes.extend(graph.neighbor_ids(*a));
+				nodes.extend(graph.neighbor_ids(*b));
+				nodes.sort_unstable();
+				nodes.dedup();
+
+				let mut others : Vec<usize> = nodes.iter()
+					.flat_map(|id| incident[*id as usize].iter().cloned())
+					.filter(|j| *j != i)
+					.collect();
+				others.sort_unstable();
+				others.dedup();
+				conflicts[i] = others;
+			}
+		}
+
+		conflicts
+	}
+
+	/*
+	 * Greedy coloring of the links, links with the most interfering
+	 * links first. Every link gets the channel used the least by
+	 * the interfering links that have a channel already.
+	 * Returns the number of interfering link pairs on the same channel.
+	 */
+	pub fn assign_channels(&self, graph: &mut Graph, locations: &Locations, channels: u8) -> Result<usize, MyError> {
+		if channels == 0 {
+			return Err(MyError::new("Expected at least one channel.".to_string()));
+		}
+
+		let pairs = link_pairs(graph);
+		let conflicts = self.conflicts(graph, locations, &pairs);
+
+		let mut order : Vec<usize> = (0..pairs.len()).collect();
+		order.sort_by(|a, b| conflicts[*b].len().cmp(&conflicts[*a].len()));
+
+		let mut assigned : Vec<Option<u8>> = vec![None; pairs.len()];
+		for i in order {
+			let mut used = vec![0; channels as usize];
+			for j in &conflicts[i] {
+				if let Some(channel) = assigned[*j] {
+					used[channel as usize] += 1;
+				}
+			}
+			let channel = (0..channels).min_by_key(|c| used[*c as usize]).unwrap_or(0);
+			assigned[i] = Some(channel);
+		}
+
+		for (i, (a, b)) in pairs.iter().enumerate() {
+			let channel = assigned[i].unwrap_or(0);
+			if let Some(link) = graph.get_link_mut(*a, *b) {
+				link.set_channel(channel);
+			}
+			if let Some(link) = graph.get_link_mut(*b, *a) {
+				link.set_channel(channel);
+			}
+		}
+
+		Ok(count_interfering(graph, &pairs, &conflicts))
+	}
+
+	// bandwidth of every link (both directions) shared with the interfering links on the same channel
+	pub fn effective_bandwidth(&self, graph: &Graph, locations: &Locations) -> HashMap<(ID, ID), f32> {
+		let pairs = link_pairs(graph);
+		let conflicts = self.conflicts(graph, locations, &pairs);
+
+		let mut bandwidth = HashMap::new();
+		for (i, (a, b)) in pairs.iter().enumerate() {
+			let channel = pair_channel(graph, *a, *b);
+			let shared = 1 + conflicts[i].iter()
+				.filter(|j| pair_channel(graph, pairs[**j].0, pairs[**j].1) == channel)
+				.count();
+			for (from, to) in &[(*a, *b), (*b, *a)] {
+				if let Some(link) = graph.get_link(*from, *to) {
+					bandwidth.insert((*from, *to), link.bandwidth() / shared as f32);
+				}
+			}
+		}
+		bandwidth
+	}
+
+	pub fn print_capacity(&self, out: &mut dyn std::fmt::Write, graph: &Graph, locations: &Locations) -> Result<(), MyError> {
+		let pairs = link_pairs(graph);
+		let conflicts = self.conflicts(graph, locations, &pairs);
+
+		let mut channels : Vec<u8> = pairs.iter().map(|(a, b)| pair_channel(graph, *a, *b)).collect();
+		channels.sort_unstable();
+		channels.dedup();
+
+		let bandwidth = self.effective_bandwidth(graph, locations);
+		let total : f32 = bandwidth.values().sum();
+		let nominal : f32 = graph.links().iter().map(|link| link.bandwidth()).sum();
+
+		// all links on one channel for comparison
+		let single : f32 = pairs.iter().enumerate().map(|(i, (a, b))| {
+			let shared = (1 + conflicts[i].len()) as f32;
+			[(*a, *b), (*b, *a)].iter()
+				.filter_map(|(from, to)| graph.get_link(*from, *to))
+				.map(|link| link.bandwidth() / shared)
+				.sum::<f32>()
+		}).sum();
+
+		let links = graph.link_count().max(1) as f32;
+		writeln!(out, "interference: {}, channels used: {}, interfering link pairs: {}",
+			self.get_range(), channels.len(), count_interfering(graph, &pairs, &conflicts))?;
+		writeln!(out, "capacity: {:.2} MBit/s of {:.2} MBit/s nominal ({:.2} per link), single channel: {:.2} MBit/s",
+			total, nominal, total / links, single)?;
+		Ok(())
+	}
+}
+
+fn pair_channel(graph: &Graph, a: ID, b: ID) -> u8 {
+	graph.get_link(a, b).or_else(|| graph.get_link(b, a)).map_or(0, |link| link.channel())
+}
+
+// interfering link pairs on the same channel
+fn count_interfering(graph: &Graph, pairs: &[(ID, ID)], conflicts: &[Vec<usize>]) -> usize {
+	let channels : Vec<u8> = pairs.iter().map(|(a, b)| pair_channel(graph, *a, *b)).collect();
+	let mut count = 0;
+	for (i, others) in conflicts.iter().enumerate() {
+		count += others.iter().filter(|j| **j > i && channels[**j] == channels[i]).count();
+	}
+	count
+}
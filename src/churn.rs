@@ -0,0 +1,264 @@
+
+use std::collections::HashSet;
+
+use crate::graph::{Graph, Link, ID};
+use crate::locations::Locations;
+use crate::utils::random;
+use crate::sim::RoutingAlgorithm;
+
+
+/*
+ * Link and node churn, applied on every simulation step.
+ * Links fail with a probability and are removed from the graph,
+ * failed links recover with another probability. Both directions
+ * of a link fail and recover together.
+ * Nodes leave by losing all links and join again as new node
+ * (reset routing state) at a random position.
+ * Targeted failures remove links for a number of steps or permanently.
+ */
+#[derive(Clone)]
+pub struct Churn {
+	link_fail: f32,
+	link_recover: f32,
+	// removed links (one or both directions)
+	failed_links: Vec<Vec<Link>>,
+	node_leave: f32,
+	node_join: f32,
+	// connection range of joining nodes (in km)
+	join_range: f32,
+	// nodes that left and the number of links they had
+	down_nodes: Vec<(ID, usize)>,
+	// links removed by targeted failures and the remaining steps until they recover
+	failures: Vec<(Vec<Link>, Option<u32>)>
+}
+
+impl Default for Churn {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Churn {
+	pub fn new() -> Self {
+		Self {
+			link_fail: 0.0,
+			link_recover: 0.0,
+			failed_links: vec![],
+			node_leave: 0.0,
+			node_join: 0.0,
+			join_range: 0.0,
+			down_nodes: vec![],
+			failures: vec![]
+		}
+	}
+
+	// forget failed links and nodes, e.g. when the topology was replaced
+	pub fn clear(&mut self) {
+		self.failed_links.clear();
+		self.down_nodes.clear();
+		self.failures.clear();
+	}
+
+	// remove all links that match, returns the number of removed links
+	pub fn fail_links<F>(&mut self, graph: &mut Graph, duration: Option<u32>, filter: F) -> usize
+		where F: Fn(&Link) -> bool
+	{
+		let links : Vec<Link> = graph.links().iter().filter(|link| filter(link)).cloned().collect();
+		let count = links.len();
+		if count > 0 {
+			graph.retain_links(|link| !filter(link));
+			if duration.is_some() {
+				self.failures.push((links, duration));
+			}
+		}
+		count
+	}
+
+	pub fn get_failures(&self) -> String {
+		let temporary = self.failures.iter().map(|(links, _)| links.len()).sum::<usize>();
+		format!("temporarily failed links: {}", temporary)
+	}
+
+	pub fn is_down(&self, id: ID) -> bool {
+		self.down_nodes.iter().any(|(down, _)| *down == id)
+	}
+
+	pub fn set_nodes(&mut self, leave: f32, join: f32, join_range: f32) {
+		self.node_leave = leave;
+		self.node_join = join;
+		self.join_range = join_range;
+	}
+
+	pub fn get_nodes(&self) -> String {
+		format!("leave {}, join {}, join range {}, nodes down: {}",
+			self.node_leave, self.node_join, self.join_range, self.down_nodes.len())
+	}
+
+	pub fn set_links(&mut self, fail: f32, recover: f32) {
+		self.link_fail = fail;
+		self.link_recover = recover;
+	}
+
+	// disable link churn and put back all failed links
+	pub fn disable_links(&mut self, graph: &mut Graph) {
+		self.link_fail = 0.0;
+		self.link_recover = 0.0;
+		for links in self.failed_links.drain(..) {
+			for link in links {
+				graph.insert_link(link);
+			}
+		}
+	}
+
+	pub fn get_links(&self) -> String {
+		format!("fail {}, recover {}, failed links: {}",
+			self.link_fail, self.link_recover, self.failed_links.len())
+	}
+
+	pub fn step(&mut self, graph: &mut Graph, locations: &mut Locations, algorithm: &mut Box<dyn RoutingAlgorithm>) {
+		self.step_failures(graph);
+		self.step_links(graph);
+		self.step_nodes(graph, locations, algorithm);
+	}
+
+	fn step_failures(&mut self, graph: &mut Graph) {
+		let mut recovered = vec![];
+		self.failures.retain(|(links, remaining)| {
+			match remaining {
+				Some(n) if *n <= 1 => {
+					recovered.extend(links.iter().cloned());
+					false
+				},
+				_ => true
+			}
+		});
+
+		for (_, remaining) in self.failures.iter_mut() {
+			if let Some(n) = remaining {
+				*n -= 1;
+			}
+		}
+
+		for link in recovered {
+			graph.insert_link(link);
+		}
+	}
+
+	fn step_nodes(&mut self, graph: &mut Graph, locations: &mut Locations, algorithm: &mut Box<dyn RoutingAlgorithm>) {
+		if self.node_leave <= 0.0 && self.down_nodes.is_empty() {
+			return;
+		}
+
+		// join nodes
+		let join = self.node_join;
+		let mut joined = vec![];
+		self.down_nodes.retain(|down| {
+			if random::<f32>() < join {
+				joined.push(*down);
+				false
+			} else {
+				true
+			}
+		});
+
+		// leave nodes
+		for id in 0..graph.node_count() as ID {
+			if !self.is_down(id) && !joined.iter().any(|(j, _)| *j == id)
+				&& random::<f32>() < self.node_leave {
+				let degree = graph.get_neighbors(id).len();
+				graph.retain_links(|link| link.from != id && link.to != id);
+				self.failed_links.retain(|links| links.iter().all(|link| link.from != id && link.to != id));
+				self.down_nodes.push((id, degree));
+			}
+		}
+
+		// area of all nodes
+		let mut min = [f32::INFINITY; 3];
+		let mut max = [f32::NEG_INFINITY; 3];
+		for pos in locations.data.values() {
+			for i in 0..3 {
+				min[i] = min[i].min(pos[i]);
+				max[i] = max[i].max(pos[i]);
+			}
+		}
+
+		for (id, degree) in joined {
+			algorithm.reset_node(id);
+
+			if locations.get_position(id).is_some() && self.join_range > 0.0 {
+				// new random position, connect to nodes in range
+				let mut pos = [0.0; 3];
+				for i in 0..3 {
+					pos[i] = min[i] + random::<f32>() * (max[i] - min[i]);
+				}
+				locations.insert(id, pos);
+
+				for other in 0..graph.node_count() as ID {
+					if other != id && !self.is_down(other) {
+						if let Some(distance) = locations.pos_distance(id, other) {
+							if distance <= self.join_range {
+								graph.connect(id, other);
+							}
+						}
+					}
+				}
+			} else {
+				// no positions, connect to as many random nodes as before
+				let count = graph.node_count() as ID;
+				for _ in 0..degree {
+					let other = random::<ID>() % count;
+					if other != id && !self.is_down(other) {
+						graph.connect(id, other);
+					}
+				}
+			}
+		}
+	}
+
+	fn step_links(&mut self, graph: &mut Graph) {
+		if self.link_fail <= 0.0 && self.failed_links.is_empty() {
+			return;
+		}
+
+		// recover links
+		let recover = self.link_recover;
+		let mut recovered = vec![];
+		self.failed_links.retain(|links| {
+			if random::<f32>() < recover {
+				recovered.push(links.clone());
+				false
+			} else {
+				true
+			}
+		});
+
+		// fail links
+		let mut failed = HashSet::<(ID, ID)>::new();
+		for link in graph.links() {
+			let reverse = graph.get_link(link.to, link.from);
+			if reverse.is_some() && link.from > link.to {
+				continue;
+			}
+
+			if random::<f32>() < self.link_fail {
+				failed.insert((link.from, link.to));
+				let mut links = vec![link.clone()];
+				if let Some(reverse) = reverse {
+					failed.insert((reverse.from, reverse.to));
+					links.push(reverse);
+				}
+				self.failed_links.push(links);
+			}
+		}
+
+		if !failed.is_empty() {
+			graph.retain_links(|link| !failed.contains(&(link.from, link.to)));
+		}
+
+		for links in recovered {
+			for link in links {
+				graph.insert_link(link);
+			}
+		}
+	}
+}
@@ -0,0 +1,238 @@
+
+use std::collections::HashMap;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::graph::Graph;
+use crate::utils::{with_rng, MyError};
+
+
+/*
+ * Community detection. Links are treated as undirected
+ * and every node is assigned a cluster id, the largest
+ * cluster has id 0.
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ClusterMethod {
+	LabelPropagation,
+	Louvain
+}
+
+impl ClusterMethod {
+	pub fn parse(name: &str) -> Result<Self, MyError> {
+		match name {
+			"label" => Ok(ClusterMethod::LabelPropagation),
+			"louvain" => Ok(ClusterMethod::Louvain),
+			_ => Err(MyError::new(format!("Unknown cluster method: {} (use label or louvain)", name)))
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			ClusterMethod::LabelPropagation => "label",
+			ClusterMethod::Louvain => "louvain"
+		}
+	}
+}
+
+// cluster id of every node
+pub fn cluster(graph: &Graph, method: ClusterMethod) -> Vec<u32> {
+	let adj = undirected_neighbors(graph);
+	let labels = match method {
+		ClusterMethod::LabelPropagation => with_rng(|rng| label_propagation(&adj, rng)),
+		ClusterMethod::Louvain => louvain(&adj)
+	};
+	renumber(&labels)
+}
+
+// neighbors of each node, every undirected link is counted once
+fn undirected_neighbors(graph: &Graph) -> Vec<Vec<usize>> {
+	let mut adj = vec![vec![]; graph.node_count()];
+	for link in graph.links() {
+		if link.from < link.to || !graph.has_link(link.to, link.from) {
+			adj[link.from as usize].push(link.to as usize);
+			adj[link.to as usize].push(link.from as usize);
+		}
+	}
+	adj
+}
+
+// number clusters by size, largest first
+fn renumber(labels: &[usize]) -> Vec<u32> {
+	let mut sizes = HashMap::<usize, usize>::new();
+	for label in labels {
+		*sizes.entry(*label).or_insert(0) += 1;
+	}
+
+	let mut order : Vec<(usize, usize)> = sizes.into_iter().collect();
+	order.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+	let ids : HashMap<usize, u32> = order.iter().enumerate().map(|(i, (label, _))| (*label, i as u32)).collect();
+	labels.iter().map(|label| ids[label]).collect()
+}
+
+// Every node takes the most frequent label of its neighbors until no label changes
+fn label_propagation<R: Rng>(adj: &[Vec<usize>], rng: &mut R) -> Vec<usize> {
+	let mut labels : Vec<usize> = (0..adj.len()).collect();
+	let mut order : Vec<usize> = (0..adj.len()).collect();
+	let mut counts = HashMap::<usize, u32>::new();
+
+	for _ in 0..100 {
+		let mut changed = false;
+		order.shuffle(rng);
+
+		for u in &order {
+			if adj[*u].is_empty() {
+				continue;
+			}
+
+			counts.clear();
+			for v in &adj[*u] {
+				*counts.entry(labels[*v]).or_insert(0) += 1;
+			}
+
+			let max = *counts.values().max().unwrap();
+			// keep the current label on ties
+			if counts.get(&labels[*u]) == Some(&max) {
+				continue;
+			}
+
+			let mut best : Vec<usize> = counts.iter().filter(|(_, c)| **c == max).map(|(l, _)| *l).collect();
+			best.sort_unstable();
+			labels[*u] = *best.choose(rng).unwrap();
+			changed = true;
+		}
+
+		if !changed {
+			break;
+		}
+	}
+
+	labels
+}
+
+// Louvain method: move nodes between communities as long as the modularity
+// increases, then merge the communities into single nodes and repeat
+fn louvain(adj: &[Vec<usize>]) -> Vec<usize> {
+	// weighted adjacency, self loops are stored with twice the inner weight
+	let mut graph : Vec<Vec<(usize, f64)>> = adj.iter()
+		.map(|ns| ns.iter().map(|v| (*v, 1.0)).collect())
+		.collect();
+	let mut labels : Vec<usize> = (0..adj.len()).collect();
+
+	loop {
+		let (communities, moved) = louvain_level(&graph);
+		if !moved {
+			break;
+		}
+
+		// renumber communities
+		let mut map = HashMap::new();
+		for c in &communities {
+			let len = map.len();
+			map.entry(*c).or_insert(len);
+		}
+
+		for label in labels.iter_mut() {
+			*label = map[&communities[*label]];
+		}
+
+		// aggregate communities
+		let mut weights = vec![HashMap::<usize, f64>::new(); map.len()];
+		for (u, ns) in graph.iter().enumerate() {
+			let cu = map[&communities[u]];
+			for (v, w) in ns {
+				*weights[cu].entry(map[&communities[*v]]).or_insert(0.0) += w;
+			}
+		}
+		graph = weights.into_iter().map(|ws| ws.into_iter().collect()).collect();
+	}
+
+	labels
+}
+
+fn louvain_level(graph: &[Vec<(usize, f64)>]) -> (Vec<usize>, bool) {
+	let n = graph.len();
+	let degrees : Vec<f64> = graph.iter().map(|ns| ns.iter().map(|(_, w)| w).sum()).collect();
+	let m2 : f64 = degrees.iter().sum();
+	let mut communities : Vec<usize> = (0..n).collect();
+	let mut totals = degrees.clone();
+	let mut moved = false;
+
+	if m2 == 0.0 {
+		return (communities, false);
+	}
+
+	let mut links = HashMap::<usize, f64>::new();
+	for _ in 0..100 {
+		let mut changed = false;
+		for u in 0..n {
+			let cu = communities[u];
+			let k = degrees[u];
+
+			// weights to neighbor communities
+			links.clear();
+			for (v, w) in &graph[u] {
+				if *v != u {
+					*links.entry(communities[*v]).or_insert(0.0) += w;
+				}
+			}
+
+			totals[cu] -= k;
+			let gain = |c: usize, w: f64| w - totals[c] * k / m2;
+			let mut best = (cu, gain(cu, *links.get(&cu).unwrap_or(&0.0)));
+			for (c, w) in &links {
+				let g = gain(*c, *w);
+				// ties go to the current or else the lowest community for reproducible results
+				if g > best.1 || (g == best.1 && best.0 != cu && *c < best.0) {
+					best = (*c, g);
+				}
+			}
+			totals[best.0] += k;
+
+			if best.0 != cu {
+				communities[u] = best.0;
+				changed = true;
+				moved = true;
+			}
+		}
+
+		if !changed {
+			break;
+		}
+	}
+
+	(communities, moved)
+}
+
+// modularity of a clustering (-0.5..1)
+pub fn modularity(graph: &Graph, clusters: &[u32]) -> f32 {
+	let adj = undirected_neighbors(graph);
+	let m2 : usize = adj.iter().map(|ns| ns.len()).sum();
+	if m2 == 0 {
+		return 0.0;
+	}
+
+	let count = clusters.iter().max().map_or(0, |c| *c as usize + 1);
+	let mut inner = vec![0usize; count];
+	let mut totals = vec![0usize; count];
+	for (u, ns) in adj.iter().enumerate() {
+		let c = clusters[u] as usize;
+		totals[c] += ns.len();
+		inner[c] += ns.iter().filter(|v| clusters[**v] == c as u32).count();
+	}
+
+	let m2 = m2 as f64;
+	(0..count).map(|c| inner[c] as f64 / m2 - (totals[c] as f64 / m2).powi(2)).sum::<f64>() as f32
+}
+
+// number of nodes per cluster
+pub fn cluster_sizes(clusters: &[u32]) -> Vec<usize> {
+	let count = clusters.iter().max().map_or(0, |c| *c as usize + 1);
+	let mut sizes = vec![0; count];
+	for c in clusters {
+		sizes[*c as usize] += 1;
+	}
+	sizes
+}
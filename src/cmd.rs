@@ -1,31 +1,54 @@
 
-use std::f32;
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::fs::File;
+use std::sync::{Mutex, TryLockError};
 use std::time::{Duration, Instant};
 use std::io::{BufRead, BufReader};
 use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use rand::seq::SliceRandom;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 
 use crate::eval_paths::EvalPaths;
 use crate::debug_path::DebugPath;
-use crate::graph::Graph;
-use crate::progress::Progress;
-use crate::sim::{Io, GlobalState, RoutingAlgorithm};
-use crate::algorithms::vivaldi_routing::VivaldiRouting;
-use crate::algorithms::random_routing::RandomRouting;
-use crate::algorithms::spring_routing::SpringRouting;
-use crate::algorithms::genetic_routing::GeneticRouting;
-use crate::algorithms::spanning_tree_routing::SpanningTreeRouting;
-use crate::importer::import_file;
-use crate::exporter::export_file;
-use crate::utils::{fmt_duration, DEG2KM, MyError};
-use crate::movements::Movements;
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::graph_diff::GraphDiff;
+use crate::validate::Validation;
+use crate::capacity::{create_flows, max_min_fair, print_flows};
+use crate::source_routing::HeaderStats;
+use crate::workspace::Workspaces;
+use crate::interconnect::{between_components, between_sets};
+use crate::placement::{optimize_placement, PlacementMethod};
+use crate::render::Scene;
+use crate::style::NodeMetric;
+use crate::progress::{clear_cancel, is_cancelled, request_cancel, Progress};
+use crate::sim::{Io, GlobalState, RoutingAlgorithm, SavedState, TestPacket};
+use crate::history::History;
+use crate::algorithms::{create_algorithm, algorithm_names, load_plugin, load_wasm};
+use crate::algorithms::mixed_routing::{self, MixedRouting};
+use crate::state::{save_state, load_state};
+use crate::importer::{import_file, import_meshviewer, import_roads};
+use crate::exporter::{export_file, export_json};
+use crate::jobs;
+use crate::utils::{fmt_duration, get_str, get_seed, random, set_seed, with_rng, DEG2KM, MyError};
+use crate::script::Script;
+use crate::traffic::{Traffic, parse_id_list};
+use crate::mst::MstWeight;
+use crate::flow::{min_cut, edge_connectivity};
+use crate::cluster::{cluster, cluster_sizes, modularity, ClusterMethod};
 
 
 #[derive(PartialEq)]
-enum AllowRecursiveCall {
+pub enum AllowRecursiveCall {
 	No,
 	Yes
 }
@@ -47,6 +70,12 @@ fn send_dummy_to_stdin() {
 	let _ = std::io::stdout().write("".as_bytes());
 }
 
+// after exit, unblock the console and the socket loop
+fn wake_up_loops(sim: &GlobalState) {
+	send_dummy_to_socket(&sim.cmd_address);
+	send_dummy_to_stdin();
+}
+
 pub fn ext_loop(sim: Arc<Mutex<GlobalState>>, address: &str) {
 	match TcpListener::bind(address) {
 		Err(err) => {
@@ -54,25 +83,257 @@ pub fn ext_loop(sim: Arc<Mutex<GlobalState>>, address: &str) {
 		},
 		Ok(listener) => {
 			println!("Listen for commands on {}", address);
-			//let mut input =  vec![0u8; 512];
-			let mut output = String::new();
+			// connection threads and their streams for shutdown
+			let mut connections : Vec<(thread::JoinHandle<()>, TcpStream)> = vec![];
 
 			loop {
-				//input.clear();
-				output.clear();
-				if let Ok((mut stream, _addr)) = listener.accept() {
-					let mut buf = [0; 512];
-					if let Ok(n) = stream.read(&mut buf) {
-						if let (Ok(s), Ok(mut sim)) = (std::str::from_utf8(&buf[0..n]), sim.lock()) {
-							if sim.abort_simulation {
-								// abort loop
-								break;
-							} else if let Err(e) = cmd_handler(&mut output, &mut sim, s, AllowRecursiveCall::Yes) {
-								let _ = stream.write(e.to_string().as_bytes());
-							} else {
-								let _ = stream.write(&output.as_bytes());
-							}
+				if let Ok((stream, _addr)) = listener.accept() {
+					// do not wait for a running command, the connection might want to cancel it
+					let abort = match sim.try_lock() {
+						Ok(sim) => sim.abort_simulation,
+						Err(TryLockError::WouldBlock) => false,
+						Err(_) => true
+					};
+					if abort {
+						// abort loop
+						break;
+					}
+
+					if let Ok(shutdown_handle) = stream.try_clone() {
+						let sim = sim.clone();
+						let handle = thread::spawn(move || {
+							ext_connection(&sim, stream);
+						});
+						connections.retain(|(handle, _)| !handle.is_finished());
+						connections.push((handle, shutdown_handle));
+					}
+				}
+			}
+
+			// unblock all connection threads and wait for them
+			for (_, stream) in &connections {
+				let _ = stream.shutdown(Shutdown::Both);
+			}
+			for (handle, _) in connections {
+				let _ = handle.join();
+			}
+		}
+	}
+}
+
+const RESPONSE_CHUNK_SIZE : usize = 4096;
+
+// response frame: length of the payload in bytes as decimal line, then the payload
+fn ext_write_response(stream: &mut TcpStream, response: &str) -> std::io::Result<()> {
+	stream.write_all(format!("{}\n", response.len()).as_bytes())?;
+	for chunk in response.as_bytes().chunks(RESPONSE_CHUNK_SIZE) {
+		stream.write_all(chunk)?;
+	}
+	stream.flush()
+}
+
+/*
+ * Handle one client connection.
+ * Every line is a command, the connection
+ * stays open until it is closed by the client.
+ */
+fn ext_connection(sim: &Arc<Mutex<GlobalState>>, stream: TcpStream) {
+	let mut writer = match stream.try_clone() {
+		Ok(writer) => writer,
+		Err(_) => return
+	};
+	let mut reader = BufReader::new(stream);
+	let mut line = String::new();
+	let mut output = String::new();
+	// output format of the last command, for responses without the lock
+	let mut json_output = false;
+
+	loop {
+		line.clear();
+		output.clear();
+
+		match reader.read_line(&mut line) {
+			Ok(0) | Err(_) => break,
+			Ok(_) => {}
+		}
+
+		let input = line.trim();
+		if input.is_empty() {
+			continue;
+		}
+
+		// the running command holds the lock, it can still be cancelled from here
+		if let Some(result) = cmd_unlocked(&mut output, sim, input) {
+			output = format_response(json_output, &output, &result);
+			if ext_write_response(&mut writer, &output).is_err() {
+				break;
+			}
+			continue;
+		}
+
+		let mut sim = match sim.lock() {
+			Ok(sim) => sim,
+			Err(_) => break
+		};
+
+		if sim.abort_simulation {
+			break;
+		}
+
+		clear_cancel();
+		let result = cmd_handler(&mut output, &mut sim, input, AllowRecursiveCall::Yes);
+		output = format_response(sim.json_output, &output, &result);
+
+		json_output = sim.json_output;
+		let abort = sim.abort_simulation;
+		if abort {
+			wake_up_loops(&sim);
+		}
+
+		// do not hold the lock while writing to a slow client
+		drop(sim);
+
+		if ext_write_response(&mut writer, &output).is_err() || abort {
+			break;
+		}
+	}
+}
+
+fn http_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+	let header = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+		status, content_type, body.len());
+	let _ = stream.write_all(header.as_bytes());
+	let _ = stream.write_all(body.as_bytes());
+}
+
+// largest request body accepted by the HTTP API
+const HTTP_MAX_BODY: u64 = 1024 * 1024;
+
+const HTTP_BAD_REQUEST: &str = "400 Bad Request";
+const HTTP_TOO_LARGE: &str = "413 Payload Too Large";
+
+// read request line, headers and body, the error is the response status
+fn http_read_request(stream: &TcpStream) -> Result<(String, String, String), &'static str> {
+	let mut reader = BufReader::new(stream);
+	let mut line = String::new();
+	reader.read_line(&mut line).map_err(|_| HTTP_BAD_REQUEST)?;
+
+	let mut parts = line.split_whitespace();
+	let method = parts.next().ok_or(HTTP_BAD_REQUEST)?.to_string();
+	let path = parts.next().ok_or(HTTP_BAD_REQUEST)?.to_string();
+
+	let mut content_length = 0;
+	loop {
+		line.clear();
+		reader.read_line(&mut line).map_err(|_| HTTP_BAD_REQUEST)?;
+		let header = line.trim();
+		if header.is_empty() {
+			break;
+		}
+		if let Some((key, value)) = header.split_once(':') {
+			if key.trim().eq_ignore_ascii_case("content-length") {
+				content_length = value.trim().parse().map_err(|_| HTTP_BAD_REQUEST)?;
+			}
+		}
+	}
+
+	if content_length > HTTP_MAX_BODY {
+		return Err(HTTP_TOO_LARGE);
+	}
+
+	let mut body = vec![];
+	reader.take(content_length).read_to_end(&mut body).map_err(|_| HTTP_BAD_REQUEST)?;
+	if body.len() as u64 != content_length {
+		return Err(HTTP_BAD_REQUEST);
+	}
+
+	Ok((method, path, String::from_utf8_lossy(&body).to_string()))
+}
+
+fn http_stats(sim: &GlobalState) -> String {
+	let mut algorithm = String::new();
+	let _ = sim.algorithm.get("name", &mut algorithm);
+
+	serde_json::json!({
+		"nodes": sim.graph.node_count(),
+		"links": sim.graph.link_count(),
+		"steps": sim.sim_steps,
+		"algorithm": algorithm,
+		"test": {
+			"arrived": sim.test.arrived(),
+			"stretch": sim.test.stretch()
+		},
+		"events": {
+			"sent": sim.events.stats.sent,
+			"delivered": sim.events.stats.delivered
+		}
+	}).to_string()
+}
+
+/*
+ * REST API:
+ * GET /graph  graph export as JSON
+ * GET /stats  simulation statistics
+ * POST /cmd   execute command in body (plain text or {"cmd": "..."})
+ */
+pub fn http_loop(sim: Arc<Mutex<GlobalState>>, address: &str) {
+	match TcpListener::bind(address) {
+		Err(err) => {
+			println!("{}", err);
+		},
+		Ok(listener) => {
+			println!("Serve HTTP API on {}", address);
+
+			for stream in listener.incoming() {
+				let mut stream = match stream {
+					Ok(stream) => stream,
+					Err(_) => continue
+				};
+
+				let (method, path, body) = match http_read_request(&stream) {
+					Ok(request) => request,
+					Err(status) => {
+						http_response(&mut stream, status, "text/plain", &status[4..]);
+						continue;
+					}
+				};
+
+				let mut sim = match sim.lock() {
+					Ok(sim) => sim,
+					Err(_) => break
+				};
+
+				match (method.as_str(), path.as_str()) {
+					("GET", "/graph") => {
+						let json = export_json(&sim.graph, Some(&sim.locations), Some(&sim.meta), Some(&*sim.algorithm), Some(&sim.energy), Some(&sim.test), None, Some(&sim.export_style));
+						http_response(&mut stream, "200 OK", "application/json", &json);
+					},
+					("GET", "/stats") => {
+						http_response(&mut stream, "200 OK", "application/json", &http_stats(&sim));
+					},
+					("POST", "/cmd") => {
+						let input = match serde_json::from_str::<serde_json::Value>(&body) {
+							Ok(value) => get_str(&value, "cmd").unwrap_or("").to_string(),
+							Err(_) => body
+						};
+
+						let mut output = String::new();
+						clear_cancel();
+						let result = cmd_handler(&mut output, &mut sim, &input, AllowRecursiveCall::Yes);
+						if sim.abort_simulation {
+							wake_up_loops(&sim);
 						}
+						let json = match result {
+							Ok(()) => serde_json::json!({ "output": output, "error": null }),
+							Err(e) => serde_json::json!({ "output": output, "error": e.to_string() })
+						};
+						http_response(&mut stream, "200 OK", "application/json", &json.to_string());
+					},
+					("GET", _) | ("POST", _) => {
+						http_response(&mut stream, "404 Not Found", "text/plain", "Not Found");
+					},
+					_ => {
+						http_response(&mut stream, "405 Method Not Allowed", "text/plain", "Method Not Allowed");
 					}
 				}
 			}
@@ -80,23 +341,212 @@ pub fn ext_loop(sim: Arc<Mutex<GlobalState>>, address: &str) {
 	}
 }
 
+// tab completion of command and algorithm names
+struct CmdHelper;
+
+impl Completer for CmdHelper {
+	type Candidate = String;
+
+	fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+		let line = &line[..pos];
+		let start = line.rfind(' ').map_or(0, |i| i + 1);
+		let word = &line[start..];
+
+		let names : Vec<String> = if start == 0 {
+			COMMANDS.iter().filter_map(|item| item.0.split_whitespace().next()).map(String::from).collect()
+		} else if line.split_whitespace().next() == Some("algo") {
+			algorithm_names()
+		} else {
+			vec![]
+		};
+
+		let candidates = names.iter()
+			.filter(|name| name.starts_with(word))
+			.map(|name| name.to_string())
+			.collect();
+
+		Ok((start, candidates))
+	}
+}
+
+impl Hinter for CmdHelper {
+	type Hint = String;
+}
+
+impl Highlighter for CmdHelper {}
+impl Validator for CmdHelper {}
+impl Helper for CmdHelper {}
+
+// in JSON mode every response is a single JSON object,
+// commands without structured output are wrapped
+fn format_response(json_output: bool, output: &str, result: &Result<(), MyError>) -> String {
+	if !json_output {
+		return match result {
+			Ok(()) => output.to_string(),
+			Err(e) => format!("{}\n", e)
+		};
+	}
+
+	let response = match result {
+		Err(e) => serde_json::json!({ "error": e.to_string() }),
+		Ok(()) => match serde_json::from_str::<serde_json::Value>(output) {
+			Ok(value @ serde_json::Value::Object(_)) => value,
+			_ => serde_json::json!({ "output": output })
+		}
+	};
+	format!("{}\n", response)
+}
+
+/*
+ * Commands that do not wait for the simulator state: job control,
+ * starting a background job (trailing &) and cancelling the running
+ * command. Everything else is left to cmd_handler.
+ */
+fn cmd_unlocked(out: &mut dyn std::fmt::Write, sim: &Arc<Mutex<GlobalState>>, input: &str) -> Option<Result<(), MyError>> {
+	if let Some(command) = input.trim().strip_suffix('&') {
+		return Some(start_job(out, sim, command.trim()));
+	}
+
+	match parse_command(input) {
+		Command::Jobs => Some(jobs::list(out)),
+		Command::JobStatus(id) => Some(jobs::status(out, id)),
+		Command::JobCancel(id) => Some(jobs::cancel(out, id)),
+		Command::Cancel => {
+			// nothing is running if the state is not locked
+			if let Err(TryLockError::WouldBlock) = sim.try_lock() {
+				request_cancel();
+				Some(writeln!(out, "Cancel requested.").map_err(MyError::from))
+			} else {
+				None
+			}
+		},
+		_ => None
+	}
+}
+
+// run a command on its own thread, it waits for the running command
+fn start_job(out: &mut dyn std::fmt::Write, sim: &Arc<Mutex<GlobalState>>, command: &str) -> Result<(), MyError> {
+	if command.is_empty() {
+		return Err(MyError::new("Missing command before &".to_string()));
+	}
+
+	let id = jobs::add(command);
+	let sim = sim.clone();
+	let line = command.to_string();
+	thread::spawn(move || {
+		let mut sim = match sim.lock() {
+			Ok(sim) => sim,
+			Err(_) => {
+				jobs::finish(id, String::new(), Err(MyError::new("Simulator state is not available".to_string())));
+				return;
+			}
+		};
+		if jobs::start(id) {
+			let mut output = String::new();
+			let result = cmd_handler(&mut output, &mut sim, &line, AllowRecursiveCall::Yes);
+			jobs::finish(id, output, result);
+			if sim.abort_simulation {
+				wake_up_loops(&sim);
+			}
+		}
+	});
+
+	writeln!(out, "[{}] {}", id, command)?;
+	Ok(())
+}
+
+// run a script without console and sockets, returns the process exit code
+pub fn batch_run(sim: &mut GlobalState, path: &str) -> i32 {
+	let text = match std::fs::read_to_string(path) {
+		Ok(text) => text,
+		Err(e) => {
+			eprintln!("{}: {}", path, e);
+			return 1;
+		}
+	};
+
+	let mut output = String::new();
+	let mut exit = false;
+	let mut script = Script::new();
+	let result = script.run(&text, &mut |line| {
+		// skip the rest of the script, there is no console to wake up
+		if exit || line.trim() == "exit" {
+			exit = true;
+			return Ok(());
+		}
+		output.clear();
+		let result = cmd_handler(&mut output, sim, line, AllowRecursiveCall::Yes);
+		if result.is_ok() {
+			let _ = std::io::stdout().write(format_response(sim.json_output, &output, &result).as_bytes());
+		}
+		result
+	});
+
+	match result {
+		Err(e) => {
+			eprintln!("{}:{}", path, e);
+			1
+		},
+		// set by scripts called with run
+		Ok(()) if sim.abort_simulation => 1,
+		Ok(()) => 0
+	}
+}
+
 pub fn cmd_loop(sim: Arc<Mutex<GlobalState>>, run: &str) {
 	let mut input = run.to_owned();
 	let mut output = String::new();
+	// output format of the last command, for responses without the lock
+	let mut json_output = false;
+
+	let mut editor = match Editor::<CmdHelper, DefaultHistory>::new() {
+		Ok(editor) => editor,
+		Err(e) => {
+			eprintln!("{}", e);
+			return;
+		}
+	};
+	editor.set_helper(Some(CmdHelper));
 
 	loop {
-		if input.len() == 0 {
-			let _ = std::io::stdin().read_line(&mut input);
+		if input.is_empty() {
+			match editor.readline("> ") {
+				Ok(line) => {
+					if !line.trim().is_empty() {
+						let _ = editor.add_history_entry(line.as_str());
+					}
+					input = line;
+				},
+				Err(ReadlineError::Interrupted) => {
+					continue;
+				},
+				Err(_) => {
+					// stdin closed, commands are still accepted over the socket
+					break;
+				}
+			}
 		}
-		if let Ok(mut sim) = sim.lock() {
-			output.clear();
-			if let Err(e) = cmd_handler(&mut output, &mut sim, &input, AllowRecursiveCall::Yes) {
-				let _ = std::io::stderr().write(e.to_string().as_bytes());
+		output.clear();
+		if let Some(result) = cmd_unlocked(&mut output, &sim, &input) {
+			let response = format_response(json_output, &output, &result);
+			if result.is_err() && !json_output {
+				let _ = std::io::stderr().write(response.as_bytes());
+			} else {
+				let _ = std::io::stdout().write(response.as_bytes());
+			}
+		} else if let Ok(mut sim) = sim.lock() {
+			clear_cancel();
+			let result = cmd_handler(&mut output, &mut sim, &input, AllowRecursiveCall::Yes);
+			let response = format_response(sim.json_output, &output, &result);
+			json_output = sim.json_output;
+			if result.is_err() && !sim.json_output {
+				let _ = std::io::stderr().write(response.as_bytes());
 			} else {
-				let _ = std::io::stdout().write(output.as_bytes());
+				let _ = std::io::stdout().write(response.as_bytes());
 			}
 			if sim.abort_simulation {
 				// abort loop
+				wake_up_loops(&sim);
 				break;
 			}
 		}
@@ -110,40 +560,121 @@ macro_rules! scan {
     }}
 }
 
-enum Command {
+pub enum Command {
 	Error(String),
 	Ignore,
 	Help,
 	ClearGraph,
+	Undo,
+	Redo,
+	History(Option<u32>),
+	Workspace(Option<(String, String)>),
 	GraphInfo,
+	GraphMetrics(u32),
 	SimInfo,
 	ResetSim,
+	Output(Option<bool>),
+	Seed(Option<Option<u64>>),
 	Exit,
 	Progress(Option<bool>),
-	ShowMinimumSpanningTree,
-	CropMinimumSpanningTree,
-	Test(u32),
+	Cancel,
+	Jobs,
+	JobStatus(u32),
+	JobCancel(u32),
+	ShowMinimumSpanningTree(Option<MstWeight>),
+	CropMinimumSpanningTree(Option<MstWeight>),
+	Test(u32, Option<String>, bool),
+	TestMultipath(u32),
+	Compare(Vec<String>, u32),
+	Sweep(String, f64, f64, f64, u32, u32, Option<String>),
+	TestLog(Option<String>),
 	Debug(u32, u32),
+	MoveModel(Option<String>, Vec<f32>),
+	MoveTrace(String),
+	RadioModel(Option<String>, Vec<f32>),
+	ChurnLinks(Option<String>, Option<f32>),
+	LinkModel(Option<String>, Option<f32>, Option<f32>, Option<f32>, Option<f32>, Option<u32>),
+	AssignChannels(u8, Option<f32>),
+	Channels(Option<String>),
+	Capacity(Option<u32>),
+	ChurnNodes(Option<(f32, f32, Option<f32>)>),
+	FailNode(Vec<u32>, Option<u32>),
+	FailLink(u32, u32, Option<u32>),
+	FailRegion(f32, f32, f32, Option<u32>),
+	EnergyInit(f32, Option<f32>, Option<f32>),
+	EnergySolar(f32, u32),
+	EnergyInfo,
+	Traffic(Vec<String>),
+	SetGateway(Option<String>),
+	TestGateways,
+	LoadInfo(u32),
+	OverheadInfo(bool),
+	Threads(Option<u32>),
+	ImportMerge(String, f32, f32),
+	Interconnect(u32, Option<(Vec<ID>, Vec<ID>)>),
+	OptimizePlacement(u32, f32, PlacementMethod),
+	ImportMeshviewer(String, Option<String>),
+	ImportRoads(String, f32, f32),
+	ImportObstacles(String),
+	ClearObstacles,
+	Record(Option<String>, Option<String>),
+	Metrics(Option<String>, Option<String>, Option<u32>),
+	Timeline(Option<String>, Option<u32>),
+	Send(u32, u32, u32),
+	Stats(bool),
 	DebugStep(u32),
-	Get(String),
-	Set(String, String),
+	DebugTrace(u32, u32),
+	Get(Option<u32>, String),
+	Set(Option<u32>, String, String),
 	ConnectInRange(f32),
 	RandomizePositions(f32),
 	RemoveUnconnected,
 	Algorithm(Option<String>),
+	AlgorithmNode(Option<(Vec<ID>, String, Option<String>)>),
+	AlgorithmLoad(String),
+	AlgorithmWasm(String),
 	AddLine(u32, bool),
 	AddTree(u32, u32),
 	AddStar(u32),
 	AddLattice4(u32, u32),
 	AddLattice8(u32, u32),
+	AddLatticeTri(u32, u32),
+	AddLatticeHex(u32, u32),
+	AddLattice3d(u32, u32, u32),
+	AddTorus(u32, u32),
+	AddRgg(u32, f32),
+	AddErdos(u32, f32),
+	AddBarabasi(u32, u32),
+	AddSmallworld(u32, u32, f32),
 	Positions(bool),
 	RemoveNodes(Vec<u32>),
+	Crop(Vec<u32>),
+	CropLargestComponent,
+	Components,
+	MinCut(u32, u32),
+	Connectivity,
+	Cluster(Option<ClusterMethod>),
+	Select(Option<String>, Option<String>),
+	Unselect(String),
+	MetaGet(u32, Option<String>),
+	MetaFilter(String, String),
+	Resolve(String),
+	CompactIds,
 	ConnectNodes(Vec<u32>),
+	ConnectNodesOneway(u32, Vec<u32>),
 	DisconnectNodes(Vec<u32>),
+	SetLink(u32, u32, String, String),
 	SimStep(u32),
+	Converge(u32),
 	Run(String),
 	Import(String),
+	GraphDiff(String),
+	GraphValidate(bool, bool),
+	SaveState(String),
+	LoadState(String),
 	ExportPath(Option<String>),
+	Render(String, u32, Option<NodeMetric>),
+	ExportStyle(Vec<String>),
 	MoveNode(u32, f32, f32, f32),
 	MoveNodes(f32, f32, f32),
 	MoveTo(f32, f32, f32),
@@ -154,83 +685,258 @@ enum Cid {
 	Error,
 	Help,
 	ClearGraph,
+	Undo,
+	Redo,
+	History,
+	Workspace,
 	GraphInfo,
+	GraphMetrics,
 	SimInfo,
 	ResetSim,
+	Output,
+	Seed,
 	Exit,
 	Progress,
+	Cancel,
+	Jobs,
+	JobStatus,
+	JobCancel,
 	ShowMinimumSpanningTree,
 	CropMinimumSpanningTree,
 	Test,
+	TestMultipath,
+	Compare,
+	Sweep,
+	TestLog,
 	Debug,
+	Send,
+	Stats,
 	DebugStep,
+	DebugTrace,
 	Get,
 	Set,
 	ConnectInRange,
 	RandomizePositions,
 	RemoveUnconnected,
 	Algorithm,
+	AlgorithmNode,
 	AddLine,
 	AddTree,
 	AddStar,
 	AddLattice4,
 	AddLattice8,
+	AddLatticeTri,
+	AddLatticeHex,
+	AddLattice3d,
+	AddTorus,
+	AddRgg,
+	AddErdos,
+	AddBarabasi,
+	AddSmallworld,
 	Positions,
 	RemoveNodes,
+	Crop,
+	CropLargestComponent,
+	Components,
+	MinCut,
+	Connectivity,
+	Cluster,
+	Select,
+	Unselect,
+	MetaGet,
+	MetaFilter,
+	Resolve,
+	CompactIds,
 	ConnectNodes,
+	ConnectNodesOneway,
 	DisconnectNodes,
+	SetLink,
 	SimStep,
+	Converge,
 	Run,
 	Import,
+	GraphDiff,
+	GraphValidate,
+	SaveState,
+	LoadState,
+	ImportMerge,
+	Interconnect,
+	OptimizePlacement,
+	ImportMeshviewer,
+	ImportRoads,
+	ImportObstacles,
+	ClearObstacles,
 	ExportPath,
+	Render,
+	ExportStyle,
+	Record,
+	Metrics,
+	Timeline,
 	MoveNode,
 	MoveNodes,
+	MoveModel,
+	MoveTrace,
+	RadioModel,
+	ChurnLinks,
+	LinkModel,
+	AssignChannels,
+	Channels,
+	Capacity,
+	ChurnNodes,
+	FailNode,
+	FailLink,
+	FailRegion,
+	EnergyInit,
+	EnergySolar,
+	EnergyInfo,
+	Traffic,
+	SetGateway,
+	TestGateways,
+	LoadInfo,
+	OverheadInfo,
+	Threads,
 	MoveTo
 }
 
 
-const COMMANDS: &'static [(&'static str, Cid)] = &[
-	("algo [<algorithm>]                 Get or set given algorithm.", Cid::Algorithm),
+const COMMANDS: &[(&str, Cid)] = &[
+	("algo [<algorithm>|load <path>|wasm <path>] Get or set given algorithm, or load and select a routing algorithm plugin (shared library) or WebAssembly module.", Cid::Algorithm),
+	("algo_node [<ids> <algorithm> [<group>]] Run another algorithm on the given nodes (e.g. 0-49), or show the groups.", Cid::AlgorithmNode),
 	("sim_step [<steps>]                 Run simulation steps. Default is 1.", Cid::SimStep),
+	("converge [<max_steps>]             Run simulation steps until the routing is stable. Default is 1000 steps at most.", Cid::Converge),
 	("sim_reset                          Reset simulation.", Cid::ResetSim),
 	("sim_info                           Show simulator information.", Cid::SimInfo),
+	("seed [<value>|random]              Get or set the seed of the random number generator for reproducible runs.", Cid::Seed),
 	("progress [<true|false>]            Show simulation progress.", Cid::Progress),
-	("test [<samples>]                   Test routing algorithm with (test packets arrived, path stretch).", Cid::Test),
+	("cancel                             Stop a running sim_step, test, sweep, run, import or connect_in_range (command socket or background job).", Cid::Cancel),
+	("jobs                               List background jobs (started with a trailing &, e.g. test 10000 &).", Cid::Jobs),
+	("job_status <id>                    Show state and output of a background job.", Cid::JobStatus),
+	("job_cancel <id>                    Cancel a queued or running background job.", Cid::JobCancel),
+	("output [text|json]                 Get or set the output format of command results.", Cid::Output),
+	("test [--source-routing] [<samples> [<histogram>]] Test routing algorithm with (test packets arrived, path stretch).", Cid::Test),
+	("test_multipath [<samples>]         Test routing algorithm following all candidate next hops.", Cid::TestMultipath),
+	("compare <algo_list> [<samples>]    Converge and test several algorithms (comma separated) on the same topology.", Cid::Compare),
+	("sweep <cmd> <start>:<end>:<step> [test <samples>] [steps <n>] [> <file>] Test for each value of the command argument.", Cid::Sweep),
+	("test_log [<file>|off]              Append test results to a CSV or JSON lines (.json/.jsonl) file.", Cid::TestLog),
+	("send <from> <to> [<count>]         Send packets through the event simulation. Default is 1.", Cid::Send),
+	("stats [clear]                      Show (or clear) event simulation statistics.", Cid::Stats),
 	("debug_init <from> <to>             Debug a path step wise.", Cid::Debug),
 	("debug_step [<steps>]               Perform step on path.", Cid::DebugStep),
+	("debug_trace <from> <to>            Route a whole path, print every hop and mark it in the export.", Cid::DebugTrace),
 	("", Cid::Error),
 	("graph_info                         Show graph information", Cid::GraphInfo),
-	("get <key>                          Get node property.", Cid::Get),
-	("set <key> <value>                  Set node property.", Cid::Set),
+	("graph_metrics [<samples>]          Show diameter, radius, average path length and clustering coefficient.", Cid::GraphMetrics),
+	("get [<node_id>] <key>              Get algorithm property, of a single node if an id is given.", Cid::Get),
+	("set [<node_id>] <key> <value>      Set algorithm property, of a single node if an id is given.", Cid::Set),
 	("", Cid::Error),
 	("graph_clear                        Clear graph", Cid::ClearGraph),
+	("undo                               Undo the last destructive graph command (remove_nodes, crop, graph_clear, ...).", Cid::Undo),
+	("redo                               Redo the last undone command.", Cid::Redo),
+	("history [<limit>]                  Show commands that can be undone or set the number of kept states.", Cid::History),
+	("workspace [new|switch|copy|remove <name>] Switch between named graphs or list them.", Cid::Workspace),
 	("line <node_count> [<create_loop>]  Add a line of nodes. Connect ends to create a loop.", Cid::AddLine),
 	("star <edge_count>                  Add star structure of nodes.", Cid::AddStar),
 	("tree <node_count> [<inter_count>]  Add a tree structure of nodes with interconnections", Cid::AddTree),
 	("lattice4 <x_xount> <y_count>       Create a lattice structure of squares.", Cid::AddLattice4),
 	("lattice8 <x_xount> <y_count>       Create a lattice structure of squares and diagonal connections.", Cid::AddLattice8),
+	("lattice_tri <x_count> <y_count>    Create a lattice structure of triangles.", Cid::AddLatticeTri),
+	("lattice_hex <x_count> <y_count>    Create a lattice structure of hexagons (honeycomb).", Cid::AddLatticeHex),
+	("lattice3d <x_count> <y_count> <z_count> Create a cubic lattice structure.", Cid::AddLattice3d),
+	("torus <x_count> <y_count>          Create a lattice structure of squares with wrap-around links.", Cid::AddTorus),
+	("rgg <node_count> <radius>          Add random geometric graph. Connect nodes within radius (in km).", Cid::AddRgg),
+	("erdos <node_count> <probability>   Add Erdős–Rényi random graph. Connect node pairs with probability.", Cid::AddErdos),
+	("barabasi <node_count> <m>          Add Barabási–Albert scale-free graph. New nodes connect to m nodes.", Cid::AddBarabasi),
+	("smallworld <node_count> <k> <p>    Add Watts–Strogatz small-world graph. Ring of k neighbors, rewired with probability p.", Cid::AddSmallworld),
 	("remove_nodes <node_list>           Remove nodes. Node list is a comma separated list of node ids.", Cid::RemoveNodes),
 	("connect_nodes <node_list>          Connect nodes. Node list is a comma separated list of node ids.", Cid::ConnectNodes),
+	("connect_nodes_oneway <from> <node_list> Add unidirectional links from one node to a list of nodes.", Cid::ConnectNodesOneway),
 	("disconnect_nodes <node_list>       Disconnect nodes. Node list is a comma separated list of node ids.", Cid::DisconnectNodes),
+	("set_link <a> <b> <key> <value>     Set link attribute (cost, quality, bandwidth, latency, loss).", Cid::SetLink),
 	("remove_unconnected                 Remove nodes without any connections.", Cid::RemoveUnconnected),
+	("crop <node_list>                   Only leave the given nodes, they are renumbered in list order.", Cid::Crop),
+	("crop_largest_component             Only leave the largest connected component.", Cid::CropLargestComponent),
+	("keep_largest_component             Same as crop_largest_component.", Cid::CropLargestComponent),
+	("components                         Show the number and sizes of connected components.", Cid::Components),
+	("mincut <a> <b>                     Show the minimum set of links that disconnects node b from node a.", Cid::MinCut),
+	("connectivity                       Show the edge connectivity of the graph.", Cid::Connectivity),
+	("cluster [<method>]                 Assign a cluster id to every node (label or louvain).", Cid::Cluster),
+	("select [<name> [<expression>]]     List, show or define a named node selection, use as @<name> in node lists.", Cid::Select),
+	("unselect <name>                    Remove a named node selection.", Cid::Unselect),
+	("meta_get <node_id> [<key>]         Show imported metadata of a node, key may contain * and ?.", Cid::MetaGet),
+	("meta_filter <key>=<value>          List nodes with matching metadata, key and value may contain * and ?.", Cid::MetaFilter),
+	("resolve <name>|<node_id>           Show the node ids of a node name, or the name of a node id.", Cid::Resolve),
+	("compact_ids                        Make the stable node ids (#<id>) equal to the node ids again.", Cid::CompactIds),
 	("", Cid::Error),
 	("positions <true|false>             Enable geo positions.", Cid::Positions),
 	("move_node <node_id> <x> <y> <z>    Move a node by x/y/z (in km).", Cid::MoveNode),
 	("move_nodes <x> <y> <z>             Move all nodes by x/y/z (in km).", Cid::MoveNodes),
+	("move_model [<model> <args>]        Get or set mobility model (none, waypoint, gauss_markov, random_walk, rpgm).", Cid::MoveModel),
+	("move_trace <file>                  Replay node movements from a ns-2 or CSV (step, node_id, x, y, z) trace.", Cid::MoveTrace),
 	("move_to <x> <y> <z>                Move all nodes to x/y/z (in degrees).", Cid::MoveTo),
 	("rnd_pos <range>                    Randomize node positions in an area with width (in km) around node center.", Cid::RandomizePositions),
 	("connect_in_range <range>           Connect all nodes in range of less then range (in km).", Cid::ConnectInRange),
+	("interconnect <k> [<nodes_a> <nodes_b>] Add the k shortest links between disconnected components or between two node lists.", Cid::Interconnect),
+	("optimize_placement <new_nodes> <range> [greedy|anneal] Add relay nodes at positions that connect the graph best, linked to all nodes in range (in km).", Cid::OptimizePlacement),
+	("radio_model [<model> <args>]       Get or set radio model for connect_in_range (range, free_space, log_distance, two_ray).", Cid::RadioModel),
+	("churn_links [<fail> <recover>|off] Links fail and recover with a probability on every simulation step.", Cid::ChurnLinks),
+	("link_model [gilbert <good_bad> <bad_good> <loss_good> <loss_bad> [<probes>]|off] Bursty link loss (Gilbert-Elliott) measured with probe packets on every simulation step.", Cid::LinkModel),
+	("assign_channels <n> [<range>]     Assign n channels to the links with few interfering links on the same channel.", Cid::AssignChannels),
+	("channels [<range>|hops]           Show the capacity estimate under interference, set the interference range (in km).", Cid::Channels),
+	("capacity [<flows>]                 Max-min fair throughput of flows (traffic pattern) over the routes of the algorithm.", Cid::Capacity),
+	("churn_nodes [<leave> <join> [<range>]] Nodes leave and join (at random position) with a probability on every simulation step.", Cid::ChurnNodes),
+	("fail_node <node_list> [<steps>]    Remove all links of nodes for a number of simulation steps or permanently.", Cid::FailNode),
+	("fail_link <from> <to> [<steps>]    Remove a link (both directions) for a number of simulation steps or permanently.", Cid::FailLink),
+	("fail_region <x> <y> <radius> [<steps>] Remove all links of nodes in a circle (in km).", Cid::FailRegion),
+	("energy_init <joules> [<idle> <tx>] Give every node a battery. 0 disables the energy model.", Cid::EnergyInit),
+	("energy_solar <joules> <day_steps>  Recharge batteries by a solar model (peak energy per step).", Cid::EnergySolar),
+	("energy_info                        Show energy statistics.", Cid::EnergyInfo),
+	("traffic [uniform|gateway <ids>|hotspot <fraction> [<count>]|file <path>] Set the source/destination distribution of test.", Cid::Traffic),
+	("set_gateway [<id_list>|none]       Set gateway nodes (comma separated ids).", Cid::SetGateway),
+	("test_gateways                      Test routing from all nodes to their nearest gateway.", Cid::TestGateways),
+	("load_info [<count>]                Show the nodes and links most used by routes of the last test.", Cid::LoadInfo),
+	("overhead_info [reset]              Show control messages sent by the routing algorithm.", Cid::OverheadInfo),
+	("threads [<n>]                      Set the number of threads used by test.", Cid::Threads),
 	("", Cid::Error),
 	("run <file>                         Run commands from a script.", Cid::Run),
-	("import <file>                      Import a graph as JSON file.", Cid::Import),
-	("export [<file>]                    Get or set graph export file.", Cid::ExportPath),
-	("show_mst                           Mark the minimum spanning tree.", Cid::ShowMinimumSpanningTree),
-	("crop_mst                           Only leave the minimum spanning tree.", Cid::CropMinimumSpanningTree),
+	("import <file>                      Import a graph as JSON, GraphML (.graphml) or GEXF (.gexf) file.", Cid::Import),
+	("graph_diff <file>                  Show added/removed nodes and links and moved nodes of a graph file compared to the current graph.", Cid::GraphDiff),
+	("graph_validate [--directed] [--fix] Check for self-loops, duplicate and asymmetric links, positions and metadata (and fix them).", Cid::GraphValidate),
+	("import_merge <file> [<offset_x> <offset_y>] Add the graph of a file to the current graph, positions moved by the offset (in km).", Cid::ImportMerge),
+	("import_meshviewer <file> [<graph>] Import Freifunk meshviewer.json or nodes.json and graph.json.", Cid::ImportMeshviewer),
+	("import_roads <file> <spacing> <range> Place nodes along roads (OSM .osm or GeoJSON) and connect in range (in km).", Cid::ImportRoads),
+	("import_obstacles <file>            Import obstacle polygons (GeoJSON) or a heightmap (.asc) for connect_in_range.", Cid::ImportObstacles),
+	("clear_obstacles                    Remove all obstacles.", Cid::ClearObstacles),
+	("export [<file>]                    Get or set graph export file (JSON or GEXF).", Cid::ExportPath),
+	("render <file> [<width>] [<node_color>] Draw the graph to an SVG or PNG file. Node colors by component (default), degree, load, cluster, energy or algorithm.", Cid::Render),
+	("export_style [<key>=<metric> ...|off] Bind node_color/node_size (component, degree, load, cluster, energy, algorithm) and link_color/link_width (quality, etx, load, cost, bandwidth, latency, loss) of the exports to metrics.", Cid::ExportStyle),
+	("record [start <dir>|stop]          Export the graph on every simulation step into a directory.", Cid::Record),
+	("metrics [start <file> [<samples>]|stop] Append measurements of every simulation step to a CSV file.", Cid::Metrics),
+	("timeline [<dir|pattern> [<steps>]|stop] Play back graph snapshots, the next snapshot every <steps> simulation steps.", Cid::Timeline),
+	("save_state <file>                  Save graph, locations, metadata, algorithm state and step count.", Cid::SaveState),
+	("load_state <file>                  Restore a state saved with save_state.", Cid::LoadState),
+	("show_mst [<weight>]                Mark the minimum spanning tree (weight: cost, hops, latency, etx).", Cid::ShowMinimumSpanningTree),
+	("crop_mst [<weight>]                Only leave the minimum spanning tree.", Cid::CropMinimumSpanningTree),
 	("exit                               Exit simulator.", Cid::Exit),
 	("help                               Show this help.", Cid::Help),
 ];
 
-fn parse_command(input: &str) -> Command {
+// positions of the tokens that take node ids or node lists,
+// node names are only replaced by node ids there
+fn node_arguments(tokens: &[&str]) -> Vec<usize> {
+	match (tokens.first().cloned().unwrap_or(""), tokens.len()) {
+		("remove_nodes", _) | ("connect_nodes", _) | ("disconnect_nodes", _) | ("crop", _) | ("fail_node", _)
+			| ("meta_get", _) | ("move_node", _) | ("set_gateway", _) | ("algo_node", _) => vec![1],
+		("send", _) | ("debug_init", _) | ("debug_trace", _) | ("connect_nodes_oneway", _)
+			| ("set_link", _) | ("mincut", _) | ("fail_link", _) => vec![1, 2],
+		("interconnect", 4) => vec![2, 3],
+		// with a node id only
+		("get", 3) | ("set", 4) => vec![1],
+		("traffic", _) if tokens.get(1) == Some(&"gateway") => vec![2],
+		_ => vec![]
+	}
+}
+
+pub fn parse_command(input: &str) -> Command {
 	let mut tokens = Vec::new();
 	for tok in input.split_whitespace() {
 		// trim ' " characters
@@ -238,12 +944,12 @@ fn parse_command(input: &str) -> Command {
 	}
 
 	let mut iter = tokens.iter().skip(1);
-	let cmd = tokens.get(0).unwrap_or(&"");
+	let cmd = tokens.first().unwrap_or(&"");
 
 	fn is_first_token(string: &str, tok: &str) -> bool {
 		string.starts_with(tok)
 			&& (string.len() > tok.len())
-			&& (string.as_bytes()[tok.len()] == ' ' as u8)
+			&& (string.as_bytes()[tok.len()] == b' ')
 	}
 
 	fn lookup_cmd(cmd: &str) -> Cid {
@@ -268,13 +974,74 @@ fn parse_command(input: &str) -> Command {
 		Ok(v)
 	}
 
+	// parse list of numbers and ranges, e.g. 1,5-9
+	fn parse_ranges(numbers: Option<&&str>) -> Result<Vec<u32>, ()> {
+		let mut v = Vec::<u32>::new();
+		for part in numbers.ok_or(())?.split(",") {
+			if let Some((start, end)) = part.split_once('-') {
+				let start = start.parse::<u32>().map_err(|_| ())?;
+				let end = end.parse::<u32>().map_err(|_| ())?;
+				if start > end {
+					return Err(());
+				}
+				v.extend(start..=end);
+			} else {
+				v.push(part.parse::<u32>().map_err(|_| ())?);
+			}
+		}
+		Ok(v)
+	}
+
 	let error = Command::Error("Missing Arguments".to_string());
 
 	match lookup_cmd(cmd) {
 		Cid::Help => Command::Help,
 		Cid::SimInfo => Command::SimInfo,
 		Cid::GraphInfo => Command::GraphInfo,
+		Cid::GraphMetrics => {
+			if let (Some(samples),) = scan!(iter, u32) {
+				Command::GraphMetrics(samples)
+			} else {
+				Command::GraphMetrics(1000)
+			}
+		},
 		Cid::ClearGraph => Command::ClearGraph,
+		Cid::Output => {
+			match tokens.get(1) {
+				None => Command::Output(None),
+				Some(&"text") => Command::Output(Some(false)),
+				Some(&"json") => Command::Output(Some(true)),
+				Some(_) => error
+			}
+		},
+		Cid::Seed => {
+			match tokens.get(1) {
+				None => Command::Seed(None),
+				Some(&"random") => Command::Seed(Some(None)),
+				Some(value) => match value.parse::<u64>() {
+					Ok(seed) => Command::Seed(Some(Some(seed))),
+					Err(_) => error
+				}
+			}
+		},
+		Cid::Undo => Command::Undo,
+		Cid::Redo => Command::Redo,
+		Cid::History => {
+			if let (Some(limit),) = scan!(iter, u32) {
+				Command::History(Some(limit))
+			} else {
+				Command::History(None)
+			}
+		},
+		Cid::Workspace => {
+			match (tokens.get(1), tokens.get(2)) {
+				(None, _) => Command::Workspace(None),
+				(Some(action), Some(name)) if ["new", "switch", "copy", "remove"].contains(action) => {
+					Command::Workspace(Some((action.to_string(), name.to_string())))
+				},
+				_ => error
+			}
+		},
 		Cid::ResetSim => Command::ResetSim,
 		Cid::Exit => Command::Exit,
 		Cid::Progress => {
@@ -284,15 +1051,104 @@ fn parse_command(input: &str) -> Command {
 				Command::Progress(None)
 			}
 		},
-		Cid::ShowMinimumSpanningTree => Command::ShowMinimumSpanningTree,
-		Cid::CropMinimumSpanningTree => Command::CropMinimumSpanningTree,
+		Cid::Cancel => Command::Cancel,
+		Cid::Jobs => Command::Jobs,
+		Cid::JobStatus => {
+			if let (Some(id),) = scan!(iter, u32) {
+				Command::JobStatus(id)
+			} else {
+				Command::Error("Missing job id".to_string())
+			}
+		},
+		Cid::JobCancel => {
+			if let (Some(id),) = scan!(iter, u32) {
+				Command::JobCancel(id)
+			} else {
+				Command::Error("Missing job id".to_string())
+			}
+		},
+		Cid::ShowMinimumSpanningTree => {
+			match tokens.get(1).map(|name| MstWeight::parse(name)).transpose() {
+				Ok(weight) => Command::ShowMinimumSpanningTree(weight),
+				Err(e) => Command::Error(e.to_string())
+			}
+		},
+		Cid::CropMinimumSpanningTree => {
+			match tokens.get(1).map(|name| MstWeight::parse(name)).transpose() {
+				Ok(weight) => Command::CropMinimumSpanningTree(weight),
+				Err(e) => Command::Error(e.to_string())
+			}
+		},
 		Cid::Test => {
-			if let (Some(samples),) = scan!(iter, u32) {
-				Command::Test(samples)
+			let source_routing = tokens.contains(&"--source-routing");
+			let mut iter = tokens.iter().skip(1).filter(|tok| **tok != "--source-routing");
+			if let (Some(samples), histogram) = scan!(iter, u32, String) {
+				Command::Test(samples, histogram, source_routing)
+			} else {
+				Command::Test(1000, None, source_routing)
+			}
+		},
+		Cid::TestMultipath => {
+			let (samples,) = scan!(iter, u32);
+			Command::TestMultipath(samples.unwrap_or(1000))
+		},
+		Cid::Compare => {
+			if let (Some(names), samples) = scan!(iter, String, u32) {
+				let names = names.split(',').filter(|n| !n.is_empty()).map(|n| n.to_string()).collect();
+				Command::Compare(names, samples.unwrap_or(1000))
 			} else {
-				Command::Test(1000)
+				error
+			}
+		},
+		Cid::Sweep => {
+			let command = tokens.get(1).map(|s| s.to_string());
+			let range : Vec<f64> = tokens.get(2).unwrap_or(&"").split(':')
+				.filter_map(|s| s.parse::<f64>().ok()).collect();
+
+			let mut samples = 1000;
+			let mut steps = 0;
+			let mut path = None;
+			let mut valid = true;
+			let mut args = tokens.iter().skip(3).peekable();
+			while let Some(arg) = args.next() {
+				// the numbers are optional
+				let number = args.peek().and_then(|s| s.parse::<u32>().ok());
+				match *arg {
+					"test" => {
+						if let Some(n) = number {
+							samples = n;
+							args.next();
+						}
+					},
+					"steps" => {
+						if let Some(n) = number {
+							steps = n;
+							args.next();
+						}
+					},
+					">" => {
+						path = args.next().map(|s| s.to_string());
+					},
+					s if s.starts_with('>') => {
+						path = Some(s[1..].to_string());
+					},
+					_ => {
+						valid = false;
+					}
+				}
+			}
+
+			match (command, range.as_slice()) {
+				(Some(command), &[start, end, step]) if valid && step > 0.0 => {
+					Command::Sweep(command, start, end, step, samples, steps, path)
+				},
+				_ => error
 			}
 		},
+		Cid::TestLog => {
+			let (path,) = scan!(iter, String);
+			Command::TestLog(path)
+		},
 		Cid::Debug => {
 			if let (Some(from), Some(to)) = scan!(iter, u32, u32) {
 				Command::Debug(from, to)
@@ -307,78 +1163,324 @@ fn parse_command(input: &str) -> Command {
 				Command::DebugStep(1)
 			}
 		},
-		Cid::Get => { if let (Some(key),) = scan!(iter, String) {
-				Command::Get(key)
+		Cid::DebugTrace => {
+			if let (Some(from), Some(to)) = scan!(iter, u32, u32) {
+				Command::DebugTrace(from, to)
 			} else {
 				error
 			}
 		},
-		Cid::Set => { if let (Some(key),Some(value)) = scan!(iter, String, String) {
-				Command::Set(key, value)
+		Cid::Send => {
+			if let (Some(from), Some(to), count) = scan!(iter, u32, u32, u32) {
+				Command::Send(from, to, count.unwrap_or(1))
 			} else {
 				error
 			}
 		},
-		Cid::SimStep => {
-			Command::SimStep(if let (Some(count),) = scan!(iter, u32) {
-				count
-			} else {
-				1
-			})
+		Cid::Stats => {
+			Command::Stats(tokens.get(1) == Some(&"clear"))
 		},
-		Cid::Run => {
-			if let (Some(path),) = scan!(iter, String) {
-				Command::Run(path)
-			} else {
-				error
+		Cid::MoveModel => {
+			let mut args = vec![];
+			for token in tokens.iter().skip(2) {
+				if let Ok(arg) = token.parse::<f32>() {
+					args.push(arg);
+				} else {
+					return Command::Error(format!("Invalid argument: {}", token));
+				}
 			}
+			Command::MoveModel(tokens.get(1).map(|t| t.to_string()), args)
 		},
-		Cid::Import => {
-			if let (Some(path),) = scan!(iter, String) {
-				Command::Import(path)
+		Cid::RadioModel => {
+			let mut args = vec![];
+			for token in tokens.iter().skip(2) {
+				if let Ok(arg) = token.parse::<f32>() {
+					args.push(arg);
+				} else {
+					return Command::Error(format!("Invalid argument: {}", token));
+				}
+			}
+			Command::RadioModel(tokens.get(1).map(|t| t.to_string()), args)
+		},
+		Cid::ChurnLinks => {
+			let (fail, recover) = scan!(iter, String, f32);
+			Command::ChurnLinks(fail, recover)
+		},
+		Cid::LinkModel => {
+			let (model, good_bad, bad_good, loss_good, loss_bad, probes) = scan!(iter, String, f32, f32, f32, f32, u32);
+			Command::LinkModel(model, good_bad, bad_good, loss_good, loss_bad, probes)
+		},
+		Cid::AssignChannels => {
+			if let (Some(channels), range) = scan!(iter, u8, f32) {
+				Command::AssignChannels(channels, range)
 			} else {
 				error
 			}
 		},
-		Cid::ExportPath => {
-			if let (Some(path),) = scan!(iter, String) {
-				Command::ExportPath(Some(path))
-			} else {
-				Command::ExportPath(None)
+		Cid::Channels => {
+			let (range,) = scan!(iter, String);
+			Command::Channels(range)
+		},
+		Cid::Capacity => {
+			let (flows,) = scan!(iter, u32);
+			Command::Capacity(flows)
+		},
+		Cid::ChurnNodes => {
+			match scan!(iter, f32, f32, f32) {
+				(Some(leave), Some(join), range) => Command::ChurnNodes(Some((leave, join, range))),
+				(None, None, None) if tokens.len() == 1 => Command::ChurnNodes(None),
+				_ => error
 			}
 		},
-		Cid::MoveNodes => {
-			if let (Some(x), Some(y), Some(z)) = scan!(iter, f32, f32, f32) {
-				Command::MoveNodes(x, y, z)
+		Cid::FailNode => {
+			if let (Ok(ids), steps) = (parse_list(tokens.get(1)), tokens.get(2).map(|s| s.parse::<u32>())) {
+				match steps.transpose() {
+					Ok(steps) => Command::FailNode(ids, steps),
+					Err(_) => error
+				}
 			} else {
 				error
 			}
 		},
-		Cid::MoveNode => {
-			if let (Some(id), Some(x), Some(y), Some(z)) = scan!(iter, u32, f32, f32, f32) {
-				Command::MoveNode(id, x, y, z)
+		Cid::FailLink => {
+			if let (Some(from), Some(to), steps) = scan!(iter, u32, u32, u32) {
+				Command::FailLink(from, to, steps)
 			} else {
 				error
 			}
 		},
-		Cid::AddLine => {
-			let mut iter1 = iter.clone();
-			let mut iter2 = iter.clone();
-			if let (Some(count), Some(close)) = scan!(iter1, u32, bool) {
-				Command::AddLine(count, close)
-			} else if let (Some(count),) = scan!(iter2, u32) {
-				Command::AddLine(count, false)
+		Cid::FailRegion => {
+			if let (Some(x), Some(y), Some(radius), steps) = scan!(iter, f32, f32, f32, u32) {
+				Command::FailRegion(x, y, radius, steps)
 			} else {
 				error
 			}
 		},
-		Cid::AddTree => {
-			let mut iter1 = iter.clone();
-			let mut iter2 = iter.clone();
-			if let (Some(count), Some(intra)) = scan!(iter1, u32, u32) {
-				Command::AddTree(count, intra)
-			} else if let (Some(count),) = scan!(iter2, u32) {
-				Command::AddTree(count, 0)
+		Cid::EnergyInit => {
+			if let (Some(capacity), idle, tx) = scan!(iter, f32, f32, f32) {
+				Command::EnergyInit(capacity, idle, tx)
+			} else {
+				error
+			}
+		},
+		Cid::EnergySolar => {
+			if let (Some(power), Some(day_steps)) = scan!(iter, f32, u32) {
+				Command::EnergySolar(power, day_steps)
+			} else {
+				error
+			}
+		},
+		Cid::EnergyInfo => Command::EnergyInfo,
+		Cid::SetGateway => {
+			let (ids,) = scan!(iter, String);
+			Command::SetGateway(ids)
+		},
+		Cid::TestGateways => Command::TestGateways,
+		Cid::Threads => {
+			let (threads,) = scan!(iter, u32);
+			Command::Threads(threads)
+		},
+		Cid::OverheadInfo => {
+			match scan!(iter, String) {
+				(Some(ref arg),) if arg == "reset" => Command::OverheadInfo(true),
+				(None,) => Command::OverheadInfo(false),
+				_ => error
+			}
+		},
+		Cid::LoadInfo => {
+			Command::LoadInfo(if let (Some(count),) = scan!(iter, u32) {
+				count
+			} else {
+				10
+			})
+		},
+		Cid::Traffic => {
+			Command::Traffic(tokens.iter().skip(1).map(|s| s.to_string()).collect())
+		},
+		Cid::MoveTrace => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::MoveTrace(path)
+			} else {
+				error
+			}
+		},
+		Cid::Get => {
+			if tokens.len() == 3 {
+				if let (Some(id), Some(key)) = scan!(iter, u32, String) {
+					Command::Get(Some(id), key)
+				} else {
+					error
+				}
+			} else if let (Some(key),) = scan!(iter, String) {
+				Command::Get(None, key)
+			} else {
+				error
+			}
+		},
+		Cid::Set => {
+			if tokens.len() == 4 {
+				if let (Some(id), Some(key), Some(value)) = scan!(iter, u32, String, String) {
+					Command::Set(Some(id), key, value)
+				} else {
+					error
+				}
+			} else if let (Some(key), Some(value)) = scan!(iter, String, String) {
+				Command::Set(None, key, value)
+			} else {
+				error
+			}
+		},
+		Cid::SimStep => {
+			Command::SimStep(if let (Some(count),) = scan!(iter, u32) {
+				count
+			} else {
+				1
+			})
+		},
+		Cid::Converge => {
+			Command::Converge(if let (Some(max_steps),) = scan!(iter, u32) {
+				max_steps
+			} else {
+				1000
+			})
+		},
+		Cid::Run => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::Run(path)
+			} else {
+				error
+			}
+		},
+		Cid::Import => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::Import(path)
+			} else {
+				error
+			}
+		},
+		Cid::GraphDiff => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::GraphDiff(path)
+			} else {
+				error
+			}
+		},
+		Cid::GraphValidate => {
+			if tokens.iter().skip(1).all(|tok| *tok == "--directed" || *tok == "--fix") {
+				Command::GraphValidate(tokens.contains(&"--directed"), tokens.contains(&"--fix"))
+			} else {
+				error
+			}
+		},
+		Cid::SaveState => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::SaveState(path)
+			} else {
+				error
+			}
+		},
+		Cid::LoadState => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::LoadState(path)
+			} else {
+				error
+			}
+		},
+		Cid::ImportMerge => {
+			match scan!(iter, String, f32, f32) {
+				(Some(path), None, None) => Command::ImportMerge(path, 0.0, 0.0),
+				(Some(path), Some(x), Some(y)) => Command::ImportMerge(path, x, y),
+				_ => error
+			}
+		},
+		Cid::ImportMeshviewer => {
+			if let (Some(path), graph_path) = scan!(iter, String, String) {
+				Command::ImportMeshviewer(path, graph_path)
+			} else {
+				error
+			}
+		},
+		Cid::ImportRoads => {
+			if let (Some(path), Some(spacing), Some(range)) = scan!(iter, String, f32, f32) {
+				Command::ImportRoads(path, spacing, range)
+			} else {
+				error
+			}
+		},
+		Cid::ImportObstacles => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::ImportObstacles(path)
+			} else {
+				error
+			}
+		},
+		Cid::ClearObstacles => Command::ClearObstacles,
+		Cid::Record => {
+			let (action, dir) = scan!(iter, String, String);
+			Command::Record(action, dir)
+		},
+		Cid::Metrics => {
+			let (action, path, samples) = scan!(iter, String, String, u32);
+			Command::Metrics(action, path, samples)
+		},
+		Cid::Timeline => {
+			let (source, steps) = scan!(iter, String, u32);
+			Command::Timeline(source, steps)
+		},
+		Cid::ExportPath => {
+			if let (Some(path),) = scan!(iter, String) {
+				Command::ExportPath(Some(path))
+			} else {
+				Command::ExportPath(None)
+			}
+		},
+		Cid::ExportStyle => {
+			Command::ExportStyle(tokens[1..].iter().map(|t| t.to_string()).collect())
+		},
+		Cid::Render => {
+			if let (Some(path), width) = scan!(iter, String, u32) {
+				// the width can be left out
+				let color = tokens.get(if width.is_some() { 3 } else { 2 });
+				match color.map(|name| NodeMetric::parse(name)).transpose() {
+					Ok(color) => Command::Render(path, width.unwrap_or(800), color),
+					Err(e) => Command::Error(e.to_string())
+				}
+			} else {
+				error
+			}
+		},
+		Cid::MoveNodes => {
+			if let (Some(x), Some(y), Some(z)) = scan!(iter, f32, f32, f32) {
+				Command::MoveNodes(x, y, z)
+			} else {
+				error
+			}
+		},
+		Cid::MoveNode => {
+			if let (Some(id), Some(x), Some(y), Some(z)) = scan!(iter, u32, f32, f32, f32) {
+				Command::MoveNode(id, x, y, z)
+			} else {
+				error
+			}
+		},
+		Cid::AddLine => {
+			let mut iter1 = iter.clone();
+			let mut iter2 = iter.clone();
+			if let (Some(count), Some(close)) = scan!(iter1, u32, bool) {
+				Command::AddLine(count, close)
+			} else if let (Some(count),) = scan!(iter2, u32) {
+				Command::AddLine(count, false)
+			} else {
+				error
+			}
+		},
+		Cid::AddTree => {
+			let mut iter1 = iter.clone();
+			let mut iter2 = iter.clone();
+			if let (Some(count), Some(intra)) = scan!(iter1, u32, u32) {
+				Command::AddTree(count, intra)
+			} else if let (Some(count),) = scan!(iter2, u32) {
+				Command::AddTree(count, 0)
 			} else {
 				error
 			}
@@ -404,6 +1506,62 @@ fn parse_command(input: &str) -> Command {
 				error
 			}
 		},
+		Cid::AddLatticeTri => {
+			if let (Some(x_count), Some(y_count)) = scan!(iter, u32, u32) {
+				Command::AddLatticeTri(x_count, y_count)
+			} else {
+				error
+			}
+		},
+		Cid::AddLatticeHex => {
+			if let (Some(x_count), Some(y_count)) = scan!(iter, u32, u32) {
+				Command::AddLatticeHex(x_count, y_count)
+			} else {
+				error
+			}
+		},
+		Cid::AddLattice3d => {
+			if let (Some(x_count), Some(y_count), Some(z_count)) = scan!(iter, u32, u32, u32) {
+				Command::AddLattice3d(x_count, y_count, z_count)
+			} else {
+				error
+			}
+		},
+		Cid::AddTorus => {
+			if let (Some(x_count), Some(y_count)) = scan!(iter, u32, u32) {
+				Command::AddTorus(x_count, y_count)
+			} else {
+				error
+			}
+		},
+		Cid::AddRgg => {
+			if let (Some(count), Some(radius)) = scan!(iter, u32, f32) {
+				Command::AddRgg(count, radius)
+			} else {
+				error
+			}
+		},
+		Cid::AddErdos => {
+			if let (Some(count), Some(p)) = scan!(iter, u32, f32) {
+				Command::AddErdos(count, p)
+			} else {
+				error
+			}
+		},
+		Cid::AddBarabasi => {
+			if let (Some(count), Some(m)) = scan!(iter, u32, u32) {
+				Command::AddBarabasi(count, m)
+			} else {
+				error
+			}
+		},
+		Cid::AddSmallworld => {
+			if let (Some(count), Some(k), Some(p)) = scan!(iter, u32, u32, f32) {
+				Command::AddSmallworld(count, k, p)
+			} else {
+				error
+			}
+		},
 		Cid::Positions => {
 			if let (Some(enable),) = scan!(iter, bool) {
 				Command::Positions(enable)
@@ -418,6 +1576,29 @@ fn parse_command(input: &str) -> Command {
 				error
 			}
 		},
+		Cid::Interconnect => {
+			match (tokens.get(1).map(|t| t.parse::<u32>()), tokens.len()) {
+				(Some(Ok(k)), 2) => Command::Interconnect(k, None),
+				(Some(Ok(k)), 4) => {
+					if let (Ok(a), Ok(b)) = (parse_list(tokens.get(2)), parse_list(tokens.get(3))) {
+						Command::Interconnect(k, Some((a, b)))
+					} else {
+						error
+					}
+				},
+				_ => error
+			}
+		},
+		Cid::OptimizePlacement => {
+			if let (Some(count), Some(range)) = scan!(iter, u32, f32) {
+				match tokens.get(3).map(|name| PlacementMethod::parse(name)).transpose() {
+					Ok(method) => Command::OptimizePlacement(count, range, method.unwrap_or(PlacementMethod::Greedy)),
+					Err(e) => Command::Error(e.to_string())
+				}
+			} else {
+				error
+			}
+		},
 		Cid::RandomizePositions => {
 			if let (Some(range),) = scan!(iter, f32) {
 				Command::RandomizePositions(range)
@@ -426,12 +1607,33 @@ fn parse_command(input: &str) -> Command {
 			}
 		},
 		Cid::Algorithm => {
-			if let (Some(algo),) = scan!(iter, String) {
+			if tokens.get(1) == Some(&"load") {
+				if let Some(path) = tokens.get(2) {
+					Command::AlgorithmLoad(path.to_string())
+				} else {
+					Command::Error("Missing plugin path".to_string())
+				}
+			} else if tokens.get(1) == Some(&"wasm") {
+				if let Some(path) = tokens.get(2) {
+					Command::AlgorithmWasm(path.to_string())
+				} else {
+					Command::Error("Missing WebAssembly module path".to_string())
+				}
+			} else if let (Some(algo),) = scan!(iter, String) {
 				Command::Algorithm(Some(algo))
 			} else {
 				Command::Algorithm(None)
 			}
 		},
+		Cid::AlgorithmNode => {
+			if tokens.len() == 1 {
+				Command::AlgorithmNode(None)
+			} else if let (Ok(ids), Some(algo)) = (parse_ranges(tokens.get(1)), tokens.get(2)) {
+				Command::AlgorithmNode(Some((ids, algo.to_string(), tokens.get(3).map(|s| s.to_string()))))
+			} else {
+				error
+			}
+		},
 		Cid::RemoveNodes => {
 			if let Ok(ids) = parse_list(tokens.get(1)) {
 				Command::RemoveNodes(ids)
@@ -439,6 +1641,63 @@ fn parse_command(input: &str) -> Command {
 				error
 			}
 		},
+		Cid::Crop => {
+			if let Ok(ids) = parse_list(tokens.get(1)) {
+				Command::Crop(ids)
+			} else {
+				error
+			}
+		},
+		Cid::CropLargestComponent => Command::CropLargestComponent,
+		Cid::Components => Command::Components,
+		Cid::MinCut => {
+			if let (Some(a), Some(b)) = scan!(iter, u32, u32) {
+				Command::MinCut(a, b)
+			} else {
+				error
+			}
+		},
+		Cid::Connectivity => Command::Connectivity,
+		Cid::Select => {
+			let expr = tokens[2.min(tokens.len())..].join(" ");
+			Command::Select(tokens.get(1).map(|s| s.to_string()), if expr.is_empty() { None } else { Some(expr) })
+		},
+		Cid::Unselect => {
+			if let (Some(name),) = scan!(iter, String) {
+				Command::Unselect(name)
+			} else {
+				error
+			}
+		},
+		Cid::MetaGet => {
+			if let (Some(id),) = scan!(iter, u32) {
+				Command::MetaGet(id, iter.next().map(|key| key.to_string()))
+			} else {
+				error
+			}
+		},
+		Cid::MetaFilter => {
+			let filter = tokens[1.min(tokens.len())..].join(" ");
+			if let Some(pos) = filter.find('=') {
+				Command::MetaFilter(filter[..pos].trim().to_string(), filter[pos + 1..].trim().to_string())
+			} else {
+				error
+			}
+		},
+		Cid::Resolve => {
+			if let Some(name) = tokens.get(1) {
+				Command::Resolve(name.to_string())
+			} else {
+				error
+			}
+		},
+		Cid::CompactIds => Command::CompactIds,
+		Cid::Cluster => {
+			match tokens.get(1).map(|name| ClusterMethod::parse(name)).transpose() {
+				Ok(method) => Command::Cluster(method),
+				Err(e) => Command::Error(e.to_string())
+			}
+		},
 		Cid::ConnectNodes => {
 			if let Ok(ids) = parse_list(tokens.get(1)) {
 				Command::ConnectNodes(ids)
@@ -446,6 +1705,13 @@ fn parse_command(input: &str) -> Command {
 				error
 			}
 		},
+		Cid::ConnectNodesOneway => {
+			if let (Some(Ok(from)), Ok(ids)) = (tokens.get(1).map(|t| t.parse::<u32>()), parse_list(tokens.get(2))) {
+				Command::ConnectNodesOneway(from, ids)
+			} else {
+				error
+			}
+		},
 		Cid::DisconnectNodes => {
 			if let Ok(ids) = parse_list(tokens.get(1)) {
 				Command::DisconnectNodes(ids)
@@ -453,6 +1719,13 @@ fn parse_command(input: &str) -> Command {
 				error
 			}
 		},
+		Cid::SetLink => {
+			if let (Some(a), Some(b), Some(key), Some(value)) = scan!(iter, u32, u32, String, String) {
+				Command::SetLink(a, b, key, value)
+			} else {
+				error
+			}
+		},
 		Cid::RemoveUnconnected => {
 			Command::RemoveUnconnected
 		},
@@ -464,9 +1737,7 @@ fn parse_command(input: &str) -> Command {
 			}
 		},
 		Cid::Error => {
-			if cmd.is_empty() {
-				Command::Ignore
-			} else if cmd.trim_start().starts_with("#") {
+			if cmd.is_empty() || cmd.trim_start().starts_with('#') {
 				Command::Ignore
 			} else {
 				Command::Error(format!("Unknown Command: {}", cmd))
@@ -475,103 +1746,637 @@ fn parse_command(input: &str) -> Command {
 	}
 }
 
-fn print_help(out: &mut std::fmt::Write) -> Result<(), MyError> {
+// keys can be prefixed with the name of the selected algorithm, e.g. vivaldi.dim
+fn algorithm_key<'a>(sim: &GlobalState, key: &'a str) -> Result<&'a str, MyError> {
+	// <group>.<key> is handled by the groups
+	if sim.algorithm_name == mixed_routing::NAME {
+		return Ok(key);
+	}
+	if let Some((prefix, rest)) = key.split_once('.') {
+		if prefix == sim.algorithm_name {
+			return Ok(rest);
+		}
+		if algorithm_names().iter().any(|name| name == prefix) {
+			return Err(MyError::new(format!("{} is not the selected algorithm", prefix)));
+		}
+	}
+	Ok(key)
+}
+
+fn print_help(out: &mut dyn std::fmt::Write, algorithm: &dyn RoutingAlgorithm) -> Result<(), MyError> {
 	for item in COMMANDS {
 		if item.1 != Cid::Error {
 			writeln!(out, "{}", item.0)?;
 		}
 	}
+
+	// settings of the selected algorithm for get/set
+	let mut parameters = String::new();
+	algorithm.get("parameters", &mut parameters)?;
+	if !parameters.is_empty() {
+		write!(out, "\nParameters of the selected algorithm (get/set <key> [<value>]):\n{}", parameters)?;
+	}
 	Ok(())
 }
 
-fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, call: AllowRecursiveCall) -> Result<(), MyError> {
-	let mut mark_links : Option<Graph> = None;
-	let mut do_init = false;
+// result of the last test
+fn test_result_json(sim: &GlobalState, samples: u32) -> serde_json::Value {
+	let mut algorithm = String::new();
+	let _ = sim.algorithm.get("name", &mut algorithm);
 
-	//println!("command: '{}'", input);
+	serde_json::json!({
+		"samples": samples,
+		"arrived": sim.test.arrived(),
+		"stretch": sim.test.stretch(),
+		"duration_ms": sim.test.duration().as_millis() as u64,
+		"steps": sim.sim_steps,
+		"nodes": sim.graph.node_count(),
+		"algorithm": algorithm
+	})
+}
 
-	let command = parse_command(input);
+// append result of the last test as CSV or JSON line
+fn append_test_log(path: &str, sim: &GlobalState, samples: u32) -> Result<(), MyError> {
+	let mut algorithm = String::new();
+	sim.algorithm.get("name", &mut algorithm)?;
 
-	match command {
-		Command::Ignore => {
-			// nothing to do
-		},
-		Command::Progress(show) => {
-			if let Some(show) = show {
-				sim.show_progress = show;
-			}
+	let line = if path.ends_with(".json") || path.ends_with(".jsonl") {
+		test_result_json(sim, samples).to_string()
+	} else {
+		format!("{},{},{},{},{},{},\"{}\"", samples, sim.test.arrived(), sim.test.stretch(),
+			sim.test.duration().as_millis(), sim.sim_steps, sim.graph.node_count(), algorithm)
+	};
 
-			writeln!(out, "show progress: {}", if sim.show_progress {
-				"enabled"
-			} else {
-				"disabled"
-			})?;
-		},
-		Command::Exit => {
+	let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+
+	// header for new CSV files
+	if file.metadata()?.len() == 0 && !line.starts_with('{') {
+		file.write_all("samples,arrived,stretch,duration_ms,steps,nodes,algorithm\n".as_bytes())?;
+	}
+
+	file.write_all(format!("{}\n", line).as_bytes())?;
+	Ok(())
+}
+
+// run one simulation step
+fn sim_step(sim: &mut GlobalState) -> Result<(), MyError> {
+	sim.timeline.step(sim.sim_steps, &mut sim.graph, &mut sim.locations);
+	sim.churn.step(&mut sim.graph, &mut sim.locations, &mut sim.algorithm);
+	sim.link_model.step(&mut sim.graph);
+	sim.energy.step(&mut sim.graph);
+
+	let mut io = Io::new(&sim.graph, Some(&sim.locations));
+	sim.algorithm.step(&mut io);
+	sim.overhead.add_step(&io);
+	let sent = io.sent();
+	sim.movements.step(&mut sim.locations);
+	sim.sim_steps += 1;
+
+	if let Err(e) = sim.metrics.record(sim.sim_steps, &sim.graph, &*sim.algorithm, sent) {
+		sim.metrics.stop();
+		return Err(MyError::new(format!("metrics: {}", e)));
+	}
+
+	if let Some(dir) = &sim.record_dir {
+		export_file(
+			&sim.graph,
+			Some(&sim.locations),
+			Some(&sim.meta),
+			Some(&*sim.algorithm),
+			Some(&sim.energy),
+			Some(&sim.test),
+			None,
+			Some(&sim.export_style),
+			&format!("{}/step_{:06}.json", dir, sim.sim_steps)
+		);
+	}
+
+	Ok(())
+}
+
+// routing decisions sampled to detect convergence
+const CONVERGE_SAMPLES : usize = 1000;
+// minimum steps without changed decisions until the algorithm counts as converged
+const CONVERGE_STABLE_STEPS : u32 = 10;
+
+// default number of flows for capacity
+const CAPACITY_FLOWS : u32 = 20;
+
+// default probe packets per link loss measurement (ETX window)
+const LINK_MODEL_PROBES : u32 = 10;
+
+// default simulation steps per timeline snapshot
+const TIMELINE_STEPS : u32 = 10;
+
+// run simulation steps until the algorithm is stable, returns the number of steps needed
+fn converge(sim: &mut GlobalState, max_steps: u32, sim_step: fn(&mut GlobalState) -> Result<(), MyError>) -> Result<Option<u32>, MyError> {
+	let node_count = sim.graph.node_count();
+	let packets : Vec<TestPacket> = if node_count < 2 {
+		vec![]
+	} else {
+		(0..CONVERGE_SAMPLES).filter_map(|_| {
+			let source = random::<usize>() % node_count;
+			let destination = random::<usize>() % node_count;
+			if source == destination {
+				None
+			} else {
+				Some(TestPacket::new(source as ID, source as ID, source as ID, destination as ID))
+			}
+		}).collect()
+	};
+
+	let mut decisions = vec![];
+	let mut last_change = 0;
+	for step in 1..=max_steps {
+		if sim.abort_simulation {
+			break;
+		}
+
+		sim_step(sim)?;
+
+		match sim.algorithm.is_converged() {
+			Some(true) => {
+				return Ok(Some(step));
+			},
+			Some(false) => {},
+			None => {
+				// Detect unchanged routing decisions. Some algorithms learn slowly,
+				// so decisions need to be stable for as long as it took to reach them.
+				let next : Vec<Option<ID>> = packets.iter().map(|p| sim.algorithm.route(p)).collect();
+				// no routes at all is not a stable state
+				if next == decisions && next.iter().any(|d| d.is_some()) {
+					if step - last_change >= u32::max(CONVERGE_STABLE_STEPS, last_change) {
+						return Ok(Some(last_change));
+					}
+				} else {
+					last_change = step;
+					decisions = next;
+				}
+			}
+		}
+	}
+	Ok(None)
+}
+
+// simulation step of the routing algorithm only, the topology does not change
+fn algorithm_step(sim: &mut GlobalState) -> Result<(), MyError> {
+	let mut io = Io::new(&sim.graph, Some(&sim.locations));
+	sim.algorithm.step(&mut io);
+	sim.overhead.add_step(&io);
+	sim.sim_steps += 1;
+	Ok(())
+}
+
+// Test the routing algorithm for every value of the command argument.
+// Every value starts with the current state, all of it is put back afterwards.
+#[allow(clippy::too_many_arguments)]
+fn sweep(sim: &mut GlobalState, command: &str, start: f64, end: f64, step: f64, samples: u32, steps: u32, path: Option<&str>) -> Result<String, MyError> {
+	let saved = sim.save();
+
+	// destructive commands of the sweep do not end up in the undo history
+	let mut history = History::new();
+	history.set_limit(0);
+	let history = std::mem::replace(&mut sim.history, history);
+
+	let result = sweep_values(sim, &saved, command, start, end, step, samples, steps, path);
+
+	sim.restore_saved(saved);
+	sim.history = history;
+
+	result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sweep_values(sim: &mut GlobalState, saved: &SavedState, command: &str, start: f64, end: f64, step: f64, samples: u32, steps: u32, path: Option<&str>) -> Result<String, MyError> {
+	let separator = if path.is_some_and(|p| p.ends_with(".tsv")) { "\t" } else { "," };
+	let mut table = String::new();
+	table.push_str(&format!("value{0}nodes{0}links{0}arrived{0}stretch\n", separator));
+
+	let mut ignore = String::new();
+	let mut i = 0;
+	loop {
+		// round to avoid values like 0.30000000000000004
+		let value = ((start + i as f64 * step) * 1e9).round() / 1e9;
+		if value > end || sim.abort_simulation || is_cancelled() {
+			break;
+		}
+		i += 1;
+
+		sim.restore_saved(saved.clone());
+
+		ignore.clear();
+		cmd_handler(&mut ignore, sim, &format!("{} {}", command, value), AllowRecursiveCall::No)?;
+		sim.algorithm.reset(sim.graph.node_count());
+		if steps > 0 {
+			cmd_handler(&mut ignore, sim, &format!("sim_step {}", steps), AllowRecursiveCall::No)?;
+		}
+
+		let algorithm = &sim.algorithm;
+		sim.test.clear();
+		sim.test.run_samples(&sim.graph, &sim.locations, |p| algorithm.route(p), samples as usize, sim.threads);
+
+		table.push_str(&format!("{1}{0}{2}{0}{3}{0}{4}{0}{5}\n", separator,
+			value, sim.graph.node_count(), sim.graph.link_count(),
+			sim.test.arrived(), sim.test.stretch()));
+	}
+
+	Ok(table)
+}
+
+// Converge and test every algorithm on the current topology with the same
+// random seed and traffic. The selected algorithm and its state are kept.
+fn compare_algorithms(out: &mut dyn std::fmt::Write, sim: &mut GlobalState, names: &[String], samples: u32) -> Result<(), MyError> {
+	let mut algorithms = vec![];
+	for name in names {
+		match create_algorithm(name) {
+			Some(algorithm) => algorithms.push((name, algorithm)),
+			None => return Err(MyError::new(format!("Unknown algorithm: {}", name)))
+		}
+	}
+
+	let old_seed = get_seed();
+	let seed = old_seed.unwrap_or_else(random::<u64>);
+	let old_overhead = std::mem::take(&mut sim.overhead);
+	let old_steps = sim.sim_steps;
+	let mut old_algorithm = None;
+
+	let mut rows = vec![];
+	for (name, algorithm) in algorithms {
+		if sim.abort_simulation || is_cancelled() {
+			break;
+		}
+
+		set_seed(Some(seed));
+		let algorithm = std::mem::replace(&mut sim.algorithm, algorithm);
+		old_algorithm.get_or_insert(algorithm);
+		sim.overhead.clear();
+		sim.algorithm.reset(sim.graph.node_count());
+
+		let converged = converge(sim, 1000, algorithm_step)?;
+		let steps = sim.overhead.steps;
+
+		// same test packets for all algorithms
+		set_seed(Some(seed));
+		let algorithm = &sim.algorithm;
+		sim.test.clear();
+		sim.test.run_samples(&sim.graph, &sim.locations, |p| algorithm.route(p), samples as usize, sim.threads);
+
+		let sizes : Vec<(usize, usize)> = (0..sim.graph.node_count())
+			.filter_map(|id| algorithm.state_size(id as ID)).collect();
+		let state = if sizes.is_empty() {
+			None
+		} else {
+			let entries = sizes.iter().map(|s| s.0).sum::<usize>() as f32 / sizes.len() as f32;
+			let bytes = sizes.iter().map(|s| s.1).sum::<usize>() as f32 / sizes.len() as f32;
+			Some((entries, bytes))
+		};
+
+		rows.push(serde_json::json!({
+			"algorithm": name,
+			"converged": converged,
+			"steps": steps,
+			"arrived": sim.test.arrived(),
+			"stretch": sim.test.stretch(),
+			"messages": sim.overhead.messages,
+			"bytes": sim.overhead.bytes,
+			"state_entries": state.map(|s| s.0),
+			"state_bytes": state.map(|s| s.1)
+		}));
+	}
+
+	set_seed(old_seed);
+	if let Some(algorithm) = old_algorithm {
+		sim.algorithm = algorithm;
+	}
+	sim.overhead = old_overhead;
+	sim.sim_steps = old_steps;
+	sim.test.clear();
+
+	if sim.json_output {
+		writeln!(out, "{}", serde_json::json!({ "samples": samples, "seed": seed, "results": rows }))?;
+		return Ok(());
+	}
+
+	writeln!(out, "{:<16} {:>9} {:>8} {:>8} {:>10} {:>12} {:>8} {:>10}",
+		"algorithm", "converged", "arrived", "stretch", "messages", "bytes", "entries", "state")?;
+	for row in &rows {
+		let float = |key: &str, precision: usize| row[key].as_f64()
+			.map_or("-".to_string(), |v| format!("{:.*}", precision, v));
+		writeln!(out, "{:<16} {:>9} {:>8} {:>8} {:>10} {:>12} {:>8} {:>10}",
+			row["algorithm"].as_str().unwrap_or(""),
+			row["converged"].as_u64().map_or("no".to_string(), |s| s.to_string()),
+			float("arrived", 1), float("stretch", 3),
+			row["messages"].as_u64().unwrap_or(0), row["bytes"].as_u64().unwrap_or(0),
+			float("state_entries", 1), float("state_bytes", 0))?;
+	}
+	writeln!(out, "samples: {}, seed: {}", samples, seed)?;
+
+	if is_cancelled() {
+		writeln!(out, "Cancelled, results are incomplete.")?;
+	}
+	Ok(())
+}
+
+// Path metrics (in hops) from all nodes or a random sample of source nodes
+fn graph_metrics(out: &mut dyn std::fmt::Write, graph: &Graph, samples: usize) -> Result<(), MyError> {
+	let node_count = graph.node_count();
+	let mut sources : Vec<ID> = (0..node_count as ID).collect();
+	let sampled = samples < node_count;
+	if sampled {
+		with_rng(|rng| sources.shuffle(rng));
+		sources.truncate(samples);
+	}
+
+	let mut diameter = 0;
+	let mut radius = u32::MAX;
+	let mut length_sum = 0u64;
+	let mut path_count = 0u64;
+	for source in &sources {
+		let dists = graph.get_hop_distances(*source);
+		let mut eccentricity = 0;
+		for dist in dists.into_iter().filter(|d| *d != 0 && *d != u32::MAX) {
+			eccentricity = u32::max(eccentricity, dist);
+			length_sum += dist as u64;
+			path_count += 1;
+		}
+		// isolated nodes are ignored
+		if eccentricity > 0 {
+			diameter = u32::max(diameter, eccentricity);
+			radius = u32::min(radius, eccentricity);
+		}
+	}
+
+	let (degree_mean, degree_variance) = graph.get_mean_link_count();
+	writeln!(out, "sources: {}{}", sources.len(), if sampled { " (sampled)" } else { "" })?;
+	if path_count > 0 {
+		writeln!(out, "diameter: {}, radius: {}", diameter, radius)?;
+		writeln!(out, "average shortest path length: {:.3}", length_sum as f32 / path_count as f32)?;
+	}
+	writeln!(out, "mean clustering coefficient: {}", graph.get_mean_clustering_coefficient())?;
+	writeln!(out, "mean link count: {} ({} variance)", degree_mean, degree_variance)?;
+	Ok(())
+}
+
+// run a command and return its output, used when embedded as library
+pub fn execute(sim: &mut GlobalState, input: &str) -> Result<String, MyError> {
+	let mut output = String::new();
+	cmd_handler(&mut output, sim, input, AllowRecursiveCall::Yes)?;
+	Ok(output)
+}
+
+/*
+ * Execute a command on the simulator state. The output is written
+ * to out, in JSON mode commands with structured results write a
+ * JSON object. Only progress meters are printed to stdout.
+ */
+pub fn cmd_handler(out: &mut dyn std::fmt::Write, sim: &mut GlobalState, input: &str, call: AllowRecursiveCall) -> Result<(), MyError> {
+	let mut mark_links : Option<Graph> = None;
+	let mut do_init = false;
+
+	//println!("command: '{}'", input);
+
+	let line = input.trim();
+
+	if line.ends_with('&') {
+		return Err(MyError::new("Background jobs are only available in the console and over the command socket".to_string()));
+	}
+
+	// the expansions below do not change the number of tokens
+	let positions = node_arguments(&input.split_whitespace().collect::<Vec<&str>>());
+
+	// node selections are resolved before parsing
+	let input = sim.selections.expand(input, &positions, &sim.graph, &sim.locations, &sim.meta)?;
+
+	// new nodes get stable ids before they can be referenced
+	sim.node_ids.update(sim.graph.node_count());
+	let input = sim.node_ids.expand(&input, &positions)?;
+
+	// node names are accepted wherever node ids are expected
+	let input = sim.meta.expand_names(&input, &positions)?;
+
+	let command = parse_command(&input);
+
+	// these only reset the algorithm state, the graph and its pending link failures stay valid
+	let keep_churn = matches!(command, Command::ResetSim | Command::Algorithm(_)
+		| Command::AlgorithmNode(_) | Command::AlgorithmLoad(_) | Command::AlgorithmWasm(_));
+
+	match command {
+		Command::Ignore => {
+			// nothing to do
+		},
+		Command::Progress(show) => {
+			if let Some(show) = show {
+				sim.show_progress = show;
+			}
+
+			writeln!(out, "show progress: {}", if sim.show_progress {
+				"enabled"
+			} else {
+				"disabled"
+			})?;
+		},
+		Command::Cancel => {
+			// handled by the socket connection while a command is running
+			writeln!(out, "Nothing to cancel.")?;
+		},
+		Command::Jobs => {
+			jobs::list(out)?;
+		},
+		Command::JobStatus(id) => {
+			jobs::status(out, id)?;
+		},
+		Command::JobCancel(id) => {
+			jobs::cancel(out, id)?;
+		},
+		Command::Exit => {
 			sim.abort_simulation = true;
-			send_dummy_to_socket(&sim.cmd_address);
-			send_dummy_to_stdin();
 		},
-		Command::ShowMinimumSpanningTree => {
-			let mst = sim.graph.minimum_spanning_tree();
-			if mst.node_count() > 0 {
-				mark_links = Some(mst);
+		Command::ShowMinimumSpanningTree(weight) => {
+			if let Some(weight) = weight {
+				sim.mst.set_weight(weight);
+			}
+			sim.mst.update(&sim.graph);
+			sim.mst.get_info(out)?;
+			if sim.mst.link_count() > 0 {
+				mark_links = Some(sim.mst.tree_links(&sim.graph));
 			}
 		},
-		Command::CropMinimumSpanningTree => {
-			// mst is only a uni-directional graph...!!
-			let mst = sim.graph.minimum_spanning_tree();
-			if mst.node_count() > 0 {
-				sim.graph = mst;
+		Command::CropMinimumSpanningTree(weight) => {
+			if let Some(weight) = weight {
+				sim.mst.set_weight(weight);
 			}
+			sim.mst.update(&sim.graph);
+			sim.mst.get_info(out)?;
+			sim.checkpoint(line);
+			sim.mst.crop(&mut sim.graph);
 		},
 		Command::Error(msg) => {
-			//TODO: return Result error
-			writeln!(out, "{}", msg)?;
+			return Err(MyError::new(msg));
 		},
 		Command::Help => {
-			print_help(out)?;
+			print_help(out, &*sim.algorithm)?;
 		},
-		Command::Get(key) => {
+		Command::Get(Some(id), key) => {
+			if id as usize >= sim.graph.node_count() {
+				return Err(MyError::new(format!("Invalid node id: {}", id)));
+			}
 			let mut buf = String::new();
-			sim.algorithm.get(&key, &mut buf)?;
+			sim.algorithm.get_node(id, algorithm_key(sim, &key)?, &mut buf)?;
 			writeln!(out, "{}", buf)?;
 		},
-		Command::Set(key, value) => {
-			sim.algorithm.set(&key, &value)?;
+		Command::Set(Some(id), key, value) => {
+			if id as usize >= sim.graph.node_count() {
+				return Err(MyError::new(format!("Invalid node id: {}", id)));
+			}
+			let key = algorithm_key(sim, &key)?.to_string();
+			sim.algorithm.set_node(id, &key, &value)?;
+		},
+		Command::Get(None, key) => {
+			let mut buf = String::new();
+			if let Some(key) = key.strip_prefix("events.") {
+				sim.events.get(key, &mut buf)?;
+			} else if let Some(key) = key.strip_prefix("test.") {
+				sim.test.get(key, &mut buf)?;
+			} else {
+				sim.algorithm.get(algorithm_key(sim, &key)?, &mut buf)?;
+			}
+			writeln!(out, "{}", buf)?;
+		},
+		Command::Set(None, key, value) => {
+			if let Some(key) = key.strip_prefix("events.") {
+				sim.events.set(key, &value)?;
+			} else if let Some(key) = key.strip_prefix("test.") {
+				sim.test.set(key, &value)?;
+			} else {
+				let key = algorithm_key(sim, &key)?.to_string();
+				sim.algorithm.set(&key, &value)?;
+			}
 		},
 		Command::GraphInfo => {
 			let node_count = sim.graph.node_count();
 			let link_count = sim.graph.link_count();
 			let avg_node_degree = sim.graph.get_avg_node_degree();
+			let unidirectional = sim.graph.unidirectional_link_count();
 
-			writeln!(out, "nodes: {}, links: {}", node_count, link_count)?;
-			writeln!(out, "locations: {}, metadata: {}", sim.locations.data.len(), sim.meta.data.len())?;
-			writeln!(out, "average node degree: {}", avg_node_degree)?;
-/*
-			if (verbose) {
-				let mean_clustering_coefficient = state.graph.get_mean_clustering_coefficient();
-				let mean_link_count = state.graph.get_mean_link_count();
-				let mean_link_distance = state.get_mean_link_distance();
-				writeln!(out, "mean clustering coefficient: {}", mean_clustering_coefficient)?;
-				writeln!(out, "mean link count: {} ({} variance)", mean_link_count.0, mean_link_count.1)?;
-				writeln!(out, "mean link distance: {} ({} variance)", mean_link_distance.0, mean_link_distance.1)?;
+			if sim.json_output {
+				writeln!(out, "{}", serde_json::json!({
+					"nodes": node_count,
+					"links": link_count,
+					"unidirectional_links": unidirectional,
+					"locations": sim.locations.data.len(),
+					"metadata": sim.meta.data.len(),
+					"average_node_degree": avg_node_degree
+				}))?;
+			} else {
+				writeln!(out, "nodes: {}, links: {}", node_count, link_count)?;
+				if unidirectional > 0 {
+					writeln!(out, "unidirectional links: {}", unidirectional)?;
+				}
+				writeln!(out, "locations: {}, metadata: {}", sim.locations.data.len(), sim.meta.data.len())?;
+				writeln!(out, "average node degree: {}", avg_node_degree)?;
 			}
-*/
+		},
+		Command::GraphMetrics(samples) => {
+			graph_metrics(out, &sim.graph, samples as usize)?;
 		},
 		Command::SimInfo => {
-			write!(out, " algo: ")?;
-			sim.algorithm.get("name", out)?;
+			if sim.json_output {
+				let mut algorithm = String::new();
+				sim.algorithm.get("name", &mut algorithm)?;
+				writeln!(out, "{}", serde_json::json!({
+					"algorithm": algorithm,
+					"algorithm_id": sim.algorithm_name,
+					"steps": sim.sim_steps,
+					"seed": get_seed()
+				}))?;
+			} else {
+				write!(out, " algo: ")?;
+				sim.algorithm.get("name", out)?;
 
-			writeln!(out, "\n steps: {}", sim.sim_steps)?;
+				writeln!(out, "\n steps: {}", sim.sim_steps)?;
+			}
+		},
+		Command::Seed(seed) => {
+			if let Some(seed) = seed {
+				set_seed(seed);
+			}
+			if sim.json_output {
+				writeln!(out, "{}", serde_json::json!({ "seed": get_seed() }))?;
+			} else {
+				match get_seed() {
+					Some(seed) => writeln!(out, "seed: {}", seed)?,
+					None => writeln!(out, "seed: random")?
+				}
+			}
+		},
+		Command::Output(json) => {
+			if let Some(json) = json {
+				sim.json_output = json;
+			}
+			writeln!(out, "output: {}", if sim.json_output { "json" } else { "text" })?;
 		},
 		Command::ClearGraph => {
+			sim.checkpoint(line);
 			sim.graph.clear();
 			do_init = true;
 			writeln!(out, "done")?;
 		},
+		Command::Undo => {
+			if let Some(command) = sim.undo() {
+				writeln!(out, "undo: {}", command)?;
+				writeln!(out, "nodes: {}, links: {}", sim.graph.node_count(), sim.graph.link_count())?;
+				do_init = true;
+			} else {
+				writeln!(out, "nothing to undo")?;
+			}
+		},
+		Command::Workspace(None) => {
+			sim.workspaces.print(out, sim)?;
+		},
+		Command::Workspace(Some((action, name))) => {
+			match action.as_str() {
+				"new" => Workspaces::create(sim, &name)?,
+				"copy" => Workspaces::copy(sim, &name)?,
+				"switch" => Workspaces::switch(sim, &name)?,
+				_ => {
+					sim.workspaces.remove(&name)?;
+					writeln!(out, "Removed workspace {}", name)?;
+					return Ok(());
+				}
+			}
+			// the algorithm state of the workspace is kept
+			sim.test.clear();
+			sim.marked_links = None;
+			sim.churn.clear();
+			sim.energy.reset(sim.graph.node_count());
+			sim.overhead.clear();
+			writeln!(out, "workspace: {}, nodes: {}, links: {}, algorithm: {}", sim.workspaces.current(),
+				sim.graph.node_count(), sim.graph.link_count(), sim.algorithm_name)?;
+		},
+		Command::Redo => {
+			if let Some(command) = sim.redo() {
+				writeln!(out, "redo: {}", command)?;
+				writeln!(out, "nodes: {}, links: {}", sim.graph.node_count(), sim.graph.link_count())?;
+				do_init = true;
+			} else {
+				writeln!(out, "nothing to redo")?;
+			}
+		},
+		Command::History(limit) => {
+			if let Some(limit) = limit {
+				sim.history.set_limit(limit as usize);
+			}
+			writeln!(out, "limit: {}", sim.history.limit())?;
+			for command in sim.history.undo_commands() {
+				writeln!(out, "  undo: {}", command)?;
+			}
+			for command in sim.history.redo_commands() {
+				writeln!(out, "  redo: {}", command)?;
+			}
+		},
 		Command::ResetSim => {
 			sim.test.clear();
+			sim.events.clear();
 			//state.graph.clear();
 			sim.sim_steps = 0;
 			do_init = true;
@@ -580,65 +2385,620 @@ fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, ca
 		Command::SimStep(count) => {
 			let mut progress = Progress::new();
 			let now = Instant::now();
-			let mut io = Io::new(&sim.graph);
 
+			let mut steps = 0;
 			for step in 0..count {
-				if sim.abort_simulation {
+				if sim.abort_simulation || is_cancelled() {
 					break;
 				}
 
-				sim.algorithm.step(&mut io);
-				sim.movements.step(&mut sim.locations);
-				sim.sim_steps += 1;
+				sim_step(sim)?;
+				steps += 1;
 
 				if sim.show_progress {
 					progress.update((count + 1) as usize, step as usize);
 				}
 			}
 
-			let duration = now.elapsed();
-
-			writeln!(out, "Run {} simulation steps, duration: {}", count, fmt_duration(duration))?;
-		},
-		Command::Test(samples) => {
-			fn run_test(out: &mut std::fmt::Write, test: &mut EvalPaths, graph: &Graph, algo: &Box<RoutingAlgorithm>, samples: u32)
-				-> Result<(), std::fmt::Error>
-			{
-				test.clear();
-				test.run_samples(graph, |p| algo.route(&p), samples as usize);
-				writeln!(out, "samples: {},  arrived: {:.1}, stretch: {}, duration: {}",
-					samples,
-					test.arrived(), test.stretch(),
-					fmt_duration(test.duration())
-				)
+			let duration = now.elapsed();
+
+			if steps < count && is_cancelled() {
+				writeln!(out, "Cancelled after {} of {} simulation steps, duration: {}", steps, count, fmt_duration(duration))?;
+			} else {
+				writeln!(out, "Run {} simulation steps, duration: {}", count, fmt_duration(duration))?;
+			}
+		},
+		Command::Converge(max_steps) => {
+			let now = Instant::now();
+			let steps = sim.sim_steps;
+			let converged = converge(sim, max_steps, sim_step)?;
+			let duration = fmt_duration(now.elapsed());
+			match converged {
+				Some(n) => writeln!(out, "Converged after {} steps (ran {} steps), duration: {}", n, sim.sim_steps - steps, duration)?,
+				None => writeln!(out, "Not converged after {} steps, duration: {}", sim.sim_steps - steps, duration)?
+			}
+		},
+		Command::MoveModel(name, args) => {
+			if let Some(name) = name {
+				sim.movements.set_model(&name, &args)?;
+			}
+			writeln!(out, "move model: {}", sim.movements.get_model())?;
+		},
+		Command::RadioModel(name, args) => {
+			if let Some(name) = name {
+				sim.radio.set_model(&name, &args)?;
+			}
+			writeln!(out, "radio model: {}", sim.radio.get_model())?;
+		},
+		Command::ChurnLinks(fail, recover) => {
+			match (fail, recover) {
+				(Some(ref fail), _) if fail == "off" => {
+					sim.churn.disable_links(&mut sim.graph);
+				},
+				(Some(fail), Some(recover)) => {
+					match fail.parse::<f32>() {
+						Ok(fail) => sim.churn.set_links(fail, recover),
+						Err(_) => return Err(MyError::new(format!("Invalid probability: {}", fail)))
+					}
+				},
+				(None, None) => {},
+				_ => {
+					return Err(MyError::new("Expected fail and recover probability.".to_string()));
+				}
+			}
+			writeln!(out, "link churn: {}", sim.churn.get_links())?;
+		},
+		Command::LinkModel(model, good_bad, bad_good, loss_good, loss_bad, probes) => {
+			match (model.as_deref(), good_bad, bad_good, loss_good, loss_bad) {
+				(Some("off"), _, _, _, _) => {
+					sim.link_model.disable(&mut sim.graph);
+				},
+				(Some("gilbert"), Some(good_bad), Some(bad_good), Some(loss_good), Some(loss_bad)) => {
+					sim.link_model.set_gilbert(good_bad, bad_good, loss_good, loss_bad, probes.unwrap_or(LINK_MODEL_PROBES))?;
+				},
+				(None, _, _, _, _) => {},
+				_ => {
+					return Err(MyError::new("Usage: link_model [gilbert <good_bad> <bad_good> <loss_good> <loss_bad> [<probes>]|off]".to_string()));
+				}
+			}
+			writeln!(out, "link model: {}", sim.link_model.get_model())?;
+		},
+		Command::AssignChannels(channels, range) => {
+			if range.is_some() {
+				sim.interference.set_range(range);
+			}
+			let interfering = sim.interference.assign_channels(&mut sim.graph, &sim.locations, channels)?;
+			writeln!(out, "interfering link pairs on the same channel: {}", interfering)?;
+			sim.interference.print_capacity(out, &sim.graph, &sim.locations)?;
+		},
+		Command::Channels(range) => {
+			match range.as_deref() {
+				Some("hops") => sim.interference.set_range(None),
+				Some(range) => match range.parse::<f32>() {
+					Ok(range) if range >= 0.0 => sim.interference.set_range(Some(range)),
+					_ => return Err(MyError::new(format!("Invalid interference range: {}", range)))
+				},
+				None => {}
+			}
+			sim.interference.print_capacity(out, &sim.graph, &sim.locations)?;
+		},
+		Command::Capacity(count) => {
+			let flows = create_flows(&sim.test.traffic, sim.graph.node_count(), count.unwrap_or(CAPACITY_FLOWS) as usize);
+			// bandwidth shared with interfering links, reduced by retransmissions
+			let mut capacities = sim.interference.effective_bandwidth(&sim.graph, &sim.locations);
+			for ((from, to), capacity) in capacities.iter_mut() {
+				*capacity *= sim.graph.get_link(*from, *to).map_or(0.0, |link| link.delivery_ratio());
+			}
+			let flows = max_min_fair(&sim.graph, &*sim.algorithm, &flows, &capacities);
+			print_flows(out, &flows, sim.json_output)?;
+		},
+		Command::ChurnNodes(args) => {
+			if let Some((leave, join, range)) = args {
+				// joining nodes connect to nodes in mean link distance by default
+				let range = range.unwrap_or_else(|| sim.get_mean_link_distance().0);
+				let range = if range.is_nan() { 0.0 } else { range };
+				sim.churn.set_nodes(leave, join, range);
+			}
+			writeln!(out, "node churn: {}", sim.churn.get_nodes())?;
+		},
+		Command::FailNode(ids, steps) => {
+			let count = sim.churn.fail_links(&mut sim.graph, steps, |link| ids.contains(&link.from) || ids.contains(&link.to));
+			writeln!(out, "Removed {} links. {}", count, sim.churn.get_failures())?;
+		},
+		Command::FailLink(from, to, steps) => {
+			let count = sim.churn.fail_links(&mut sim.graph, steps, |link| {
+				(link.from == from && link.to == to) || (link.from == to && link.to == from)
+			});
+			writeln!(out, "Removed {} links. {}", count, sim.churn.get_failures())?;
+		},
+		Command::FailRegion(x, y, radius, steps) => {
+			let locations = &sim.locations;
+			let in_region = |id: ID| {
+				locations.get_position(id).is_some_and(|pos| {
+					((pos[0] - x).powi(2) + (pos[1] - y).powi(2)).sqrt() <= radius
+				})
+			};
+			let count = sim.churn.fail_links(&mut sim.graph, steps, |link| in_region(link.from) || in_region(link.to));
+			writeln!(out, "Removed {} links. {}", count, sim.churn.get_failures())?;
+		},
+		Command::EnergyInit(capacity, idle, tx) => {
+			sim.energy.init(capacity, idle.unwrap_or(0.01), tx.unwrap_or(0.1), sim.graph.node_count());
+			sim.energy.get_info(out)?;
+		},
+		Command::EnergySolar(power, day_steps) => {
+			sim.energy.set_solar(power, day_steps);
+			sim.energy.get_info(out)?;
+		},
+		Command::EnergyInfo => {
+			sim.energy.get_info(out)?;
+		},
+		Command::Traffic(mut args) => {
+			// use gateway nodes if none are given
+			if args.len() == 1 && args[0] == "gateway" && !sim.gateways.is_empty() {
+				args.push(sim.gateways.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(","));
+			}
+
+			if !args.is_empty() {
+				let args : Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+				sim.test.traffic = Traffic::parse(&args, sim.graph.node_count())?;
+			}
+			writeln!(out, "traffic: {}", sim.test.traffic.name())?;
+		},
+		Command::SetGateway(ids) => {
+			match ids {
+				Some(ref ids) if ids == "none" => {
+					sim.gateways.clear();
+				},
+				Some(ids) => {
+					let ids = parse_id_list(&ids)?;
+					if let Some(id) = ids.iter().find(|id| **id as usize >= sim.graph.node_count()) {
+						return Err(MyError::new(format!("Invalid node id: {}", id)));
+					}
+					sim.gateways = ids;
+				},
+				None => {}
+			}
+			let ids : Vec<String> = sim.gateways.iter().map(|id| id.to_string()).collect();
+			writeln!(out, "gateways: {}", ids.join(","))?;
+		},
+		Command::TestGateways => {
+			if sim.gateways.is_empty() {
+				return Err(MyError::new("No gateways set.".to_string()));
+			}
+
+			let algorithm = &sim.algorithm;
+			let load = sim.test.run_gateways(&sim.graph, &sim.locations, |p| algorithm.route(p), &sim.gateways);
+			writeln!(out, "nodes: {}, arrived: {:.1}, connectivity: {:.1}, stretch: {}, duration: {}",
+				sim.graph.node_count().saturating_sub(sim.gateways.len()),
+				sim.test.arrived(), sim.test.connectivity(), sim.test.stretch(),
+				fmt_duration(sim.test.duration())
+			)?;
+
+			let total = load.iter().sum::<u32>();
+			let min = load.iter().min().cloned().unwrap_or(0);
+			let max = load.iter().max().cloned().unwrap_or(0);
+			writeln!(out, "gateway load: min {}, max {}, mean {:.1}", min, max, total as f32 / load.len() as f32)?;
+			if let Some((latency, latency_stretch, delivery)) = sim.test.link_metrics() {
+				writeln!(out, "latency: {:.2} ms, latency stretch: {:.3}, delivery probability: {:.3}",
+					latency, latency_stretch, delivery)?;
+			}
+			for (gateway, load) in sim.gateways.iter().zip(load.iter()) {
+				writeln!(out, "  gateway {}: {} packets", gateway, load)?;
+			}
+		},
+		Command::Threads(threads) => {
+			if let Some(threads) = threads {
+				sim.threads = u32::max(threads, 1) as usize;
+			}
+			writeln!(out, "threads: {}", sim.threads)?;
+		},
+		Command::OverheadInfo(reset) => {
+			if reset {
+				sim.overhead.clear();
+			}
+
+			let overhead = &sim.overhead;
+			let nodes = sim.graph.node_count() as f32;
+			let steps = overhead.steps as f32;
+			writeln!(out, "steps: {}, messages: {}, bytes: {}", overhead.steps, overhead.messages, overhead.bytes)?;
+			if overhead.steps > 0 && nodes > 0.0 {
+				writeln!(out, "per step: {:.1} messages (max {}), {:.1} bytes",
+					overhead.messages as f32 / steps, overhead.max_step_messages, overhead.bytes as f32 / steps)?;
+				writeln!(out, "per node and step: {:.2} messages, {:.1} bytes",
+					overhead.messages as f32 / steps / nodes, overhead.bytes as f32 / steps / nodes)?;
+			}
+		},
+		Command::LoadInfo(count) => {
+			if !sim.test.has_load() {
+				return Err(MyError::new("No test results.".to_string()));
+			}
+
+			writeln!(out, "nodes (forwarded routes):")?;
+			for (id, load) in sim.test.top_nodes(count as usize) {
+				writeln!(out, "  {}: {}", id, load)?;
+			}
+			writeln!(out, "links (routes):")?;
+			for (from, to, load) in sim.test.top_links(count as usize) {
+				writeln!(out, "  {} => {}: {}", from, to, load)?;
+			}
+		},
+		Command::MoveTrace(path) => {
+			// trace positions need locations
+			let node_count = sim.graph.node_count();
+			sim.locations.init_positions(node_count, [0.0, 0.0, 0.0]);
+			sim.movements.load_trace(&path)?;
+			writeln!(out, "move model: {}", sim.movements.get_model())?;
+		},
+		Command::Send(from, to, count) => {
+			let node_count = sim.graph.node_count();
+			for id in [from, to] {
+				if id as usize >= node_count {
+					return Err(MyError::new(format!("Invalid node id: {}", id)));
+				}
+			}
+			sim.events.send(from, to, count, node_count);
+			sim.events.run(&sim.graph, &*sim.algorithm);
+			writeln!(out, "delivered: {}/{}", sim.events.stats.delivered, sim.events.stats.sent)?;
+		},
+		Command::Stats(clear) => {
+			if clear {
+				sim.events.clear();
+			} else {
+				sim.events.write_stats(out)?;
+			}
+		},
+		Command::Test(samples, histogram, source_routing) => {
+			#[allow(clippy::too_many_arguments)]
+			fn run_test(out: &mut dyn std::fmt::Write, test: &mut EvalPaths, graph: &Graph, locations: &Locations,
+				algo: &dyn RoutingAlgorithm, samples: u32, threads: usize, headers: Option<&HeaderStats>) -> Result<(), std::fmt::Error>
+			{
+				test.clear();
+				if let Some(headers) = headers {
+					test.run_samples(graph, locations, |p| headers.route(graph, algo, p), samples as usize, threads);
+				} else {
+					test.run_samples(graph, locations, |p| algo.route(p), samples as usize, threads);
+				}
+				write!(out, "samples: {},  arrived: {:.1}, stretch: {}, duration: {}",
+					samples,
+					test.arrived(), test.stretch(),
+					fmt_duration(test.duration())
+				)?;
+
+				// message costs, if the algorithm keeps track of them
+				let mut overhead = String::new();
+				algo.get("overhead", &mut overhead)?;
+				if !overhead.is_empty() {
+					write!(out, ", overhead: {}", overhead)?;
+				}
+				writeln!(out)?;
+
+				let s = test.stretch_percentiles();
+				let h = test.hops_percentiles();
+				writeln!(out, "stretch p50: {}, p90: {}, p99: {}, max: {}", s[0], s[1], s[2], s[3])?;
+				if test.stretch_dropped() > 0 {
+					writeln!(out, "stretch samples dropped (not finite): {}", test.stretch_dropped())?;
+				}
+				writeln!(out, "hops p50: {}, p90: {}, p99: {}, max: {}", h[0], h[1], h[2], h[3])?;
+				if test.paths_no_reference() > 0 {
+					writeln!(out, "paths without reference (node without position): {}", test.paths_no_reference())?;
+				}
+
+				// only if links have latency/loss attributes
+				if let Some((latency, latency_stretch, delivery)) = test.link_metrics() {
+					writeln!(out, "latency: {:.2} ms, latency stretch: {:.3}, delivery probability: {:.3}",
+						latency, latency_stretch, delivery)?;
+				}
+
+				// routing state per node, if the algorithm reports it
+				let sizes : Vec<(usize, usize)> = (0..graph.node_count())
+					.filter_map(|id| algo.state_size(id as ID)).collect();
+				if !sizes.is_empty() {
+					let entries : Vec<usize> = sizes.iter().map(|s| s.0).collect();
+					let bytes : Vec<usize> = sizes.iter().map(|s| s.1).collect();
+					let stats = |v: &[usize]| (
+						v.iter().min().cloned().unwrap_or(0),
+						v.iter().sum::<usize>() as f32 / v.len() as f32,
+						v.iter().max().cloned().unwrap_or(0)
+					);
+					let (e_min, e_avg, e_max) = stats(&entries);
+					let (b_min, b_avg, b_max) = stats(&bytes);
+					writeln!(out, "state entries min: {}, avg: {:.1}, max: {}, bytes min: {}, avg: {:.1}, max: {}",
+						e_min, e_avg, e_max, b_min, b_avg, b_max)?;
+				}
+
+				if let Some(headers) = headers {
+					headers.print(out, graph.node_count())?;
+				}
+				Ok(())
+			}
+			let headers = if source_routing { Some(HeaderStats::new()) } else { None };
+			sim.test.show_progress(sim.show_progress);
+			if sim.json_output {
+				let mut text = String::new();
+				run_test(&mut text, &mut sim.test, &sim.graph, &sim.locations, &*sim.algorithm, samples, sim.threads, headers.as_ref())?;
+				let mut result = test_result_json(sim, samples);
+				result["stretch_percentiles"] = serde_json::json!(sim.test.stretch_percentiles());
+				result["hops_percentiles"] = serde_json::json!(sim.test.hops_percentiles());
+				result["stretch_dropped"] = serde_json::json!(sim.test.stretch_dropped());
+				result["paths_no_reference"] = serde_json::json!(sim.test.paths_no_reference());
+				if let Some((latency, latency_stretch, delivery)) = sim.test.link_metrics() {
+					result["latency_ms"] = serde_json::json!(latency);
+					result["latency_stretch"] = serde_json::json!(latency_stretch);
+					result["delivery_probability"] = serde_json::json!(delivery);
+				}
+				if let Some(headers) = &headers {
+					result["source_routing"] = headers.to_json(sim.graph.node_count());
+				}
+				writeln!(out, "{}", result)?;
+			} else {
+				run_test(out, &mut sim.test, &sim.graph, &sim.locations, &*sim.algorithm, samples, sim.threads, headers.as_ref())?;
+			}
+
+			if is_cancelled() {
+				writeln!(out, "Cancelled, results are incomplete.")?;
+				return Ok(());
+			}
+
+			if let Some(path) = histogram {
+				sim.test.write_histogram(&path)?;
+				writeln!(out, "Wrote histogram to {}", path)?;
+			}
+
+			if let Some(path) = &sim.test_log {
+				append_test_log(path, sim, samples)?;
+			}
+		},
+		Command::TestMultipath(samples) => {
+			let algorithm = &sim.algorithm;
+			sim.test.show_progress(sim.show_progress);
+			sim.test.run_samples_multipath(&sim.graph, &sim.locations, |p| algorithm.route_multi(p), samples as usize, sim.threads);
+			let test = &sim.test;
+			writeln!(out, "samples: {}, arrived (any path): {:.1}, flow arrived: {:.1}, stretch (best path): {}, duration: {}",
+				samples, test.arrived(), test.flow_arrived(), test.stretch(), fmt_duration(test.duration()))?;
+			writeln!(out, "next hops per node: {:.2}, link load max/mean: {:.2}", test.next_hops(), test.load_spread())?;
+
+			let s = test.stretch_percentiles();
+			let h = test.hops_percentiles();
+			writeln!(out, "stretch p50: {}, p90: {}, p99: {}, max: {}", s[0], s[1], s[2], s[3])?;
+			if test.stretch_dropped() > 0 {
+				writeln!(out, "stretch samples dropped (not finite): {}", test.stretch_dropped())?;
+			}
+			writeln!(out, "hops p50: {}, p90: {}, p99: {}, max: {}", h[0], h[1], h[2], h[3])?;
+			if test.paths_no_reference() > 0 {
+				writeln!(out, "paths without reference (node without position): {}", test.paths_no_reference())?;
+			}
+
+			if is_cancelled() {
+				writeln!(out, "Cancelled, results are incomplete.")?;
+			}
+		},
+		Command::Compare(names, samples) => {
+			compare_algorithms(out, sim, &names, samples)?;
+		},
+		Command::TestLog(path) => {
+			match path {
+				Some(ref path) if path == "off" => {
+					sim.test_log = None;
+				},
+				Some(path) => {
+					sim.test_log = Some(path);
+				},
+				None => {}
+			}
+			writeln!(out, "test log: {}", sim.test_log.as_ref().map_or("off", |p| p.as_str()))?;
+		},
+		Command::Sweep(command, start, end, step, samples, steps, path) => {
+			let table = sweep(sim, &command, start, end, step, samples, steps, path.as_deref())?;
+
+			if let Some(path) = path {
+				std::fs::write(&path, table)?;
+				writeln!(out, "Wrote {}", path)?;
+			} else {
+				write!(out, "{}", table)?;
+			}
+		},
+		Command::Debug(from, to) => {
+			let node_count = sim.graph.node_count() as u32;
+			if (from < node_count) && (to < node_count) {
+				sim.debug_path.init(from, to);
+				writeln!(out, "Init path debugger: {} => {}", from, to)?;
+			} else {
+				writeln!(out, "Invalid path: {} => {}", from, to)?;
+			}
+		},
+		Command::DebugStep(steps) => {
+			fn run_test(out: &mut dyn std::fmt::Write, debug_path: &mut DebugPath, graph: &Graph, algo: &dyn RoutingAlgorithm)
+				-> Result<(), MyError>
+			{
+				debug_path.step(out, graph, |p| algo.route(p))
+			}
+
+			for _ in 0..steps {
+				run_test(out, &mut sim.debug_path, &sim.graph, &*sim.algorithm)?;
+			}
+		}
+		Command::DebugTrace(from, to) => {
+			let node_count = sim.graph.node_count() as u32;
+			if (from < node_count) && (to < node_count) {
+				let path = sim.debug_path.trace(out, &sim.graph, &sim.locations, &*sim.algorithm, from, to)?;
+				let hops : Vec<(ID, ID)> = path.windows(2).map(|w| (w[0], w[1])).collect();
+				mark_links = Some(sim.graph.filter_links(|link|
+					hops.contains(&(link.from, link.to)) || hops.contains(&(link.to, link.from))
+				));
+			} else {
+				writeln!(out, "Invalid path: {} => {}", from, to)?;
+			}
+		}
+		Command::GraphDiff(ref path) => {
+			let mut graph = Graph::new();
+			let mut locations = Locations::new();
+			let mut meta = Meta::new();
+			let summary = import_file(&mut graph, Some(&mut locations), Some(&mut meta), path.as_str(), sim.show_progress)?;
+			let diff = GraphDiff::new(&sim.graph, &sim.locations, &sim.meta, &graph, &locations, &meta);
+			if summary.has_errors() && !sim.json_output {
+				summary.print(out)?;
+			}
+			if sim.json_output {
+				writeln!(out, "{}", diff.to_json())?;
+			} else if diff.is_empty() {
+				writeln!(out, "no differences")?;
+			} else {
+				diff.print(out)?;
+			}
+		},
+		Command::GraphValidate(directed, fix) => {
+			let validation = Validation::new(&sim.graph, &sim.locations, &sim.meta, directed);
+			let fixed = if fix && validation.fixable_count() > 0 {
+				sim.checkpoint(line);
+				validation.fix(&mut sim.graph, &mut sim.locations, &mut sim.meta);
+				do_init = true;
+				validation.fixable_count()
+			} else {
+				0
+			};
+
+			if sim.json_output {
+				let mut json = validation.to_json();
+				json["fixed"] = serde_json::json!(fixed);
+				writeln!(out, "{}", json)?;
+			} else {
+				validation.print(out)?;
+				if fixed > 0 {
+					writeln!(out, "fixed {} problems (nodes: {}, links: {})", fixed, sim.graph.node_count(), sim.graph.link_count())?;
+				}
+			}
+		},
+		Command::Import(ref path) => {
+			// keep the current state if the import fails or is cancelled
+			let mut graph = sim.graph.clone();
+			let mut locations = sim.locations.clone();
+			let mut meta = sim.meta.clone();
+			let summary = import_file(&mut graph, Some(&mut locations), Some(&mut meta), path.as_str(), sim.show_progress)?;
+			sim.graph = graph;
+			sim.locations = locations;
+			sim.meta = meta;
+			do_init = true;
+			if summary.has_errors() {
+				summary.print(out)?;
+			}
+			writeln!(out, "Import done: {}", path)?;
+		},
+		Command::SaveState(path) => {
+			save_state(sim, &path)?;
+			writeln!(out, "State saved: {}", path)?;
+		},
+		Command::LoadState(path) => {
+			// the algorithm state is restored, no reset
+			load_state(sim, &path)?;
+			writeln!(out, "State loaded: {} (nodes: {}, links: {}, steps: {})",
+				path, sim.graph.node_count(), sim.graph.link_count(), sim.sim_steps)?;
+		},
+		Command::ImportMerge(path, x, y) => {
+			let mut graph = Graph::new();
+			let mut locations = Locations::new();
+			let mut meta = Meta::new();
+			let summary = import_file(&mut graph, Some(&mut locations), Some(&mut meta), path.as_str(), sim.show_progress)?;
+			if summary.has_errors() {
+				summary.print(out)?;
+			}
+
+			// names that are used in both graphs become ambiguous
+			let duplicates = (0..graph.node_count() as ID)
+				.filter_map(|id| meta.name(id))
+				.filter(|name| !sim.meta.find_name(name).is_empty())
+				.count();
+
+			sim.checkpoint(line);
+			let first = sim.graph.node_count();
+			sim.merge(&graph, &locations, &meta, [x, y, 0.0]);
+			do_init = true;
+			writeln!(out, "Merged {} nodes (ids {} to {}) and {} links from {}",
+				graph.node_count(), first, sim.graph.node_count().max(1) - 1, graph.link_count(), path)?;
+			if duplicates > 0 {
+				writeln!(out, "Warning: {} node names exist in both graphs", duplicates)?;
+			}
+		},
+		Command::ImportMeshviewer(path, graph_path) => {
+			import_meshviewer(&mut sim.graph, Some(&mut sim.locations), Some(&mut sim.meta), &path, graph_path.as_deref())?;
+			do_init = true;
+			writeln!(out, "Import done: {}", path)?;
+		},
+		Command::ImportRoads(path, spacing, range) => {
+			sim.checkpoint(line);
+			let count = sim.graph.node_count();
+			import_roads(&mut sim.graph, &mut sim.locations, &path, spacing)?;
+			if !sim.connect_new_in_range(count as ID, range) {
+				writeln!(out, "Cancelled, not all nodes are connected.")?;
+			}
+			do_init = true;
+			writeln!(out, "Import done: {} ({} nodes)", path, sim.graph.node_count() - count)?;
+		},
+		Command::ImportObstacles(path) => {
+			sim.obstacles.load(&path)?;
+			writeln!(out, "Import done: {} (polygons: {}, heightmap: {})", path,
+				sim.obstacles.polygon_count(), sim.obstacles.has_heightmap())?;
+		},
+		Command::ClearObstacles => {
+			sim.obstacles.clear();
+		},
+		Command::Record(action, dir) => {
+			match (action.as_deref(), dir) {
+				(Some("start"), Some(dir)) => {
+					std::fs::create_dir_all(&dir)?;
+					sim.record_dir = Some(dir);
+				},
+				(Some("stop"), _) => {
+					sim.record_dir = None;
+				},
+				(None, _) => {},
+				_ => {
+					return Err(MyError::new("Usage: record [start <dir>|stop]".to_string()));
+				}
+			}
+
+			match &sim.record_dir {
+				Some(dir) => writeln!(out, "recording to: {}", dir)?,
+				None => writeln!(out, "recording: disabled")?
 			}
-			sim.test.show_progress(sim.show_progress);
-			run_test(out, &mut sim.test, &sim.graph, &sim.algorithm, samples)?;
 		},
-		Command::Debug(from, to) => {
-			let node_count = sim.graph.node_count() as u32;
-			if (from < node_count) && (from < node_count) {
-				sim.debug_path.init(from, to);
-				writeln!(out, "Init path debugger: {} => {}", from, to)?;
-			} else {
-				writeln!(out, "Invalid path: {} => {}", from, to)?;
+		Command::Timeline(source, steps) => {
+			match source.as_deref() {
+				None => {
+					sim.timeline.print(out, sim.sim_steps)?;
+				},
+				Some("stop") => {
+					sim.timeline.clear();
+				},
+				Some(source) => {
+					sim.checkpoint(line);
+					let show_progress = sim.show_progress;
+					sim.timeline.load(source, steps.unwrap_or(TIMELINE_STEPS), sim.sim_steps,
+						&mut sim.graph, &mut sim.locations, &mut sim.meta, show_progress)?;
+					sim.gateways.clear();
+					sim.timeline.print(out, sim.sim_steps)?;
+					writeln!(out, "nodes: {}", sim.graph.node_count())?;
+					do_init = true;
+				}
 			}
 		},
-		Command::DebugStep(steps) => {
-			fn run_test(out: &mut std::fmt::Write, debug_path: &mut DebugPath, graph: &Graph, algo: &Box<RoutingAlgorithm>)
-				-> Result<(), MyError>
-			{
-				debug_path.step(out, graph, |p| algo.route(&p))
+		Command::Metrics(action, path, samples) => {
+			match (action.as_deref(), path) {
+				(Some("start"), Some(path)) => {
+					sim.metrics.start(&path, sim.graph.node_count(), samples.unwrap_or(100) as usize)?;
+				},
+				(Some("stop"), _) => {
+					sim.metrics.stop();
+				},
+				(None, _) => {},
+				_ => {
+					return Err(MyError::new("Usage: metrics [start <file> [<samples>]|stop]".to_string()));
+				}
 			}
 
-			for _ in 0..steps {
-				run_test(out, &mut sim.debug_path, &sim.graph, &sim.algorithm)?;
+			match sim.metrics.path() {
+				Some(path) => writeln!(out, "metrics to: {}", path)?,
+				None => writeln!(out, "metrics: disabled")?
 			}
-		}
-		Command::Import(ref path) => {
-			import_file(&mut sim.graph, Some(&mut sim.locations), Some(&mut sim.meta), path.as_str())?;
-			do_init = true;
-			writeln!(out, "Import done: {}", path)?;
 		},
 		Command::ExportPath(path) => {
 			if let Some(path) = path {
@@ -647,6 +3007,34 @@ fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, ca
 
 			writeln!(out, "Export done: {}", sim.export_path)?;
 		},
+		Command::ExportStyle(assignments) => {
+			if assignments.len() == 1 && assignments[0] == "off" {
+				sim.export_style.clear();
+			} else {
+				// keep the current style if an assignment is invalid
+				let mut style = sim.export_style.clone();
+				for assignment in &assignments {
+					style.set(assignment)?;
+				}
+				sim.export_style = style;
+			}
+			sim.export_style.print(out)?;
+		},
+		Command::Render(path, width, color) => {
+			let mut style = sim.export_style.clone();
+			if color.is_some() {
+				style.node_color = color;
+			}
+			let scene = Scene::new(&sim.graph, &sim.locations, &sim.meta, &*sim.algorithm, &sim.energy, &sim.test,
+				sim.marked_links.as_ref(), width, &style)?;
+			if path.ends_with(".png") {
+				scene.write_png(&path)?;
+			} else {
+				scene.write_svg(&path)?;
+			}
+			writeln!(out, "Rendered {} nodes and {} links ({}x{}) to {}",
+				scene.node_count(), scene.link_count(), scene.width, scene.height, path)?;
+		},
 		Command::AddLine(count, close) => {
 			sim.add_line(count, close);
 			do_init = true;
@@ -673,6 +3061,38 @@ fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, ca
 			sim.add_lattice8(x_count, y_count);
 			do_init = true;
 		},
+		Command::AddLatticeTri(x_count, y_count) => {
+			sim.add_lattice_tri(x_count, y_count);
+			do_init = true;
+		},
+		Command::AddLatticeHex(x_count, y_count) => {
+			sim.add_lattice_hex(x_count, y_count);
+			do_init = true;
+		},
+		Command::AddLattice3d(x_count, y_count, z_count) => {
+			sim.add_lattice3d(x_count, y_count, z_count);
+			do_init = true;
+		},
+		Command::AddTorus(x_count, y_count) => {
+			sim.add_torus(x_count, y_count);
+			do_init = true;
+		},
+		Command::AddRgg(count, radius) => {
+			sim.add_rgg(count, radius);
+			do_init = true;
+		},
+		Command::AddErdos(count, p) => {
+			sim.add_erdos(count, p);
+			do_init = true;
+		},
+		Command::AddBarabasi(count, m) => {
+			sim.add_barabasi(count, m);
+			do_init = true;
+		},
+		Command::AddSmallworld(count, k, p) => {
+			sim.add_smallworld(count, k, p);
+			do_init = true;
+		},
 		Command::Positions(enable) => {
 			if enable {
 				// add positions to node that have none
@@ -693,54 +3113,166 @@ fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, ca
 			sim.locations.randomize_positions_2d(center, range);
 		},
 		Command::ConnectInRange(range) => {
-			sim.connect_in_range(range);
+			sim.checkpoint(line);
+			if !sim.connect_in_range(range) {
+				writeln!(out, "Cancelled, not all nodes are connected.")?;
+			}
 		},
-		Command::Algorithm(algo) => {
-			if let Some(algo) = algo {
-				match algo.as_str() {
-					"random" => {
-						sim.algorithm = Box::new(RandomRouting::new());
-						do_init = true;
-					},
-					"vivaldi" => {
-						sim.algorithm = Box::new(VivaldiRouting::new());
-						do_init = true;
-					},
-					"spring" => {
-						sim.algorithm = Box::new(SpringRouting::new());
-						do_init = true;
-					},
-					"genetic" => {
-						sim.algorithm = Box::new(GeneticRouting::new());
-						do_init = true;
-					},
-					"tree" => {
-						sim.algorithm = Box::new(SpanningTreeRouting::new());
-						do_init = true;
-					}
-					_ => {
-						writeln!(out, "Unknown algorithm: {}", algo)?;
+		Command::Interconnect(k, sets) => {
+			let node_count = sim.graph.node_count() as ID;
+			let links = if let Some((a, b)) = sets {
+				if let Some(id) = a.iter().chain(b.iter()).find(|id| **id >= node_count) {
+					return Err(MyError::new(format!("Invalid node id: {}", id)));
+				}
+				between_sets(&sim.graph, &sim.locations, k as usize, &a, &b)?
+			} else {
+				between_components(&sim.graph, &sim.locations, k as usize)?
+			};
+
+			if links.is_empty() {
+				writeln!(out, "No links to add")?;
+			} else {
+				sim.checkpoint(line);
+				for (a, b, distance) in &links {
+					sim.graph.connect(*a, *b);
+					writeln!(out, "{} <=> {}: {:.3} km", a, b, distance)?;
+				}
+				let total : f32 = links.iter().map(|(_, _, d)| d).sum();
+				writeln!(out, "Added {} links, total distance: {:.3} km, components: {}",
+					links.len(), total, sim.graph.get_components().len())?;
+				mark_links = Some(sim.graph.filter_links(|link|
+					links.iter().any(|(a, b, _)| (link.from == *a && link.to == *b) || (link.from == *b && link.to == *a))
+				));
+				do_init = true;
+			}
+		},
+		Command::OptimizePlacement(count, range, method) => {
+			let placement = {
+				let radio = &sim.radio;
+				let obstacles = &sim.obstacles;
+				let in_range = |a: &[f32; 3], b: &[f32; 3]| {
+					let distance = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+					radio.link_quality(distance, range).is_some() && !obstacles.is_blocked(a, b)
+				};
+				optimize_placement(&sim.graph, &sim.locations, count as usize, range, method, &in_range)?
+			};
+
+			if placement.positions.is_empty() {
+				writeln!(out, "No position in range of any node")?;
+			} else {
+				sim.checkpoint(line);
+				let first = sim.graph.node_count() as ID;
+				sim.graph.add_nodes(placement.positions.len() as u32);
+				for (i, pos) in placement.positions.iter().enumerate() {
+					sim.locations.insert(first + i as ID, *pos);
+					sim.meta.set_value(first + i as ID, "candidate", serde_json::Value::Bool(true));
+				}
+
+				// link the new nodes like connect_in_range
+				for a in first..sim.graph.node_count() as ID {
+					for b in 0..a {
+						if let Some(distance) = sim.locations.pos_distance(a, b) {
+							if let Some(quality) = sim.radio.link_quality(distance, range) {
+								if !sim.obstacles.is_blocked(sim.locations.get_position(a).unwrap(), sim.locations.get_position(b).unwrap()) {
+									let quality = (quality * u16::MAX as f32) as u16;
+									sim.graph.add_link(a, b, quality);
+									sim.graph.add_link(b, a, quality);
+								}
+							}
+						}
 					}
 				}
-				if do_init {
+
+				write!(out, "before: ")?;
+				placement.before.print(out)?;
+				for (i, pos) in placement.positions.iter().enumerate() {
+					let id = first + i as ID;
+					writeln!(out, "  {}: {:.3}, {:.3} (links: {})", id, pos[0], pos[1], sim.graph.get_neighbors(id).len())?;
+				}
+				write!(out, "after ({}): ", method.name())?;
+				placement.after.print(out)?;
+				mark_links = Some(sim.graph.filter_links(|link| link.from >= first || link.to >= first));
+				do_init = true;
+			}
+		},
+		Command::Algorithm(algo) => {
+			if let Some(algo) = algo {
+				if let Some(algorithm) = create_algorithm(&algo) {
+					sim.algorithm = algorithm;
+					sim.algorithm_name = algo;
+					do_init = true;
 					writeln!(out, "Done")?;
+				} else {
+					return Err(MyError::new(format!("Unknown algorithm: {}", algo)));
 				}
 			} else {
 				write!(out, "selected: ")?;
 				sim.algorithm.get("name", out)?;
-				write!(out, "\n")?;
-				write!(out, "available: random, vivaldi, spring, genetic, tree\n")?;
+				writeln!(out)?;
+				writeln!(out, "available: {}", algorithm_names().join(", "))?;
+			}
+		},
+		Command::AlgorithmNode(None) => {
+			if sim.algorithm_name == mixed_routing::NAME {
+				let mut groups = String::new();
+				sim.algorithm.get("groups", &mut groups)?;
+				writeln!(out, "{}", groups)?;
+			} else {
+				writeln!(out, "{} ({}): {} nodes", sim.algorithm_name, sim.algorithm_name, sim.graph.node_count())?;
+			}
+		},
+		Command::AlgorithmNode(Some((ids, algo, group))) => {
+			if algo == mixed_routing::NAME || create_algorithm(&algo).is_none() {
+				return Err(MyError::new(format!("Unknown algorithm: {}", algo)));
+			}
+			let node_count = sim.graph.node_count();
+			if let Some(id) = ids.iter().find(|id| **id as usize >= node_count) {
+				return Err(MyError::new(format!("Invalid node id: {}", id)));
+			}
+
+			// the selected algorithm keeps running on the other nodes
+			if sim.algorithm_name != mixed_routing::NAME {
+				let current = std::mem::replace(&mut sim.algorithm, create_algorithm("random").unwrap());
+				sim.algorithm = Box::new(MixedRouting::with_default(&sim.algorithm_name, current, node_count));
+				sim.algorithm_name = mixed_routing::NAME.to_string();
+			}
+
+			let value = format!("{} {}", algo, group.unwrap_or_else(|| algo.clone()));
+			for id in &ids {
+				sim.algorithm.set_node(*id, "algorithm", &value)?;
+			}
+			do_init = true;
+			writeln!(out, "Done")?;
+		},
+		Command::AlgorithmLoad(ref path) | Command::AlgorithmWasm(ref path) => {
+			let name = if let Command::AlgorithmWasm(_) = command {
+				load_wasm(path)?
+			} else {
+				load_plugin(path)?
+			};
+			if let Some(algorithm) = create_algorithm(&name) {
+				sim.algorithm = algorithm;
+				sim.algorithm_name = name.clone();
+				do_init = true;
 			}
+			writeln!(out, "Loaded {} from {}", name, path)?;
 		},
 		Command::Run(path) => {
 			if call == AllowRecursiveCall::Yes {
-				if let Ok(file) = File::open(&path) {
-					for (index, line) in BufReader::new(file).lines().enumerate() {
-						let line = line.unwrap();
-						if let Err(err) = cmd_handler(out, sim, &line, AllowRecursiveCall::No) {
-							writeln!(out, "Error in {}:{}: {}", path, index, err)?;
+				if let Ok(text) = std::fs::read_to_string(&path) {
+					let mut script = Script::new();
+					let result = script.run(&text, &mut |line| {
+						if is_cancelled() {
+							return Err(MyError::new("Cancelled".to_string()));
+						}
+						cmd_handler(out, sim, line, AllowRecursiveCall::No)
+					});
+					if let Err(err) = result {
+						if is_cancelled() {
+							writeln!(out, "Cancelled {}:{}", path, err)?;
+						} else {
+							writeln!(out, "Error in {}:{}", path, err)?;
 							sim.abort_simulation = true;
-							break;
 						}
 					}
 				} else {
@@ -751,36 +3283,641 @@ fn cmd_handler(out: &mut std::fmt::Write, sim: &mut GlobalState, input: &str, ca
 			}
 		},
 		Command::RemoveUnconnected => {
-			sim.graph.remove_unconnected_nodes();
+			let keep : Vec<ID> = (0..sim.graph.node_count() as ID)
+				.filter(|id| sim.graph.get_node_degree(*id) > 0).collect();
+			sim.checkpoint(line);
+			sim.crop(&keep);
 			do_init = true;
 		},
 		Command::RemoveNodes(ids) => {
-			sim.graph.remove_nodes(&ids);
+			let keep : Vec<ID> = (0..sim.graph.node_count() as ID).filter(|id| !ids.contains(id)).collect();
+			sim.checkpoint(line);
+			sim.crop(&keep);
+			do_init = true;
+		},
+		Command::Crop(ids) => {
+			sim.checkpoint(line);
+			sim.crop(&ids);
+			writeln!(out, "nodes: {}, links: {}", sim.graph.node_count(), sim.graph.link_count())?;
+			do_init = true;
+		},
+		Command::CropLargestComponent => {
+			let components = sim.graph.get_components();
+			if let Some(largest) = components.first() {
+				sim.checkpoint(line);
+				sim.crop(largest);
+			}
+			writeln!(out, "removed {} components, nodes: {}, links: {}",
+				components.len().saturating_sub(1), sim.graph.node_count(), sim.graph.link_count())?;
+			do_init = true;
+		},
+		Command::Components => {
+			let components = sim.graph.get_components();
+			writeln!(out, "components: {}", components.len())?;
+			for (i, component) in components.iter().enumerate().take(20) {
+				writeln!(out, "  {}: {} nodes", i, component.len())?;
+			}
+			if components.len() > 20 {
+				writeln!(out, "  ... ({} more)", components.len() - 20)?;
+			}
+		},
+		Command::MinCut(a, b) => {
+			let node_count = sim.graph.node_count() as u32;
+			if a >= node_count || b >= node_count || a == b {
+				return Err(MyError::new(format!("Invalid node pair: {} {}", a, b)));
+			}
+			let cut = min_cut(&sim.graph, a, b);
+			writeln!(out, "min cut: {} links", cut.len())?;
+			for (from, to) in cut {
+				writeln!(out, "  {} => {}", from, to)?;
+			}
+		},
+		Command::Connectivity => {
+			let (connectivity, pair) = edge_connectivity(&sim.graph);
+			write!(out, "edge connectivity: {}", connectivity)?;
+			if let Some((a, b)) = pair {
+				write!(out, " (from {} to {})", a, b)?;
+			}
+			writeln!(out)?;
+		},
+		Command::Cluster(method) => {
+			let method = method.unwrap_or(ClusterMethod::Louvain);
+			let clusters = cluster(&sim.graph, method);
+			for (id, c) in clusters.iter().enumerate() {
+				sim.meta.set_value(id as ID, "cluster", (*c).into());
+			}
+			let sizes = cluster_sizes(&clusters);
+			writeln!(out, "method: {}, clusters: {}, modularity: {:.3}",
+				method.name(), sizes.len(), modularity(&sim.graph, &clusters))?;
+			for (i, size) in sizes.iter().enumerate().take(20) {
+				writeln!(out, "  {}: {} nodes", i, size)?;
+			}
+			if sizes.len() > 20 {
+				writeln!(out, "  ... ({} more)", sizes.len() - 20)?;
+			}
+		},
+		Command::Select(name, expr) => {
+			match (name, expr) {
+				(Some(name), Some(expr)) => {
+					// check expression
+					let ids = sim.selections.evaluate(&expr, &sim.graph, &sim.locations, &sim.meta)?;
+					sim.selections.define(&name, &expr)?;
+					writeln!(out, "@{}: {} nodes", name, ids.len())?;
+				},
+				(Some(name), None) => {
+					let ids = sim.selections.resolve(&name, &sim.graph, &sim.locations, &sim.meta)?;
+					let ids : Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+					writeln!(out, "@{}: {} nodes", name, ids.len())?;
+					writeln!(out, "{}", ids.join(","))?;
+				},
+				_ => {
+					for (name, expr) in sim.selections.list() {
+						writeln!(out, "@{}: {}", name, expr)?;
+					}
+				}
+			}
+		},
+		Command::Unselect(name) => {
+			if !sim.selections.remove(&name) {
+				return Err(MyError::new(format!("Unknown selection: @{}", name)));
+			}
+		},
+		Command::MetaGet(id, key) => {
+			if id as usize >= sim.graph.node_count() {
+				return Err(MyError::new(format!("Invalid node id: {}", id)));
+			}
+			match key {
+				None => {
+					writeln!(out, "{}", sim.meta.data.get(&id).map_or("{}", |data| data.as_str()))?;
+				},
+				Some(key) => {
+					let entries = sim.meta.get_matching(id, &key);
+					if entries.is_empty() {
+						writeln!(out, "no metadata for key: {}", key)?;
+					}
+					for (key, value) in entries {
+						writeln!(out, "{}: {}", key, value)?;
+					}
+				}
+			}
+		},
+		Command::Resolve(name) => {
+			if let Ok(id) = name.parse::<ID>() {
+				if id as usize >= sim.graph.node_count() {
+					return Err(MyError::new(format!("Invalid node id: {}", id)));
+				}
+				let uid = sim.node_ids.get(id).unwrap_or(id);
+				writeln!(out, "{} (stable id #{})", sim.meta.name(id).unwrap_or_else(|| "no name".to_string()), uid)?;
+			} else {
+				let ids = sim.meta.find_name(&name);
+				if ids.is_empty() {
+					return Err(MyError::new(format!("Unknown node name: {}", name)));
+				}
+				let ids : Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+				writeln!(out, "{}", ids.join(","))?;
+			}
+		},
+		Command::CompactIds => {
+			if sim.node_ids.is_compact() {
+				writeln!(out, "Stable node ids are already compact.")?;
+			} else {
+				sim.checkpoint(line);
+				sim.node_ids.compact();
+				writeln!(out, "Stable node ids renumbered to 0..{}.", sim.graph.node_count())?;
+			}
+		},
+		Command::MetaFilter(key, value) => {
+			let ids = sim.meta.filter(&key, &value);
+			let ids : Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+			writeln!(out, "{} nodes", ids.len())?;
+			if !ids.is_empty() {
+				writeln!(out, "{}", ids.join(","))?;
+			}
 		},
 		Command::ConnectNodes(ids) => {
 			sim.graph.connect_nodes(&ids);
 		},
+		Command::ConnectNodesOneway(from, ids) => {
+			for id in std::iter::once(&from).chain(&ids) {
+				if *id as usize >= sim.graph.node_count() {
+					return Err(MyError::new(format!("Invalid node id: {}", id)));
+				}
+			}
+			sim.graph.connect_nodes_oneway(from, &ids);
+		},
 		Command::DisconnectNodes(ids) => {
 			sim.graph.disconnect_nodes(&ids);
 		},
+		Command::SetLink(a, b, key, value) => {
+			let mut found = false;
+			for (from, to) in &[(a, b), (b, a)] {
+				if let Some(link) = sim.graph.get_link_mut(*from, *to) {
+					link.set_attribute(&key, &value)?;
+					found = true;
+				}
+			}
+
+			if !found {
+				return Err(MyError::new(format!("Link not found: {} => {}", a, b)));
+			}
+		},
 		Command::MoveTo(x, y, z) => {
 			let center = sim.locations.graph_center();
 			sim.locations.move_nodes([center[0] + x * DEG2KM, center[1] + y * DEG2KM, center[2] + z * DEG2KM]);
 		}
 	};
 
+	sim.node_ids.update(sim.graph.node_count());
+
 	if do_init {
 		sim.algorithm.reset(sim.graph.node_count());
 		sim.test.clear();
+		if !keep_churn {
+			sim.churn.clear();
+		}
+		sim.energy.reset(sim.graph.node_count());
+		sim.overhead.clear();
+	}
+
+	if mark_links.is_some() {
+		sim.marked_links = mark_links.clone();
+	} else if do_init {
+		sim.marked_links = None;
 	}
 
 	export_file(
 		&sim.graph,
 		Some(&sim.locations),
+		Some(&sim.meta),
 		Some(&*sim.algorithm),
+		Some(&sim.energy),
+		Some(&sim.test),
 		mark_links.as_ref(),
+		Some(&sim.export_style),
 		sim.export_path.as_ref()
 	);
 
+	if !sim.ws_clients.is_empty() {
+		let snapshot = export_json(&sim.graph, Some(&sim.locations), Some(&sim.meta), Some(&*sim.algorithm), Some(&sim.energy), Some(&sim.test), mark_links.as_ref(), Some(&sim.export_style));
+		sim.ws_clients.broadcast(snapshot);
+	}
+
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn new_sim(name: &str) -> GlobalState {
+		let mut sim = GlobalState::new("");
+		// every command exports the graph, keep it out of the source tree
+		let path = std::env::temp_dir().join(format!("mesh_simulator_{}_{}.json", name, std::process::id()));
+		sim.export_path = path.to_string_lossy().to_string();
+		sim
+	}
+
+	// output of the last command
+	fn run(sim: &mut GlobalState, commands: &[&str]) -> String {
+		let mut output = String::new();
+		for command in commands {
+			output = match execute(sim, command) {
+				Ok(output) => output,
+				Err(e) => panic!("{}: {}", command, e)
+			};
+		}
+		output
+	}
+
+	// export the graph as file with the given extension, clear the graph and import the file
+	fn round_trip(sim: &mut GlobalState, extension: &str) {
+		let path = std::env::temp_dir().join(format!("mesh_simulator_round_trip_{}.{}", std::process::id(), extension));
+		let path = path.to_string_lossy().to_string();
+		let export_path = sim.export_path.clone();
+		run(sim, &[&format!("export {}", path)]);
+		sim.export_path = export_path;
+		run(sim, &["graph_clear", &format!("import {}", path)]);
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn geo_reference_without_position() {
+		let mut sim = new_sim("geo_reference");
+		run(&mut sim, &["line 3", "algo dijkstra", "sim_step 1"]);
+		sim.locations.data.remove(&1);
+		let output = run(&mut sim, &["set test.reference geo", "test 20"]);
+
+		assert!(sim.test.paths_no_reference() > 0);
+		assert!(output.contains("paths without reference"));
+	}
+
+	#[test]
+	fn gateways_follow_removed_nodes() {
+		let mut sim = new_sim("gateways");
+		run(&mut sim, &["line 6", "remove_nodes 2", "set_gateway 1,3"]);
+		assert_eq!(sim.gateways, vec![1, 3]);
+
+		// nodes 2, 3 and 4 are left, node 3 becomes node 1
+		run(&mut sim, &["crop_largest_component"]);
+		assert_eq!(sim.graph.node_count(), 3);
+		assert_eq!(sim.gateways, vec![1]);
+
+		run(&mut sim, &["set_gateway 0", "remove_nodes 0"]);
+		assert!(sim.gateways.is_empty());
+
+		assert!(execute(&mut sim, "set_gateway 2").is_err());
+	}
+
+	#[test]
+	fn link_state_refresh_every_step() {
+		let mut sim = new_sim("link_state");
+		run(&mut sim, &["line 5", "algo link_state", "set refresh_interval 1", "set max_age 3", "sim_step 20", "test 50"]);
+
+		assert_eq!(run(&mut sim, &["get max_age"]).trim(), "3");
+		assert_eq!(sim.test.arrived(), 100.0);
+	}
+
+	#[test]
+	fn names_only_expanded_in_node_arguments() {
+		let mut sim = new_sim("names");
+		run(&mut sim, &["line 3"]);
+		sim.meta.insert(1, r#"{"name": "tree"}"#.to_string());
+
+		// the algorithm name is not a node argument
+		run(&mut sim, &["algo tree"]);
+		assert_eq!(sim.algorithm_name, "tree");
+
+		run(&mut sim, &["remove_nodes tree"]);
+		assert_eq!(sim.graph.node_count(), 2);
+		assert_eq!(sim.graph.link_count(), 0);
+	}
+
+	#[test]
+	fn connect_oneway_checks_node_ids() {
+		let mut sim = new_sim("connect_oneway");
+		run(&mut sim, &["line 3", "connect_nodes_oneway 0 2"]);
+		assert!(sim.graph.has_link(0, 2));
+		assert!(!sim.graph.has_link(2, 0));
+
+		assert!(execute(&mut sim, "connect_nodes_oneway 0 7").is_err());
+		assert!(execute(&mut sim, "connect_nodes_oneway 7 0").is_err());
+		assert_eq!(sim.graph.link_count(), 5);
+	}
+
+	#[test]
+	fn export_with_dangling_link() {
+		let mut sim = new_sim("dangling_link");
+		run(&mut sim, &["line 3", "connect_nodes 0,7"]);
+		assert_eq!(sim.graph.get_components().len(), 1);
+		assert_eq!(sim.graph.get_component_ids(), vec![0, 0, 0]);
+
+		let json = export_json(&sim.graph, Some(&sim.locations), Some(&sim.meta), Some(&*sim.algorithm), None, None, None, Some(&sim.export_style));
+		assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+	}
+
+	#[test]
+	fn selections_only_expanded_in_node_arguments() {
+		let mut sim = new_sim("selections");
+		run(&mut sim, &["line 4", "select ends degree=1"]);
+
+		// a workspace name is not a node list
+		run(&mut sim, &["workspace new @ends"]);
+		assert!(run(&mut sim, &["workspace"]).contains("@ends"));
+		run(&mut sim, &["line 4", "select ends degree=1"]);
+
+		run(&mut sim, &["remove_nodes @ends"]);
+		assert_eq!(sim.graph.node_count(), 2);
+		assert!(execute(&mut sim, "remove_nodes @missing").is_err());
+	}
+
+	#[test]
+	fn stable_ids_only_expanded_in_node_arguments() {
+		let mut sim = new_sim("stable_ids");
+		run(&mut sim, &["line 4", "remove_nodes 0"]);
+
+		// stable id #3 is node 2 now
+		run(&mut sim, &["fail_node #3", "sim_step 5 # warm up"]);
+		assert_eq!(sim.sim_steps, 5);
+		assert!(!sim.graph.has_link(1, 2));
+
+		assert!(execute(&mut sim, "remove_nodes #0").is_err());
+		run(&mut sim, &["remove_nodes #1"]);
+		assert_eq!(sim.graph.node_count(), 2);
+	}
+
+	#[test]
+	fn link_failures_survive_algorithm_change() {
+		let mut sim = new_sim("churn_algo");
+		run(&mut sim, &["line 4", "fail_link 0 1 2", "algo dijkstra", "sim_step 5"]);
+		assert_eq!(sim.graph.link_count(), 6);
+	}
+
+	#[test]
+	fn sweep_restores_state() {
+		let mut sim = new_sim("sweep");
+		run(&mut sim, &["line 5", "remove_nodes 4", "set_gateway 0", "fail_link 0 1 2"]);
+
+		// the inner commands are not added to the undo history
+		let table = run(&mut sim, &["sweep crop 0:1:1 test"]);
+		assert!(table.starts_with("value,nodes,links"));
+		assert_eq!(table.lines().count(), 3);
+		assert_eq!(sim.graph.node_count(), 4);
+		assert_eq!(sim.gateways, vec![0]);
+
+		// gateway 6 does not exist, the state of the failed sweep is put back
+		assert!(execute(&mut sim, "sweep set_gateway 3:6:3").is_err());
+		assert_eq!(sim.gateways, vec![0]);
+
+		// the timed link failure is still pending
+		run(&mut sim, &["sim_step 5"]);
+		assert_eq!(sim.graph.link_count(), 6);
+
+		assert!(run(&mut sim, &["undo"]).contains("undo: remove_nodes 4"));
+	}
+
+	#[test]
+	fn import_roads_keeps_links() {
+		let mut sim = new_sim("roads");
+		let path = std::env::temp_dir().join(format!("mesh_simulator_roads_{}.geojson", std::process::id()));
+		std::fs::write(&path, r#"{"type": "LineString", "coordinates": [[0.0, 0.0], [0.0, 0.01]]}"#).unwrap();
+
+		run(&mut sim, &["line 2", "set_link 0 1 cost 7"]);
+		run(&mut sim, &[&format!("import_roads {} 0.5 1.0", path.display())]);
+		std::fs::remove_file(&path).unwrap();
+
+		assert!(sim.graph.node_count() > 2);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(7));
+
+		run(&mut sim, &["undo"]);
+		assert_eq!(sim.graph.node_count(), 2);
+	}
+
+	#[test]
+	fn set_link_missing_link() {
+		let mut sim = new_sim("set_link");
+		run(&mut sim, &["line 3", "set_link 1 0 cost 5"]);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(5));
+		assert!(execute(&mut sim, "set_link 0 2 cost 5").is_err());
+	}
+
+	#[test]
+	fn send_checks_node_ids() {
+		let mut sim = new_sim("send");
+		run(&mut sim, &["line 3"]);
+		assert!(run(&mut sim, &["send 0 2 1"]).starts_with("delivered:"));
+		assert!(execute(&mut sim, "send 0 3 1").is_err());
+	}
+
+	#[test]
+	fn churn_nodes_incomplete_arguments() {
+		let mut sim = new_sim("churn_nodes");
+		run(&mut sim, &["line 3"]);
+		assert!(execute(&mut sim, "churn_nodes 0.1").is_err());
+		assert!(execute(&mut sim, "churn_nodes 0.1 x").is_err());
+		let before = run(&mut sim, &["churn_nodes"]);
+		assert_ne!(run(&mut sim, &["churn_nodes 0.1 0.2 1.5"]), before);
+	}
+
+	#[test]
+	fn gexf_round_trip() {
+		let mut sim = new_sim("gexf");
+		run(&mut sim, &["lattice4 3 3", "set_link 0 1 cost 9"]);
+		round_trip(&mut sim, "gexf");
+		assert_eq!(sim.graph.node_count(), 9);
+		assert_eq!(sim.graph.link_count(), 24);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(9));
+	}
+
+	#[test]
+	fn script_variables_and_loops() {
+		let mut sim = new_sim("script");
+		let path = std::env::temp_dir().join(format!("mesh_simulator_script_{}.cmd", std::process::id()));
+		let path = path.to_string_lossy().to_string();
+
+		std::fs::write(&path, "let n = 2\nrepeat 3 { line $n; let n = n + 1 } # 2 + 3 + 4 nodes\n").unwrap();
+		run(&mut sim, &[&format!("run {}", path)]);
+		assert_eq!(sim.graph.node_count(), 9);
+		assert!(!sim.abort_simulation);
+
+		// an error stops the script
+		std::fs::write(&path, "line 1; unknown_command; line 1").unwrap();
+		assert!(run(&mut sim, &[&format!("run {}", path)]).contains("Error in"));
+		assert_eq!(sim.graph.node_count(), 10);
+		assert!(sim.abort_simulation);
+
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn random_graph_generators() {
+		let mut sim = new_sim("rgg_erdos");
+		run(&mut sim, &["seed 1", "rgg 10 0"]);
+		assert_eq!(sim.graph.node_count(), 10);
+		assert_eq!(sim.graph.link_count(), 0);
+		assert_eq!(sim.locations.data.len(), 10);
+
+		// all pairs are connected in both directions
+		run(&mut sim, &["graph_clear", "erdos 6 1.0"]);
+		assert_eq!(sim.graph.node_count(), 6);
+		assert_eq!(sim.graph.link_count(), 30);
+
+		run(&mut sim, &["graph_clear", "erdos 6 0"]);
+		assert_eq!(sim.graph.link_count(), 0);
+	}
+
+	#[test]
+	fn scale_free_and_small_world_generators() {
+		let mut sim = new_sim("barabasi_smallworld");
+		run(&mut sim, &["seed 1", "barabasi 10 2"]);
+		assert_eq!(sim.graph.node_count(), 10);
+		// every new node connects to m nodes
+		assert!(sim.graph.link_count() >= 2 * 2 * 8);
+		assert!(sim.graph.is_bidirectional());
+
+		// without rewiring every node has k neighbors
+		run(&mut sim, &["graph_clear", "smallworld 10 4 0"]);
+		assert_eq!(sim.graph.node_count(), 10);
+		assert!((0..10).all(|id| sim.graph.get_node_degree(id) == 4));
+	}
+
+	#[test]
+	fn triangle_and_hexagon_lattices() {
+		let mut sim = new_sim("lattice_tri_hex");
+		run(&mut sim, &["lattice_tri 3 3"]);
+		assert_eq!(sim.graph.node_count(), 9);
+		// 6 horizontal, 6 vertical and 4 diagonal links in both directions
+		assert_eq!(sim.graph.link_count(), 32);
+
+		run(&mut sim, &["graph_clear", "lattice_hex 3 3"]);
+		assert_eq!(sim.graph.node_count(), 9);
+		assert!((0..9).all(|id| sim.graph.get_node_degree(id) <= 3));
+		assert!(sim.graph.is_bidirectional());
+	}
+
+	#[test]
+	fn cubic_lattice_and_torus() {
+		let mut sim = new_sim("lattice3d_torus");
+		run(&mut sim, &["lattice3d 2 2 2"]);
+		assert_eq!(sim.graph.node_count(), 8);
+		assert!((0..8).all(|id| sim.graph.get_node_degree(id) == 3));
+
+		// wrap-around links give every node four neighbors
+		run(&mut sim, &["graph_clear", "torus 3 3"]);
+		assert_eq!(sim.graph.node_count(), 9);
+		assert!((0..9).all(|id| sim.graph.get_node_degree(id) == 4));
+	}
+
+	#[test]
+	fn link_churn_fail_and_recover() {
+		let mut sim = new_sim("churn_links");
+		run(&mut sim, &["line 4", "churn_links 1 0", "sim_step 1"]);
+		assert_eq!(sim.graph.link_count(), 0);
+
+		run(&mut sim, &["churn_links 0 1", "sim_step 1"]);
+		assert_eq!(sim.graph.link_count(), 6);
+	}
+
+	#[test]
+	fn undo_and_redo() {
+		let mut sim = new_sim("undo_redo");
+		run(&mut sim, &["line 4", "remove_nodes 0", "crop 0,1"]);
+		assert_eq!(sim.graph.node_count(), 2);
+
+		assert!(run(&mut sim, &["undo"]).contains("undo: crop 0,1"));
+		assert_eq!(sim.graph.node_count(), 3);
+		run(&mut sim, &["undo"]);
+		assert_eq!(sim.graph.node_count(), 4);
+		assert_eq!(sim.graph.link_count(), 6);
+		assert!(run(&mut sim, &["undo"]).contains("nothing to undo"));
+
+		run(&mut sim, &["redo"]);
+		assert_eq!(sim.graph.node_count(), 3);
+
+		// a new destructive command drops the redo states
+		run(&mut sim, &["remove_nodes 2", "redo"]);
+		assert_eq!(sim.graph.node_count(), 2);
+	}
+
+	#[test]
+	fn save_and_load_state() {
+		let mut sim = new_sim("state");
+		let path = std::env::temp_dir().join(format!("mesh_simulator_saved_state_{}.json", std::process::id()));
+		let path = path.to_string_lossy().to_string();
+
+		run(&mut sim, &["line 4", "set_gateway 3", "set_link 0 1 cost 5", "sim_step 3"]);
+		run(&mut sim, &[&format!("save_state {}", path), "graph_clear", "sim_reset"]);
+		run(&mut sim, &[&format!("load_state {}", path)]);
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(sim.graph.node_count(), 4);
+		assert_eq!(sim.graph.link_count(), 6);
+		assert_eq!(sim.gateways, vec![3]);
+		assert_eq!(sim.sim_steps, 3);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(5));
+	}
+
+	#[test]
+	fn json_round_trip_keeps_link_attributes() {
+		let mut sim = new_sim("json");
+		run(&mut sim, &["line 3", "set_link 0 1 cost 9", "set_link 1 2 latency 2.5"]);
+		round_trip(&mut sim, "json");
+		assert_eq!(sim.graph.link_count(), 4);
+		assert_eq!(sim.graph.get_link(1, 0).map(|link| link.cost()), Some(9));
+		assert_eq!(sim.graph.get_link(2, 1).map(|link| link.latency()), Some(2.5));
+	}
+
+	#[test]
+	fn geojson_round_trip() {
+		let mut sim = new_sim("geojson");
+		run(&mut sim, &["lattice4 3 3", "set_link 0 1 cost 9"]);
+		round_trip(&mut sim, "geojson");
+		assert_eq!(sim.graph.node_count(), 9);
+		assert_eq!(sim.graph.link_count(), 24);
+		assert_eq!(sim.locations.data.len(), 9);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(9));
+	}
+
+	#[test]
+	fn mgb_round_trip() {
+		let mut sim = new_sim("mgb");
+		run(&mut sim, &["lattice4 3 3", "set_link 0 1 cost 9"]);
+		round_trip(&mut sim, "mgb");
+		assert_eq!(sim.graph.node_count(), 9);
+		assert_eq!(sim.graph.link_count(), 24);
+		assert_eq!(sim.locations.data.len(), 9);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(9));
+	}
+
+	#[test]
+	fn gzip_round_trip() {
+		let mut sim = new_sim("gzip");
+		run(&mut sim, &["lattice4 3 3", "set_link 0 1 cost 9"]);
+		round_trip(&mut sim, "json.gz");
+		assert_eq!(sim.graph.node_count(), 9);
+		assert_eq!(sim.graph.link_count(), 24);
+		assert_eq!(sim.locations.data.len(), 9);
+		assert_eq!(sim.graph.get_link(0, 1).map(|link| link.cost()), Some(9));
+	}
+
+	#[test]
+	fn execute_returns_errors() {
+		let mut sim = new_sim("execute");
+		assert!(execute(&mut sim, "unknown_command").is_err());
+		assert!(execute(&mut sim, "line x").is_err());
+		assert!(run(&mut sim, &["line 2", "graph_info"]).starts_with("nodes: 2, links: 2"));
+	}
+
+	#[test]
+	fn workspaces_keep_their_graphs() {
+		let mut sim = new_sim("workspaces");
+		run(&mut sim, &["line 3", "algo dijkstra", "workspace new other", "line 5"]);
+		assert_eq!(sim.graph.node_count(), 5);
+
+		run(&mut sim, &["workspace switch default"]);
+		assert_eq!(sim.graph.node_count(), 3);
+		assert_eq!(sim.algorithm_name, "dijkstra");
+		assert!(run(&mut sim, &["workspace"]).contains("other: nodes: 5"));
+
+		assert!(execute(&mut sim, "workspace switch missing").is_err());
+	}
+}
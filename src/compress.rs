@@ -0,0 +1,91 @@
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use crate::utils::MyError;
+
+
+/*
+ * Transparent compression of imported and exported files.
+ * The compression is detected by the last extension (.gz or .zst),
+ * the format by the extension before, e.g. graph.json.gz.
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Compression {
+	None,
+	Gzip,
+	Zstd
+}
+
+// the compression and the path without the compression extension
+pub fn split_path(path: &str) -> (&str, Compression) {
+	if let Some(name) = path.strip_suffix(".gz") {
+		(name, Compression::Gzip)
+	} else if let Some(name) = path.strip_suffix(".zst") {
+		(name, Compression::Zstd)
+	} else {
+		(path, Compression::None)
+	}
+}
+
+pub fn open_file(path: &str) -> Result<Box<dyn Read>, MyError> {
+	decompress(path, File::open(path)?)
+}
+
+// decompress the input by the extension of the path
+pub fn decompress(path: &str, input: impl Read + 'static) -> Result<Box<dyn Read>, MyError> {
+	let file = BufReader::new(input);
+	Ok(match split_path(path).1 {
+		// concatenated gzip members are read as one stream
+		Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+		Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+		Compression::None => Box::new(file)
+	})
+}
+
+pub enum OutputFile {
+	Plain(BufWriter<File>),
+	Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+	Zstd(zstd::Encoder<'static, BufWriter<File>>)
+}
+
+impl OutputFile {
+	pub fn create(path: &str) -> Result<Self, MyError> {
+		let file = BufWriter::new(File::create(path)?);
+		Ok(match split_path(path).1 {
+			Compression::Gzip => OutputFile::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::default())),
+			Compression::Zstd => OutputFile::Zstd(zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+			Compression::None => OutputFile::Plain(file)
+		})
+	}
+
+	// write the end of the compressed stream, errors would be lost on drop
+	pub fn finish(self) -> Result<(), MyError> {
+		let mut file = match self {
+			OutputFile::Plain(file) => file,
+			OutputFile::Gzip(encoder) => encoder.finish()?,
+			OutputFile::Zstd(encoder) => encoder.finish()?
+		};
+		file.flush()?;
+		Ok(())
+	}
+}
+
+impl Write for OutputFile {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		match self {
+			OutputFile::Plain(file) => file.write(buf),
+			OutputFile::Gzip(encoder) => encoder.write(buf),
+			OutputFile::Zstd(encoder) => encoder.write(buf)
+		}
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		match self {
+			OutputFile::Plain(file) => file.flush(),
+			OutputFile::Gzip(encoder) => encoder.flush(),
+			OutputFile::Zstd(encoder) => encoder.flush()
+		}
+	}
+}
@@ -1,6 +1,8 @@
 
-use crate::sim::TestPacket;
+use crate::sim::{RoutingAlgorithm, TestPacket};
 use crate::graph::*;
+use crate::locations::Locations;
+use crate::dijkstra::Dijkstra;
 use crate::utils::MyError;
 
 
@@ -12,6 +14,12 @@ pub struct DebugPath {
 	step: u32
 }
 
+impl Default for DebugPath {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl DebugPath {
 	pub fn new() -> Self {
 		Self {
@@ -31,7 +39,7 @@ impl DebugPath {
 		self.step = 0;
 	}
 
-	pub fn step(&mut self, out: &mut std::fmt::Write, graph: &Graph, mut route: impl FnMut(&TestPacket) -> Option<u32>) -> Result<(), MyError> {
+	pub fn step(&mut self, out: &mut dyn std::fmt::Write, graph: &Graph, mut route: impl FnMut(&TestPacket) -> Option<u32>) -> Result<(), MyError> {
 		if !self.initialized {
 			return Err(MyError::new("Not initialized.".to_string()));
 		}
@@ -68,4 +76,70 @@ impl DebugPath {
 
 		Ok(())
 	}
+
+	// Route the whole path at once and print every hop, returns the nodes of the path
+	pub fn trace(&mut self, out: &mut dyn std::fmt::Write, graph: &Graph, locations: &Locations, algo: &dyn RoutingAlgorithm,
+			source: ID, target: ID) -> Result<Vec<ID>, MyError> {
+		let node_info = |out: &mut dyn std::fmt::Write, id: ID| -> Result<(), MyError> {
+			write!(out, "node {}", id)?;
+			if let Some(pos) = locations.get_position(id) {
+				write!(out, ", pos: {:.4}/{:.4}/{:.1}", pos[0], pos[1], pos[2])?;
+			}
+			let mut name = String::new();
+			algo.get_node(id, "name", &mut name)?;
+			if !name.is_empty() && name != id.to_string() {
+				write!(out, ", algo: {}", name)?;
+			}
+			Ok(())
+		};
+
+		self.init(source, target);
+		self.debug_running = false;
+
+		let mut path = vec![source];
+		let mut latency = 0.0;
+		// a loop-free path visits every node at most once
+		let max_hops = graph.node_count();
+
+		write!(out, "hop 0, ")?;
+		node_info(out, source)?;
+		writeln!(out)?;
+
+		let result = loop {
+			if self.step as usize >= max_hops {
+				break "Packet Lost - Hop limit reached.".to_string();
+			}
+
+			let next = match algo.route(&self.packet) {
+				Some(next) => next,
+				None => break "Packet Lost - No next hop.".to_string()
+			};
+
+			let link = match graph.get_link(self.packet.receiver, next) {
+				Some(link) => link,
+				None => break format!("Packet Lost - Invalid next hop: {}", next)
+			};
+
+			self.step += 1;
+			self.costs += link.cost() as u32;
+			latency += link.latency();
+			self.packet.transmitter = self.packet.receiver;
+			self.packet.receiver = next;
+			path.push(next);
+
+			write!(out, "hop {}, ", self.step)?;
+			node_info(out, next)?;
+			writeln!(out, ", link cost: {}, quality: {:.2}, latency: {}, loss: {}, path cost: {}",
+				link.cost(), link.quality() as f32 / u16::MAX as f32, link.latency(), link.loss(), self.costs)?;
+
+			if next == target {
+				let min = Dijkstra::new().find_shortest_distance(graph, source, target);
+				break format!("Packet arrived, hops: {}, cost: {}, shortest: {}, stretch: {}, latency: {}",
+					self.step, self.costs, min, self.costs as f32 / min, latency);
+			}
+		};
+
+		writeln!(out, "{}", result)?;
+		Ok(path)
+	}
 }
@@ -1,8 +1,6 @@
 use crate::graph::*;
 
 use std::collections::HashMap;
-use std::f32;
-use std::usize;
 
 
 /*
@@ -10,16 +8,30 @@ use std::usize;
 * Needs to be recreated if the graph changes.
 */
 
+#[derive(Clone)]
 pub struct Dijkstra {
 	dists_cache: HashMap<ID, Vec<f32>>,
-	prevs_cache: HashMap<ID, Vec<ID>>
+	prevs_cache: HashMap<ID, Vec<ID>>,
+	// link weight, the cost by default
+	weight: fn(&Link) -> f32
+}
+
+impl Default for Dijkstra {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Dijkstra {
 	pub fn new() -> Dijkstra {
+		Dijkstra::with_weight(|link| link.cost() as f32)
+	}
+
+	pub fn with_weight(weight: fn(&Link) -> f32) -> Dijkstra {
 		Dijkstra {
 			dists_cache: HashMap::new(),
-			prevs_cache: HashMap::new()
+			prevs_cache: HashMap::new(),
+			weight
 		}
 	}
 
@@ -39,39 +51,21 @@ impl Dijkstra {
 
 		// try again
 		if let Some(dists) = self.dists_cache.get(&source) {
-			return dists[target as usize];
+			dists[target as usize]
 		} else {
 			panic!("Dijkstra: Cannot compute path - should not happen.");
 		}
 	}
 
-	fn get_shortest_path(self: &Dijkstra, source: ID, target: ID) -> Option<Vec<ID>> {
-		if let Some(prevs) = self.prevs_cache.get(&source) {
-			let mut path = vec![];
-			let mut next = target;
-			loop {
-				let prev = prevs[next as usize];
-				if prev != ID::max_value() {
-					next = prev;
-					path.push(next);
-				} else {
-					break;
-				}
-			}
-			return Some(path);
-		}
-		None
-	}
-
 	pub fn calculate_shortest_paths(self: &mut Dijkstra, graph: &Graph, source: ID) {
 		let len = graph.node_count();
 		let mut dists = vec![f32::INFINITY; len];
-		let mut prevs = vec![ID::max_value(); len];
+		let mut prevs = vec![ID::MAX; len];
 		let mut q = vec![true; len];
 
 		dists[source as usize] = 0.0;
 
-		fn get_smallest(q: &Vec<bool>, dists: &Vec<f32>) -> usize {
+		fn get_smallest(q: &[bool], dists: &[f32]) -> usize {
 			let mut dist = f32::INFINITY;
 			let mut node = usize::MAX; //HM: may cause segfault
 
@@ -85,10 +79,14 @@ impl Dijkstra {
 				}
 			}
 			node
-		};
+		}
 
 		for _ in 0..len {
 			let u = get_smallest(&q, &dists);
+			if u == usize::MAX {
+				// remaining nodes are unreachable
+				break;
+			}
 			q[u] = false;
 			let links = graph.get_neighbors(u as ID);
 
@@ -96,7 +94,7 @@ impl Dijkstra {
 				let v = link.to as usize;
 				if q[v] {
 					// distance update
-					let alt = dists[u] + link.cost() as f32;
+					let alt = dists[u] + (self.weight)(link);
 					if alt < dists[v] {
 						dists[v] = alt;
 						prevs[v] = u as ID;
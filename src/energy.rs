@@ -0,0 +1,173 @@
+
+use crate::graph::{Graph, Link, ID};
+
+
+/*
+ * Battery model for node lifetime studies.
+ * On every simulation step, a node spends idle energy and
+ * transmission energy per outgoing link. Empty nodes die
+ * and lose all links. With solar recharge, dead nodes come
+ * back when the battery is charged to REVIVE_LEVEL again.
+ * Energy is in joules.
+ */
+
+// fraction of the capacity a dead node needs to come back
+const REVIVE_LEVEL : f32 = 0.1;
+
+#[derive(Clone)]
+pub struct Energy {
+	// battery capacity, 0 disables the energy model
+	capacity: f32,
+	idle_cost: f32,
+	tx_cost: f32,
+	// peak recharge per step and length of a day in steps
+	solar_power: f32,
+	day_steps: u32,
+	steps: u32,
+	levels: Vec<f32>,
+	// dead nodes and their removed links
+	dead_nodes: Vec<(ID, Vec<Link>)>
+}
+
+impl Default for Energy {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Energy {
+	pub fn new() -> Self {
+		Self {
+			capacity: 0.0,
+			idle_cost: 0.0,
+			tx_cost: 0.0,
+			solar_power: 0.0,
+			day_steps: 0,
+			steps: 0,
+			levels: vec![],
+			dead_nodes: vec![]
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.capacity > 0.0
+	}
+
+	pub fn init(&mut self, capacity: f32, idle_cost: f32, tx_cost: f32, node_count: usize) {
+		self.capacity = capacity;
+		self.idle_cost = idle_cost;
+		self.tx_cost = tx_cost;
+		self.reset(node_count);
+	}
+
+	pub fn set_solar(&mut self, power: f32, day_steps: u32) {
+		self.solar_power = power;
+		self.day_steps = day_steps;
+	}
+
+	// fill all batteries, e.g. when the topology was replaced
+	pub fn reset(&mut self, node_count: usize) {
+		self.steps = 0;
+		self.dead_nodes.clear();
+		self.levels.clear();
+		if self.is_enabled() {
+			self.levels.resize(node_count, self.capacity);
+		}
+	}
+
+	// remaining energy of a node
+	pub fn get_level(&self, id: ID) -> Option<f32> {
+		self.levels.get(id as usize).cloned()
+	}
+
+	pub fn is_dead(&self, id: ID) -> bool {
+		self.dead_nodes.iter().any(|(dead, _)| *dead == id)
+	}
+
+	pub fn get_info(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if !self.is_enabled() {
+			return writeln!(out, "energy model disabled");
+		}
+
+		let total = self.levels.iter().sum::<f32>();
+		let min = self.levels.iter().cloned().fold(f32::INFINITY, f32::min);
+		let mean = total / self.levels.len() as f32;
+
+		writeln!(out, "capacity: {}J, idle: {}J/step, tx: {}J/link/step", self.capacity, self.idle_cost, self.tx_cost)?;
+		if self.solar_power > 0.0 {
+			writeln!(out, "solar: {}J/step peak, day: {} steps", self.solar_power, self.day_steps)?;
+		}
+		writeln!(out, "energy: total {}J, mean {}J, min {}J", total, mean, min)?;
+		writeln!(out, "dead nodes: {}", self.dead_nodes.len())?;
+		Ok(())
+	}
+
+	// solar recharge, zero at night
+	fn recharge(&self) -> f32 {
+		if self.solar_power <= 0.0 || self.day_steps == 0 {
+			return 0.0;
+		}
+		let phase = (self.steps % self.day_steps) as f32 / self.day_steps as f32;
+		self.solar_power * f32::max(0.0, (2.0 * std::f32::consts::PI * phase).sin())
+	}
+
+	pub fn step(&mut self, graph: &mut Graph) {
+		if !self.is_enabled() {
+			return;
+		}
+
+		let recharge = self.recharge();
+		self.steps += 1;
+
+		if self.levels.len() != graph.node_count() {
+			self.levels.resize(graph.node_count(), self.capacity);
+		}
+
+		let mut died = vec![];
+		for id in 0..graph.node_count() {
+			let dead = self.is_dead(id as ID);
+			let cost = if dead {
+				0.0
+			} else {
+				self.idle_cost + self.tx_cost * graph.get_neighbors(id as ID).len() as f32
+			};
+			let level = &mut self.levels[id];
+			*level = f32::min(self.capacity, f32::max(0.0, *level - cost + recharge));
+			if !dead && *level <= 0.0 {
+				died.push(id as ID);
+			}
+		}
+
+		// revive recharged nodes
+		let revive_level = REVIVE_LEVEL * self.capacity;
+		let levels = &self.levels;
+		let mut revived = vec![];
+		self.dead_nodes.retain(|(id, links)| {
+			if levels[*id as usize] >= revive_level {
+				revived.push(links.clone());
+				false
+			} else {
+				true
+			}
+		});
+
+		for links in revived {
+			for link in links {
+				// links to nodes that are still dead come back with them
+				if let Some((_, other_links)) = self.dead_nodes.iter_mut()
+					.find(|(dead, _)| *dead == link.from || *dead == link.to) {
+					other_links.push(link);
+				} else {
+					graph.insert_link(link);
+				}
+			}
+		}
+
+		for id in died {
+			let links : Vec<Link> = graph.links().iter()
+				.filter(|link| link.from == id || link.to == id).cloned().collect();
+			graph.retain_links(|link| link.from != id && link.to != id);
+			self.dead_nodes.push((id, links));
+		}
+	}
+}
@@ -1,29 +1,96 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Instant, Duration};
-use rand::Rng;
 
-use crate::progress::Progress;
+use crate::progress::{is_cancelled, Progress};
 use crate::sim::TestPacket;
 use crate::dijkstra::Dijkstra;
 use crate::graph::*;
+use crate::locations::Locations;
+use crate::traffic::Traffic;
+use crate::utils::{print_unknown_key, with_rng};
+
+
+// what the length of a route is compared to for the stretch
+#[derive(Clone, Copy, PartialEq)]
+pub enum Reference {
+	// hop count of the shortest path
+	Hops,
+	// link cost of the cheapest path
+	Weighted,
+	// distance between the node positions, the route length is the sum of the link lengths
+	Geo
+}
+
+impl Reference {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Reference::Hops => "hops",
+			Reference::Weighted => "weighted",
+			Reference::Geo => "geo"
+		}
+	}
+
+	pub fn parse(name: &str) -> Option<Self> {
+		match name {
+			"hops" => Some(Reference::Hops),
+			"weighted" => Some(Reference::Weighted),
+			"geo" => Some(Reference::Geo),
+			_ => None
+		}
+	}
+}
 
 
 /*
  * Test if all paths allow for routing.
  * This test does not allow the state of the routing algorithm to change.
  */
+#[derive(Clone)]
 pub struct EvalPaths {
 	show_progress: bool,
 	is_done: bool,
 	packets_send: u32,
 	packets_lost: u32,
 	packets_arrived: u32,
-	route_costs_sum: u32,
-	route_costs_min_sum: u32,
+	route_costs_sum: f32,
+	route_costs_min_sum: f32,
 	nodes_connected: usize,
 	nodes_disconnected: usize,
+	// paths over nodes without position, they have no geo reference
+	paths_no_reference: usize,
 	max_stretch: u32,
 	run_time: Duration,
-	dijkstra: Dijkstra
+	dijkstra: Dijkstra,
+	hop_dijkstra: Dijkstra,
+	reference: Reference,
+	// node positions for the geo reference
+	positions: Vec<[f32; 3]>,
+	// shortest paths by latency, only used if links have attributes
+	latency_dijkstra: Dijkstra,
+	link_metrics: bool,
+	// number of routes forwarded by each node / sent over each link
+	node_load: Vec<u32>,
+	link_load: HashMap<(ID, ID), u32>,
+	// stretch and hop count of every arrived packet
+	path_stretch: Vec<f32>,
+	path_hops: Vec<u32>,
+	// latency, latency stretch and delivery probability of every arrived packet
+	path_latency: Vec<f32>,
+	latency_stretch: Vec<f32>,
+	path_delivery: Vec<f32>,
+	// multipath: fraction of the evenly split packets that arrived,
+	// next hops per forwarding decision
+	flow_arrived: f32,
+	next_hops: u32,
+	forwards: u32,
+	pub traffic: Traffic
+}
+
+impl Default for EvalPaths {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl EvalPaths {
@@ -34,13 +101,30 @@ impl EvalPaths {
 			packets_send: 0,
 			packets_lost: 0,
 			packets_arrived: 0,
-			route_costs_sum: 0,
-			route_costs_min_sum: 0,
+			route_costs_sum: 0.0,
+			route_costs_min_sum: 0.0,
 			nodes_connected: 0,
 			nodes_disconnected: 0,
+			paths_no_reference: 0,
 			max_stretch: 2,
 			run_time: Duration::new(0, 0),
 			dijkstra: Dijkstra::new(),
+			hop_dijkstra: Dijkstra::with_weight(|_| 1.0),
+			reference: Reference::Weighted,
+			positions: vec![],
+			latency_dijkstra: Dijkstra::with_weight(|link| link.latency()),
+			link_metrics: false,
+			node_load: vec![],
+			link_load: HashMap::new(),
+			path_stretch: vec![],
+			path_hops: vec![],
+			path_latency: vec![],
+			latency_stretch: vec![],
+			path_delivery: vec![],
+			flow_arrived: 0.0,
+			next_hops: 0,
+			forwards: 0,
+			traffic: Traffic::Uniform
 		}
 	}
 
@@ -49,30 +133,126 @@ impl EvalPaths {
 		self.packets_send = 0;
 		self.packets_lost = 0;
 		self.packets_arrived = 0;
-		self.route_costs_sum = 0;
-		self.route_costs_min_sum = 0;
+		self.route_costs_sum = 0.0;
+		self.route_costs_min_sum = 0.0;
 		self.nodes_connected = 0;
 		self.nodes_disconnected = 0;
+		self.paths_no_reference = 0;
 		self.run_time = Duration::new(0, 0);
+		self.node_load.clear();
+		self.link_load.clear();
+		self.path_stretch.clear();
+		self.path_hops.clear();
+		self.path_latency.clear();
+		self.latency_stretch.clear();
+		self.path_delivery.clear();
+		self.flow_arrived = 0.0;
+		self.next_hops = 0;
+		self.forwards = 0;
 	}
 
 	pub fn clear(&mut self) {
 		self.dijkstra.clear();
+		self.hop_dijkstra.clear();
+		self.latency_dijkstra.clear();
 		self.clear_stats();
 	}
 
+	pub fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		if key == "reference" {
+			write!(out, "{}", self.reference.name())?;
+		}
+		Ok(())
+	}
+
+	pub fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"reference" => match Reference::parse(value) {
+				Some(reference) => self.reference = reference,
+				None => println!("invalid reference value, expected geo, hops or weighted")
+			},
+			_ => {
+				print_unknown_key(key);
+			}
+		}
+		Ok(())
+	}
+
+	// called before every test run
+	fn prepare(&mut self, graph: &Graph, locations: &Locations) {
+		// latency and loss are only evaluated if any link has attributes
+		self.link_metrics = graph.links().iter().any(|link| !link.has_default_attributes());
+
+		self.positions.clear();
+		if self.reference == Reference::Geo {
+			// nodes without position have no geo reference
+			let missing = [f32::NAN; 3];
+			self.positions = (0..graph.node_count() as ID)
+				.map(|id| *locations.get_position(id).unwrap_or(&missing))
+				.collect();
+		}
+	}
+
+	fn geo_distance(&self, from: ID, to: ID) -> f32 {
+		let a = &self.positions[from as usize];
+		let b = &self.positions[to as usize];
+		((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+	}
+
+	// length of a link used for the stretch
+	fn link_length(&self, link: &Link) -> f32 {
+		match self.reference {
+			Reference::Hops => 1.0,
+			Reference::Weighted => link.cost() as f32,
+			Reference::Geo => self.geo_distance(link.from, link.to)
+		}
+	}
+
+	// Costs of the shortest path to limit the route length and the
+	// reference length for the stretch, None if there is no path.
+	// The geo reference is NaN if the source or target has no position.
+	fn reference_length(&mut self, graph: &Graph, source: ID, target: ID) -> Option<(u32, f32)> {
+		let (costs, reference) = match self.reference {
+			Reference::Weighted => {
+				let min = self.dijkstra.find_shortest_distance(graph, source, target);
+				(min, min)
+			},
+			Reference::Hops => {
+				let min = self.hop_dijkstra.find_shortest_distance(graph, source, target);
+				(min, min)
+			},
+			Reference::Geo => {
+				let min = self.hop_dijkstra.find_shortest_distance(graph, source, target);
+				(min, self.geo_distance(source, target))
+			}
+		};
+
+		if costs.is_finite() {
+			Some((costs as u32, reference))
+		} else {
+			None
+		}
+	}
+
 	pub fn show_progress(&mut self, show_progress: bool) {
-		self.show_progress = true;
+		self.show_progress = show_progress;
 	}
 
 	fn test_path(&mut self, graph: &Graph, mut route: impl FnMut(&TestPacket) -> Option<u32>,
-			source: ID, target: ID, costs_min: u32) {
+			source: ID, target: ID, (costs_min, reference): (u32, f32)) {
 		// maximum stretch we record
 		let mut packet = TestPacket::new(source, source, source, target);
-		let mut path_costs = 0u32;
+		let mut path_costs = 0.0;
+		let mut hops = 0u32;
+		let mut latency = 0.0;
+		let mut delivery = 1.0;
 
 		self.packets_send += 1;
 
+		if self.node_load.len() != graph.node_count() {
+			self.node_load.resize(graph.node_count(), 0);
+		}
+
 		// max steps to try until we give up
 		let max_steps = costs_min * self.max_stretch;
 
@@ -80,13 +260,30 @@ impl EvalPaths {
 			if let Some(next) = route(&packet) {
 				// Check if link really exists
 				if let Some(link) = graph.get_link(packet.receiver, next) {
-					path_costs += link.cost() as u32;
+					path_costs += self.link_length(&link);
+					latency += link.latency();
+					delivery *= link.delivery_ratio();
+					*self.link_load.entry((packet.receiver, next)).or_insert(0) += 1;
+					hops += 1;
 					if next == packet.destination {
 						// packet arrived
 						self.packets_arrived += 1;
+						if reference > 0.0 && path_costs.is_finite() {
+							self.path_stretch.push(path_costs / reference);
+						}
+						self.path_hops.push(hops);
+						if self.link_metrics {
+							let latency_min = self.latency_dijkstra.find_shortest_distance(graph, source, target);
+							if latency_min > 0.0 {
+								self.latency_stretch.push(latency / latency_min);
+							}
+							self.path_latency.push(latency);
+							self.path_delivery.push(delivery);
+						}
 						break;
 					} else {
 						// forward packet
+						self.node_load[next as usize] += 1;
 						packet.transmitter = packet.receiver;
 						packet.receiver = next;
 					}
@@ -102,13 +299,123 @@ impl EvalPaths {
 			}
 		}
 
-		self.route_costs_sum += path_costs;
-		self.route_costs_min_sum += costs_min;
+		self.add_route_costs(path_costs, reference);
+	}
+
+	// paths over nodes without position are left out of the stretch
+	fn add_route_costs(&mut self, costs: f32, reference: f32) {
+		if costs.is_finite() && reference.is_finite() {
+			self.route_costs_sum += costs;
+			self.route_costs_min_sum += reference;
+		} else {
+			self.paths_no_reference += 1;
+		}
+	}
+
+	// Follow all candidate next hops at once. The packet arrives if any path
+	// reaches the destination, the stretch is that of the best path.
+	// The packet is split evenly over the next hops to see how much arrives,
+	// parts caught in a loop are lost after the maximum number of steps.
+	fn test_multipath(&mut self, graph: &Graph, route: impl Fn(&TestPacket) -> Vec<u32>,
+			source: ID, target: ID, (costs_min, reference): (u32, f32)) {
+		// (transmitter, receiver, flow, costs, hops)
+		let mut frontier = vec![(source, source, 1.0f32, 0.0f32, 0u32)];
+		let mut best : Option<(f32, u32)> = None;
+		let mut max_costs = 0.0f32;
+		let mut flow_arrived = 0.0;
+
+		self.packets_send += 1;
+
+		if self.node_load.len() != graph.node_count() {
+			self.node_load.resize(graph.node_count(), 0);
+		}
+
+		let max_steps = costs_min * self.max_stretch;
+
+		for _ in 0..max_steps {
+			if frontier.is_empty() {
+				break;
+			}
+
+			let mut next_frontier : Vec<(ID, ID, f32, f32, u32)> = vec![];
+			for (transmitter, receiver, flow, costs, hops) in frontier {
+				let packet = TestPacket::new(transmitter, receiver, source, target);
+				let mut nexts = route(&packet);
+				nexts.sort_unstable();
+				nexts.dedup();
+				// only existing links
+				let nexts : Vec<(ID, f32)> = nexts.into_iter()
+					.filter_map(|next| graph.get_link(receiver, next).map(|link| (next, self.link_length(&link))))
+					.collect();
+
+				if nexts.is_empty() {
+					continue;
+				}
+
+				self.forwards += 1;
+				self.next_hops += nexts.len() as u32;
+				let share = flow / nexts.len() as f32;
+
+				for (next, cost) in nexts {
+					*self.link_load.entry((receiver, next)).or_insert(0) += 1;
+					let costs = costs + cost;
+					// NaN if a node on the path has no position
+					max_costs = if max_costs.is_nan() || costs.is_nan() { f32::NAN } else { max_costs.max(costs) };
+					if next == target {
+						flow_arrived += share;
+						if best.is_none_or(|(c, _)| costs < c) {
+							best = Some((costs, hops + 1));
+						}
+					} else if let Some(entry) = next_frontier.iter_mut().find(|e| e.1 == next) {
+						// reached over several paths
+						entry.2 += share;
+						if costs < entry.3 {
+							entry.0 = receiver;
+							entry.3 = costs;
+						}
+					} else {
+						next_frontier.push((receiver, next, share, costs, hops + 1));
+					}
+				}
+			}
+
+			for entry in &next_frontier {
+				self.node_load[entry.1 as usize] += 1;
+			}
+			frontier = next_frontier;
+		}
+
+		if let Some((costs, hops)) = best {
+			self.packets_arrived += 1;
+			if reference > 0.0 && costs.is_finite() {
+				self.path_stretch.push(costs / reference);
+			}
+			self.path_hops.push(hops);
+			self.add_route_costs(costs, reference);
+		} else {
+			self.packets_lost += 1;
+			self.add_route_costs(max_costs, reference);
+		}
+
+		self.flow_arrived += flow_arrived;
 	}
 
-	pub fn run_samples(&mut self, graph: &Graph, mut route: impl FnMut(&TestPacket) -> Option<u32>,
-			samples: usize) {
+	pub fn run_samples(&mut self, graph: &Graph, locations: &Locations, route: impl Fn(&TestPacket) -> Option<u32> + Sync,
+			samples: usize, threads: usize) {
+		self.run_sampled(graph, locations, samples, threads,
+			|test, graph, source, target, min| test.test_path(graph, &route, source, target, min));
+	}
+
+	pub fn run_samples_multipath(&mut self, graph: &Graph, locations: &Locations, route: impl Fn(&TestPacket) -> Vec<u32> + Sync,
+			samples: usize, threads: usize) {
+		self.run_sampled(graph, locations, samples, threads,
+			|test, graph, source, target, min| test.test_multipath(graph, &route, source, target, min));
+	}
+
+	fn run_sampled(&mut self, graph: &Graph, locations: &Locations, samples: usize, threads: usize,
+			test: impl Fn(&mut EvalPaths, &Graph, ID, ID, (u32, f32)) + Sync) {
 		self.clear();
+		self.prepare(graph, locations);
 
 		let node_count = graph.node_count();
 		if node_count < 2 {
@@ -117,36 +424,73 @@ impl EvalPaths {
 
 		let now = Instant::now();
 		let mut progress = Progress::new();
-		let mut sample = 0;
 
 		if self.show_progress {
 			progress.start(samples, 0);
 		}
 
-		for _ in 0..samples {
-			let source = rand::thread_rng().gen_range(0, node_count);
-			let target = rand::thread_rng().gen_range(0, node_count);
+		let mut pairs = Vec::with_capacity(samples);
+		with_rng(|rng| {
+			for _ in 0..samples {
+				let (source, target) = self.traffic.sample(rng, node_count);
 
-			if source == target {
-				// we do not test those paths
-				continue;
-			}
+				if source == target || source as usize >= node_count || target as usize >= node_count {
+					// we do not test those paths
+					continue;
+				}
 
-			let min = self.dijkstra.find_shortest_distance(graph, source as ID, target as ID);
-			if !min.is_finite() {
-				// no path from target to source => ignore
-				self.nodes_disconnected += 1;
-				continue;
-			} else {
-				self.nodes_connected += 1;
+				pairs.push((source, target));
 			}
+		});
+
+		// paths from the same source share the shortest path calculation
+		pairs.sort_unstable();
 
-			self.test_path(&graph, &mut route, source as ID, target as ID, min as u32);
+		let done = AtomicUsize::new(0);
+		let threads = threads.max(1).min(pairs.len().max(1));
+
+		if threads == 1 {
+			let show_progress = self.show_progress;
+			self.run_pairs(graph, &test, &pairs, &done, |done| {
+				if show_progress {
+					progress.update(samples, done);
+				}
+			});
+		} else {
+			// every thread has its own statistics and shortest path cache
+			let chunk_size = pairs.len().div_ceil(threads);
+			let max_stretch = self.max_stretch;
+			let link_metrics = self.link_metrics;
+			let reference = self.reference;
+			let positions = &self.positions;
+			let show_progress = self.show_progress;
+			let workers : Vec<EvalPaths> = std::thread::scope(|scope| {
+				let handles : Vec<_> = pairs.chunks(chunk_size).map(|chunk| {
+					let test = &test;
+					let done = &done;
+					scope.spawn(move || {
+						let mut worker = EvalPaths::new();
+						worker.max_stretch = max_stretch;
+						worker.link_metrics = link_metrics;
+						worker.reference = reference;
+						worker.positions = positions.clone();
+						worker.run_pairs(graph, test, chunk, done, |_| {});
+						worker
+					})
+				}).collect();
+
+				while !handles.iter().all(|handle| handle.is_finished()) {
+					if show_progress {
+						progress.update(samples, done.load(Ordering::Relaxed));
+					}
+					std::thread::sleep(Duration::from_millis(100));
+				}
 
-			sample += 1;
+				handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+			});
 
-			if self.show_progress {
-				progress.update(samples, sample);
+			for worker in workers {
+				self.merge(worker);
 			}
 		}
 
@@ -158,8 +502,105 @@ impl EvalPaths {
 		self.is_done = true;
 	}
 
-	pub fn run_all(&mut self, graph: &Graph, mut route: impl FnMut(&TestPacket) -> Option<u32>) {
+	fn run_pairs(&mut self, graph: &Graph, test: &(impl Fn(&mut EvalPaths, &Graph, ID, ID, (u32, f32)) + Sync),
+			pairs: &[(ID, ID)], done: &AtomicUsize, mut update: impl FnMut(usize)) {
+		for (source, target) in pairs {
+			if is_cancelled() {
+				break;
+			}
+
+			if let Some(min) = self.reference_length(graph, *source, *target) {
+				self.nodes_connected += 1;
+				test(self, graph, *source, *target, min);
+			} else {
+				// no path from target to source => ignore
+				self.nodes_disconnected += 1;
+			}
+
+			update(done.fetch_add(1, Ordering::Relaxed) + 1);
+		}
+	}
+
+	// add the statistics of a test run in another thread
+	fn merge(&mut self, other: EvalPaths) {
+		self.packets_send += other.packets_send;
+		self.packets_lost += other.packets_lost;
+		self.packets_arrived += other.packets_arrived;
+		self.route_costs_sum += other.route_costs_sum;
+		self.route_costs_min_sum += other.route_costs_min_sum;
+		self.nodes_connected += other.nodes_connected;
+		self.nodes_disconnected += other.nodes_disconnected;
+		self.paths_no_reference += other.paths_no_reference;
+
+		if self.node_load.len() < other.node_load.len() {
+			self.node_load.resize(other.node_load.len(), 0);
+		}
+		for (load, other) in self.node_load.iter_mut().zip(other.node_load.iter()) {
+			*load += other;
+		}
+		for (link, load) in other.link_load {
+			*self.link_load.entry(link).or_insert(0) += load;
+		}
+
+		self.path_stretch.extend(other.path_stretch);
+		self.path_hops.extend(other.path_hops);
+		self.path_latency.extend(other.path_latency);
+		self.latency_stretch.extend(other.latency_stretch);
+		self.path_delivery.extend(other.path_delivery);
+		self.flow_arrived += other.flow_arrived;
+		self.next_hops += other.next_hops;
+		self.forwards += other.forwards;
+	}
+
+	// route from every node to the nearest gateway, returns the number of arrived packets per gateway
+	pub fn run_gateways(&mut self, graph: &Graph, locations: &Locations, mut route: impl FnMut(&TestPacket) -> Option<u32>,
+			gateways: &[ID]) -> Vec<u32> {
+		self.clear();
+		self.prepare(graph, locations);
+
+		let mut load = vec![0; gateways.len()];
+		let node_count = graph.node_count();
+		let now = Instant::now();
+
+		for source in 0..node_count as ID {
+			if gateways.contains(&source) {
+				continue;
+			}
+
+			// nearest gateway
+			let mut nearest = None;
+			for (i, gateway) in gateways.iter().enumerate() {
+				if *gateway as usize >= node_count {
+					continue;
+				}
+				let distance = self.dijkstra.find_shortest_distance(graph, source, *gateway);
+				if distance.is_finite() && nearest.is_none_or(|(_, d)| distance < d) {
+					nearest = Some((i, distance));
+				}
+			}
+
+			match nearest.and_then(|(i, _)| self.reference_length(graph, source, gateways[i]).map(|min| (i, min))) {
+				Some((i, min)) => {
+					self.nodes_connected += 1;
+					let arrived = self.packets_arrived;
+					self.test_path(graph, &mut route, source, gateways[i], min);
+					load[i] += self.packets_arrived - arrived;
+				},
+				None => {
+					self.nodes_disconnected += 1;
+				}
+			}
+		}
+
+		self.run_time = now.elapsed();
+		self.is_done = true;
+
+		load
+	}
+
+	pub fn run_all(&mut self, graph: &Graph, locations: &Locations, mut route: impl FnMut(&TestPacket) -> Option<u32>) {
 		self.clear();
+		self.prepare(graph, locations);
 
 		let node_count = graph.node_count();
 		if node_count < 2 {
@@ -167,7 +608,6 @@ impl EvalPaths {
 		}
 
 		let now = Instant::now();
-		let tests = (node_count as usize).pow(2);
 		//let mut progress = Progress::new("test: ");
 		//let mut test = 0;
 
@@ -178,16 +618,17 @@ impl EvalPaths {
 					continue;
 				}
 
-				let min = self.dijkstra.find_shortest_distance(graph, source as ID, target as ID);
-				if !min.is_finite() {
-					// no path from target to source => ignore
-					self.nodes_disconnected += 1;
-					continue;
-				} else {
-					self.nodes_connected += 1;
-				}
+				let min = match self.reference_length(graph, source as ID, target as ID) {
+					Some(min) => min,
+					None => {
+						// no path from target to source => ignore
+						self.nodes_disconnected += 1;
+						continue;
+					}
+				};
+				self.nodes_connected += 1;
 
-				self.test_path(&graph, &mut route, source as ID, target as ID, min as u32);
+				self.test_path(graph, &mut route, source as ID, target as ID, min);
 
 				//test += 1;
 				//progress.update(tests, test);
@@ -200,18 +641,122 @@ impl EvalPaths {
 		//progress.clear_line();
 	}
 
+	pub fn has_load(&self) -> bool {
+		!self.node_load.is_empty()
+	}
+
+	// routes forwarded by a node (source and destination excluded)
+	pub fn node_load(&self, id: ID) -> u32 {
+		self.node_load.get(id as usize).cloned().unwrap_or(0)
+	}
+
+	// routes sent over a link
+	pub fn link_load(&self, from: ID, to: ID) -> u32 {
+		self.link_load.get(&(from, to)).cloned().unwrap_or(0)
+	}
+
+	// most loaded nodes first
+	pub fn top_nodes(&self, count: usize) -> Vec<(ID, u32)> {
+		let mut nodes : Vec<(ID, u32)> = self.node_load.iter().enumerate()
+			.filter(|(_, load)| **load > 0)
+			.map(|(id, load)| (id as ID, *load)).collect();
+		nodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		nodes.truncate(count);
+		nodes
+	}
+
+	// most loaded links first
+	pub fn top_links(&self, count: usize) -> Vec<(ID, ID, u32)> {
+		let mut links : Vec<(ID, ID, u32)> = self.link_load.iter()
+			.map(|(link, load)| (link.0, link.1, *load)).collect();
+		links.sort_by(|a, b| b.2.cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+		links.truncate(count);
+		links
+	}
+
+	// p50, p90, p99 and max of the stretch of arrived packets
+	pub fn stretch_percentiles(&self) -> [f32; 4] {
+		percentiles(&self.path_stretch)
+	}
+
+	// stretch samples that are not finite and left out of the percentiles
+	pub fn stretch_dropped(&self) -> usize {
+		self.path_stretch.iter().filter(|s| !s.is_finite()).count()
+	}
+
+	// p50, p90, p99 and max of the hop count of arrived packets
+	pub fn hops_percentiles(&self) -> [f32; 4] {
+		let hops : Vec<f32> = self.path_hops.iter().map(|h| *h as f32).collect();
+		percentiles(&hops)
+	}
+
+	// write stretch (bins of 0.1) and hop count histograms as CSV
+	pub fn write_histogram(&self, path: &str) -> Result<(), std::io::Error> {
+		use std::io::Write;
+		let mut stretch = std::collections::BTreeMap::<u32, u32>::new();
+		for s in &self.path_stretch {
+			*stretch.entry((s * 10.0).floor() as u32).or_insert(0) += 1;
+		}
+		let mut hops = std::collections::BTreeMap::<u32, u32>::new();
+		for h in &self.path_hops {
+			*hops.entry(*h).or_insert(0) += 1;
+		}
+
+		let mut file = std::fs::File::create(path)?;
+		writeln!(file, "metric,value,count")?;
+		for (bin, count) in stretch {
+			writeln!(file, "stretch,{:.1},{}", bin as f32 / 10.0, count)?;
+		}
+		for (hops, count) in hops {
+			writeln!(file, "hops,{},{}", hops, count)?;
+		}
+		Ok(())
+	}
+
 	pub fn duration(&self) -> Duration {
 		self.run_time
 	}
 
 	pub fn stretch(&self) -> f32 {
-		(self.route_costs_sum as f32) / (self.route_costs_min_sum as f32)
+		self.route_costs_sum / self.route_costs_min_sum
 	}
 
 	pub fn arrived(&self) -> f32 {
 		100.0 * (self.packets_arrived as f32) / (self.packets_send as f32)
 	}
 
+	// mean latency (ms), latency stretch and delivery probability of the arrived packets,
+	// None without link attributes
+	pub fn link_metrics(&self) -> Option<(f32, f32, f32)> {
+		if !self.link_metrics || self.path_latency.is_empty() {
+			return None;
+		}
+		let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+		Some((mean(&self.path_latency), mean(&self.latency_stretch), mean(&self.path_delivery)))
+	}
+
+	// percentage of the packets that arrived when split evenly over all next hops
+	pub fn flow_arrived(&self) -> f32 {
+		100.0 * self.flow_arrived / (self.packets_send as f32)
+	}
+
+	// next hops per forwarding decision of a multipath test
+	pub fn next_hops(&self) -> f32 {
+		self.next_hops as f32 / self.forwards as f32
+	}
+
+	// load of the most used link compared to the mean of all used links, 1 is even
+	pub fn load_spread(&self) -> f32 {
+		let max = self.link_load.values().max().cloned().unwrap_or(0);
+		let sum : u32 = self.link_load.values().sum();
+		max as f32 * self.link_load.len() as f32 / sum as f32
+	}
+
+	// tested paths without stretch, since a node on the path has no position
+	pub fn paths_no_reference(&self) -> usize {
+		self.paths_no_reference
+	}
+
 	pub fn connectivity(&self) -> f32 {
 		100.0 * (self.nodes_connected as f32) / (self.nodes_connected + self.nodes_disconnected) as f32
 	}
@@ -224,3 +769,14 @@ impl EvalPaths {
 		]
 	}
 }
+
+// values that are not finite are left out
+fn percentiles(values: &[f32]) -> [f32; 4] {
+	let mut sorted : Vec<f32> = values.iter().cloned().filter(|v| v.is_finite()).collect();
+	if sorted.is_empty() {
+		return [f32::NAN; 4];
+	}
+	sorted.sort_by(f32::total_cmp);
+	let get = |p: f32| sorted[((p * sorted.len() as f32).ceil() as usize).max(1) - 1];
+	[get(0.5), get(0.9), get(0.99), sorted[sorted.len() - 1]]
+}
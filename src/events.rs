@@ -0,0 +1,296 @@
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::graph::{Graph, ID};
+use crate::utils::random;
+use crate::sim::{RoutingAlgorithm, TestPacket};
+
+
+/*
+ * Discrete event packet simulation.
+ * In contrast to EvalPaths, every packet is queued at the nodes and needs
+ * time to be transmitted over a link (packet size / bandwidth + latency).
+ * Packets are dropped on full queues, lossy links or missing routes.
+ * Time is measured in microseconds.
+ */
+
+const MAX_HOPS : u32 = 64;
+const HISTOGRAM_BUCKETS : usize = 10;
+
+#[derive(Clone)]
+struct Packet {
+	source: ID,
+	destination: ID,
+	transmitter: ID,
+	hops: u32,
+	created: u64
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum EventKind {
+	// node finished sending a packet
+	TransmitDone,
+	// packet arrives at the node
+	Arrive(usize)
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Event {
+	time: u64,
+	seq: u64,
+	node: ID,
+	kind: EventKind
+}
+
+#[derive(Clone)]
+struct NodeQueue {
+	queue: VecDeque<Packet>,
+	busy: bool
+}
+
+impl NodeQueue {
+	fn new() -> Self {
+		Self { queue: VecDeque::new(), busy: false }
+	}
+}
+
+#[derive(Clone)]
+pub struct EventStats {
+	pub sent: u64,
+	pub delivered: u64,
+	pub dropped_queue: u64,
+	pub dropped_loss: u64,
+	pub dropped_route: u64,
+	pub dropped_hops: u64,
+	// end to end delays of delivered packets
+	pub latencies: Vec<u64>
+}
+
+impl EventStats {
+	fn new() -> Self {
+		Self {
+			sent: 0,
+			delivered: 0,
+			dropped_queue: 0,
+			dropped_loss: 0,
+			dropped_route: 0,
+			dropped_hops: 0,
+			latencies: vec![]
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct EventSim {
+	time: u64,
+	seq: u64,
+	nodes: Vec<NodeQueue>,
+	events: BinaryHeap<Reverse<Event>>,
+	// packets in transit, referenced by Arrive events
+	packets: Vec<Option<Packet>>,
+	pub queue_size: usize,
+	pub packet_size: u32,
+	pub stats: EventStats
+}
+
+impl Default for EventSim {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl EventSim {
+	pub fn new() -> Self {
+		Self {
+			time: 0,
+			seq: 0,
+			nodes: vec![],
+			events: BinaryHeap::new(),
+			packets: vec![],
+			queue_size: 32,
+			packet_size: 1500,
+			stats: EventStats::new()
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.time = 0;
+		self.seq = 0;
+		self.nodes.clear();
+		self.events.clear();
+		self.packets.clear();
+		self.stats = EventStats::new();
+	}
+
+	pub fn get(&self, key: &str, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		match key {
+			"queue_size" => {
+				write!(out, "{}", self.queue_size)?;
+			},
+			"packet_size" => {
+				write!(out, "{}", self.packet_size)?;
+			},
+			"time" => {
+				write!(out, "{}", self.time)?;
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	pub fn set(&mut self, key: &str, value: &str) -> Result<(), std::fmt::Error> {
+		match key {
+			"queue_size" => {
+				if let Ok(size) = value.parse::<usize>() {
+					self.queue_size = usize::max(size, 1);
+				} else {
+					println!("invalid queue_size value");
+				}
+			},
+			"packet_size" => {
+				if let Ok(size) = value.parse::<u32>() {
+					self.packet_size = u32::max(size, 1);
+				} else {
+					println!("invalid packet_size value");
+				}
+			},
+			_ => {}
+		}
+		Ok(())
+	}
+
+	fn schedule(&mut self, time: u64, node: ID, kind: EventKind) {
+		self.seq += 1;
+		self.events.push(Reverse(Event { time, seq: self.seq, node, kind }));
+	}
+
+	fn enqueue(&mut self, node: ID, packet: Packet) {
+		let q = &mut self.nodes[node as usize];
+		if q.queue.len() >= self.queue_size {
+			self.stats.dropped_queue += 1;
+		} else {
+			q.queue.push_back(packet);
+		}
+	}
+
+	// start next transmission of an idle node
+	fn transmit(&mut self, graph: &Graph, algorithm: &dyn RoutingAlgorithm, node: ID) {
+		while !self.nodes[node as usize].busy {
+			let packet = match self.nodes[node as usize].queue.pop_front() {
+				Some(packet) => packet,
+				None => return
+			};
+
+			let test = TestPacket::new(packet.transmitter, node, packet.source, packet.destination);
+			let link = match algorithm.route(&test).and_then(|next| graph.get_link(node, next)) {
+				Some(link) => link,
+				None => {
+					self.stats.dropped_route += 1;
+					continue;
+				}
+			};
+
+			// bandwidth in MBit/s => bits per microsecond
+			let tx_time = (self.packet_size as f32 * 8.0 / link.bandwidth().max(0.001)) as u64;
+			let delay = (link.latency() * 1000.0) as u64;
+			let done = self.time + tx_time;
+
+			self.nodes[node as usize].busy = true;
+			self.schedule(done, node, EventKind::TransmitDone);
+
+			if random::<f32>() <= link.delivery_ratio() {
+				let idx = self.packets.len();
+				self.packets.push(Some(Packet { transmitter: node, hops: packet.hops + 1, ..packet }));
+				self.schedule(done + delay, link.to, EventKind::Arrive(idx));
+			} else {
+				self.stats.dropped_loss += 1;
+			}
+		}
+	}
+
+	// inject packets at the current time
+	pub fn send(&mut self, from: ID, to: ID, count: u32, node_count: usize) {
+		if self.nodes.len() != node_count {
+			self.nodes.resize(node_count, NodeQueue::new());
+		}
+
+		for _ in 0..count {
+			self.stats.sent += 1;
+			let packet = Packet { source: from, destination: to, transmitter: from, hops: 0, created: self.time };
+			self.enqueue(from, packet);
+		}
+	}
+
+	// process events until all packets are delivered or dropped
+	pub fn run(&mut self, graph: &Graph, algorithm: &dyn RoutingAlgorithm) {
+		for id in 0..self.nodes.len() {
+			self.transmit(graph, algorithm, id as ID);
+		}
+
+		while let Some(Reverse(event)) = self.events.pop() {
+			self.time = event.time;
+			let node = event.node;
+
+			if (node as usize) >= self.nodes.len() {
+				continue;
+			}
+
+			match event.kind {
+				EventKind::TransmitDone => {
+					self.nodes[node as usize].busy = false;
+				},
+				EventKind::Arrive(idx) => {
+					if let Some(packet) = self.packets[idx].take() {
+						if packet.destination == node {
+							self.stats.delivered += 1;
+							self.stats.latencies.push(self.time - packet.created);
+						} else if packet.hops >= MAX_HOPS {
+							self.stats.dropped_hops += 1;
+						} else {
+							self.enqueue(node, packet);
+						}
+					}
+				}
+			}
+
+			self.transmit(graph, algorithm, node);
+		}
+
+		self.packets.clear();
+	}
+
+	pub fn write_stats(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		let s = &self.stats;
+		writeln!(out, "sent: {}, delivered: {}", s.sent, s.delivered)?;
+		writeln!(out, "dropped: {} (queue), {} (loss), {} (no route), {} (hop limit)",
+			s.dropped_queue, s.dropped_loss, s.dropped_route, s.dropped_hops)?;
+
+		if s.latencies.is_empty() {
+			return Ok(());
+		}
+
+		let min = *s.latencies.iter().min().unwrap();
+		let max = *s.latencies.iter().max().unwrap();
+		let avg = s.latencies.iter().sum::<u64>() / s.latencies.len() as u64;
+		writeln!(out, "latency: min {:.3}ms, avg {:.3}ms, max {:.3}ms",
+			min as f32 / 1000.0, avg as f32 / 1000.0, max as f32 / 1000.0)?;
+
+		// histogram
+		let width = u64::max((max - min + HISTOGRAM_BUCKETS as u64) / HISTOGRAM_BUCKETS as u64, 1);
+		let mut buckets = [0u64; HISTOGRAM_BUCKETS];
+		for latency in &s.latencies {
+			let i = usize::min(((latency - min) / width) as usize, HISTOGRAM_BUCKETS - 1);
+			buckets[i] += 1;
+		}
+
+		let most = *buckets.iter().max().unwrap();
+		for (i, count) in buckets.iter().enumerate() {
+			let start = min + i as u64 * width;
+			let bar = (40 * count / u64::max(most, 1)) as usize;
+			writeln!(out, "{:>10.3}ms {:>8} {}", start as f32 / 1000.0, count, "#".repeat(bar))?;
+		}
+
+		Ok(())
+	}
+}
@@ -1,27 +1,64 @@
 
-use std::fs::File;
-use std::u16;
 use std::fmt::Write;
 
 use crate::sim::RoutingAlgorithm;
 use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::energy::Energy;
+use crate::eval_paths::EvalPaths;
 use crate::graph::{Graph, ID};
+use crate::style::{format_color, ExportStyle, Styling};
+use crate::binary::write_binary;
+use crate::compress::{split_path, OutputFile};
 use crate::utils::*;
 
 
-pub fn export_file(graph: &Graph, locations: Option<&Locations>,
-	algo: Option<&RoutingAlgorithm>, mark_links: Option<&Graph>, path: &str) {
+#[allow(clippy::too_many_arguments)]
+pub fn export_file(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>,
+	algo: Option<&dyn RoutingAlgorithm>, energy: Option<&Energy>, test: Option<&EvalPaths>, mark_links: Option<&Graph>,
+	style: Option<&ExportStyle>, path: &str) {
 	use std::io::Write;
-	if let Ok(mut file) = File::create(path) {
-		let content = export_json(&graph, locations, algo, mark_links);
-		file.write_all(content.as_bytes()).unwrap();
-		//println!("Wrote {}", path);
+	// compressed by the last extension (.gz or .zst), the format is given by the extension before
+	let name = split_path(path).0;
+	let mut file = match OutputFile::create(path) {
+		Ok(file) => file,
+		Err(_) => {
+			println!("Failed to create: {}", path);
+			return;
+		}
+	};
+
+	let result = if name.ends_with(".mgb") {
+		write_binary(graph, locations, meta, &mut file)
 	} else {
-		println!("Failed to create: {}", path);
+		let content = if name.ends_with(".gexf") {
+			export_gexf(graph, locations, meta)
+		} else if name.ends_with(".geojson") {
+			export_geojson(graph, locations, meta)
+		} else if name.ends_with(".kml") || name.ends_with(".kmz") {
+			export_kml(graph, locations, meta, algo, energy, test, mark_links, style)
+		} else if name.ends_with(".dot") {
+			export_dot(graph, locations, meta, algo, energy, test, mark_links, style)
+		} else {
+			export_json(graph, locations, meta, algo, energy, test, mark_links, style)
+		};
+		if name.ends_with(".kmz") {
+			write_kmz(&mut file, &content)
+		} else {
+			file.write_all(content.as_bytes()).map_err(MyError::from)
+		}
+	};
+
+	if let Err(e) = result.and_then(|_| file.finish()) {
+		println!("Failed to write {}: {}", path, e);
 	}
+	//println!("Wrote {}", path);
 }
 
-pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&RoutingAlgorithm>, mark_links: Option<&Graph>) -> String {
+#[allow(clippy::too_many_arguments)]
+pub fn export_json(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>, algo: Option<&dyn RoutingAlgorithm>,
+	energy: Option<&Energy>, test: Option<&EvalPaths>, mark_links: Option<&Graph>, style: Option<&ExportStyle>) -> String {
+	let styling = style.filter(|style| !style.is_empty()).map(|style| Styling::new(style, graph, meta, algo, energy, test));
 	let mut ret = String::new();
 	let mut name = String::new();
 	let mut label = String::new();
@@ -30,6 +67,7 @@ pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&R
 	write!(&mut ret, "{{").unwrap();
 	write!(&mut ret, "\"nodes\": [").unwrap();
 
+	let components = graph.get_component_ids();
 	let mut comma1 = false;
 	for id in 0..graph.node_count() as ID {
 		if comma1 {
@@ -46,6 +84,11 @@ pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&R
 			let _ = algo.get_node(id, "color", &mut color);
 		}
 
+		// the export style overrides the algorithm colors
+		if let Some(style_color) = styling.as_ref().and_then(|styling| styling.node_color(id)) {
+			color = format_color(&style_color);
+		}
+
 		write!(&mut ret, "{{\"id\": \"{}\"", id).unwrap();
 		if let Some(locs) = locations {
 			if let Some(pos) = locs.get_position(id) {
@@ -65,13 +108,40 @@ pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&R
 			write!(&mut ret, ", \"color\": \"{}\"", color).unwrap();
 		}
 
+		if let Some(size) = styling.as_ref().and_then(|styling| styling.node_size(id)) {
+			write!(&mut ret, ", \"size\": {}", size).unwrap();
+		}
+
+		write!(&mut ret, ", \"component\": {}", components[id as usize]).unwrap();
+
+		// community from the cluster command
+		if let Some(cluster) = meta.and_then(|meta| meta.get_value(id, "cluster")) {
+			write!(&mut ret, ", \"cluster\": {}", cluster).unwrap();
+		}
+
+		// relay suggested by optimize_placement
+		if meta.and_then(|meta| meta.get_value(id, "candidate")).is_some() {
+			write!(&mut ret, ", \"candidate\": true").unwrap();
+		}
+
+		// remaining battery energy
+		if let Some(level) = energy.and_then(|energy| energy.get_level(id)) {
+			write!(&mut ret, ", \"energy\": {}", level).unwrap();
+		}
+
+		// routes forwarded in the last test
+		if let Some(test) = test.filter(|test| test.has_load()) {
+			write!(&mut ret, ", \"load\": {}", test.node_load(id)).unwrap();
+		}
+
 		write!(&mut ret, "}}").unwrap();
 	}
 
 	write!(&mut ret, "], \"links\": [").unwrap();
 	let mut comma2 = false;
-	for link in &graph.links {
-		if link.from > link.to {
+	for link in graph.links() {
+		// bidirectional links are exported once
+		if link.from > link.to && graph.has_link(link.to, link.from) {
 			continue;
 		}
 
@@ -100,11 +170,30 @@ pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&R
 			).unwrap();
 		}
 
+		if !link.has_default_attributes() {
+			write!(&mut ret, ", \"cost\": {}, \"bandwidth\": {}, \"latency\": {}, \"loss\": {}",
+				link.cost(), link.bandwidth(), link.latency(), link.loss()
+			).unwrap();
+		}
+
+		if link.channel() != 0 {
+			write!(&mut ret, ", \"channel\": {}", link.channel()).unwrap();
+		}
+
+		// routes sent over the link (both directions) in the last test
+		if let Some(test) = test.filter(|test| test.has_load()) {
+			write!(&mut ret, ", \"load\": {}", test.link_load(source_id, target_id) + test.link_load(target_id, source_id)).unwrap();
+		}
+
 		// mark link with color
-		if let Some(mark) = mark_links {
-			if mark.has_link(source_id, target_id) {
-				write!(&mut ret, ", \"color\": \"#FF00FF\"").unwrap();
-			}
+		if mark_links.is_some_and(|mark| mark.has_link(source_id, target_id)) {
+			write!(&mut ret, ", \"color\": \"#FF00FF\"").unwrap();
+		} else if let Some(color) = styling.as_ref().and_then(|styling| styling.link_color(source_id, target_id)) {
+			write!(&mut ret, ", \"color\": \"{}\"", format_color(&color)).unwrap();
+		}
+
+		if let Some(width) = styling.as_ref().and_then(|styling| styling.link_width(source_id, target_id)) {
+			write!(&mut ret, ", \"width\": {}", width).unwrap();
 		}
 
 		write!(&mut ret, "}}").unwrap();
@@ -115,6 +204,370 @@ pub fn export_json(graph: &Graph, locations: Option<&Locations>, algo: Option<&R
 	ret
 }
 
+fn xml_escape(s: &str) -> String {
+	s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// node metadata as list of key/value pairs
+fn meta_attributes(meta: Option<&Meta>, id: ID) -> Vec<(String, String)> {
+	let mut ret = vec![];
+	if let Some(data) = meta.and_then(|meta| meta.data.get(&id)) {
+		if let Ok(serde_json::Value::Object(obj)) = serde_json::from_str::<serde_json::Value>(data) {
+			for (key, value) in obj {
+				let value = match value {
+					serde_json::Value::String(s) => s,
+					other => other.to_string()
+				};
+				ret.push((key, value));
+			}
+		}
+	}
+	ret
+}
+
+pub fn export_gexf(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>) -> String {
+	let mut ret = String::new();
+
+	// collect metadata keys for the attribute declarations
+	let mut keys : Vec<String> = vec![];
+	for id in 0..graph.node_count() as ID {
+		for (key, _) in meta_attributes(meta, id) {
+			if !keys.contains(&key) {
+				keys.push(key);
+			}
+		}
+	}
+
+	writeln!(&mut ret, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+	writeln!(&mut ret, "<gexf xmlns=\"http://gexf.net/1.3\" xmlns:viz=\"http://gexf.net/1.3/viz\" version=\"1.3\">").unwrap();
+	writeln!(&mut ret, "<graph mode=\"static\" defaultedgetype=\"undirected\">").unwrap();
+
+	writeln!(&mut ret, "<attributes class=\"node\">").unwrap();
+	writeln!(&mut ret, "<attribute id=\"component\" title=\"component\" type=\"integer\"/>").unwrap();
+	for (i, key) in keys.iter().enumerate() {
+		writeln!(&mut ret, "<attribute id=\"{}\" title=\"{}\" type=\"string\"/>", i, xml_escape(key)).unwrap();
+	}
+	writeln!(&mut ret, "</attributes>").unwrap();
+
+	writeln!(&mut ret, "<attributes class=\"edge\">").unwrap();
+	for key in &["quality", "bandwidth", "latency", "loss"] {
+		writeln!(&mut ret, "<attribute id=\"{}\" title=\"{}\" type=\"float\"/>", key, key).unwrap();
+	}
+	writeln!(&mut ret, "</attributes>").unwrap();
+
+	let components = graph.get_component_ids();
+	writeln!(&mut ret, "<nodes>").unwrap();
+	for id in 0..graph.node_count() as ID {
+		let attributes = meta_attributes(meta, id);
+		let label = attributes.iter().find(|(key, _)| key == "label" || key == "hostname")
+			.map_or(id.to_string(), |(_, value)| value.clone());
+
+		writeln!(&mut ret, "<node id=\"{}\" label=\"{}\">", id, xml_escape(&label)).unwrap();
+		writeln!(&mut ret, "<attvalues>").unwrap();
+		writeln!(&mut ret, "<attvalue for=\"component\" value=\"{}\"/>", components[id as usize]).unwrap();
+		for (key, value) in &attributes {
+			let i = keys.iter().position(|k| k == key).unwrap();
+			writeln!(&mut ret, "<attvalue for=\"{}\" value=\"{}\"/>", i, xml_escape(value)).unwrap();
+		}
+		writeln!(&mut ret, "</attvalues>").unwrap();
+		if let Some(pos) = locations.and_then(|locs| locs.get_position(id)) {
+			writeln!(&mut ret, "<viz:position x=\"{}\" y=\"{}\" z=\"{}\"/>", pos[0], pos[1], pos[2]).unwrap();
+		}
+		writeln!(&mut ret, "</node>").unwrap();
+	}
+	writeln!(&mut ret, "</nodes>").unwrap();
+
+	writeln!(&mut ret, "<edges>").unwrap();
+	for (i, link) in graph.links().iter().enumerate() {
+		let bidirectional = graph.has_link(link.to, link.from);
+		if bidirectional && link.from > link.to {
+			continue;
+		}
+
+		write!(&mut ret, "<edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"",
+			i, link.from, link.to, link.cost()).unwrap();
+		if !bidirectional {
+			write!(&mut ret, " type=\"directed\"").unwrap();
+		}
+		writeln!(&mut ret, ">").unwrap();
+		writeln!(&mut ret, "<attvalues>").unwrap();
+		writeln!(&mut ret, "<attvalue for=\"quality\" value=\"{}\"/>", link.quality() as f32 / u16::MAX as f32).unwrap();
+		writeln!(&mut ret, "<attvalue for=\"bandwidth\" value=\"{}\"/>", link.bandwidth()).unwrap();
+		writeln!(&mut ret, "<attvalue for=\"latency\" value=\"{}\"/>", link.latency()).unwrap();
+		writeln!(&mut ret, "<attvalue for=\"loss\" value=\"{}\"/>", link.loss()).unwrap();
+		writeln!(&mut ret, "</attvalues>").unwrap();
+		writeln!(&mut ret, "</edge>").unwrap();
+	}
+	writeln!(&mut ret, "</edges>").unwrap();
+
+	writeln!(&mut ret, "</graph>").unwrap();
+	writeln!(&mut ret, "</gexf>").unwrap();
+
+	ret
+}
+
+// [longitude, latitude] in degrees, the altitude in m if not zero
+fn geojson_coordinates(pos: &[f32; 3]) -> serde_json::Value {
+	if pos[2] != 0.0 {
+		serde_json::json!([pos[0] / DEG2KM, pos[1] / DEG2KM, pos[2] * 1000.0])
+	} else {
+		serde_json::json!([pos[0] / DEG2KM, pos[1] / DEG2KM])
+	}
+}
+
+// nodes as Points and links as LineStrings, without a position the geometry is null
+pub fn export_geojson(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>) -> String {
+	use serde_json::{json, Value};
+
+	let position = |id: ID| locations.and_then(|locs| locs.get_position(id));
+	let components = graph.get_component_ids();
+	let mut features = vec![];
+
+	for id in 0..graph.node_count() as ID {
+		let mut properties = meta.and_then(|meta| meta.get_object(id)).unwrap_or_default();
+		properties.insert("id".to_string(), json!(id.to_string()));
+		properties.insert("component".to_string(), json!(components[id as usize]));
+
+		let geometry = position(id).map_or(Value::Null, |pos| {
+			json!({"type": "Point", "coordinates": geojson_coordinates(pos)})
+		});
+		features.push(json!({"type": "Feature", "geometry": geometry, "properties": properties}));
+	}
+
+	for link in graph.links() {
+		// bidirectional links are exported once
+		if link.from > link.to && graph.has_link(link.to, link.from) {
+			continue;
+		}
+
+		let mut properties = serde_json::Map::new();
+		properties.insert("source".to_string(), json!(link.from.to_string()));
+		properties.insert("target".to_string(), json!(link.to.to_string()));
+		properties.insert("source_tq".to_string(), json!(link.quality() as f32 / u16::MAX as f32));
+		properties.insert("target_tq".to_string(), json!(graph.get_link(link.to, link.from)
+			.map_or(0.0, |link| link.quality() as f32 / u16::MAX as f32)));
+		if !link.has_default_attributes() {
+			properties.insert("cost".to_string(), json!(link.cost()));
+			properties.insert("bandwidth".to_string(), json!(link.bandwidth()));
+			properties.insert("latency".to_string(), json!(link.latency()));
+			properties.insert("loss".to_string(), json!(link.loss()));
+		}
+		if link.channel() != 0 {
+			properties.insert("channel".to_string(), json!(link.channel()));
+		}
+
+		let geometry = match (position(link.from), position(link.to)) {
+			(Some(a), Some(b)) => json!({"type": "LineString", "coordinates": [geojson_coordinates(a), geojson_coordinates(b)]}),
+			_ => Value::Null
+		};
+		features.push(json!({"type": "Feature", "geometry": geometry, "properties": properties}));
+	}
+
+	json!({"type": "FeatureCollection", "features": features}).to_string()
+}
+
+// KML color (aabbggrr) for load from green (none) to red (max)
+fn kml_load_color(load: u32, max_load: u32) -> String {
+	let f = load as f32 / max_load.max(1) as f32;
+	let red = (255.0 * f.min(0.5) * 2.0) as u8;
+	let green = (255.0 * (1.0 - f).min(0.5) * 2.0) as u8;
+	format!("ff00{:02x}{:02x}", green, red)
+}
+
+// KML color (aabbggrr) from #rrggbb
+fn kml_color(color: &str) -> Option<String> {
+	let hex = color.trim_start_matches('#');
+	if hex.len() != 6 || u32::from_str_radix(hex, 16).is_err() {
+		return None;
+	}
+	Some(format!("ff{}{}{}", &hex[4..6], &hex[2..4], &hex[0..2]).to_lowercase())
+}
+
+// longitude,latitude in degrees and the altitude in m
+fn kml_coordinates(pos: &[f32; 3]) -> String {
+	format!("{},{},{}", pos[0] / DEG2KM, pos[1] / DEG2KM, pos[2] * 1000.0)
+}
+
+/*
+ * Nodes as placemarks and links as lines for Google Earth. Nodes are
+ * colored by the export style, the algorithm (color key) or by their load
+ * in the last test, links by the export style or their load. Marked links
+ * are magenta.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn export_kml(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>, algo: Option<&dyn RoutingAlgorithm>,
+		energy: Option<&Energy>, test: Option<&EvalPaths>, mark_links: Option<&Graph>, style: Option<&ExportStyle>) -> String {
+	let styling = style.filter(|style| !style.is_empty()).map(|style| Styling::new(style, graph, meta, algo, energy, test));
+	let mut ret = String::new();
+	let position = |id: ID| locations.and_then(|locs| locs.get_position(id));
+	let test = test.filter(|test| test.has_load());
+	let max_node_load = test.map_or(0, |test| (0..graph.node_count() as ID).map(|id| test.node_load(id)).max().unwrap_or(0));
+	let link_load = |a: ID, b: ID| test.map_or(0, |test| test.link_load(a, b) + test.link_load(b, a));
+	let max_link_load = graph.links().iter().map(|link| link_load(link.from, link.to)).max().unwrap_or(0);
+
+	writeln!(&mut ret, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+	writeln!(&mut ret, "<kml xmlns=\"http://www.opengis.net/kml/2.2\">").unwrap();
+	writeln!(&mut ret, "<Document>").unwrap();
+	writeln!(&mut ret, "<name>Mesh Network</name>").unwrap();
+	writeln!(&mut ret, "<Style id=\"node\"><IconStyle><scale>0.6</scale></IconStyle></Style>").unwrap();
+	writeln!(&mut ret, "<Style id=\"link\"><LineStyle><color>ff00ff00</color><width>2</width></LineStyle></Style>").unwrap();
+	writeln!(&mut ret, "<Style id=\"marked\"><LineStyle><color>ffff00ff</color><width>4</width></LineStyle></Style>").unwrap();
+
+	let mut color = String::new();
+	writeln!(&mut ret, "<Folder><name>Nodes</name>").unwrap();
+	for id in 0..graph.node_count() as ID {
+		let pos = match position(id) {
+			Some(pos) => pos,
+			None => continue
+		};
+
+		let attributes = meta_attributes(meta, id);
+		let name = attributes.iter().find(|(key, _)| key == "name" || key == "label" || key == "hostname")
+			.map_or(id.to_string(), |(_, value)| value.clone());
+
+		color.clear();
+		if let Some(algo) = algo {
+			let _ = algo.get_node(id, "color", &mut color);
+		}
+		let color = styling.as_ref().and_then(|styling| styling.node_color(id)).and_then(|c| kml_color(&format_color(&c)))
+			.or_else(|| kml_color(&color))
+			.or_else(|| test.map(|test| kml_load_color(test.node_load(id), max_node_load)));
+		let scale = 0.6 * styling.as_ref().and_then(|styling| styling.node_size(id)).unwrap_or(1.0);
+
+		writeln!(&mut ret, "<Placemark>").unwrap();
+		writeln!(&mut ret, "<name>{}</name>", xml_escape(&name)).unwrap();
+		write!(&mut ret, "<description>id: {}", id).unwrap();
+		if let Some(test) = test {
+			write!(&mut ret, "\nload: {}", test.node_load(id)).unwrap();
+		}
+		for (key, value) in attributes.iter().filter(|(key, _)| key != "id") {
+			write!(&mut ret, "\n{}: {}", xml_escape(key), xml_escape(value)).unwrap();
+		}
+		writeln!(&mut ret, "</description>").unwrap();
+		writeln!(&mut ret, "<styleUrl>#node</styleUrl>").unwrap();
+		if let Some(color) = color {
+			writeln!(&mut ret, "<Style><IconStyle><color>{}</color><scale>{}</scale></IconStyle></Style>", color, scale).unwrap();
+		} else if scale != 0.6 {
+			writeln!(&mut ret, "<Style><IconStyle><scale>{}</scale></IconStyle></Style>", scale).unwrap();
+		}
+		writeln!(&mut ret, "<Point><altitudeMode>relativeToGround</altitudeMode><coordinates>{}</coordinates></Point>", kml_coordinates(pos)).unwrap();
+		writeln!(&mut ret, "</Placemark>").unwrap();
+	}
+	writeln!(&mut ret, "</Folder>").unwrap();
+
+	writeln!(&mut ret, "<Folder><name>Links</name>").unwrap();
+	for link in graph.links() {
+		// bidirectional links are exported once
+		if link.from > link.to && graph.has_link(link.to, link.from) {
+			continue;
+		}
+		let (a, b) = match (position(link.from), position(link.to)) {
+			(Some(a), Some(b)) => (a, b),
+			_ => continue
+		};
+		let marked = mark_links.is_some_and(|mark| mark.has_link(link.from, link.to));
+
+		writeln!(&mut ret, "<Placemark>").unwrap();
+		writeln!(&mut ret, "<name>{} - {}</name>", link.from, link.to).unwrap();
+		write!(&mut ret, "<description>quality: {}", link.quality() as f32 / u16::MAX as f32).unwrap();
+		if test.is_some() {
+			write!(&mut ret, "\nload: {}", link_load(link.from, link.to)).unwrap();
+		}
+		writeln!(&mut ret, "</description>").unwrap();
+		if marked {
+			writeln!(&mut ret, "<styleUrl>#marked</styleUrl>").unwrap();
+		} else {
+			writeln!(&mut ret, "<styleUrl>#link</styleUrl>").unwrap();
+			let color = styling.as_ref().and_then(|styling| styling.link_color(link.from, link.to)).and_then(|c| kml_color(&format_color(&c)))
+				.or_else(|| test.map(|_| kml_load_color(link_load(link.from, link.to), max_link_load)));
+			let width = styling.as_ref().and_then(|styling| styling.link_width(link.from, link.to));
+			if color.is_some() || width.is_some() {
+				writeln!(&mut ret, "<Style><LineStyle><color>{}</color><width>{}</width></LineStyle></Style>",
+					color.unwrap_or_else(|| "ff00ff00".to_string()), width.unwrap_or(2.0)).unwrap();
+			}
+		}
+		writeln!(&mut ret, "<LineString><altitudeMode>relativeToGround</altitudeMode><coordinates>{} {}</coordinates></LineString>",
+			kml_coordinates(a), kml_coordinates(b)).unwrap();
+		writeln!(&mut ret, "</Placemark>").unwrap();
+	}
+	writeln!(&mut ret, "</Folder>").unwrap();
+
+	writeln!(&mut ret, "</Document>").unwrap();
+	writeln!(&mut ret, "</kml>").unwrap();
+
+	ret
+}
+
+/*
+ * Graphviz DOT with fixed positions (1 km is 1 inch, e.g. for neato -n),
+ * colors and sizes from the export style. Marked links are magenta.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn export_dot(graph: &Graph, locations: Option<&Locations>, meta: Option<&Meta>, algo: Option<&dyn RoutingAlgorithm>,
+		energy: Option<&Energy>, test: Option<&EvalPaths>, mark_links: Option<&Graph>, style: Option<&ExportStyle>) -> String {
+	let styling = style.filter(|style| !style.is_empty()).map(|style| Styling::new(style, graph, meta, algo, energy, test));
+	let mut ret = String::new();
+
+	writeln!(&mut ret, "digraph mesh {{").unwrap();
+	writeln!(&mut ret, "node [shape=circle, style=filled, fillcolor=\"#a0a0a0\", fixedsize=true, width=0.3, fontsize=8];").unwrap();
+
+	for id in 0..graph.node_count() as ID {
+		let label = meta_attributes(meta, id).into_iter()
+			.find(|(key, _)| key == "name" || key == "label" || key == "hostname")
+			.map_or(id.to_string(), |(_, value)| value);
+
+		write!(&mut ret, "{} [label=\"{}\"", id, label.replace('"', "\\\"")).unwrap();
+		if let Some(pos) = locations.and_then(|locs| locs.get_position(id)) {
+			// in points
+			write!(&mut ret, ", pos=\"{},{}!\"", pos[0] * 72.0, pos[1] * 72.0).unwrap();
+		}
+		if let Some(color) = styling.as_ref().and_then(|styling| styling.node_color(id)) {
+			write!(&mut ret, ", fillcolor=\"{}\"", format_color(&color)).unwrap();
+		}
+		if let Some(size) = styling.as_ref().and_then(|styling| styling.node_size(id)) {
+			write!(&mut ret, ", width={}", 0.3 * size).unwrap();
+		}
+		writeln!(&mut ret, "];").unwrap();
+	}
+
+	for link in graph.links() {
+		let bidirectional = graph.has_link(link.to, link.from);
+		if bidirectional && link.from > link.to {
+			continue;
+		}
+
+		write!(&mut ret, "{} -> {} [", link.from, link.to).unwrap();
+		if bidirectional {
+			write!(&mut ret, "dir=none, ").unwrap();
+		}
+		let color = if mark_links.is_some_and(|mark| mark.has_link(link.from, link.to)) {
+			"#ff00ff".to_string()
+		} else {
+			styling.as_ref().and_then(|styling| styling.link_color(link.from, link.to))
+				.map_or("#a0a0a0".to_string(), |color| format_color(&color))
+		};
+		let width = styling.as_ref().and_then(|styling| styling.link_width(link.from, link.to)).unwrap_or(1.0);
+		writeln!(&mut ret, "color=\"{}\", penwidth={}];", color, width).unwrap();
+	}
+
+	writeln!(&mut ret, "}}").unwrap();
+
+	ret
+}
+
+// KMZ is a zip archive with the KML document as doc.kml
+fn write_kmz(out: &mut impl std::io::Write, kml: &str) -> Result<(), MyError> {
+	use std::io::Write;
+	// the zip writer needs to seek
+	let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+	let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+	zip.start_file("doc.kml", options).map_err(|e| MyError::new(e.to_string()))?;
+	zip.write_all(kml.as_bytes())?;
+	let data = zip.finish().map_err(|e| MyError::new(e.to_string()))?.into_inner();
+	out.write_all(&data)?;
+	Ok(())
+}
+
 /*
 pub fn export_netjson(graph: &Graph) -> String {
 	let mut ret = String::new();
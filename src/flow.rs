@@ -0,0 +1,149 @@
+
+use std::collections::VecDeque;
+
+use crate::graph::{Graph, ID};
+
+
+/*
+ * Maximum flow between two nodes with a capacity of one per link
+ * (Edmonds-Karp). The flow value is the number of link disjoint
+ * paths, the minimum cut are the links that separate both nodes.
+ */
+pub struct MaxFlow<'a> {
+	graph: &'a Graph,
+	// flow over each link, same index as in graph.links()
+	flow: Vec<bool>,
+	// indices of links that end at a node
+	incoming: Vec<Vec<usize>>
+}
+
+impl<'a> MaxFlow<'a> {
+	pub fn new(graph: &'a Graph) -> Self {
+		let mut incoming = vec![vec![]; graph.node_count()];
+		for (idx, link) in graph.links().iter().enumerate() {
+			incoming[link.to as usize].push(idx);
+		}
+
+		Self { graph, flow: vec![false; graph.link_count()], incoming }
+	}
+
+	// find a path in the residual graph and push one unit of flow over it
+	fn augment(&mut self, source: ID, target: ID) -> bool {
+		let links = self.graph.links();
+		// link index used to reach a node and if it was used backwards
+		let mut prev : Vec<Option<(usize, bool)>> = vec![None; self.graph.node_count()];
+		let mut visited = vec![false; self.graph.node_count()];
+		let mut queue = VecDeque::new();
+
+		visited[source as usize] = true;
+		queue.push_back(source);
+
+		while let Some(u) = queue.pop_front() {
+			if u == target {
+				break;
+			}
+
+			for idx in self.graph.neighbor_range(u) {
+				let v = links[idx].to;
+				if !self.flow[idx] && !visited[v as usize] {
+					visited[v as usize] = true;
+					prev[v as usize] = Some((idx, false));
+					queue.push_back(v);
+				}
+			}
+
+			// cancel flow of an incoming link
+			for idx in &self.incoming[u as usize] {
+				let v = links[*idx].from;
+				if self.flow[*idx] && !visited[v as usize] {
+					visited[v as usize] = true;
+					prev[v as usize] = Some((*idx, true));
+					queue.push_back(v);
+				}
+			}
+		}
+
+		if !visited[target as usize] {
+			return false;
+		}
+
+		let mut v = target;
+		while v != source {
+			let (idx, backwards) = prev[v as usize].unwrap();
+			self.flow[idx] = !backwards;
+			v = if backwards { links[idx].to } else { links[idx].from };
+		}
+
+		true
+	}
+
+	// number of link disjoint paths, stops early at limit
+	pub fn run(&mut self, source: ID, target: ID, limit: u32) -> u32 {
+		let mut value = 0;
+		while value < limit && self.augment(source, target) {
+			value += 1;
+		}
+		value
+	}
+
+	// links from the nodes still reachable from the source in the residual graph to all others
+	pub fn cut_links(&self, source: ID) -> Vec<(ID, ID)> {
+		let links = self.graph.links();
+		let mut visited = vec![false; self.graph.node_count()];
+		let mut stack = vec![source];
+		visited[source as usize] = true;
+
+		while let Some(u) = stack.pop() {
+			for idx in self.graph.neighbor_range(u) {
+				let v = links[idx].to;
+				if !self.flow[idx] && !visited[v as usize] {
+					visited[v as usize] = true;
+					stack.push(v);
+				}
+			}
+			for idx in &self.incoming[u as usize] {
+				let v = links[*idx].from;
+				if self.flow[*idx] && !visited[v as usize] {
+					visited[v as usize] = true;
+					stack.push(v);
+				}
+			}
+		}
+
+		links.iter()
+			.filter(|link| visited[link.from as usize] && !visited[link.to as usize])
+			.map(|link| (link.from, link.to))
+			.collect()
+	}
+}
+
+// links that need to fail to disconnect target from source
+pub fn min_cut(graph: &Graph, source: ID, target: ID) -> Vec<(ID, ID)> {
+	let mut flow = MaxFlow::new(graph);
+	flow.run(source, target, u32::MAX);
+	flow.cut_links(source)
+}
+
+// minimum number of links that need to fail to disconnect any node
+// from any other node and a pair of nodes with that connectivity
+pub fn edge_connectivity(graph: &Graph) -> (u32, Option<(ID, ID)>) {
+	let node_count = graph.node_count() as ID;
+	let mut min = u32::MAX;
+	let mut pair = None;
+
+	// every cut separates node 0 from some other node (in one direction)
+	for id in 1..node_count {
+		for (source, target) in &[(0, id), (id, 0)] {
+			let value = MaxFlow::new(graph).run(*source, *target, min);
+			if value < min {
+				min = value;
+				pair = Some((*source, *target));
+			}
+		}
+		if min == 0 {
+			break;
+		}
+	}
+
+	(if pair.is_some() { min } else { 0 }, pair)
+}
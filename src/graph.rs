@@ -1,11 +1,11 @@
 
-use std::f32;
-use std::u16;
 use std::fmt;
 
 use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ops::Range;
 
-use crate::utils::vec_filter;
+use crate::utils::{vec_filter, MyError};
 
 //TODO: rename to Links and GraphState to Graph
 
@@ -18,28 +18,111 @@ pub struct Link
 	pub from: ID,
 	pub to: ID,
 	pub quality: u16,
-	//bandwidth: u16,
-	//channel: u8
+	channel: u8,
 	cost: u16,
+	bandwidth: f32, // in MBit/s
+	latency: f32, // in ms
+	loss: f32, // packet loss ratio (0..1)
 }
 
 impl Link {
 	pub fn new(from: ID, to: ID, quality: u16) -> Self {
-		Self {from, to, quality, cost: 1}
+		Self {from, to, quality, channel: 0, cost: 1, bandwidth: 1.0, latency: 1.0, loss: 0.0}
 	}
 
+	// weight used for shortest paths and spanning trees
 	pub fn cost(&self) -> u16 {
 		self.cost
 	}
 
-	pub fn bandwidth(&self) -> u16 {
-		1
+	pub fn bandwidth(&self) -> f32 {
+		self.bandwidth
+	}
+
+	pub fn latency(&self) -> f32 {
+		self.latency
+	}
+
+	pub fn loss(&self) -> f32 {
+		self.loss
 	}
 
 	pub fn quality(&self) -> u16 {
 		self.quality
 	}
 
+	// radio channel, see assign_channels
+	pub fn channel(&self) -> u8 {
+		self.channel
+	}
+
+	pub fn set_channel(&mut self, channel: u8) {
+		self.channel = channel;
+	}
+
+	// probability that a packet is received over this link
+	pub fn delivery_ratio(&self) -> f32 {
+		(self.quality as f32 / u16::MAX as f32) * (1.0 - self.loss)
+	}
+
+	// expected transmissions in this direction (infinite without delivery)
+	pub fn etx(&self) -> f32 {
+		1.0 / self.delivery_ratio()
+	}
+
+	// max() and min() instead of clamp() also map NaN into the range
+	#[allow(clippy::manual_clamp)]
+	pub fn set_loss(&mut self, loss: f32) {
+		self.loss = loss.max(0.0).min(1.0);
+	}
+
+	pub fn has_default_attributes(&self) -> bool {
+		self.cost == 1 && self.bandwidth == 1.0 && self.latency == 1.0 && self.loss == 0.0
+	}
+
+	// max() and min() instead of clamp() also map NaN into the range
+	#[allow(clippy::manual_clamp)]
+	pub fn set_attributes(&mut self, cost: u16, bandwidth: f32, latency: f32, loss: f32) {
+		self.cost = cost.max(1);
+		self.bandwidth = bandwidth.max(0.0);
+		self.latency = latency.max(0.0);
+		self.loss = loss.max(0.0).min(1.0);
+	}
+
+	// max() and min() instead of clamp() also map NaN into the range
+	#[allow(clippy::manual_clamp)]
+	pub fn set_attribute(&mut self, key: &str, value: &str) -> Result<(), MyError> {
+		fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, MyError> {
+			value.parse::<T>().map_err(|_| MyError::new(format!("Invalid value for {}: {}", key, value)))
+		}
+
+		match key {
+			"cost" => {
+				self.cost = parse::<f32>(key, value)?.round().max(1.0).min(u16::MAX as f32) as u16;
+			},
+			"quality" => {
+				let quality = parse::<f32>(key, value)?.max(0.0).min(1.0);
+				self.quality = (quality * u16::MAX as f32) as u16;
+			},
+			"bandwidth" => {
+				self.bandwidth = parse::<f32>(key, value)?.max(0.0);
+			},
+			"latency" => {
+				self.latency = parse::<f32>(key, value)?.max(0.0);
+			},
+			"loss" => {
+				self.loss = parse::<f32>(key, value)?.max(0.0).min(1.0);
+			},
+			"channel" => {
+				self.channel = parse::<u8>(key, value)?;
+			},
+			_ => {
+				return Err(MyError::new(format!("Unknown link attribute: {}", key)));
+			}
+		}
+		Ok(())
+	}
+
 	fn cmp(&self, from: ID, to: ID) -> Ordering {
 		let i = ((self.from as u64) << 32) + (self.to as u64);
 		let j = ((from as u64) << 32) + (to as u64);
@@ -55,33 +138,106 @@ impl fmt::Debug for Link {
 
 /*
  * This graph is designed for fast iteration and access of neighbors.
+ * The sorted link list and the offsets of the first link of each
+ * node form a compressed sparse row (CSR) adjacency structure.
 */
 #[derive(Clone)]
 pub struct Graph {
-	pub links: Vec<Link>, // sorted link list
-	pub node_count: usize,
+	links: Vec<Link>, // sorted link list
+	// links of node i are links[offsets[i]..offsets[i + 1]]
+	offsets: Vec<usize>,
+	node_count: usize,
+}
+
+impl Default for Graph {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Graph {
 	pub fn new() -> Self {
 		Self {
 			links: vec![],
+			offsets: vec![0],
 			node_count: 0,
 		}
 	}
 
+	// graph from links in any order, duplicate links are removed
+	pub fn from_links(node_count: usize, mut links: Vec<Link>) -> Self {
+		links.sort_by(|a, b| a.cmp(b.from, b.to));
+		links.dedup_by(|a, b| a.from == b.from && a.to == b.to);
+		let mut graph = Self { links, offsets: vec![], node_count };
+		graph.rebuild_offsets();
+		graph
+	}
+
+	// recalculate all offsets after bulk changes of the link list
+	fn rebuild_offsets(&mut self) {
+		let len = usize::max(self.node_count, self.links.last().map_or(0, |link| link.from as usize + 1));
+		self.offsets.clear();
+		self.offsets.resize(len + 1, 0);
+		for link in &self.links {
+			self.offsets[link.from as usize + 1] += 1;
+		}
+		for i in 0..len {
+			self.offsets[i + 1] += self.offsets[i];
+		}
+	}
+
+	// a link from a node was inserted (1) or removed (-1)
+	fn update_offsets(&mut self, from: ID, change: isize) {
+		let from = from as usize;
+		if from + 1 < self.offsets.len() {
+			for offset in &mut self.offsets[from + 1..] {
+				*offset = (*offset as isize + change) as usize;
+			}
+		} else {
+			self.rebuild_offsets();
+		}
+	}
+
 	pub fn clear(&mut self) {
 		self.links.clear();
 		self.node_count = 0;
+		self.rebuild_offsets();
+	}
+
+	// sorted list of all links
+	pub fn links(&self) -> &[Link] {
+		&self.links
+	}
+
+	// links to change attributes, from and to must not be changed
+	pub fn links_mut(&mut self) -> &mut [Link] {
+		&mut self.links
+	}
+
+	// keep only the links that match
+	pub fn retain_links<F>(&mut self, f: F) where F: FnMut(&Link) -> bool {
+		self.links.retain(f);
+		self.rebuild_offsets();
+	}
+
+	// ids of all nodes a node has a link to
+	pub fn neighbor_ids(&self, id: ID) -> impl Iterator<Item = ID> + '_ {
+		self.get_neighbors(id).iter().map(|link| link.to)
 	}
 
 	pub fn connect(&mut self, a: ID, b: ID) {
-		self.add_link(a, b, std::u16::MAX);
-		self.add_link(b, a, std::u16::MAX);
+		self.add_link(a, b, u16::MAX);
+		self.add_link(b, a, u16::MAX);
+	}
+
+	// add a unidirectional link from a to b
+	pub fn connect_oneway(&mut self, a: ID, b: ID) {
+		self.add_link(a, b, u16::MAX);
 	}
 
 	pub fn add_nodes(&mut self, count: u32) {
 		self.node_count += count as usize;
+		self.rebuild_offsets();
 	}
 
 	pub fn add_graph(&mut self, graph: Graph) {
@@ -97,6 +253,7 @@ impl Graph {
 		}
 
 		self.links.sort_unstable_by(|a, b| a.cmp(b.from, b.to));
+		self.rebuild_offsets();
 	}
 
 	pub fn disconnect_nodes(&mut self, ids: &Vec<ID>) {
@@ -115,51 +272,59 @@ impl Graph {
 		}
 	}
 
+	pub fn connect_nodes_oneway(&mut self, from: ID, ids: &Vec<ID>) {
+		for to in ids {
+			self.connect_oneway(from, *to);
+		}
+	}
+
 	// Check if all nodes have the same degree
 	pub fn is_regular(&self) -> bool {
 		let mut from = 0;
-		let mut n = 0;
 		let mut prev_n = 0;
-		for (i, link) in self.links.iter().enumerate() {
+		for (n, link) in self.links.iter().enumerate() {
 			if from != link.from {
 				from = link.from;
-				if i > 0 && prev_n != n {
+				if n > 0 && prev_n != n {
 					return false;
 				}
 				prev_n = n;
 			}
-			n += 1;
 		}
 		true
 	}
 
-/*
-//https://www.youtube.com/watch?v=YbCn8d4Enos
-//rename node => vertex and link to edge?
-
-
-	// maximum of the minimal distances from id to any other node
-	pub fn get_node_eccentricity(&self, id: ID) -> f32 {
-		
-	}
-
-	// minimum eccentricity of all vertices
-	pub fn get_radius(&self) -> f32 {
+	// hop count from a node to all other nodes (u32::MAX if not reachable)
+	pub fn get_hop_distances(&self, id: ID) -> Vec<u32> {
+		let mut dists = vec![u32::MAX; self.node_count];
+		let mut queue = VecDeque::new();
 
-	}
+		if (id as usize) < self.node_count {
+			dists[id as usize] = 0;
+			queue.push_back(id);
+		}
 
-	// maximum eccentricity of all vertices
-	pub fn get_diameter(&self) -> f32 {
+		while let Some(u) = queue.pop_front() {
+			let dist = dists[u as usize] + 1;
+			for link in self.get_neighbors(u) {
+				if dists[link.to as usize] == u32::MAX {
+					dists[link.to as usize] = dist;
+					queue.push_back(link.to);
+				}
+			}
+		}
 
+		dists
 	}
 
-	pub fn get_peripheral_nodes(&self) -> Vec<ID> {
-	
+	// maximum of the minimal distances (in hops) from id to any reachable node
+	pub fn get_node_eccentricity(&self, id: ID) -> u32 {
+		self.get_hop_distances(id).into_iter().filter(|d| *d != u32::MAX).max().unwrap_or(0)
 	}
 
-	pub fn get_central_nodes(&self) -> Vec<ID> {
-	
-	}
+/*
+//https://www.youtube.com/watch?v=YbCn8d4Enos
+//rename node => vertex and link to edge?
 
 	fn pos_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
 		((a[0] - b[0]).powi(2)
@@ -227,7 +392,7 @@ impl Graph {
 			v += (degree as f32 - mean).powi(2);
 		}
 
-		let variance = ((v as f32) / (len as f32)).sqrt();
+		let variance = (v / (len as f32)).sqrt();
 		(mean, variance)
 	}
 /*
@@ -268,15 +433,7 @@ impl Graph {
 */
 
 	pub fn has_link(&self, from: ID, to: ID) -> bool {
-		if let Some(_) = self.link_idx(from, to) {
-			true
-		} else {
-			false
-		}
-	}
-
-	fn has_any_link(&self, from: ID, to: ID) -> bool {
-		self.has_link(from, to) || self.has_link(to, from)
+		self.link_idx(from, to).is_some()
 	}
 
 	/*
@@ -305,21 +462,21 @@ impl Graph {
 	}
 
 	fn del_link(&mut self, a: ID, b: ID) {
-		self.del_links(&vec![a, b]);
+		self.del_links(&[a, b]);
 	}
 
-	pub fn del_links(&mut self, links: &Vec<ID>) {
-		if (links.len() % 2) != 0 {
+	pub fn del_links(&mut self, links: &[ID]) {
+		if !links.len().is_multiple_of(2) {
 			panic!("del_links: Uneven elements for link list");
 		}
 
 		fn any(link: &Link, a: ID, b: ID) -> bool {
-			(link.from == a && link.to == b) || (link.from == b || link.to == a)
+			(link.from == a && link.to == b) || (link.from == b && link.to == a)
 		}
 
-		self.links.retain(|link| {
+		self.retain_links(|link| {
 			for s in links.chunks(2) {
-				if any(&link, s[0], s[1]) {
+				if any(link, s[0], s[1]) {
 					return false;
 				}
 			}
@@ -327,6 +484,11 @@ impl Graph {
 		});
 	}
 
+	// number of links without a link in the opposite direction
+	pub fn unidirectional_link_count(&self) -> usize {
+		self.links.iter().filter(|link| !self.has_link(link.to, link.from)).count()
+	}
+
 	pub fn is_bidirectional(&self) -> bool {
 		for link in &self.links {
 			if !self.has_link(link.to, link.from) {
@@ -372,35 +534,36 @@ impl Graph {
 		}
 
 		// remove links
-		vec_filter(&mut self.links, |ref link| link.from != id && link.to != id);
+		vec_filter(&mut self.links, |link| link.from != id && link.to != id);
 
 		// sort
 		self.links.sort_unstable_by(|a, b| a.cmp(b.from, b.to));
+		self.rebuild_offsets();
 	}
 
-	pub fn remove_nodes(&mut self, nodes: &Vec<ID>) {
-		for id in nodes {
+	pub fn remove_nodes(&mut self, nodes: &[ID]) {
+		// start with the highest id, removal shifts the ids of later nodes
+		let mut nodes = nodes.to_vec();
+		nodes.sort_unstable();
+		nodes.dedup();
+		for id in nodes.iter().rev() {
 			self.remove_node(*id);
 		}
 	}
 
 	fn link_idx(&self, from: ID, to: ID) -> Option<usize> {
-		match self.links.binary_search_by(|link| link.cmp(from, to)) {
-			Ok(idx) => {
-				Some(idx)
-			},
-			Err(_) => {
-				None
-			}
+		self.links.binary_search_by(|link| link.cmp(from, to)).ok()
+	}
+
+	pub fn get_link_mut(&mut self, from: ID, to: ID) -> Option<&mut Link> {
+		match self.link_idx(from, to) {
+			Some(idx) => Some(&mut self.links[idx]),
+			None => None
 		}
 	}
 
 	pub fn get_link(&self, from: ID, to: ID) -> Option<Link> {
-		if let Some(idx) = self.link_idx(from, to) {
-			Some(self.links[idx].clone())
-		} else {
-			None
-		}
+		self.link_idx(from, to).map(|idx| self.links[idx].clone())
 	}
 
 	pub fn add_link(&mut self, from: ID, to: ID, tq: u16) {
@@ -411,36 +574,45 @@ impl Graph {
 				},
 				Err(idx) => {
 					self.links.insert(idx, Link::new(from, to, tq));
+					self.update_offsets(from, 1);
 				}
 			}
 		}
 	}
 
-	pub fn get_neighbors(&self, id: ID) -> &[Link] {
-		match self.links.binary_search_by(|link| link.from.cmp(&id)) {
-			Ok(idx) => {
-				let mut start = idx;
-				let mut end = idx;
-				for i in (0..idx).rev() {
-					if self.links[i].from == id {
-						start = i;
-					}
-				}
-				for i in idx..self.links.len() {
-					if self.links[i].from == id {
-						end = i;
-					}
+	// add or replace a link including its attributes
+	pub fn insert_link(&mut self, link: Link) {
+		if link.from != link.to {
+			match self.links.binary_search_by(|l| l.cmp(link.from, link.to)) {
+				Ok(idx) => {
+					self.links[idx] = link;
+				},
+				Err(idx) => {
+					let from = link.from;
+					self.links.insert(idx, link);
+					self.update_offsets(from, 1);
 				}
-				&self.links[start..end+1]
-			},
-			Err(idx) => {
-				&self.links[0..0]
 			}
 		}
 	}
 
+	pub fn get_neighbors(&self, id: ID) -> &[Link] {
+		&self.links[self.neighbor_range(id)]
+	}
+
+	// index range of the links of a node in links()
+	pub fn neighbor_range(&self, id: ID) -> Range<usize> {
+		let id = id as usize;
+		if id + 1 < self.offsets.len() {
+			self.offsets[id]..self.offsets[id + 1]
+		} else {
+			0..0
+		}
+	}
+
 	pub fn clear_links(&mut self) {
 		self.links.clear();
+		self.rebuild_offsets();
 	}
 
 	pub fn is_directed(&self) -> bool {
@@ -452,79 +624,102 @@ impl Graph {
 		true
 	}
 
-	pub fn remove_unconnected_nodes(&mut self) {
-		let mut remove = Vec::new();
-		for id in 0..self.node_count as ID {
-			if self.get_node_degree(id) == 0 {
-				remove.push(id);
+	// graph of the given nodes only, node ids[i] becomes node i
+	pub fn subgraph(&self, ids: &[ID]) -> Graph {
+		let mut map = vec![None; self.node_count];
+		for (i, id) in ids.iter().enumerate() {
+			if let Some(entry) = map.get_mut(*id as usize) {
+				*entry = Some(i as ID);
 			}
 		}
-		self.remove_nodes(&remove);
-	}
-
-	pub fn node_count(&self) -> usize {
-		self.node_count
-	}
-
-	pub fn link_count(&self) -> usize {
-		self.links.len()
-	}
 
-	pub fn link_cost_sum(&self) -> f32 {
-		self.links.iter().fold(0.0, |acc, link| acc + link.cost() as f32)
-	}
+		let mut links = vec![];
+		for id in ids {
+			for link in self.get_neighbors(*id) {
+				if let (Some(from), Some(to)) = (map[link.from as usize], map[link.to as usize]) {
+					let mut link = link.clone();
+					link.from = from;
+					link.to = to;
+					links.push(link);
+				}
+			}
+		}
 
-	pub fn spanning_tree(&self) -> Graph {
-		Self::minimum_spanning_tree_impl(&self.links, self.node_count)
+		Graph::from_links(ids.len(), links)
 	}
 
-	pub fn minimum_spanning_tree(&self) -> Graph {
-		// sort links by cost
-		let links = {
-			let mut links = self.links.clone();
-			links.sort_unstable_by(|a, b| a.cost().cmp(&b.cost()));
-			links
-		};
-
-		Self::minimum_spanning_tree_impl(&links, self.node_count)
+	// copy of the graph with only the links that match
+	pub fn filter_links<F>(&self, f: F) -> Graph where F: Fn(&Link) -> bool {
+		let links = self.links.iter().filter(|link| f(link)).cloned().collect();
+		Graph::from_links(self.node_count, links)
 	}
 
-	// Implementation of the Kruskal minimum spanning tree algorithm
-	fn minimum_spanning_tree_impl(links: &[Link], node_count: usize) -> Graph {
-		let mut roots = Vec::with_capacity(node_count);
-		let mut mst = Vec::new();
-
-		// initial root of every node is itself
-		for i in 0..node_count {
-			roots.push(i as ID);
-		}
+	// weakly connected components, largest first
+	pub fn get_components(&self) -> Vec<Vec<ID>> {
+		let mut roots : Vec<ID> = (0..self.node_count as ID).collect();
 
-		// find root of node
-		fn root(roots: &mut [ID], i: ID) -> usize {
+		fn root(roots: &mut [ID], i: ID) -> ID {
 			let mut i = i as usize;
 			while roots[i] != i as ID {
-				// Path halving optimization
 				let tmp = roots[roots[i] as usize];
 				roots[i] = tmp;
 				i = tmp as usize;
 			}
-			i
+			i as ID
 		}
 
-		for link in links {
+		for link in &self.links {
+			// links to nodes that do not exist do not connect anything
+			if link.from as usize >= self.node_count || link.to as usize >= self.node_count {
+				continue;
+			}
 			let x = root(&mut roots, link.from);
 			let y = root(&mut roots, link.to);
 			if x != y {
-				mst.push(link.clone());
-				roots[x] = roots[y];
+				roots[x as usize] = y;
 			}
 		}
 
-		// count roots of all minimum spanning trees
-		let roots_count = roots.iter().enumerate().fold(0,
-			|acc, (i, j)| acc + ((i as u32) == *j) as usize
-		);
+		let mut components = vec![vec![]; self.node_count];
+		for id in 0..self.node_count as ID {
+			let r = root(&mut roots, id);
+			components[r as usize].push(id);
+		}
+		components.retain(|c| !c.is_empty());
+		components.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+		components
+	}
+
+	// index of the component (see get_components) of each node
+	pub fn get_component_ids(&self) -> Vec<u32> {
+		let mut ids = vec![0; self.node_count];
+		for (i, component) in self.get_components().iter().enumerate() {
+			for id in component {
+				ids[*id as usize] = i as u32;
+			}
+		}
+		ids
+	}
 
-		Graph{ node_count: (mst.len() + roots_count), links: mst }
+	pub fn remove_unconnected_nodes(&mut self) {
+		let mut remove = Vec::new();
+		for id in 0..self.node_count as ID {
+			if self.get_node_degree(id) == 0 {
+				remove.push(id);
+			}
+		}
+		self.remove_nodes(&remove);
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.node_count
+	}
+
+	pub fn link_count(&self) -> usize {
+		self.links.len()
+	}
+
+	pub fn link_cost_sum(&self) -> f32 {
+		self.links.iter().fold(0.0, |acc, link| acc + link.cost() as f32)
 	}
 }
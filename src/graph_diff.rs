@@ -0,0 +1,185 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::utils::MyError;
+
+
+/*
+ * Differences between the current graph and another graph, e.g.
+ * two snapshots of a real mesh. Nodes are matched by their name
+ * in the metadata (see Meta::name) or by node id if they have none.
+ */
+
+// positions closer than this are unchanged (in km)
+const MOVE_THRESHOLD : f32 = 0.001;
+
+// entries listed per category in text output
+const MAX_LISTED : usize = 100;
+
+pub struct GraphDiff {
+	// nodes of the other graph
+	pub added_nodes: Vec<ID>,
+	// nodes of the current graph
+	pub removed_nodes: Vec<ID>,
+	// links of the other graph
+	pub added_links: Vec<(ID, ID)>,
+	// links of the current graph
+	pub removed_links: Vec<(ID, ID)>,
+	// nodes of the current graph and the distance moved (in km)
+	pub moved_nodes: Vec<(ID, f32)>,
+	// labels of the nodes of both graphs
+	labels: Vec<String>,
+	other_labels: Vec<String>
+}
+
+// name of every node or #<id> to match nodes of different graphs
+pub fn node_keys(graph: &Graph, meta: &Meta) -> Vec<String> {
+	(0..graph.node_count() as ID).map(|id| {
+		meta.name(id).unwrap_or_else(|| format!("#{}", id))
+	}).collect()
+}
+
+fn node_labels(graph: &Graph, meta: &Meta) -> Vec<String> {
+	(0..graph.node_count() as ID).map(|id| {
+		match meta.name(id) {
+			Some(name) => format!("{} ({})", id, name),
+			None => id.to_string()
+		}
+	}).collect()
+}
+
+impl GraphDiff {
+	pub fn new(graph: &Graph, locations: &Locations, meta: &Meta,
+			other: &Graph, other_locations: &Locations, other_meta: &Meta) -> Self {
+		let keys = node_keys(graph, meta);
+		let other_keys = node_keys(other, other_meta);
+
+		// first node with a key wins, duplicate names stay unmatched
+		let mut other_ids = HashMap::<&str, ID>::new();
+		for (id, key) in other_keys.iter().enumerate().rev() {
+			other_ids.insert(key.as_str(), id as ID);
+		}
+
+		// current node id => other node id
+		let mut map = vec![None; keys.len()];
+		let mut matched = vec![false; other_keys.len()];
+		for (id, key) in keys.iter().enumerate() {
+			if let Some(other_id) = other_ids.get(key.as_str()) {
+				if !matched[*other_id as usize] {
+					matched[*other_id as usize] = true;
+					map[id] = Some(*other_id);
+				}
+			}
+		}
+
+		let mut removed_nodes = vec![];
+		let mut moved_nodes = vec![];
+		for (id, other_id) in map.iter().enumerate() {
+			let id = id as ID;
+			match other_id {
+				Some(other_id) => {
+					if let (Some(a), Some(b)) = (locations.get_position(id), other_locations.get_position(*other_id)) {
+						let distance = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+						if distance > MOVE_THRESHOLD {
+							moved_nodes.push((id, distance));
+						}
+					}
+				},
+				None => {
+					removed_nodes.push(id);
+				}
+			}
+		}
+
+		let added_nodes = (0..other.node_count() as ID).filter(|id| !matched[*id as usize]).collect();
+
+		let mut removed_links = vec![];
+		for link in graph.links() {
+			match (map[link.from as usize], map[link.to as usize]) {
+				(Some(from), Some(to)) if other.has_link(from, to) => {},
+				_ => removed_links.push((link.from, link.to))
+			}
+		}
+
+		// other node id => current node id
+		let mut reverse = vec![None; other_keys.len()];
+		for (id, other_id) in map.iter().enumerate() {
+			if let Some(other_id) = other_id {
+				reverse[*other_id as usize] = Some(id as ID);
+			}
+		}
+
+		let mut added_links = vec![];
+		for link in other.links() {
+			match (reverse[link.from as usize], reverse[link.to as usize]) {
+				(Some(from), Some(to)) if graph.has_link(from, to) => {},
+				_ => added_links.push((link.from, link.to))
+			}
+		}
+
+		Self {
+			added_nodes,
+			removed_nodes,
+			added_links,
+			removed_links,
+			moved_nodes,
+			labels: node_labels(graph, meta),
+			other_labels: node_labels(other, other_meta)
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added_nodes.is_empty() && self.removed_nodes.is_empty()
+			&& self.added_links.is_empty() && self.removed_links.is_empty()
+			&& self.moved_nodes.is_empty()
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write) -> Result<(), MyError> {
+		writeln!(out, "nodes: +{} -{}, links: +{} -{}, moved: {}",
+			self.added_nodes.len(), self.removed_nodes.len(),
+			self.added_links.len(), self.removed_links.len(),
+			self.moved_nodes.len())?;
+
+		fn list<T>(out: &mut dyn std::fmt::Write, title: &str, items: &[T], fmt: impl Fn(&T) -> String) -> Result<(), MyError> {
+			if items.is_empty() {
+				return Ok(());
+			}
+			let mut entries : Vec<String> = items.iter().take(MAX_LISTED).map(fmt).collect();
+			if items.len() > MAX_LISTED {
+				entries.push(format!("({} more)", items.len() - MAX_LISTED));
+			}
+			writeln!(out, "{}: {}", title, entries.join(", "))?;
+			Ok(())
+		}
+
+		// added nodes and links use the node ids of the other graph
+		list(out, "added nodes", &self.added_nodes, |id| self.other_labels[*id as usize].clone())?;
+		list(out, "removed nodes", &self.removed_nodes, |id| self.labels[*id as usize].clone())?;
+		list(out, "added links", &self.added_links, |(from, to)| {
+			format!("{} > {}", self.other_labels[*from as usize], self.other_labels[*to as usize])
+		})?;
+		list(out, "removed links", &self.removed_links, |(from, to)| {
+			format!("{} > {}", self.labels[*from as usize], self.labels[*to as usize])
+		})?;
+		list(out, "moved nodes", &self.moved_nodes, |(id, distance)| {
+			format!("{} ({:.1} m)", self.labels[*id as usize], distance * 1000.0)
+		})?;
+
+		Ok(())
+	}
+
+	pub fn to_json(&self) -> serde_json::Value {
+		serde_json::json!({
+			"added_nodes": self.added_nodes,
+			"removed_nodes": self.removed_nodes,
+			"added_links": self.added_links,
+			"removed_links": self.removed_links,
+			"moved_nodes": self.moved_nodes.iter().map(|(id, distance)| {
+				serde_json::json!({ "id": id, "distance_m": distance * 1000.0 })
+			}).collect::<Vec<_>>()
+		})
+	}
+}
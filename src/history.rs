@@ -0,0 +1,93 @@
+
+use std::collections::VecDeque;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::node_ids::NodeIds;
+
+
+/*
+ * Undo/redo of destructive graph commands. A snapshot of the
+ * graph, locations, metadata and gateways is taken before the
+ * command runs. Movement models and algorithm state are not restored.
+ */
+pub struct Snapshot {
+	// command that was run after the snapshot was taken
+	pub command: String,
+	pub graph: Graph,
+	pub locations: Locations,
+	pub meta: Meta,
+	pub node_ids: NodeIds,
+	pub gateways: Vec<ID>
+}
+
+pub struct History {
+	undo: VecDeque<Snapshot>,
+	redo: Vec<Snapshot>,
+	limit: usize
+}
+
+impl Default for History {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl History {
+	pub fn new() -> Self {
+		Self { undo: VecDeque::new(), redo: vec![], limit: 10 }
+	}
+
+	pub fn clear(&mut self) {
+		self.undo.clear();
+		self.redo.clear();
+	}
+
+	pub fn limit(&self) -> usize {
+		self.limit
+	}
+
+	pub fn set_limit(&mut self, limit: usize) {
+		self.limit = limit;
+		while self.undo.len() > limit {
+			self.undo.pop_front();
+		}
+		self.redo.truncate(limit);
+	}
+
+	// record the state before a destructive command, invalidates redo
+	pub fn push(&mut self, snapshot: Snapshot) {
+		if self.limit == 0 {
+			return;
+		}
+		if self.undo.len() == self.limit {
+			self.undo.pop_front();
+		}
+		self.undo.push_back(snapshot);
+		self.redo.clear();
+	}
+
+	// the snapshot to restore and store the current state for redo
+	pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+		let snapshot = self.undo.pop_back()?;
+		self.redo.push(Snapshot { command: snapshot.command.clone(), ..current });
+		Some(snapshot)
+	}
+
+	// the snapshot to restore and store the current state for undo
+	pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+		let snapshot = self.redo.pop()?;
+		self.undo.push_back(Snapshot { command: snapshot.command.clone(), ..current });
+		Some(snapshot)
+	}
+
+	// commands that can be undone, oldest first
+	pub fn undo_commands(&self) -> Vec<&str> {
+		self.undo.iter().map(|s| s.command.as_str()).collect()
+	}
+
+	pub fn redo_commands(&self) -> Vec<&str> {
+		self.redo.iter().rev().map(|s| s.command.as_str()).collect()
+	}
+}
@@ -1,84 +1,960 @@
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufReader, Read};
 use std::collections::HashMap;
 use std::borrow::BorrowMut;
 
 use serde_json::Value;
 use crate::meta::Meta;
 use crate::locations::Locations;
-use crate::graph::{Graph, ID};
+use crate::graph::{Graph, Link, ID};
+use crate::progress::{is_cancelled, Progress, ProgressReader};
+use crate::binary::read_binary;
+use crate::compress::{decompress, open_file, split_path};
 use crate::utils::*;
 
 
-pub fn import_file(graph: &mut Graph, loc: Option<&mut Locations>, meta: Option<&mut Meta>, path: &str) -> Result<(), MyError> {
+// errors listed in the summary, the others are only counted
+const MAX_ERRORS: usize = 10;
+
+/*
+ * Problems with single records of an import. Nodes and links that
+ * cannot be imported are skipped instead of failing the import,
+ * nodes with bad coordinates are imported without a position.
+ */
+pub struct ImportSummary {
+	pub nodes: usize,
+	pub links: usize,
+	pub skipped_nodes: usize,
+	pub skipped_links: usize,
+	pub bad_positions: usize,
+	errors: Vec<String>,
+	error_count: usize
+}
+
+impl ImportSummary {
+	fn new() -> Self {
+		Self {
+			nodes: 0,
+			links: 0,
+			skipped_nodes: 0,
+			skipped_links: 0,
+			bad_positions: 0,
+			errors: vec![],
+			error_count: 0
+		}
+	}
+
+	fn error(&mut self, message: String) {
+		if self.errors.len() < MAX_ERRORS {
+			self.errors.push(message);
+		}
+		self.error_count += 1;
+	}
+
+	fn skip_node(&mut self, message: String) {
+		self.skipped_nodes += 1;
+		self.error(message);
+	}
+
+	fn skip_link(&mut self, message: String) {
+		self.skipped_links += 1;
+		self.error(message);
+	}
+
+	fn bad_position(&mut self, message: String) {
+		self.bad_positions += 1;
+		self.error(message);
+	}
+
+	pub fn has_errors(&self) -> bool {
+		self.error_count > 0
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		writeln!(out, "imported nodes: {}, links: {}", self.nodes, self.links)?;
+		writeln!(out, "skipped nodes: {}, links: {}, bad coordinates: {}", self.skipped_nodes, self.skipped_links, self.bad_positions)?;
+		for error in &self.errors {
+			writeln!(out, "  {}", error)?;
+		}
+		if self.error_count > self.errors.len() {
+			writeln!(out, "  ... and {} more", self.error_count - self.errors.len())?;
+		}
+		Ok(())
+	}
+}
+
+pub fn import_file(graph: &mut Graph, loc: Option<&mut Locations>, meta: Option<&mut Meta>, path: &str,
+		show_progress: bool) -> Result<ImportSummary, MyError> {
+	let mut summary = ImportSummary::new();
+	// the format of compressed files is given by the inner extension
+	let name = split_path(path).0;
+	if name.ends_with(".mgb") {
+		let (node_count, link_count) = (graph.node_count(), graph.link_count());
+		read_binary(graph, loc, meta, open_file(path)?, show_progress)?;
+		summary.nodes = graph.node_count() - node_count;
+		summary.links = graph.link_count() - link_count;
+		return Ok(summary);
+	}
+
+	// the progress is given by the (compressed) bytes read
+	let file = File::open(path)?;
+	let size = file.metadata()?.len() as usize;
+	let mut input = BufReader::new(decompress(path, ProgressReader::new(file, size, show_progress))?);
+
+	if name.ends_with(".graphml") || name.ends_with(".gexf") {
+		let mut data = String::new();
+		input.read_to_string(&mut data)?;
+		if name.ends_with(".graphml") {
+			parse_graphml(graph, loc, meta, &data, &mut summary, show_progress)?;
+		} else {
+			parse_gexf(graph, loc, meta, &data, &mut summary)?;
+		}
+	} else {
+		// parsed while reading, without a copy of the file content
+		let v = serde_json::from_reader::<_, Value>(input)?;
+		if name.ends_with(".geojson") {
+			parse_geojson(graph, loc, meta, &v, &mut summary)?;
+		} else {
+			parse_netjson(graph, loc, meta, &v, &mut summary, show_progress)?;
+		}
+	}
+
+	Ok(summary)
+}
+
+// progress of adding the links, fails if the import was cancelled
+fn link_progress(progress: &mut Progress, show_progress: bool, total: usize, count: usize) -> Result<(), MyError> {
+	if is_cancelled() {
+		return Err(MyError::new("Import cancelled".to_string()));
+	}
+	if show_progress {
+		progress.update(total, count);
+	}
+	Ok(())
+}
+
+// the links of an import, the graph is built once at the end
+struct ImportLinks {
+	links: Vec<Link>,
+	index: HashMap<(ID, ID), usize>
+}
+
+impl ImportLinks {
+	fn new() -> Self {
+		Self { links: vec![], index: HashMap::new() }
+	}
+
+	fn push(&mut self, from: ID, to: ID, quality: u16) {
+		self.index.insert((from, to), self.links.len());
+		self.links.push(Link::new(from, to, quality));
+	}
+
+	// add a link and the link back (if given)
+	fn add(&mut self, a: ID, b: ID, quality: u16, back_quality: Option<u16>) -> Result<(), &'static str> {
+		if a == b {
+			return Err("self-loop");
+		}
+		if self.index.contains_key(&(a, b)) || (back_quality.is_some() && self.index.contains_key(&(b, a))) {
+			return Err("duplicate link");
+		}
+		self.push(a, b, quality);
+		if let Some(back_quality) = back_quality {
+			self.push(b, a, back_quality);
+		}
+		Ok(())
+	}
+
+	// set known edge attributes on both link directions
+	fn set_attribute(&mut self, a: ID, b: ID, name: &str, value: &str) -> Result<(), MyError> {
+		let key = match name {
+			"weight" | "cost" => "cost",
+			"quality" | "tq" => "quality",
+			"bandwidth" | "latency" | "loss" | "channel" => name,
+			_ => return Ok(())
+		};
+
+		for key_pair in &[(a, b), (b, a)] {
+			if let Some(idx) = self.index.get(key_pair) {
+				self.links[*idx].set_attribute(key, value)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn build(self, graph: &mut Graph) {
+		let mut links = graph.links().to_vec();
+		links.extend(self.links);
+		*graph = Graph::from_links(graph.node_count(), links);
+	}
+}
+
+// link quality from a fraction between 0 and 1
+fn to_quality(tq: f64) -> Option<u16> {
+	if (0.0..=1.0).contains(&tq) {
+		Some((tq * u16::MAX as f64) as u16)
+	} else {
+		None
+	}
+}
+
+// coordinates in degrees
+fn valid_degrees(lon: f64, lat: f64) -> bool {
+	lon.is_finite() && lat.is_finite() && lon.abs() <= 180.0 && lat.abs() <= 90.0
+}
+
+fn read_json(path: &str) -> Result<Value, MyError> {
 	let mut file = File::open(path)?;
 	let mut data = String::new();
 	file.read_to_string(&mut data)?;
-	parse_netjson(graph, loc, meta, &data)
+	Ok(serde_json::from_str::<Value>(&data)?)
 }
 
-fn extract_location(node: &Value) -> (f32, f32) {
-	if let (Some(lat), Some(lon)) = (
-		node.pointer("location/latitude").and_then(Value::as_f64),
-		node.pointer("location/longitude").and_then(Value::as_f64)) {
-			(lat as f32, lon as f32)
+/*
+ * Import Freifunk meshviewer data. Either a single meshviewer.json
+ * or a nodes.json (version 1 or 2) with a graph.json.
+ */
+pub fn import_meshviewer(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>,
+		path: &str, graph_path: Option<&str>) -> Result<(), MyError> {
+	let nodes_data = read_json(path)?;
+
+	// nodes.json version 1 uses an object with the node id as key
+	let nodes : Vec<&Value> = match nodes_data.get("nodes") {
+		Some(Value::Array(nodes)) => nodes.iter().collect(),
+		Some(Value::Object(nodes)) => nodes.values().collect(),
+		_ => return Err(MyError::new("nodes field missing".to_string()))
+	};
+
+	let mut map = HashMap::<String, ID>::new();
+	let mut id = graph.node_count() as ID;
+
+	for node in nodes {
+		// nodes.json nests the information
+		let info = node.get("nodeinfo").unwrap_or(node);
+		let node_id = match get_str(info, "node_id") {
+			Some(node_id) => node_id.to_string(),
+			None => continue
+		};
+
+		if map.contains_key(&node_id) {
+			continue;
+		}
+
+		let lat = info.pointer("/location/latitude").and_then(Value::as_f64);
+		let lon = info.pointer("/location/longitude").and_then(Value::as_f64);
+		if let (Some(lat), Some(lon), Some(loc)) = (lat, lon, loc.borrow_mut()) {
+			loc.insert(id, [lon as f32 * DEG2KM, lat as f32 * DEG2KM, 0.0]);
+		}
+
+		if let Some(meta) = meta.borrow_mut() {
+			let firmware = info.pointer("/software/firmware/release")
+				.or_else(|| info.pointer("/firmware/release"));
+			let model = info.pointer("/hardware/model")
+				.or_else(|| info.get("model"));
+
+			let mut obj = serde_json::Map::new();
+			obj.insert("node_id".to_string(), Value::String(node_id.clone()));
+			if let Some(hostname) = info.get("hostname") {
+				obj.insert("hostname".to_string(), hostname.clone());
+			}
+			if let Some(firmware) = firmware {
+				obj.insert("firmware".to_string(), firmware.clone());
+			}
+			if let Some(model) = model {
+				obj.insert("model".to_string(), model.clone());
+			}
+			meta.insert(id, Value::Object(obj).to_string());
+		}
+
+		map.insert(node_id, id);
+		id += 1;
+	}
+
+	graph.add_nodes(map.len() as u32);
+
+	fn quality(tq: f64) -> u16 {
+		// graph.json stores the inverse transmit quality
+		let tq = if tq > 1.0 { 1.0 / tq } else { tq };
+		(tq.clamp(0.0, 1.0) * u16::MAX as f64) as u16
+	}
+
+	if let Some(graph_path) = graph_path {
+		let graph_data = read_json(graph_path)?;
+		let batadv = graph_data.get("batadv").unwrap_or(&graph_data);
+
+		let ids : Vec<Option<ID>> = get_array(batadv, "nodes").map_or(vec![], |nodes| {
+			nodes.iter().map(|node| {
+				get_str(node, "node_id").or_else(|| get_str(node, "id"))
+					.and_then(|node_id| map.get(node_id).cloned())
+			}).collect()
+		});
+
+		for link in get_array(batadv, "links").unwrap_or(&vec![]) {
+			let source = get_u64(link, "source").and_then(|i| ids.get(i as usize).cloned().flatten());
+			let target = get_u64(link, "target").and_then(|i| ids.get(i as usize).cloned().flatten());
+			if let (Some(source), Some(target)) = (source, target) {
+				let tq = quality(get_f64(link, "tq").unwrap_or(1.0));
+				graph.add_link(source, target, tq);
+				if link.get("bidirect").and_then(Value::as_bool).unwrap_or(true) {
+					graph.add_link(target, source, tq);
+				}
+			}
+		}
 	} else {
-		(0.0, 0.0)
+		// meshviewer.json links reference the node ids
+		for link in get_array(&nodes_data, "links").unwrap_or(&vec![]) {
+			let source = get_str(link, "source").and_then(|node_id| map.get(node_id));
+			let target = get_str(link, "target").and_then(|node_id| map.get(node_id));
+			if let (Some(source), Some(target)) = (source, target) {
+				if let Some(tq) = get_f64(link, "source_tq") {
+					graph.add_link(*source, *target, quality(tq));
+				}
+				if let Some(tq) = get_f64(link, "target_tq") {
+					graph.add_link(*target, *source, quality(tq));
+				}
+			}
+		}
 	}
+
+	Ok(())
 }
 
-// parse the meshviewer data
-fn parse_netjson(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, data: &str) -> Result<(), MyError> {
+// road polylines from GeoJSON (LineString/MultiLineString) as [lon, lat]
+fn parse_geojson_roads(data: &str) -> Result<Vec<Vec<[f32; 2]>>, MyError> {
 	let v = serde_json::from_str::<Value>(data)?;
+	let mut roads = vec![];
 
-	if let (Some(nodes), Some(links)) = (get_array(&v, "nodes"), get_array(&v, "links")) {
-		// map target/source field to node id in graph.nodes
-		let mut map = HashMap::<&str, usize>::new();
-		let mut id = graph.node_count();
-
-		for node in nodes {
-			// try different keys (netjson uses node_id)
-			for key in &["node_id", "id"] {
-				if let Some(node_id) = get_str(&node, key) {
-					let (x, y) = extract_location(node);
-					let meta_data = serde_json::to_string(&node).unwrap_or(String::new());
-					// TODO: move to Node::new() ctor
-					//state.graph.add_node_with_meta(x, y, 0.0, meta);
-					//add("".to_string(), [x, y, 0.0]);
+	fn parse_line(coords: &Value) -> Vec<[f32; 2]> {
+		coords.as_array().map_or(vec![], |points| {
+			points.iter().filter_map(|p| {
+				Some([p.get(0)?.as_f64()? as f32, p.get(1)?.as_f64()? as f32])
+			}).collect()
+		})
+	}
 
-					if let Some(loc) = loc.borrow_mut() {
-						loc.data.insert(id as ID, [x, y, 0.0]);
-					}
+	let features = get_array(&v, "features").cloned().unwrap_or_else(|| vec![v.clone()]);
+	for feature in &features {
+		let geometry = feature.get("geometry").unwrap_or(feature);
+		let coords = match geometry.get("coordinates") {
+			Some(coords) => coords,
+			None => continue
+		};
+		match get_str(geometry, "type") {
+			Some("LineString") => {
+				roads.push(parse_line(coords));
+			},
+			Some("MultiLineString") => {
+				for line in coords.as_array().unwrap_or(&vec![]) {
+					roads.push(parse_line(line));
+				}
+			},
+			_ => {}
+		}
+	}
 
-					if let Some(meta) = meta.borrow_mut() {
-						meta.data.insert(id as ID, meta_data);
+	Ok(roads)
+}
+
+// road polylines from OpenStreetMap XML (ways with highway tag) as [lon, lat]
+fn parse_osm_roads(data: &str) -> Result<Vec<Vec<[f32; 2]>>, MyError> {
+	let doc = roxmltree::Document::parse(data).map_err(|e| MyError::new(e.to_string()))?;
+	let root = doc.root_element();
+
+	let mut points = HashMap::<&str, [f32; 2]>::new();
+	for node in root.children().filter(|n| n.has_tag_name("node")) {
+		let lat = node.attribute("lat").and_then(|s| s.parse::<f32>().ok());
+		let lon = node.attribute("lon").and_then(|s| s.parse::<f32>().ok());
+		if let (Some(id), Some(lat), Some(lon)) = (node.attribute("id"), lat, lon) {
+			points.insert(id, [lon, lat]);
+		}
+	}
+
+	let mut roads = vec![];
+	for way in root.children().filter(|n| n.has_tag_name("way")) {
+		let is_road = way.children().any(|n| n.has_tag_name("tag") && n.attribute("k") == Some("highway"));
+		if is_road {
+			roads.push(way.children()
+				.filter(|n| n.has_tag_name("nd"))
+				.filter_map(|n| n.attribute("ref").and_then(|r| points.get(r).cloned()))
+				.collect());
+		}
+	}
+
+	Ok(roads)
+}
+
+/*
+ * Place nodes along roads of an OpenStreetMap extract (.osm)
+ * or GeoJSON file, every spacing km. Nodes closer than half
+ * the spacing to an existing road node are skipped (crossings).
+ */
+pub fn import_roads(graph: &mut Graph, loc: &mut Locations, path: &str, spacing: f32) -> Result<(), MyError> {
+	let mut file = File::open(path)?;
+	let mut data = String::new();
+	file.read_to_string(&mut data)?;
+
+	if spacing <= 0.0 {
+		return Err(MyError::new("spacing must be positive".to_string()));
+	}
+
+	let roads = if path.ends_with(".osm") {
+		parse_osm_roads(&data)?
+	} else {
+		parse_geojson_roads(&data)?
+	};
+
+	// grid of placed positions to find close nodes
+	let mut grid = HashMap::<(i32, i32), Vec<[f32; 3]>>::new();
+	let cell = |pos: &[f32; 3]| ((pos[0] / spacing).floor() as i32, (pos[1] / spacing).floor() as i32);
+	let mut positions : Vec<[f32; 3]> = vec![];
+
+	let mut place = |pos: [f32; 3], positions: &mut Vec<[f32; 3]>| {
+		let (cx, cy) = cell(&pos);
+		for x in (cx - 1)..=(cx + 1) {
+			for y in (cy - 1)..=(cy + 1) {
+				if let Some(others) = grid.get(&(x, y)) {
+					for other in others {
+						let d = ((pos[0] - other[0]).powi(2) + (pos[1] - other[1]).powi(2)).sqrt();
+						if d < spacing / 2.0 {
+							return;
+						}
 					}
+				}
+			}
+		}
+		grid.entry((cx, cy)).or_default().push(pos);
+		positions.push(pos);
+	};
+
+	for road in roads {
+		let points : Vec<[f32; 3]> = road.iter().map(|p| [p[0] * DEG2KM, p[1] * DEG2KM, 0.0]).collect();
+		if let Some(first) = points.first() {
+			place(*first, &mut positions);
+		}
+
+		// distance walked since the last placed node
+		let mut walked = 0.0;
+		for segment in points.windows(2) {
+			let (a, b) = (segment[0], segment[1]);
+			let length = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+			let mut d = spacing - walked;
+			while d <= length {
+				let t = d / length;
+				place([a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1]), 0.0], &mut positions);
+				d += spacing;
+			}
+			walked = length - (d - spacing);
+		}
+	}
+
+	let offset = graph.node_count() as ID;
+	graph.add_nodes(positions.len() as u32);
+	for (i, pos) in positions.into_iter().enumerate() {
+		loc.insert(offset + i as ID, pos);
+	}
+
+	Ok(())
+}
+
+// position from x/y (in degrees, as exported) or a meshviewer location, fails for bad coordinates
+fn extract_location(node: &Value) -> Result<Option<[f32; 3]>, ()> {
+	let (lon, lat) = if node.get("x").is_some() || node.get("y").is_some() {
+		(get_f64(node, "x"), get_f64(node, "y"))
+	} else if let Some(location) = node.get("location").filter(|location| !location.is_null()) {
+		(get_f64(location, "longitude"), get_f64(location, "latitude"))
+	} else {
+		return Ok(None);
+	};
+
+	match (lon, lat) {
+		(Some(lon), Some(lat)) if valid_degrees(lon, lat) => Ok(Some([lon as f32 * DEG2KM, lat as f32 * DEG2KM, 0.0])),
+		_ => Err(())
+	}
+}
+
+// parse the meshviewer data
+fn parse_netjson(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, v: &Value,
+		summary: &mut ImportSummary, show_progress: bool) -> Result<(), MyError> {
+	let (nodes, links) = match (get_array(v, "nodes"), get_array(v, "links")) {
+		(Some(nodes), Some(links)) => (nodes, links),
+		_ => return Err(MyError::new("nodes/links fields missing".to_string()))
+	};
+
+	// map target/source field to node id in graph.nodes
+	let mut map = HashMap::<&str, usize>::new();
+	let mut id = graph.node_count();
+
+	for (i, node) in nodes.iter().enumerate() {
+		// try different keys (netjson uses node_id)
+		let node_id = match get_str(node, "node_id").or_else(|| get_str(node, "id")) {
+			Some(node_id) => node_id,
+			None => {
+				summary.skip_node(format!("node #{}: id missing", i));
+				continue;
+			}
+		};
+
+		if map.contains_key(node_id) {
+			summary.skip_node(format!("node {}: duplicate id", node_id));
+			continue;
+		}
+
+		match extract_location(node) {
+			Ok(Some(pos)) => if let Some(loc) = loc.borrow_mut() {
+				loc.data.insert(id as ID, pos);
+			},
+			Ok(None) => {},
+			Err(()) => summary.bad_position(format!("node {}: bad coordinates", node_id))
+		}
+
+		if let Some(meta) = meta.borrow_mut() {
+			meta.data.insert(id as ID, serde_json::to_string(&node).unwrap_or(String::new()));
+		}
+
+		// remember node id
+		map.insert(node_id, id);
+		id += 1;
+	}
+
+	summary.nodes = map.len();
+	graph.add_nodes(map.len() as u32);
 
-					// remember node id
-					map.insert(&node_id, id);
-					id += 1;
-					break;
+	let mut new_links = ImportLinks::new();
+	let mut progress = Progress::new();
+	for (i, link) in links.iter().enumerate() {
+		link_progress(&mut progress, show_progress, links.len(), i)?;
+		let (source, target) = match (get_str(link, "source"), get_str(link, "target")) {
+			(Some(source), Some(target)) => (source, target),
+			_ => {
+				summary.skip_link(format!("link #{}: source/target missing", i));
+				continue;
+			}
+		};
+
+		let (a, b) = match (map.get(source), map.get(target)) {
+			(Some(a), Some(b)) => (*a as ID, *b as ID),
+			_ => {
+				summary.skip_link(format!("link {} -> {}: unknown node", source, target));
+				continue;
+			}
+		};
+
+		let (source_tq, target_tq) = match (get_f64(link, "source_tq").and_then(to_quality), get_f64(link, "target_tq").and_then(to_quality)) {
+			(Some(source_tq), Some(target_tq)) => (source_tq, target_tq),
+			_ => {
+				summary.skip_link(format!("link {} -> {}: bad source_tq/target_tq", source, target));
+				continue;
+			}
+		};
+
+		let back = if target_tq > 0 { Some(target_tq) } else { None };
+		if let Err(e) = new_links.add(a, b, source_tq, back) {
+			summary.skip_link(format!("link {} -> {}: {}", source, target, e));
+			continue;
+		}
+		summary.links += 1;
+
+		// attributes written by the export for links with non default values
+		for name in ["cost", "bandwidth", "latency", "loss", "channel"] {
+			if let Some(value) = link.get(name).filter(|value| value.is_number()) {
+				if let Err(e) = new_links.set_attribute(a, b, name, &value.to_string()) {
+					summary.error(format!("link {} -> {}: {}", source, target, e));
 				}
 			}
 		}
+	}
 
-		graph.add_nodes(map.len() as u32);
+	if show_progress {
+		progress.update(links.len(), links.len());
+	}
+
+	new_links.build(graph);
+	Ok(())
+}
+
+// add the edges of GraphML or GEXF, attributes are (name, value) pairs
+fn add_edge(new_links: &mut ImportLinks, map: &HashMap<&str, usize>, summary: &mut ImportSummary,
+		source: Option<&str>, target: Option<&str>, directed: bool, attributes: Vec<(String, String)>) {
+	let (source, target) = match (source, target) {
+		(Some(source), Some(target)) => (source, target),
+		_ => {
+			summary.skip_link("edge: source/target missing".to_string());
+			return;
+		}
+	};
+
+	let (a, b) = match (map.get(source), map.get(target)) {
+		(Some(a), Some(b)) => (*a as ID, *b as ID),
+		_ => {
+			summary.skip_link(format!("edge {} -> {}: unknown node", source, target));
+			return;
+		}
+	};
 
-		for link in links {
-			if let (Some(source), Some(source_tq), Some(target), Some(target_tq)) =
-					(get_str(link, "source"), get_f64(link, "source_tq"),
-					get_str(link, "target"), get_f64(link, "target_tq")) {
-				if let (Some(source_id), Some(target_id)) = (map.get(source), map.get(target)) {
-					graph.add_link(*source_id as ID, *target_id as ID, (source_tq * std::u16::MAX as f64) as u16);
-					graph.add_link(*target_id as ID, *source_id as ID, (target_tq * std::u16::MAX as f64) as u16);
+	let back = if directed { None } else { Some(u16::MAX) };
+	if let Err(e) = new_links.add(a, b, u16::MAX, back) {
+		summary.skip_link(format!("edge {} -> {}: {}", source, target, e));
+		return;
+	}
+	summary.links += 1;
+
+	// the link is kept with a bad attribute
+	for (name, value) in attributes {
+		if let Err(e) = new_links.set_attribute(a, b, &name, &value) {
+			summary.error(format!("edge {} -> {}: {}", source, target, e));
+		}
+	}
+}
+
+// parse GraphML (Gephi, NetworkX, yEd)
+fn parse_graphml(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, data: &str,
+		summary: &mut ImportSummary, show_progress: bool) -> Result<(), MyError> {
+	let doc = roxmltree::Document::parse(data).map_err(|e| MyError::new(e.to_string()))?;
+	let root = doc.root_element();
+
+	// key id => attribute name
+	let mut keys = HashMap::<&str, &str>::new();
+	for key in root.children().filter(|n| n.has_tag_name("key")) {
+		if let (Some(id), Some(name)) = (key.attribute("id"), key.attribute("attr.name")) {
+			keys.insert(id, name);
+		}
+	}
+
+	let g = root.children().find(|n| n.has_tag_name("graph"))
+		.ok_or_else(|| MyError::new("graph element missing".to_string()))?;
+	let directed = g.attribute("edgedefault") == Some("directed");
+
+	// collect data elements by attribute name
+	let get_data = |element: &roxmltree::Node| -> Vec<(String, String)> {
+		element.children().filter(|n| n.has_tag_name("data")).filter_map(|d| {
+			let key = d.attribute("key")?;
+			let name = keys.get(key).cloned().unwrap_or(key);
+			Some((name.to_string(), d.text().unwrap_or("").trim().to_string()))
+		}).collect()
+	};
+
+	let mut map = HashMap::<&str, usize>::new();
+	let mut id = graph.node_count();
+
+	for node in g.children().filter(|n| n.has_tag_name("node")) {
+		let node_id = match node.attribute("id") {
+			Some(node_id) => node_id,
+			None => {
+				summary.skip_node("node: id missing".to_string());
+				continue;
+			}
+		};
+
+		if map.contains_key(node_id) {
+			summary.skip_node(format!("node {}: duplicate id", node_id));
+			continue;
+		}
+
+		let data = get_data(&node);
+		let mut pos = [0.0f32; 3];
+		let mut has_pos = false;
+		let mut bad_pos = false;
+
+		for (name, value) in &data {
+			let i = match name.as_str() {
+				"x" | "lon" | "longitude" => 0,
+				"y" | "lat" | "latitude" => 1,
+				"z" => 2,
+				_ => continue
+			};
+			match value.parse::<f32>() {
+				Ok(v) if v.is_finite() => {
+					pos[i] = v;
+					has_pos = true;
+				},
+				_ => bad_pos = true
+			}
+		}
+
+		// yEd stores positions in a shape node
+		if let Some(geometry) = node.descendants().find(|n| n.tag_name().name() == "Geometry") {
+			if let (Some(x), Some(y)) = (geometry.attribute("x"), geometry.attribute("y")) {
+				match (x.parse::<f32>(), y.parse::<f32>()) {
+					(Ok(x), Ok(y)) if x.is_finite() && y.is_finite() => {
+						pos[0] = x;
+						pos[1] = y;
+						has_pos = true;
+					},
+					_ => bad_pos = true
 				}
 			}
 		}
-		Ok(())
-	} else {
-		Err(MyError::new("nodes/links fields missing".to_string()))
+
+		if bad_pos {
+			summary.bad_position(format!("node {}: bad coordinates", node_id));
+		} else if has_pos {
+			if let Some(loc) = loc.borrow_mut() {
+				loc.data.insert(id as ID, pos);
+			}
+		}
+
+		if let Some(meta) = meta.borrow_mut() {
+			let mut obj = serde_json::Map::new();
+			obj.insert("id".to_string(), Value::String(node_id.to_string()));
+			for (name, value) in data {
+				obj.insert(name, Value::String(value));
+			}
+			meta.data.insert(id as ID, Value::Object(obj).to_string());
+		}
+
+		map.insert(node_id, id);
+		id += 1;
+	}
+
+	summary.nodes = map.len();
+	graph.add_nodes(map.len() as u32);
+
+	let edges : Vec<roxmltree::Node> = g.children().filter(|n| n.has_tag_name("edge")).collect();
+	let mut new_links = ImportLinks::new();
+	let mut progress = Progress::new();
+	for (i, edge) in edges.iter().enumerate() {
+		link_progress(&mut progress, show_progress, edges.len(), i)?;
+		let is_directed = edge.attribute("directed").map_or(directed, |d| d == "true");
+		add_edge(&mut new_links, &map, summary, edge.attribute("source"), edge.attribute("target"), is_directed, get_data(edge));
+	}
+
+	if show_progress {
+		progress.update(edges.len(), edges.len());
 	}
+
+	new_links.build(graph);
+	Ok(())
+}
+
+// parse GEXF (Gephi)
+fn parse_gexf(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, data: &str,
+		summary: &mut ImportSummary) -> Result<(), MyError> {
+	let doc = roxmltree::Document::parse(data).map_err(|e| MyError::new(e.to_string()))?;
+
+	let g = doc.root_element().children().find(|n| n.has_tag_name("graph"))
+		.ok_or_else(|| MyError::new("graph element missing".to_string()))?;
+	let directed = g.attribute("defaultedgetype") == Some("directed");
+
+	// (class, attribute id) => title
+	let mut titles = HashMap::<(&str, &str), &str>::new();
+	for attributes in g.children().filter(|n| n.has_tag_name("attributes")) {
+		let class = attributes.attribute("class").unwrap_or("node");
+		for attribute in attributes.children().filter(|n| n.has_tag_name("attribute")) {
+			if let (Some(id), Some(title)) = (attribute.attribute("id"), attribute.attribute("title")) {
+				titles.insert((class, id), title);
+			}
+		}
+	}
+
+	let get_attvalues = |element: &roxmltree::Node, class: &str| -> Vec<(String, String)> {
+		element.descendants().filter(|n| n.has_tag_name("attvalue")).filter_map(|a| {
+			let id = a.attribute("for").or_else(|| a.attribute("id"))?;
+			let name = titles.get(&(class, id)).cloned().unwrap_or(id);
+			Some((name.to_string(), a.attribute("value").unwrap_or("").to_string()))
+		}).collect()
+	};
+
+	let mut map = HashMap::<&str, usize>::new();
+	let mut id = graph.node_count();
+
+	for node in g.descendants().filter(|n| n.has_tag_name("node")) {
+		let node_id = match node.attribute("id") {
+			Some(node_id) => node_id,
+			None => {
+				summary.skip_node("node: id missing".to_string());
+				continue;
+			}
+		};
+
+		if map.contains_key(node_id) {
+			summary.skip_node(format!("node {}: duplicate id", node_id));
+			continue;
+		}
+
+		if let Some(position) = node.children().find(|n| n.tag_name().name() == "position") {
+			// missing coordinates are 0
+			let get = |name| position.attribute(name).map_or(Some(0.0), |v: &str| v.parse::<f32>().ok().filter(|v| v.is_finite()));
+			match (get("x"), get("y"), get("z")) {
+				(Some(x), Some(y), Some(z)) => if let Some(loc) = loc.borrow_mut() {
+					loc.data.insert(id as ID, [x, y, z]);
+				},
+				_ => summary.bad_position(format!("node {}: bad coordinates", node_id))
+			}
+		}
+
+		if let Some(meta) = meta.borrow_mut() {
+			let mut obj = serde_json::Map::new();
+			// the label defaults to the id on export
+			if let Some(label) = node.attribute("label").filter(|label| *label != node_id) {
+				obj.insert("label".to_string(), Value::String(label.to_string()));
+			}
+			for (name, value) in get_attvalues(&node, "node") {
+				obj.insert(name, Value::String(value));
+			}
+			meta.data.insert(id as ID, Value::Object(obj).to_string());
+		}
+
+		map.insert(node_id, id);
+		id += 1;
+	}
+
+	summary.nodes = map.len();
+	graph.add_nodes(map.len() as u32);
+
+	let mut new_links = ImportLinks::new();
+	for edge in g.descendants().filter(|n| n.has_tag_name("edge")) {
+		let is_directed = edge.attribute("type").map_or(directed, |t| t == "directed");
+		let mut attributes = get_attvalues(&edge, "edge");
+		if let Some(weight) = edge.attribute("weight") {
+			attributes.insert(0, ("weight".to_string(), weight.to_string()));
+		}
+		add_edge(&mut new_links, &map, summary, edge.attribute("source"), edge.attribute("target"), is_directed, attributes);
+	}
+
+	new_links.build(graph);
+	Ok(())
+}
+
+// position (in km) from GeoJSON coordinates [longitude, latitude, altitude in m]
+fn geojson_position(coords: &Value) -> Option<[f32; 3]> {
+	let lon = coords.get(0)?.as_f64()?;
+	let lat = coords.get(1)?.as_f64()?;
+	let alt = coords.get(2).and_then(Value::as_f64).unwrap_or(0.0);
+	if !valid_degrees(lon, lat) || !alt.is_finite() {
+		return None;
+	}
+	Some([lon as f32 * DEG2KM, lat as f32 * DEG2KM, alt as f32 / 1000.0])
+}
+
+// GeoJSON property as string, numbers are converted
+fn property_string(value: &Value) -> Option<String> {
+	match value {
+		Value::String(s) => Some(s.clone()),
+		Value::Number(n) => Some(n.to_string()),
+		_ => None
+	}
+}
+
+/*
+ * Parse a GeoJSON FeatureCollection. Points (or features with a null
+ * geometry) are nodes with their properties as metadata. LineStrings are
+ * links between the nodes given by the source/target properties or
+ * between the nodes at the first and last coordinate.
+ */
+fn parse_geojson(graph: &mut Graph, mut loc: Option<&mut Locations>, mut meta: Option<&mut Meta>, v: &Value,
+		summary: &mut ImportSummary) -> Result<(), MyError> {
+	let features = get_array(v, "features")
+		.ok_or_else(|| MyError::new("features field missing".to_string()))?;
+
+	let empty = serde_json::Map::new();
+	let properties = |feature: &Value| feature.get("properties").and_then(Value::as_object).unwrap_or(&empty).clone();
+	let geometry_type = |feature: &Value| feature.get("geometry").and_then(|g| get_str(g, "type").map(|t| t.to_string()));
+	let is_link = |feature: &Value| match geometry_type(feature).as_deref() {
+		Some("LineString") => true,
+		None => feature.pointer("/properties/source").is_some() && feature.pointer("/properties/target").is_some(),
+		_ => false
+	};
+
+	let mut map = HashMap::<String, usize>::new();
+	// node positions rounded to about 1 cm
+	let key = |pos: &[f32; 3]| ((pos[0] as f64 * 1e5) as i64, (pos[1] as f64 * 1e5) as i64);
+	let mut at_position = HashMap::<(i64, i64), usize>::new();
+	let mut id = graph.node_count();
+
+	for (i, feature) in features.iter().enumerate() {
+		if is_link(feature) {
+			continue;
+		}
+		if let Some(t) = geometry_type(feature).filter(|t| t != "Point") {
+			summary.skip_node(format!("feature #{}: unsupported geometry {}", i, t));
+			continue;
+		}
+
+		let mut props = properties(feature);
+		// the component is computed on export
+		props.remove("component");
+		let node_id = props.remove("id").as_ref().and_then(property_string)
+			.or_else(|| feature.get("id").and_then(property_string))
+			.unwrap_or_else(|| i.to_string());
+
+		if map.contains_key(&node_id) {
+			summary.skip_node(format!("node {}: duplicate id", node_id));
+			continue;
+		}
+
+		if let Some(coords) = feature.pointer("/geometry/coordinates") {
+			match geojson_position(coords) {
+				Some(pos) => {
+					at_position.insert(key(&pos), id);
+					if let Some(loc) = loc.borrow_mut() {
+						loc.data.insert(id as ID, pos);
+					}
+				},
+				None => summary.bad_position(format!("node {}: bad coordinates", node_id))
+			}
+		}
+
+		if let Some(meta) = meta.borrow_mut() {
+			if !props.is_empty() {
+				meta.data.insert(id as ID, Value::Object(props).to_string());
+			}
+		}
+
+		map.insert(node_id, id);
+		id += 1;
+	}
+
+	summary.nodes = map.len();
+	graph.add_nodes(map.len() as u32);
+
+	let mut new_links = ImportLinks::new();
+	for (i, feature) in features.iter().enumerate().filter(|(_, feature)| is_link(feature)) {
+		let props = properties(feature);
+		let by_property = |name: &str| props.get(name).and_then(property_string).and_then(|id| map.get(&id).cloned());
+		let by_position = |index: i64| {
+			let coords = feature.pointer("/geometry/coordinates")?.as_array()?;
+			let coord = if index < 0 { coords.last()? } else { coords.first()? };
+			at_position.get(&key(&geojson_position(coord)?)).cloned()
+		};
+
+		let (a, b) = match (by_property("source").or_else(|| by_position(0)), by_property("target").or_else(|| by_position(-1))) {
+			(Some(a), Some(b)) => (a as ID, b as ID),
+			_ => {
+				summary.skip_link(format!("feature #{}: link without nodes", i));
+				continue;
+			}
+		};
+
+		let tq = |name: &str, default: u16| props.get(name).map_or(Some(default), |tq| tq.as_f64().and_then(to_quality));
+		let source_tq = tq("source_tq", u16::MAX);
+		let target_tq = source_tq.and_then(|source_tq| tq("target_tq", source_tq));
+		let (source_tq, target_tq) = match (source_tq, target_tq) {
+			(Some(source_tq), Some(target_tq)) => (source_tq, target_tq),
+			_ => {
+				summary.skip_link(format!("feature #{}: bad source_tq/target_tq", i));
+				continue;
+			}
+		};
+
+		let back = if target_tq > 0 { Some(target_tq) } else { None };
+		if let Err(e) = new_links.add(a, b, source_tq, back) {
+			summary.skip_link(format!("feature #{}: {}", i, e));
+			continue;
+		}
+		summary.links += 1;
+
+		for (name, value) in &props {
+			if let Some(value) = property_string(value) {
+				if let Err(e) = new_links.set_attribute(a, b, name, &value) {
+					summary.error(format!("feature #{}: {}", i, e));
+				}
+			}
+		}
+	}
+
+	new_links.build(graph);
+	Ok(())
 }
 
 /*
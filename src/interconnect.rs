@@ -0,0 +1,83 @@
+
+use std::collections::HashSet;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::utils::MyError;
+
+
+/*
+ * Planning of long distance links between mesh islands.
+ * Between components, the shortest node pairs are linked like in
+ * Kruskal's algorithm, so k links connect k + 1 components with the
+ * least total distance. Between two node sets, the k shortest pairs
+ * that are not linked yet are used.
+ */
+
+fn distance(p: &[f32; 3], q: &[f32; 3]) -> f32 {
+	((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt()
+}
+
+fn position(locations: &Locations, id: ID) -> Result<&[f32; 3], MyError> {
+	locations.get_position(id).ok_or_else(|| MyError::new(format!("Node {} has no position", id)))
+}
+
+// node pairs (a < b) with their distance in km
+pub fn between_components(graph: &Graph, locations: &Locations, k: usize) -> Result<Vec<(ID, ID, f32)>, MyError> {
+	let components = graph.get_component_ids();
+	let node_count = graph.node_count();
+
+	let mut pairs = vec![];
+	for a in 0..node_count as ID {
+		let p = position(locations, a)?;
+		for b in (a + 1)..node_count as ID {
+			if components[a as usize] != components[b as usize] {
+				pairs.push((a, b, distance(p, position(locations, b)?)));
+			}
+		}
+	}
+	pairs.sort_by(|x, y| x.2.partial_cmp(&y.2).unwrap_or(std::cmp::Ordering::Equal));
+
+	// union find over the components
+	let mut roots : Vec<u32> = (0..node_count as u32).collect();
+	fn root(roots: &mut [u32], mut i: u32) -> u32 {
+		while roots[i as usize] != i {
+			roots[i as usize] = roots[roots[i as usize] as usize];
+			i = roots[i as usize];
+		}
+		i
+	}
+
+	let mut links = vec![];
+	for (a, b, d) in pairs {
+		if links.len() >= k {
+			break;
+		}
+		let x = root(&mut roots, components[a as usize]);
+		let y = root(&mut roots, components[b as usize]);
+		if x != y {
+			roots[x as usize] = y;
+			links.push((a, b, d));
+		}
+	}
+	Ok(links)
+}
+
+// the k shortest pairs from nodes of a to nodes of b without a link
+pub fn between_sets(graph: &Graph, locations: &Locations, k: usize, a: &[ID], b: &[ID]) -> Result<Vec<(ID, ID, f32)>, MyError> {
+	let mut pairs = vec![];
+	// nodes in both sets would give the same pair twice
+	let mut seen = HashSet::new();
+	for x in a {
+		let p = position(locations, *x)?;
+		for y in b {
+			let pair = ((*x).min(*y), (*x).max(*y));
+			if x != y && !graph.has_link(*x, *y) && !graph.has_link(*y, *x) && seen.insert(pair) {
+				pairs.push((pair.0, pair.1, distance(p, position(locations, *y)?)));
+			}
+		}
+	}
+	pairs.sort_by(|x, y| x.2.partial_cmp(&y.2).unwrap_or(std::cmp::Ordering::Equal));
+	pairs.truncate(k);
+	Ok(pairs)
+}
@@ -0,0 +1,169 @@
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::progress::{clear_cancel, request_cancel, is_cancelled};
+use crate::utils::{fmt_duration, MyError};
+
+
+/*
+ * Commands started in the background (with a trailing &).
+ * Every job runs on its own thread and waits for the simulator
+ * state, so jobs run one after another. Job control does not
+ * need the simulator state and works while a job is running.
+ */
+
+// finished jobs that are kept for job_status
+const MAX_FINISHED : usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+	Queued,
+	Running,
+	Done,
+	Failed,
+	Cancelled
+}
+
+impl JobState {
+	fn name(&self) -> &'static str {
+		match self {
+			JobState::Queued => "queued",
+			JobState::Running => "running",
+			JobState::Done => "done",
+			JobState::Failed => "failed",
+			JobState::Cancelled => "cancelled"
+		}
+	}
+
+	fn finished(&self) -> bool {
+		!matches!(self, JobState::Queued | JobState::Running)
+	}
+}
+
+struct Job {
+	id: u32,
+	command: String,
+	state: JobState,
+	output: String,
+	started: Option<Instant>,
+	duration: Duration
+}
+
+struct Jobs {
+	next_id: u32,
+	list: Vec<Job>
+}
+
+static JOBS : Mutex<Jobs> = Mutex::new(Jobs { next_id: 1, list: Vec::new() });
+
+impl Jobs {
+	fn get(&mut self, id: u32) -> Option<&mut Job> {
+		self.list.iter_mut().find(|job| job.id == id)
+	}
+}
+
+// register a new job, returns the job id
+pub fn add(command: &str) -> u32 {
+	let mut jobs = JOBS.lock().unwrap();
+	let id = jobs.next_id;
+	jobs.next_id += 1;
+
+	// forget the oldest finished jobs
+	let finished = jobs.list.iter().filter(|job| job.state.finished()).count();
+	if finished >= MAX_FINISHED {
+		if let Some(pos) = jobs.list.iter().position(|job| job.state.finished()) {
+			jobs.list.remove(pos);
+		}
+	}
+
+	jobs.list.push(Job {
+		id,
+		command: command.to_string(),
+		state: JobState::Queued,
+		output: String::new(),
+		started: None,
+		duration: Duration::from_secs(0)
+	});
+	id
+}
+
+// called with the simulator state locked, false if the job was cancelled while queued
+pub fn start(id: u32) -> bool {
+	let mut jobs = JOBS.lock().unwrap();
+	match jobs.get(id) {
+		Some(job) if job.state == JobState::Queued => {
+			// a cancel request only applies to the job that runs now
+			clear_cancel();
+			job.state = JobState::Running;
+			job.started = Some(Instant::now());
+			true
+		},
+		_ => false
+	}
+}
+
+pub fn finish(id: u32, output: String, result: Result<(), MyError>) {
+	let mut jobs = JOBS.lock().unwrap();
+	if let Some(job) = jobs.get(id) {
+		job.state = match result {
+			Err(e) => {
+				job.output = format!("{}{}\n", output, e);
+				JobState::Failed
+			},
+			Ok(()) => {
+				job.output = output;
+				if is_cancelled() { JobState::Cancelled } else { JobState::Done }
+			}
+		};
+		job.duration = job.started.map_or(Duration::from_secs(0), |s| s.elapsed());
+	}
+}
+
+pub fn cancel(out: &mut dyn std::fmt::Write, id: u32) -> Result<(), MyError> {
+	let mut jobs = JOBS.lock().unwrap();
+	match jobs.get(id) {
+		Some(job) => {
+			match job.state {
+				JobState::Queued => {
+					job.state = JobState::Cancelled;
+					writeln!(out, "Job {} cancelled.", id)?;
+				},
+				JobState::Running => {
+					request_cancel();
+					writeln!(out, "Job {} cancel requested.", id)?;
+				},
+				state => {
+					writeln!(out, "Job {} is already {}.", id, state.name())?;
+				}
+			}
+			Ok(())
+		},
+		None => Err(MyError::new(format!("Unknown job: {}", id)))
+	}
+}
+
+pub fn list(out: &mut dyn std::fmt::Write) -> Result<(), MyError> {
+	let jobs = JOBS.lock().unwrap();
+	if jobs.list.is_empty() {
+		writeln!(out, "no jobs")?;
+	}
+	for job in &jobs.list {
+		writeln!(out, "[{}] {:9} {}", job.id, job.state.name(), job.command)?;
+	}
+	Ok(())
+}
+
+pub fn status(out: &mut dyn std::fmt::Write, id: u32) -> Result<(), MyError> {
+	let mut jobs = JOBS.lock().unwrap();
+	let job = jobs.get(id).ok_or_else(|| MyError::new(format!("Unknown job: {}", id)))?;
+
+	write!(out, "job: {}, command: {}, state: {}", job.id, job.command, job.state.name())?;
+	match (job.state, job.started) {
+		(JobState::Running, Some(started)) => writeln!(out, ", running for: {}", fmt_duration(started.elapsed()))?,
+		(JobState::Queued, _) => writeln!(out)?,
+		_ => writeln!(out, ", duration: {}", fmt_duration(job.duration))?
+	}
+	write!(out, "{}", job.output)?;
+	Ok(())
+}
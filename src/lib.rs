@@ -0,0 +1,55 @@
+
+/*
+ * The simulator as library. Commands are parsed with
+ * cmd::parse_command and run with cmd::execute on a
+ * sim::GlobalState, the binary only adds console and sockets.
+ */
+
+pub mod graph;
+pub mod graph_diff;
+pub mod dijkstra;
+pub mod utils;
+pub mod stats;
+pub mod algorithms;
+pub mod eval_paths;
+pub mod events;
+pub mod debug_path;
+pub mod importer;
+pub mod exporter;
+pub mod movements;
+pub mod radio;
+pub mod obstacles;
+pub mod churn;
+pub mod link_model;
+pub mod channels;
+pub mod capacity;
+pub mod source_routing;
+pub mod workspace;
+pub mod interconnect;
+pub mod placement;
+pub mod render;
+pub mod style;
+pub mod binary;
+pub mod compress;
+pub mod validate;
+pub mod energy;
+pub mod traffic;
+pub mod mst;
+pub mod metrics;
+pub mod timeline;
+pub mod flow;
+pub mod cluster;
+pub mod selection;
+pub mod history;
+pub mod jobs;
+pub mod state;
+pub mod locations;
+pub mod meta;
+pub mod node_ids;
+pub mod sim;
+pub mod cmd;
+pub mod progress;
+pub mod script;
+pub mod ws;
+
+extern crate rand;
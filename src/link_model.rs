@@ -0,0 +1,148 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, ID};
+use crate::utils::{random, MyError};
+
+
+/*
+ * Time varying packet loss of links (Gilbert-Elliott model).
+ * Every link is either in the good or the bad state and changes
+ * the state on every simulation step with a probability, which
+ * results in bursts of loss. The loss of a link is measured over
+ * a window of probe packets like ETX probes, so the values seen
+ * by the routing algorithms fluctuate. The loss a link had before
+ * (e.g. imported) is kept as base loss and restored when disabled.
+ */
+
+#[derive(Clone)]
+struct LinkState {
+	bad: bool,
+	// loss of the link without the model
+	base_loss: f32,
+	// loss set by the model in the last step
+	loss: f32
+}
+
+#[derive(Clone)]
+pub struct LinkModel {
+	enabled: bool,
+	// probability to change from good to bad and back per step
+	p_good_bad: f32,
+	p_bad_good: f32,
+	// packet loss ratio in the good and bad state
+	loss_good: f32,
+	loss_bad: f32,
+	// probe packets per measurement, 0 uses the exact loss ratio
+	probes: u32,
+	links: HashMap<(ID, ID), LinkState>
+}
+
+impl Default for LinkModel {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl LinkModel {
+	pub fn new() -> Self {
+		Self {
+			enabled: false,
+			p_good_bad: 0.0,
+			p_bad_good: 0.0,
+			loss_good: 0.0,
+			loss_bad: 0.0,
+			probes: 0,
+			links: HashMap::new()
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	pub fn set_gilbert(&mut self, p_good_bad: f32, p_bad_good: f32, loss_good: f32, loss_bad: f32, probes: u32) -> Result<(), MyError> {
+		for value in &[p_good_bad, p_bad_good, loss_good, loss_bad] {
+			if !(*value >= 0.0 && *value <= 1.0) {
+				return Err(MyError::new(format!("Invalid probability: {}", value)));
+			}
+		}
+
+		self.enabled = true;
+		self.p_good_bad = p_good_bad;
+		self.p_bad_good = p_bad_good;
+		self.loss_good = loss_good;
+		self.loss_bad = loss_bad;
+		self.probes = probes;
+		Ok(())
+	}
+
+	// restore the loss the links had before
+	pub fn disable(&mut self, graph: &mut Graph) {
+		for ((from, to), state) in self.links.drain() {
+			if let Some(link) = graph.get_link_mut(from, to) {
+				if link.loss() == state.loss {
+					link.set_loss(state.base_loss);
+				}
+			}
+		}
+		self.enabled = false;
+	}
+
+	// share of time a link spends in the bad state
+	fn bad_ratio(&self) -> f32 {
+		let sum = self.p_good_bad + self.p_bad_good;
+		if sum > 0.0 { self.p_good_bad / sum } else { 0.0 }
+	}
+
+	fn measure(&self, loss: f32) -> f32 {
+		if self.probes == 0 {
+			loss
+		} else {
+			let lost = (0..self.probes).filter(|_| random::<f32>() < loss).count();
+			lost as f32 / self.probes as f32
+		}
+	}
+
+	pub fn step(&mut self, graph: &mut Graph) {
+		if !self.enabled {
+			return;
+		}
+
+		// forget links that do not exist anymore
+		let mut states = HashMap::with_capacity(graph.link_count());
+		let bad_ratio = self.bad_ratio();
+
+		for link in graph.links_mut() {
+			let key = (link.from, link.to);
+			let mut state = match self.links.remove(&key) {
+				// the link was replaced if the loss is not the one we set
+				Some(state) if state.loss == link.loss() => state,
+				// new links start in the stationary distribution
+				_ => LinkState { bad: random::<f32>() < bad_ratio, base_loss: link.loss(), loss: 0.0 }
+			};
+
+			let change = if state.bad { self.p_bad_good } else { self.p_good_bad };
+			if random::<f32>() < change {
+				state.bad = !state.bad;
+			}
+
+			let measured = self.measure(if state.bad { self.loss_bad } else { self.loss_good });
+			state.loss = 1.0 - (1.0 - state.base_loss) * (1.0 - measured);
+			link.set_loss(state.loss);
+			states.insert(key, state);
+		}
+
+		self.links = states;
+	}
+
+	pub fn get_model(&self) -> String {
+		if !self.enabled {
+			return "off".to_string();
+		}
+
+		let bad = self.links.values().filter(|state| state.bad).count();
+		format!("gilbert-elliott, good => bad: {}, bad => good: {}, loss good: {}, loss bad: {}, probes: {}, bad links: {}/{}",
+			self.p_good_bad, self.p_bad_good, self.loss_good, self.loss_bad, self.probes, bad, self.links.len())
+	}
+}
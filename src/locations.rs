@@ -1,24 +1,32 @@
 use std::collections::HashMap;
 use crate::graph::ID;
+use crate::utils::random;
 
 
+#[derive(Clone)]
 pub struct Locations {
 	pub data: HashMap<ID, [f32; 3]>
 }
 
+impl Default for Locations {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Locations {
 	pub fn new() -> Self {
 		Self { data: HashMap::new() }
 	}
 
 	pub fn is_valid(pos: &[f32; 3]) -> bool {
-		!pos[0].is_nan() && pos[1].is_nan() && !pos[3].is_nan()
+		!pos[0].is_nan() && !pos[1].is_nan() && !pos[2].is_nan()
 	}
 
 	pub fn pos_distance(&self, p1: ID, p2: ID) -> Option<f32> {
 		if let (Some(a), Some(b)) = (self.data.get(&p1), self.data.get(&p2)) {
 			Some(((a[0] - b[0]).powi(2)
-				+ (a[1] - b[2]).powi(2)
+				+ (a[1] - b[1]).powi(2)
 				+ (a[2] - b[2]).powi(2)).sqrt())
 		} else {
 			None
@@ -33,6 +41,14 @@ impl Locations {
 		self.data.remove(&id);
 	}
 
+	// locations of the given nodes only, node ids[i] becomes node i
+	pub fn subgraph(&self, ids: &[ID]) -> Self {
+		let data = ids.iter().enumerate()
+			.filter_map(|(i, id)| self.data.get(id).map(|pos| (i as ID, *pos)))
+			.collect();
+		Self { data }
+	}
+
 	pub fn insert(&mut self, id: ID, pos: [f32; 3]) {
 		self.data.insert(id, pos);
 	}
@@ -72,16 +88,14 @@ impl Locations {
 
 	pub fn init_positions(&mut self, count: usize, pos: [f32; 3]) {
 		for id in 0..count as u32 {
-			if !self.data.contains_key(&id) {
-				self.data.insert(id, pos);
-			}
+			self.data.entry(id).or_insert(pos);
 		}
 	}
 
 	pub fn randomize_positions_2d(&mut self, center: [f32; 3], range: f32) {
 		for val in self.data.values_mut() {
-			val[0] = center[0] + (2.0 * rand::random::<f32>() - 1.0) * range;
-			val[1] = center[1] + (2.0 * rand::random::<f32>() - 1.0) * range;
+			val[0] = center[0] + (2.0 * random::<f32>() - 1.0) * range;
+			val[1] = center[1] + (2.0 * random::<f32>() - 1.0) * range;
 			val[2] = 0.0;
 		}
 	}
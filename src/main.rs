@@ -1,21 +1,4 @@
 
-mod graph;
-mod dijkstra;
-mod utils;
-mod stats;
-mod algorithms;
-mod eval_paths;
-mod debug_path;
-mod importer;
-mod exporter;
-mod movements;
-mod locations;
-mod meta;
-mod sim;
-mod cmd;
-mod progress;
-
-extern crate rand;
 
 use std::thread;
 use std::sync::Arc;
@@ -23,31 +6,53 @@ use std::sync::Mutex;
 use std::vec::Vec;
 use std::env;
 
-use crate::cmd::cmd_loop;
-use crate::cmd::ext_loop;
-use crate::graph::Graph;
-use crate::sim::GlobalState;
+use mesh_simulator::cmd::batch_run;
+use mesh_simulator::cmd::cmd_loop;
+use mesh_simulator::cmd::ext_loop;
+use mesh_simulator::cmd::http_loop;
+use mesh_simulator::sim::GlobalState;
+use mesh_simulator::ws::ws_loop;
 
 
-pub const VERSION : &'static str = "0.8";
-pub const CMD_SOCKET_ADDRESS : &'static str = "127.0.0.1:8011";
+pub const VERSION : &str = "0.8";
+pub const CMD_SOCKET_ADDRESS : &str = "127.0.0.1:8011";
 
 
-const HELP_TEXT: &'static str = concat!(
+const HELP_TEXT: &str = concat!(
 	"--help|-h         Display this help.\n",
 	"--version|-v      Display version.\n",
 	"--run <file>      Run commands from file.\n",
-	"--bind <address>  Bind command socket to address. (Default: 127.0.0.1:8011)\n"
+	"--batch <file>    Run commands from file without console, sockets and progress output and exit.\n",
+	"                  The exit code is 1 if a command failed, errors go to stderr as <file>:<line>: <error>.\n",
+	"--bind <address>  Bind command socket to address. (Default: 127.0.0.1:8011)\n",
+	"--http <address>  Serve HTTP API on address.\n",
+	"--ws <address>    Push graph updates to WebSocket clients on address.\n",
+	"--json            Output command results as JSON objects (same as the output json command).\n"
 );
 
 
 fn main() {
 	let mut cmd_address = CMD_SOCKET_ADDRESS.to_string();
-	let args: Vec<String> = env::args().skip(1).collect();
+	let mut args: Vec<String> = env::args().skip(1).collect();
 	let mut run_script = String::new();
+	let mut http_address = None;
+	let mut ws_address = None;
 
-	if let Some((cmd, args)) = args.split_first() {
-		match cmd.as_ref() {
+	let json_output = args.iter().any(|arg| arg == "--json");
+	args.retain(|arg| arg != "--json");
+
+	// the value of a flag, exits if it is missing
+	fn value(args: &mut impl Iterator<Item = String>, flag: &str, what: &str) -> String {
+		args.next().unwrap_or_else(|| {
+			println!("{} missing for \"{}\".", what, flag);
+			std::process::exit(1);
+		})
+	}
+
+	let mut batch_file = None;
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		match arg.as_ref() {
 			"-h" | "--help" => {
 				println!("{}", HELP_TEXT);
 				std::process::exit(0);
@@ -57,27 +62,42 @@ fn main() {
 				std::process::exit(0);
 			},
 			"--bind" => {
-				if args.len() == 1 {
-					cmd_address = args[0].clone();
-				} else if args.len() == 0 {
-					println!("Address missing for \"--bind\".");
-					std::process::exit(1);
-				} else {
-					println!("Too many arguments for \"--bind\".");
-					std::process::exit(1);
-				}
+				cmd_address = value(&mut args, &arg, "Address");
+			},
+			"--http" => {
+				http_address = Some(value(&mut args, &arg, "Address"));
+			},
+			"--ws" => {
+				ws_address = Some(value(&mut args, &arg, "Address"));
 			},
 			"--run" => {
-				run_script = format!("run {}", args.join(" "));
+				run_script = format!("run {}", value(&mut args, &arg, "File"));
+			},
+			"--batch" => {
+				batch_file = Some(value(&mut args, &arg, "File"));
 			},
 			_ => {
-				println!("Unknown argument: {}", cmd);
+				println!("Unknown argument: {}", arg);
 				std::process::exit(1);
 			}
 		}
 	}
 
-	let sim = Arc::new(Mutex::new(GlobalState::new(&cmd_address)));
+	if let Some(batch_file) = batch_file {
+		// batch mode exits when the script is done, no servers are started
+		if http_address.is_some() || ws_address.is_some() || !run_script.is_empty() {
+			println!("\"--batch\" cannot be combined with \"--http\", \"--ws\" or \"--run\".");
+			std::process::exit(1);
+		}
+
+		let mut sim = GlobalState::new(&cmd_address);
+		sim.json_output = json_output;
+		std::process::exit(batch_run(&mut sim, &batch_file));
+	}
+
+	let mut state = GlobalState::new(&cmd_address);
+	state.json_output = json_output;
+	let sim = Arc::new(Mutex::new(state));
 
 	// console
 	let cmd_handle = sim.clone();
@@ -91,6 +111,22 @@ fn main() {
 		ext_loop(ext_handle, &cmd_address);
 	});
 
+	// http api (not joined, ends with the process)
+	if let Some(http_address) = http_address {
+		let http_handle = sim.clone();
+		thread::spawn(move || {
+			http_loop(http_handle, &http_address);
+		});
+	}
+
+	// websocket (not joined, ends with the process)
+	if let Some(ws_address) = ws_address {
+		let ws_handle = sim.clone();
+		thread::spawn(move || {
+			ws_loop(ws_handle, &ws_address);
+		});
+	}
+
 	cmd_thread.join().unwrap();
 	ext_thread.join().unwrap();
 
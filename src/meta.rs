@@ -1,11 +1,23 @@
 use std::collections::HashMap;
+use serde_json::Value;
 use crate::graph::ID;
+use crate::utils::{map_tokens, wildcard_match, MyError};
 
+// keys that hold a human-readable name of a node, in order of preference
+pub const NAME_KEYS : [&str; 5] = ["name", "hostname", "label", "node_id", "id"];
 
+
+#[derive(Clone)]
 pub struct Meta {
 	pub data: HashMap<ID, String>
 }
 
+impl Default for Meta {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Meta {
 	pub fn new() -> Self {
 		Self { data: HashMap::new() }
@@ -19,6 +31,121 @@ impl Meta {
 		self.data.remove(&id);
 	}
 
+	// metadata of the given nodes only, node ids[i] becomes node i
+	pub fn subgraph(&self, ids: &[ID]) -> Self {
+		let data = ids.iter().enumerate()
+			.filter_map(|(i, id)| self.data.get(id).map(|data| (i as ID, data.clone())))
+			.collect();
+		Self { data }
+	}
+
+	// value of a key in the JSON object of a node
+	pub fn get_value(&self, id: ID, key: &str) -> Option<Value> {
+		self.get_object(id)?.remove(key)
+	}
+
+	// set a key in the JSON object of a node
+	pub fn set_value(&mut self, id: ID, key: &str, value: Value) {
+		let mut obj = match self.data.get(&id).map(|data| serde_json::from_str::<Value>(data)) {
+			Some(Ok(Value::Object(obj))) => obj,
+			_ => serde_json::Map::new()
+		};
+		obj.insert(key.to_string(), value);
+		self.data.insert(id, Value::Object(obj).to_string());
+	}
+
+	// JSON object of a node
+	pub fn get_object(&self, id: ID) -> Option<serde_json::Map<String, Value>> {
+		match serde_json::from_str::<Value>(self.data.get(&id)?) {
+			Ok(Value::Object(obj)) => Some(obj),
+			_ => None
+		}
+	}
+
+	// keys and values of a node, keys may contain wildcards
+	pub fn get_matching(&self, id: ID, key_pattern: &str) -> Vec<(String, Value)> {
+		let mut entries : Vec<(String, Value)> = self.get_object(id).map_or(vec![], |obj| {
+			obj.into_iter().filter(|(key, _)| wildcard_match(key_pattern, key)).collect()
+		});
+		entries.sort_by(|a, b| a.0.cmp(&b.0));
+		entries
+	}
+
+	// if a node has a key with a value that match the patterns,
+	// strings are compared without quotes
+	pub fn matches(&self, id: ID, key_pattern: &str, value_pattern: &str) -> bool {
+		self.get_matching(id, key_pattern).iter()
+			.any(|(_, value)| wildcard_match(value_pattern, &Self::value_to_string(value)))
+	}
+
+	// all nodes with a matching key and value, sorted by id
+	pub fn filter(&self, key_pattern: &str, value_pattern: &str) -> Vec<ID> {
+		let mut ids : Vec<ID> = self.data.keys()
+			.filter(|id| self.matches(**id, key_pattern, value_pattern))
+			.cloned().collect();
+		ids.sort_unstable();
+		ids
+	}
+
+	pub fn value_to_string(value: &Value) -> String {
+		match value {
+			Value::String(s) => s.clone(),
+			_ => value.to_string()
+		}
+	}
+
+	// first name of a node found in the metadata, numbers are
+	// not used as names since they are taken as node ids
+	pub fn name(&self, id: ID) -> Option<String> {
+		let obj = self.get_object(id)?;
+		NAME_KEYS.iter()
+			.filter_map(|key| obj.get(*key))
+			.map(Self::value_to_string)
+			.find(|name| !name.is_empty() && name.parse::<f64>().is_err())
+	}
+
+	// all nodes that carry the name under any of the name keys, sorted by id
+	pub fn find_name(&self, name: &str) -> Vec<ID> {
+		let mut ids : Vec<ID> = self.data.keys()
+			.filter(|id| self.get_object(**id).is_some_and(|obj| {
+				NAME_KEYS.iter().any(|key| obj.get(*key).is_some_and(|value| Self::value_to_string(value) == name))
+			}))
+			.cloned().collect();
+		ids.sort_unstable();
+		ids
+	}
+
+	// replace node names by their node ids in the tokens at the given positions
+	// (comma separated lists), numbers and the whitespace are left as they are
+	pub fn expand_names(&self, input: &str, positions: &[usize]) -> Result<String, MyError> {
+		if self.data.is_empty() || positions.is_empty() {
+			return Ok(input.to_string());
+		}
+
+		map_tokens(input, positions, |token| self.expand_list(token))
+	}
+
+	fn expand_list(&self, token: &str) -> Result<String, MyError> {
+		let mut items = vec![];
+		for item in token.split(',') {
+			let name = item.trim_matches(|c: char| (c == '\'') || (c == '"'));
+			if name.is_empty() || name.parse::<f64>().is_ok() {
+				items.push(item.to_string());
+				continue;
+			}
+			let ids = self.find_name(name);
+			match ids.len() {
+				0 => items.push(item.to_string()),
+				1 => items.push(ids[0].to_string()),
+				_ => {
+					let ids : Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+					return Err(MyError::new(format!("Ambiguous node name: {} (nodes {})", name, ids.join(","))));
+				}
+			}
+		}
+		Ok(items.join(","))
+	}
+
 	pub fn insert(&mut self, id: ID, data: String) {
 		self.data.insert(id, data);
 	}
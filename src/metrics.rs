@@ -0,0 +1,108 @@
+
+use std::io::Write;
+
+use crate::graph::{Graph, ID};
+use crate::sim::{RoutingAlgorithm, TestPacket};
+use crate::utils::{random_range, MyError};
+
+
+/*
+ * Measurements after every simulation step, appended to a CSV file
+ * to plot how the routing converges over time. The delivery rate is
+ * measured on a fixed set of node pairs sampled on start.
+ */
+pub struct Metrics {
+	path: Option<String>,
+	pairs: Vec<(ID, ID)>
+}
+
+impl Default for Metrics {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		Self {
+			path: None,
+			pairs: vec![]
+		}
+	}
+
+	pub fn path(&self) -> Option<&str> {
+		self.path.as_deref()
+	}
+
+	pub fn start(&mut self, path: &str, node_count: usize, samples: usize) -> Result<(), MyError> {
+		let mut file = std::fs::File::create(path)?;
+		writeln!(file, "step,arrived,error,messages,bytes,links")?;
+
+		self.pairs.clear();
+		if node_count >= 2 {
+			while self.pairs.len() < samples {
+				let source = random_range(0, node_count) as ID;
+				let target = random_range(0, node_count) as ID;
+				if source != target {
+					self.pairs.push((source, target));
+				}
+			}
+		}
+
+		self.path = Some(path.to_string());
+		Ok(())
+	}
+
+	pub fn stop(&mut self) {
+		self.path = None;
+		self.pairs.clear();
+	}
+
+	// percentage of the sampled pairs the algorithm delivers
+	fn delivery_rate(&self, graph: &Graph, algorithm: &dyn RoutingAlgorithm) -> f32 {
+		let node_count = graph.node_count();
+		let mut sent = 0;
+		let mut arrived = 0;
+
+		for &(source, target) in &self.pairs {
+			// nodes might have been removed
+			if source as usize >= node_count || target as usize >= node_count {
+				continue;
+			}
+			sent += 1;
+
+			let mut packet = TestPacket::new(source, source, source, target);
+			for _ in 0..node_count {
+				match algorithm.route(&packet) {
+					Some(next) if graph.has_link(packet.receiver, next) => {
+						if next == target {
+							arrived += 1;
+							break;
+						}
+						packet.transmitter = packet.receiver;
+						packet.receiver = next;
+					},
+					_ => break
+				}
+			}
+		}
+
+		100.0 * arrived as f32 / sent as f32
+	}
+
+	pub fn record(&self, step: u32, graph: &Graph, algorithm: &dyn RoutingAlgorithm, sent: (u64, u64)) -> Result<(), MyError> {
+		let path = match &self.path {
+			Some(path) => path,
+			None => return Ok(())
+		};
+
+		// coordinate based algorithms report their mean error
+		let mut error = String::new();
+		algorithm.get("mean_error", &mut error)?;
+
+		let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+		writeln!(file, "{},{},{},{},{},{}", step, self.delivery_rate(graph, algorithm), error,
+			sent.0, sent.1, graph.link_count())?;
+		Ok(())
+	}
+}
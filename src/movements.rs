@@ -1,46 +1,649 @@
 use std::collections::HashMap;
 
 use crate::locations::Locations;
-use crate::graph::{Graph, ID};
+use crate::graph::ID;
+use crate::utils::{random, random_range, MyError};
 
 
+/*
+ * Mobility models move the node locations on every simulation step.
+ * Speeds and distances are in the same unit as the locations (per step).
+ */
+pub trait MovementModel: Send {
+	fn name(&self) -> String;
+	fn step(&mut self, locations: &mut Locations);
+	fn remove_node(&mut self, _id: ID) {}
+	// copy of the model with its state
+	fn clone_box(&self) -> Box<dyn MovementModel>;
+}
+
+// move pos towards dest by distance, returns true if dest was reached
+fn move_towards(pos: &mut [f32; 3], dest: &[f32; 3], distance: f32) -> bool {
+	let d = [dest[0] - pos[0], dest[1] - pos[1], dest[2] - pos[2]];
+	let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+
+	if len <= distance {
+		*pos = *dest;
+		true
+	} else {
+		let f = distance / len;
+		pos[0] += d[0] * f;
+		pos[1] += d[1] * f;
+		pos[2] += d[2] * f;
+		false
+	}
+}
+
+// bounding box of all locations
+fn get_area(locations: &Locations) -> ([f32; 3], [f32; 3]) {
+	let mut min = [f32::INFINITY; 3];
+	let mut max = [f32::NEG_INFINITY; 3];
+
+	for pos in locations.data.values() {
+		for i in 0..3 {
+			min[i] = min[i].min(pos[i]);
+			max[i] = max[i].max(pos[i]);
+		}
+	}
+
+	(min, max)
+}
+
+fn random_pos(area: &([f32; 3], [f32; 3])) -> [f32; 3] {
+	let (min, max) = area;
+	let mut pos = [0.0; 3];
+	for i in 0..3 {
+		pos[i] = min[i] + random::<f32>() * (max[i] - min[i]);
+	}
+	pos
+}
+
+#[derive(Clone)]
+struct Waypoint {
+	dest: [f32; 3],
+	speed: f32,
+	pause: u32
+}
+
+/*
+ * Random waypoint model.
+ * Every node moves with a random speed to a random destination
+ * within the area of all nodes and pauses there.
+ */
+#[derive(Clone)]
+pub struct WaypointMovements {
+	speed_min: f32,
+	speed_max: f32,
+	pause: u32,
+	area: Option<([f32; 3], [f32; 3])>,
+	nodes: HashMap<ID, Waypoint>
+}
+
+impl WaypointMovements {
+	pub fn new(speed_min: f32, speed_max: f32, pause: u32) -> Self {
+		Self {
+			speed_min: speed_min.min(speed_max),
+			speed_max: speed_max.max(speed_min),
+			pause,
+			area: None,
+			nodes: HashMap::new()
+		}
+	}
+}
+
+fn next_waypoint(speed_min: f32, speed_max: f32, area: &([f32; 3], [f32; 3])) -> Waypoint {
+	let speed = if speed_min < speed_max {
+		random_range(speed_min, speed_max)
+	} else {
+		speed_min
+	};
+	Waypoint { dest: random_pos(area), speed, pause: 0 }
+}
+
+impl MovementModel for WaypointMovements {
+	fn name(&self) -> String {
+		format!("waypoint {} {} {}", self.speed_min, self.speed_max, self.pause)
+	}
+
+	fn clone_box(&self) -> Box<dyn MovementModel> {
+		Box::new(self.clone())
+	}
+
+	fn step(&mut self, locations: &mut Locations) {
+		let area = *self.area.get_or_insert_with(|| get_area(locations));
+		let (speed_min, speed_max, pause) = (self.speed_min, self.speed_max, self.pause);
+
+		for (id, pos) in locations.data.iter_mut() {
+			let waypoint = self.nodes.entry(*id)
+				.or_insert_with(|| next_waypoint(speed_min, speed_max, &area));
+
+			if waypoint.pause > 0 {
+				waypoint.pause -= 1;
+				if waypoint.pause == 0 {
+					*waypoint = next_waypoint(speed_min, speed_max, &area);
+				}
+			} else if move_towards(pos, &waypoint.dest, waypoint.speed) {
+				if pause > 0 {
+					waypoint.pause = pause;
+				} else {
+					*waypoint = next_waypoint(speed_min, speed_max, &area);
+				}
+			}
+		}
+	}
+
+	fn remove_node(&mut self, id: ID) {
+		self.nodes.remove(&id);
+	}
+}
+
+// normal distributed random value (Box-Muller)
+fn gauss() -> f32 {
+	let u1 = random::<f32>().max(f32::MIN_POSITIVE);
+	let u2 = random::<f32>();
+	(-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+fn clamp_to_area(pos: &mut [f32; 3], area: &([f32; 3], [f32; 3])) {
+	for (i, p) in pos.iter_mut().enumerate() {
+		*p = p.max(area.0[i]).min(area.1[i]);
+	}
+}
+
+fn is_in_area(pos: &[f32; 3], area: &([f32; 3], [f32; 3])) -> bool {
+	(0..2).all(|i| pos[i] >= area.0[i] && pos[i] <= area.1[i])
+}
+
+#[derive(Clone)]
+struct GaussMarkov {
+	speed: f32,
+	direction: f32
+}
+
+/*
+ * Gauss-Markov model.
+ * Speed and direction of the next step depend on the previous values
+ * and a random part. Alpha (0..1) controls the randomness: 0 is
+ * a random walk like movement, 1 is a linear movement.
+ * Nodes are steered back when leaving the area of all nodes.
+ */
+#[derive(Clone)]
+pub struct GaussMarkovMovements {
+	alpha: f32,
+	mean_speed: f32,
+	area: Option<([f32; 3], [f32; 3])>,
+	nodes: HashMap<ID, GaussMarkov>
+}
+
+impl GaussMarkovMovements {
+	// a NaN alpha becomes 0 with max() and min(), clamp() would keep it
+	#[allow(clippy::manual_clamp)]
+	pub fn new(alpha: f32, mean_speed: Option<f32>) -> Self {
+		Self {
+			alpha: alpha.max(0.0).min(1.0),
+			// derived from the area on the first step if not set
+			mean_speed: mean_speed.unwrap_or(0.0),
+			area: None,
+			nodes: HashMap::new()
+		}
+	}
+}
+
+impl MovementModel for GaussMarkovMovements {
+	fn name(&self) -> String {
+		format!("gauss_markov {} {}", self.alpha, self.mean_speed)
+	}
+
+	fn clone_box(&self) -> Box<dyn MovementModel> {
+		Box::new(self.clone())
+	}
+
+	fn step(&mut self, locations: &mut Locations) {
+		let area = *self.area.get_or_insert_with(|| get_area(locations));
+		if self.mean_speed <= 0.0 {
+			let width = (area.1[0] - area.0[0]).max(area.1[1] - area.0[1]);
+			self.mean_speed = width / 100.0;
+		}
+
+		let alpha = self.alpha;
+		let mean_speed = self.mean_speed;
+		let center = [(area.0[0] + area.1[0]) / 2.0, (area.0[1] + area.1[1]) / 2.0];
+		let r = (1.0 - alpha * alpha).sqrt();
+
+		for (id, pos) in locations.data.iter_mut() {
+			let node = self.nodes.entry(*id).or_insert_with(|| GaussMarkov {
+				speed: mean_speed,
+				direction: random::<f32>() * 2.0 * std::f32::consts::PI
+			});
+
+			// steer towards the center when outside of the area
+			let mean_direction = if is_in_area(pos, &area) {
+				node.direction
+			} else {
+				(center[1] - pos[1]).atan2(center[0] - pos[0])
+			};
+
+			node.speed = (alpha * node.speed + (1.0 - alpha) * mean_speed + r * mean_speed * gauss()).max(0.0);
+			node.direction = alpha * node.direction + (1.0 - alpha) * mean_direction + r * gauss();
+
+			pos[0] += node.speed * node.direction.cos();
+			pos[1] += node.speed * node.direction.sin();
+		}
+	}
+
+	fn remove_node(&mut self, id: ID) {
+		self.nodes.remove(&id);
+	}
+}
+
+/*
+ * Random walk model.
+ * Every node moves a fixed distance into a random direction on each step
+ * and stays within the area of all nodes.
+ */
+#[derive(Clone)]
+pub struct RandomWalkMovements {
+	step_len: f32,
+	area: Option<([f32; 3], [f32; 3])>
+}
+
+impl RandomWalkMovements {
+	pub fn new(step_len: f32) -> Self {
+		Self { step_len, area: None }
+	}
+}
+
+impl MovementModel for RandomWalkMovements {
+	fn name(&self) -> String {
+		format!("random_walk {}", self.step_len)
+	}
+
+	fn clone_box(&self) -> Box<dyn MovementModel> {
+		Box::new(self.clone())
+	}
+
+	fn step(&mut self, locations: &mut Locations) {
+		let area = *self.area.get_or_insert_with(|| get_area(locations));
+
+		for pos in locations.data.values_mut() {
+			let direction = random::<f32>() * 2.0 * std::f32::consts::PI;
+			pos[0] += self.step_len * direction.cos();
+			pos[1] += self.step_len * direction.sin();
+			clamp_to_area(pos, &area);
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Group {
+	reference: [f32; 3],
+	dest: [f32; 3]
+}
+
+/*
+ * Reference Point Group Mobility (RPGM).
+ * Nodes are split into groups by id. Each group has a reference point that moves
+ * like in the random waypoint model. Nodes keep their initial offset to the
+ * reference point plus some random deviation.
+ */
+#[derive(Clone)]
+pub struct RpgmMovements {
+	group_count: u32,
+	speed: f32,
+	area: Option<([f32; 3], [f32; 3])>,
+	groups: Vec<Group>,
+	// node => (group, offset to reference point)
+	nodes: HashMap<ID, (usize, [f32; 3])>
+}
+
+impl RpgmMovements {
+	pub fn new(group_count: u32, speed: f32) -> Self {
+		Self {
+			group_count: u32::max(group_count, 1),
+			speed,
+			area: None,
+			groups: vec![],
+			nodes: HashMap::new()
+		}
+	}
+
+	fn init(&mut self, locations: &Locations) {
+		let area = get_area(locations);
+		let mut ids : Vec<ID> = locations.data.keys().cloned().collect();
+		ids.sort();
+
+		let group_count = self.group_count as usize;
+		let mut sums = vec![([0.0f32; 3], 0); group_count];
+		for (i, id) in ids.iter().enumerate() {
+			let group = i * group_count / ids.len();
+			let pos = locations.data[id];
+			for (sum, p) in sums[group].0.iter_mut().zip(pos.iter()) {
+				*sum += p;
+			}
+			sums[group].1 += 1;
+			self.nodes.insert(*id, (group, [0.0; 3]));
+		}
+
+		// reference point is the center of the group
+		self.groups = sums.iter().map(|(sum, n)| {
+			let n = u32::max(*n, 1) as f32;
+			Group { reference: [sum[0] / n, sum[1] / n, sum[2] / n], dest: random_pos(&area) }
+		}).collect();
+
+		for (id, (group, offset)) in self.nodes.iter_mut() {
+			let pos = locations.data[id];
+			let reference = self.groups[*group].reference;
+			*offset = [pos[0] - reference[0], pos[1] - reference[1], pos[2] - reference[2]];
+		}
+
+		self.area = Some(area);
+	}
+}
+
+impl MovementModel for RpgmMovements {
+	fn name(&self) -> String {
+		format!("rpgm {} {}", self.group_count, self.speed)
+	}
+
+	fn clone_box(&self) -> Box<dyn MovementModel> {
+		Box::new(self.clone())
+	}
+
+	fn step(&mut self, locations: &mut Locations) {
+		if self.area.is_none() {
+			self.init(locations);
+		}
+
+		let area = self.area.unwrap();
+		let speed = self.speed;
+
+		for group in &mut self.groups {
+			if move_towards(&mut group.reference, &group.dest, speed) {
+				group.dest = random_pos(&area);
+			}
+		}
+
+		for (id, pos) in locations.data.iter_mut() {
+			if let Some((group, offset)) = self.nodes.get(id) {
+				let reference = self.groups[*group].reference;
+				let deviation = random::<f32>() * 2.0 * std::f32::consts::PI;
+				let target = [
+					reference[0] + offset[0] + 0.5 * speed * deviation.cos(),
+					reference[1] + offset[1] + 0.5 * speed * deviation.sin(),
+					reference[2] + offset[2]
+				];
+				move_towards(pos, &target, 2.0 * speed);
+			}
+		}
+	}
+
+	fn remove_node(&mut self, id: ID) {
+		self.nodes.remove(&id);
+	}
+}
+
 #[derive(Clone)]
-struct Movement {
+enum TraceEvent {
+	// place node at position
+	Position(ID, [f32; 3]),
+	// move node to destination with speed (per step)
+	Dest(ID, [f32; 3], f32)
+}
 
+/*
+ * Replay of recorded movements. One simulation step is one second in
+ * ns-2 traces. CSV traces contain the node positions at given steps,
+ * nodes move linearly between those positions.
+ */
+#[derive(Clone)]
+pub struct TraceMovements {
+	path: String,
+	step: u32,
+	// sorted by step
+	events: Vec<(u32, TraceEvent)>,
+	next_event: usize,
+	moving: HashMap<ID, ([f32; 3], f32)>
 }
 
-impl Movement {
-	fn new() -> Self {
-		Self {}
+impl TraceMovements {
+	pub fn load(path: &str) -> Result<Self, MyError> {
+		let content = std::fs::read_to_string(path)?;
+		let mut events = if path.ends_with(".csv") {
+			Self::parse_csv(&content)?
+		} else {
+			Self::parse_ns2(&content)?
+		};
+
+		events.sort_by_key(|(step, _)| *step);
+
+		Ok(Self {
+			path: path.to_string(),
+			step: 0,
+			events,
+			next_event: 0,
+			moving: HashMap::new()
+		})
 	}
 
-	fn move_step(&self, pos: &mut [f32; 3]) {
-		pos[0] += 0.01;
+	// lines of: step, node_id, x, y, z
+	fn parse_csv(content: &str) -> Result<Vec<(u32, TraceEvent)>, MyError> {
+		let mut keyframes : HashMap<ID, Vec<(u32, [f32; 3])>> = HashMap::new();
+
+		for (i, line) in content.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let fields : Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+			let parsed = (
+				fields.first().and_then(|f| f.parse::<u32>().ok()),
+				fields.get(1).and_then(|f| f.parse::<ID>().ok()),
+				fields.get(2).and_then(|f| f.parse::<f32>().ok()),
+				fields.get(3).and_then(|f| f.parse::<f32>().ok()),
+				fields.get(4).map_or(Some(0.0), |f| f.parse::<f32>().ok())
+			);
+
+			match parsed {
+				(Some(step), Some(id), Some(x), Some(y), Some(z)) => {
+					keyframes.entry(id).or_default().push((step, [x, y, z]));
+				},
+				_ => {
+					// ignore header line
+					if i > 0 {
+						return Err(MyError::new(format!("Invalid trace line {}: {}", i + 1, line)));
+					}
+				}
+			}
+		}
+
+		let mut events = vec![];
+		for (id, mut frames) in keyframes {
+			frames.sort_by_key(|(step, _)| *step);
+			if let Some((step, pos)) = frames.first() {
+				events.push((*step, TraceEvent::Position(id, *pos)));
+			}
+			for w in frames.windows(2) {
+				let ((s1, p1), (s2, p2)) = (w[0], w[1]);
+				let distance = ((p2[0] - p1[0]).powi(2) + (p2[1] - p1[1]).powi(2) + (p2[2] - p1[2]).powi(2)).sqrt();
+				let speed = distance / u32::max(s2 - s1, 1) as f32;
+				events.push((s1, TraceEvent::Dest(id, p2, speed)));
+			}
+		}
+
+		Ok(events)
+	}
+
+	// ns-2 setdest format
+	fn parse_ns2(content: &str) -> Result<Vec<(u32, TraceEvent)>, MyError> {
+		fn node_id(token: &str) -> Option<ID> {
+			let start = token.find('(')?;
+			let end = token.find(')')?;
+			token[start + 1..end].parse::<ID>().ok()
+		}
+
+		let mut events = vec![];
+		let mut initial : HashMap<ID, [f32; 3]> = HashMap::new();
+
+		for (i, line) in content.lines().enumerate() {
+			let tokens : Vec<&str> = line.split_whitespace()
+				.map(|t| t.trim_matches('"')).collect();
+			let invalid = || MyError::new(format!("Invalid trace line {}: {}", i + 1, line));
+
+			match tokens.as_slice() {
+				// $node_(0) set X_ 150.0
+				[node, "set", coord, value] => {
+					let id = node_id(node).ok_or_else(invalid)?;
+					let value = value.parse::<f32>().map_err(|_| invalid())?;
+					let pos = initial.entry(id).or_insert([0.0; 3]);
+					match *coord {
+						"X_" => { pos[0] = value; },
+						"Y_" => { pos[1] = value; },
+						"Z_" => { pos[2] = value; },
+						_ => {}
+					}
+				},
+				// $ns_ at 1.0 "$node_(0) setdest 100.0 200.0 1.5"
+				[_, "at", time, node, "setdest", x, y, speed] => {
+					let id = node_id(node).ok_or_else(invalid)?;
+					let parse = |s: &str| s.parse::<f32>().map_err(|_| invalid());
+					let step = parse(time)? as u32;
+					events.push((step, TraceEvent::Dest(id, [parse(x)?, parse(y)?, 0.0], parse(speed)?)));
+				},
+				[] => {},
+				_ => {
+					if !line.trim_start().starts_with('#') {
+						return Err(invalid());
+					}
+				}
+			}
+		}
+
+		for (id, pos) in initial {
+			events.push((0, TraceEvent::Position(id, pos)));
+		}
+
+		Ok(events)
+	}
+}
+
+impl MovementModel for TraceMovements {
+	fn name(&self) -> String {
+		format!("trace {}", self.path)
+	}
+
+	fn clone_box(&self) -> Box<dyn MovementModel> {
+		Box::new(self.clone())
+	}
+
+	fn step(&mut self, locations: &mut Locations) {
+		while let Some((step, event)) = self.events.get(self.next_event) {
+			if *step > self.step {
+				break;
+			}
+			match event {
+				TraceEvent::Position(id, pos) => {
+					if let Some(location) = locations.data.get_mut(id) {
+						*location = *pos;
+					}
+					self.moving.remove(id);
+				},
+				TraceEvent::Dest(id, dest, speed) => {
+					if let Some(location) = locations.data.get(id) {
+						// ns-2 traces are 2D, keep height
+						let dest = [dest[0], dest[1], if dest[2] == 0.0 { location[2] } else { dest[2] }];
+						self.moving.insert(*id, (dest, *speed));
+					}
+				}
+			}
+			self.next_event += 1;
+		}
+
+		let mut arrived = vec![];
+		for (id, (dest, speed)) in &self.moving {
+			if let Some(location) = locations.data.get_mut(id) {
+				if move_towards(location, dest, *speed) {
+					arrived.push(*id);
+				}
+			}
+		}
+
+		for id in arrived {
+			self.moving.remove(&id);
+		}
+
+		self.step += 1;
+	}
+
+	fn remove_node(&mut self, id: ID) {
+		self.moving.remove(&id);
 	}
 }
 
 pub struct Movements {
-	data: HashMap<u32, Movement>
+	model: Option<Box<dyn MovementModel>>
+}
+
+impl Clone for Movements {
+	fn clone(&self) -> Self {
+		Self { model: self.model.as_ref().map(|model| model.clone_box()) }
+	}
+}
+
+impl Default for Movements {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Movements {
 	pub fn new() -> Self {
-		Self { data: HashMap::new() }
+		Self { model: None }
 	}
 
 	pub fn clear(&mut self) {
-		self.data.clear();
+		self.model = None;
 	}
 
 	pub fn remove_node(&mut self, id: ID) {
-		self.data.remove(&id);
+		if let Some(model) = &mut self.model {
+			model.remove_node(id);
+		}
 	}
 
-	pub fn step(&self, locations: &mut Locations) {
-		for (id, movement) in self.data.iter() {
-			if let Some(location) = locations.data.get_mut(id) {
-				movement.move_step(location);
+	pub fn get_model(&self) -> String {
+		match &self.model {
+			Some(model) => model.name(),
+			None => "none".to_string()
+		}
+	}
+
+	pub fn set_model(&mut self, name: &str, args: &[f32]) -> Result<(), MyError> {
+		fn arg(args: &[f32], i: usize) -> Result<f32, MyError> {
+			args.get(i).cloned().ok_or_else(|| MyError::new(format!("Missing movement model argument {}", i + 1)))
+		}
+
+		self.model = match name {
+			"none" => None,
+			"waypoint" => Some(Box::new(WaypointMovements::new(arg(args, 0)?, arg(args, 1)?, arg(args, 2)? as u32))),
+			"gauss_markov" => Some(Box::new(GaussMarkovMovements::new(arg(args, 0)?, args.get(1).cloned()))),
+			"random_walk" => Some(Box::new(RandomWalkMovements::new(arg(args, 0)?))),
+			"rpgm" => Some(Box::new(RpgmMovements::new(arg(args, 0)? as u32, arg(args, 1)?))),
+			_ => {
+				return Err(MyError::new(format!("Unknown movement model: {}", name)));
 			}
+		};
+		Ok(())
+	}
+
+	pub fn load_trace(&mut self, path: &str) -> Result<(), MyError> {
+		self.model = Some(Box::new(TraceMovements::load(path)?));
+		Ok(())
+	}
+
+	pub fn step(&mut self, locations: &mut Locations) {
+		if let Some(model) = &mut self.model {
+			model.step(locations);
 		}
 	}
 }
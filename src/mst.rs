@@ -0,0 +1,332 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, Link, ID};
+use crate::utils::MyError;
+
+
+/*
+ * Minimum spanning tree (or forest) of the undirected graph.
+ * The tree is kept between commands and updated incrementally
+ * when only a few links have changed since the last update.
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MstWeight {
+	Cost,
+	Hops,
+	Latency,
+	Etx
+}
+
+impl MstWeight {
+	pub fn parse(name: &str) -> Result<Self, MyError> {
+		match name {
+			"cost" => Ok(MstWeight::Cost),
+			"hops" => Ok(MstWeight::Hops),
+			"latency" => Ok(MstWeight::Latency),
+			"etx" => Ok(MstWeight::Etx),
+			_ => Err(MyError::new(format!("Unknown weight: {} (use cost, hops, latency or etx)", name)))
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			MstWeight::Cost => "cost",
+			MstWeight::Hops => "hops",
+			MstWeight::Latency => "latency",
+			MstWeight::Etx => "etx"
+		}
+	}
+
+	fn link_weight(&self, link: &Link) -> u32 {
+		match self {
+			MstWeight::Cost => link.cost() as u32,
+			MstWeight::Hops => 1,
+			// in microseconds
+			MstWeight::Latency => (link.latency() * 1000.0) as u32,
+			MstWeight::Etx => {
+				let ratio = link.delivery_ratio();
+				if ratio > 0.0 {
+					f32::min(1000.0 / ratio, u32::MAX as f32) as u32
+				} else {
+					u32::MAX
+				}
+			}
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Mst {
+	weight: MstWeight,
+	node_count: usize,
+	// undirected links (a < b) of the graph the tree is based on, with weight
+	edges: HashMap<(ID, ID), u32>,
+	// tree neighbors of each node
+	tree: Vec<Vec<(ID, u32)>>,
+	// if the last update did not need a full calculation
+	incremental: bool
+}
+
+impl Default for Mst {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Mst {
+	pub fn new() -> Self {
+		Self {
+			weight: MstWeight::Cost,
+			node_count: 0,
+			edges: HashMap::new(),
+			tree: vec![],
+			incremental: false
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.node_count = 0;
+		self.edges.clear();
+		self.tree.clear();
+		self.incremental = false;
+	}
+
+	pub fn set_weight(&mut self, weight: MstWeight) {
+		if self.weight != weight {
+			self.weight = weight;
+			self.clear();
+		}
+	}
+
+	pub fn weight(&self) -> MstWeight {
+		self.weight
+	}
+
+	pub fn was_incremental(&self) -> bool {
+		self.incremental
+	}
+
+	// both directions of a link use the lower weight
+	fn graph_edges(&self, graph: &Graph) -> HashMap<(ID, ID), u32> {
+		let mut edges = HashMap::new();
+		for link in graph.links() {
+			if link.from == link.to {
+				continue;
+			}
+			let key = (ID::min(link.from, link.to), ID::max(link.from, link.to));
+			let weight = self.weight.link_weight(link);
+			let entry = edges.entry(key).or_insert(weight);
+			*entry = u32::min(*entry, weight);
+		}
+		edges
+	}
+
+	// bring the tree up to date with the graph
+	pub fn update(&mut self, graph: &Graph) {
+		let edges = self.graph_edges(graph);
+
+		if self.node_count != graph.node_count() || self.edges.is_empty() {
+			self.rebuild(graph.node_count(), edges);
+			return;
+		}
+
+		let removed : Vec<(ID, ID)> = self.edges.iter()
+			.filter(|(key, weight)| edges.get(key) != Some(weight))
+			.map(|(key, _)| *key).collect();
+		let added : Vec<((ID, ID), u32)> = edges.iter()
+			.filter(|(key, weight)| self.edges.get(key) != Some(weight))
+			.map(|(key, weight)| (*key, *weight)).collect();
+
+		// many changes are faster to handle from scratch
+		if (removed.len() + added.len()) > usize::max(16, self.node_count / 10) {
+			self.rebuild(graph.node_count(), edges);
+			return;
+		}
+
+		for (a, b) in removed {
+			self.remove_edge(a, b);
+		}
+
+		for ((a, b), weight) in added {
+			self.insert_edge(a, b, weight);
+		}
+
+		self.incremental = true;
+	}
+
+	// Kruskal minimum spanning tree algorithm
+	fn rebuild(&mut self, node_count: usize, edges: HashMap<(ID, ID), u32>) {
+		let mut sorted : Vec<(u32, ID, ID)> = edges.iter().map(|((a, b), w)| (*w, *a, *b)).collect();
+		sorted.sort_unstable();
+
+		let mut roots : Vec<ID> = (0..node_count as ID).collect();
+
+		// find root of node
+		fn root(roots: &mut [ID], i: ID) -> usize {
+			let mut i = i as usize;
+			while roots[i] != i as ID {
+				// Path halving optimization
+				let tmp = roots[roots[i] as usize];
+				roots[i] = tmp;
+				i = tmp as usize;
+			}
+			i
+		}
+
+		self.tree = vec![vec![]; node_count];
+		for (weight, a, b) in sorted {
+			let x = root(&mut roots, a);
+			let y = root(&mut roots, b);
+			if x != y {
+				self.tree[a as usize].push((b, weight));
+				self.tree[b as usize].push((a, weight));
+				roots[x] = roots[y];
+			}
+		}
+
+		self.node_count = node_count;
+		self.edges = edges;
+		self.incremental = false;
+	}
+
+	fn unlink(&mut self, a: ID, b: ID) {
+		self.tree[a as usize].retain(|(id, _)| *id != b);
+		self.tree[b as usize].retain(|(id, _)| *id != a);
+	}
+
+	// nodes reachable from a node in the tree
+	fn tree_component(&self, id: ID) -> Vec<bool> {
+		let mut seen = vec![false; self.node_count];
+		let mut stack = vec![id];
+		seen[id as usize] = true;
+		while let Some(u) = stack.pop() {
+			for (v, _) in &self.tree[u as usize] {
+				if !seen[*v as usize] {
+					seen[*v as usize] = true;
+					stack.push(*v);
+				}
+			}
+		}
+		seen
+	}
+
+	// tree path between two nodes as list of (from, to, weight)
+	fn tree_path(&self, a: ID, b: ID) -> Option<Vec<(ID, ID, u32)>> {
+		let mut prev : Vec<Option<(ID, u32)>> = vec![None; self.node_count];
+		let mut stack = vec![a];
+		prev[a as usize] = Some((a, 0));
+		while let Some(u) = stack.pop() {
+			if u == b {
+				break;
+			}
+			for (v, weight) in &self.tree[u as usize] {
+				if prev[*v as usize].is_none() {
+					prev[*v as usize] = Some((u, *weight));
+					stack.push(*v);
+				}
+			}
+		}
+
+		prev[b as usize]?;
+
+		let mut path = vec![];
+		let mut v = b;
+		while v != a {
+			let (u, weight) = prev[v as usize].unwrap();
+			path.push((u, v, weight));
+			v = u;
+		}
+		Some(path)
+	}
+
+	fn remove_edge(&mut self, a: ID, b: ID) {
+		self.edges.remove(&(a, b));
+
+		if !self.tree[a as usize].iter().any(|(id, _)| *id == b) {
+			return;
+		}
+
+		// reconnect both parts with the lightest link between them
+		self.unlink(a, b);
+		let side = self.tree_component(a);
+		let replacement = self.edges.iter()
+			.filter(|((x, y), _)| side[*x as usize] != side[*y as usize])
+			.min_by_key(|((x, y), weight)| (**weight, *x, *y))
+			.map(|(key, weight)| (*key, *weight));
+
+		if let Some(((x, y), weight)) = replacement {
+			self.tree[x as usize].push((y, weight));
+			self.tree[y as usize].push((x, weight));
+		}
+	}
+
+	fn insert_edge(&mut self, a: ID, b: ID, weight: u32) {
+		self.edges.insert((a, b), weight);
+
+		match self.tree_path(a, b) {
+			None => {
+				// connects two trees
+				self.tree[a as usize].push((b, weight));
+				self.tree[b as usize].push((a, weight));
+			},
+			Some(path) => {
+				// replace the heaviest link on the cycle
+				if let Some((x, y, max)) = path.into_iter().max_by_key(|(_, _, weight)| *weight) {
+					if max > weight {
+						self.unlink(x, y);
+						self.tree[a as usize].push((b, weight));
+						self.tree[b as usize].push((a, weight));
+					}
+				}
+			}
+		}
+	}
+
+	pub fn contains(&self, a: ID, b: ID) -> bool {
+		self.tree.get(a as usize).is_some_and(|ns| ns.iter().any(|(id, _)| *id == b))
+	}
+
+	pub fn link_count(&self) -> usize {
+		self.tree.iter().map(|ns| ns.len()).sum::<usize>() / 2
+	}
+
+	pub fn weight_sum(&self) -> u64 {
+		self.tree.iter().flat_map(|ns| ns.iter()).map(|(_, weight)| *weight as u64).sum::<u64>() / 2
+	}
+
+	// a forest has one tree for every connected component
+	pub fn tree_count(&self) -> usize {
+		self.node_count - self.link_count()
+	}
+
+	// links of the graph that belong to the tree, with all attributes
+	// and in both directions
+	pub fn tree_links(&self, graph: &Graph) -> Graph {
+		let mut tree = graph.filter_links(|link| self.contains(link.from, link.to));
+
+		// add the missing direction of unidirectional links
+		for link in graph.links() {
+			if self.contains(link.from, link.to) && !tree.has_link(link.to, link.from) {
+				let mut reverse = link.clone();
+				reverse.from = link.to;
+				reverse.to = link.from;
+				tree.insert_link(reverse);
+			}
+		}
+
+		tree
+	}
+
+	// remove all links that do not belong to the tree
+	pub fn crop(&self, graph: &mut Graph) {
+		*graph = self.tree_links(graph);
+	}
+
+	pub fn get_info(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		writeln!(out, "tree links: {}, trees: {}, weight ({}): {}, update: {}",
+			self.link_count(), self.tree_count(), self.weight.name(), self.weight_sum(),
+			if self.incremental { "incremental" } else { "full" })
+	}
+}
@@ -0,0 +1,122 @@
+
+use crate::graph::ID;
+use crate::utils::{map_tokens, MyError};
+
+
+/*
+ * Stable node ids. Node ids are indices and shift when nodes
+ * are removed, the stable id of a node stays the same until
+ * compact is called. Stable ids of removed nodes are not reused.
+ * In commands, #<id> refers to a node by its stable id.
+ */
+#[derive(Clone)]
+pub struct NodeIds {
+	ids: Vec<ID>,
+	next: ID
+}
+
+impl Default for NodeIds {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl NodeIds {
+	pub fn new() -> Self {
+		Self { ids: vec![], next: 0 }
+	}
+
+	// from saved stable ids, new nodes continue after next
+	pub fn from_ids(ids: Vec<ID>, next: ID) -> Self {
+		let next = ids.iter().map(|id| id + 1).max().unwrap_or(0).max(next);
+		Self { ids, next }
+	}
+
+	pub fn ids(&self) -> &[ID] {
+		&self.ids
+	}
+
+	// stable id the next new node gets
+	pub fn next(&self) -> ID {
+		self.next
+	}
+
+	pub fn clear(&mut self) {
+		self.ids.clear();
+		self.next = 0;
+	}
+
+	// assign stable ids to new nodes
+	pub fn update(&mut self, node_count: usize) {
+		if node_count == 0 {
+			self.clear();
+		} else if node_count < self.ids.len() {
+			self.ids.truncate(node_count);
+		} else {
+			while self.ids.len() < node_count {
+				self.ids.push(self.next);
+				self.next += 1;
+			}
+		}
+	}
+
+	pub fn remove_node(&mut self, id: ID) {
+		if (id as usize) < self.ids.len() {
+			self.ids.remove(id as usize);
+		}
+	}
+
+	// stable ids of the given nodes only, node ids[i] becomes node i
+	pub fn subgraph(&self, ids: &[ID]) -> Self {
+		Self {
+			ids: ids.iter().filter_map(|id| self.ids.get(*id as usize).cloned()).collect(),
+			next: self.next
+		}
+	}
+
+	// make the stable ids equal to the node ids again
+	pub fn compact(&mut self) {
+		let len = self.ids.len();
+		self.clear();
+		self.update(len);
+	}
+
+	// stable id of a node
+	pub fn get(&self, id: ID) -> Option<ID> {
+		self.ids.get(id as usize).cloned()
+	}
+
+	// node id of a stable id
+	pub fn find(&self, uid: ID) -> Option<ID> {
+		self.ids.iter().position(|id| *id == uid).map(|pos| pos as ID)
+	}
+
+	// if all stable ids are equal to the node ids
+	pub fn is_compact(&self) -> bool {
+		self.ids.iter().enumerate().all(|(i, id)| i as ID == *id)
+	}
+
+	// replace #<id> by the node ids in the comma separated node lists
+	// at the given token positions, the rest of the input is kept
+	pub fn expand(&self, input: &str, positions: &[usize]) -> Result<String, MyError> {
+		if positions.is_empty() || !input.contains('#') {
+			return Ok(input.to_string());
+		}
+
+		map_tokens(input, positions, |token| {
+			let mut items = vec![];
+			for item in token.split(',') {
+				if let Some(uid) = item.strip_prefix('#') {
+					let uid = uid.parse::<ID>()
+						.map_err(|_| MyError::new(format!("Invalid stable node id: {}", item)))?;
+					let id = self.find(uid)
+						.ok_or_else(|| MyError::new(format!("Unknown stable node id: {} (node was removed)", item)))?;
+					items.push(id.to_string());
+				} else {
+					items.push(item.to_string());
+				}
+			}
+			Ok(items.join(","))
+		})
+	}
+}
@@ -0,0 +1,247 @@
+
+use std::fs::File;
+use std::io::Read;
+
+use serde_json::Value;
+
+use crate::utils::*;
+
+
+/*
+ * Obstacles that block the line of sight between nodes.
+ * Polygons (e.g. buildings) are read from GeoJSON files,
+ * terrain heights from ESRI ASCII grids (.asc) with
+ * coordinates in degrees and heights in meters.
+ */
+
+#[derive(Clone)]
+struct Polygon {
+	points: Vec<[f32; 2]>,
+	// bounding box
+	min: [f32; 2],
+	max: [f32; 2]
+}
+
+impl Polygon {
+	fn new(mut points: Vec<[f32; 2]>) -> Self {
+		// close ring
+		if points.first() != points.last() {
+			points.push(points[0]);
+		}
+
+		let mut min = [f32::INFINITY, f32::INFINITY];
+		let mut max = [f32::NEG_INFINITY, f32::NEG_INFINITY];
+		for p in &points {
+			min = [min[0].min(p[0]), min[1].min(p[1])];
+			max = [max[0].max(p[0]), max[1].max(p[1])];
+		}
+		Self { points, min, max }
+	}
+
+	// line from a to b crosses any polygon edge
+	fn intersects(&self, a: &[f32; 2], b: &[f32; 2]) -> bool {
+		if a[0].max(b[0]) < self.min[0] || a[0].min(b[0]) > self.max[0]
+			|| a[1].max(b[1]) < self.min[1] || a[1].min(b[1]) > self.max[1] {
+			return false;
+		}
+
+		self.points.windows(2).any(|edge| segments_intersect(a, b, &edge[0], &edge[1]))
+	}
+}
+
+fn orientation(a: &[f32; 2], b: &[f32; 2], c: &[f32; 2]) -> f32 {
+	(b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn segments_intersect(a: &[f32; 2], b: &[f32; 2], c: &[f32; 2], d: &[f32; 2]) -> bool {
+	let o1 = orientation(a, b, c);
+	let o2 = orientation(a, b, d);
+	let o3 = orientation(c, d, a);
+	let o4 = orientation(c, d, b);
+	(o1 * o2 < 0.0) && (o3 * o4 < 0.0)
+}
+
+#[derive(Clone)]
+struct Heightmap {
+	ncols: usize,
+	nrows: usize,
+	// lower left corner and cell size (in km)
+	x: f32,
+	y: f32,
+	cellsize: f32,
+	// heights in m, first row is the northern most
+	heights: Vec<f32>
+}
+
+impl Heightmap {
+	fn parse(data: &str) -> Result<Self, MyError> {
+		let mut header = std::collections::HashMap::<String, f32>::new();
+		let mut heights = vec![];
+
+		for line in data.lines() {
+			let mut tokens = line.split_whitespace();
+			let first = match tokens.next() {
+				Some(first) => first,
+				None => continue
+			};
+
+			if first.chars().next().is_some_and(|c| c.is_alphabetic()) {
+				let value = tokens.next().and_then(|v| v.parse::<f32>().ok())
+					.ok_or_else(|| MyError::new(format!("Invalid header line: {}", line)))?;
+				header.insert(first.to_lowercase(), value);
+			} else {
+				for token in std::iter::once(first).chain(tokens) {
+					heights.push(token.parse::<f32>().map_err(|_| MyError::new(format!("Invalid height: {}", token)))?);
+				}
+			}
+		}
+
+		let get = |key: &str| header.get(key).cloned().ok_or_else(|| MyError::new(format!("Missing header: {}", key)));
+		let ncols = get("ncols")? as usize;
+		let nrows = get("nrows")? as usize;
+		let nodata = header.get("nodata_value").cloned();
+
+		if heights.len() != ncols * nrows {
+			return Err(MyError::new(format!("Expected {} heights, got {}", ncols * nrows, heights.len())));
+		}
+
+		// treat missing data as sea level
+		for h in heights.iter_mut() {
+			if Some(*h) == nodata {
+				*h = 0.0;
+			}
+		}
+
+		Ok(Self {
+			ncols,
+			nrows,
+			x: get("xllcorner")? * DEG2KM,
+			y: get("yllcorner")? * DEG2KM,
+			cellsize: get("cellsize")? * DEG2KM,
+			heights
+		})
+	}
+
+	// terrain height (in m) at position (in km)
+	fn height(&self, pos: &[f32; 2]) -> f32 {
+		let col = ((pos[0] - self.x) / self.cellsize).floor();
+		let row = ((pos[1] - self.y) / self.cellsize).floor();
+		if col < 0.0 || row < 0.0 || col >= self.ncols as f32 || row >= self.nrows as f32 {
+			return 0.0;
+		}
+		let row = self.nrows - 1 - row as usize;
+		self.heights[row * self.ncols + col as usize]
+	}
+
+	// terrain between a and b is higher than the direct line (node heights are above terrain)
+	fn blocks(&self, a: &[f32; 3], b: &[f32; 3]) -> bool {
+		let ha = self.height(&[a[0], a[1]]) + a[2] * 1000.0;
+		let hb = self.height(&[b[0], b[1]]) + b[2] * 1000.0;
+		let distance = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+		let samples = (2.0 * distance / self.cellsize).ceil() as usize;
+
+		for i in 1..samples {
+			let t = i as f32 / samples as f32;
+			let p = [a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])];
+			if self.height(&p) > ha + t * (hb - ha) {
+				return true;
+			}
+		}
+		false
+	}
+}
+
+#[derive(Clone)]
+pub struct Obstacles {
+	polygons: Vec<Polygon>,
+	heightmap: Option<Heightmap>
+}
+
+impl Default for Obstacles {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Obstacles {
+	pub fn new() -> Self {
+		Self { polygons: vec![], heightmap: None }
+	}
+
+	pub fn clear(&mut self) {
+		self.polygons.clear();
+		self.heightmap = None;
+	}
+
+	pub fn polygon_count(&self) -> usize {
+		self.polygons.len()
+	}
+
+	pub fn has_heightmap(&self) -> bool {
+		self.heightmap.is_some()
+	}
+
+	// add polygons from GeoJSON or set heightmap from ESRI ASCII grid
+	pub fn load(&mut self, path: &str) -> Result<(), MyError> {
+		let mut file = File::open(path)?;
+		let mut data = String::new();
+		file.read_to_string(&mut data)?;
+
+		if path.ends_with(".asc") {
+			self.heightmap = Some(Heightmap::parse(&data)?);
+			return Ok(());
+		}
+
+		let v = serde_json::from_str::<Value>(&data)?;
+		let features = get_array(&v, "features").cloned().unwrap_or_else(|| vec![v.clone()]);
+
+		// outer ring and holes of a polygon
+		let mut add_polygon = |rings: &Value| {
+			for ring in rings.as_array().unwrap_or(&vec![]) {
+				let points : Vec<[f32; 2]> = ring.as_array().unwrap_or(&vec![]).iter().filter_map(|p| {
+					Some([p.get(0)?.as_f64()? as f32 * DEG2KM, p.get(1)?.as_f64()? as f32 * DEG2KM])
+				}).collect();
+				if points.len() > 2 {
+					self.polygons.push(Polygon::new(points));
+				}
+			}
+		};
+
+		for feature in &features {
+			let geometry = feature.get("geometry").unwrap_or(feature);
+			let coords = match geometry.get("coordinates") {
+				Some(coords) => coords,
+				None => continue
+			};
+			match get_str(geometry, "type") {
+				Some("Polygon") => {
+					add_polygon(coords);
+				},
+				Some("MultiPolygon") => {
+					for polygon in coords.as_array().unwrap_or(&vec![]) {
+						add_polygon(polygon);
+					}
+				},
+				_ => {}
+			}
+		}
+
+		Ok(())
+	}
+
+	// line of sight between two positions (in km) is blocked
+	pub fn is_blocked(&self, a: &[f32; 3], b: &[f32; 3]) -> bool {
+		let a2 = [a[0], a[1]];
+		let b2 = [b[0], b[1]];
+
+		if self.polygons.iter().any(|polygon| polygon.intersects(&a2, &b2)) {
+			return true;
+		}
+
+		if let Some(heightmap) = &self.heightmap {
+			return heightmap.blocks(a, b);
+		}
+
+		false
+	}
+}
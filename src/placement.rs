@@ -0,0 +1,378 @@
+
+use std::collections::VecDeque;
+
+use rand::seq::SliceRandom;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::progress::is_cancelled;
+use crate::utils::{MyError, random, random_range, with_rng};
+
+
+/*
+ * Placement of additional relay nodes.
+ * Candidate positions are a grid over the area of the graph and the
+ * midpoints between nodes of different components. The greedy method
+ * adds one node after another at the candidate with the best score,
+ * annealing then moves the placed nodes around randomly and keeps
+ * worse layouts with a probability that decreases over time.
+ * The score counts unconnected pairs of the existing nodes first
+ * and the mean path length (in hops) second.
+ */
+
+// grid cells per axis
+const GRID_SIZE: usize = 24;
+// sources for the path lengths in large graphs
+const MAX_SOURCES: usize = 64;
+// moves per placed node for annealing
+const ANNEAL_MOVES: usize = 200;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlacementMethod {
+	Greedy,
+	Anneal
+}
+
+impl PlacementMethod {
+	pub fn parse(name: &str) -> Result<Self, MyError> {
+		match name {
+			"greedy" => Ok(PlacementMethod::Greedy),
+			"anneal" => Ok(PlacementMethod::Anneal),
+			_ => Err(MyError::new(format!("Unknown placement method: {} (use greedy or anneal)", name)))
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			PlacementMethod::Greedy => "greedy",
+			PlacementMethod::Anneal => "anneal"
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct Score {
+	// components with existing nodes
+	pub components: usize,
+	// fraction of connected pairs of existing nodes
+	pub connected: f32,
+	// mean path length of connected pairs (sampled in large graphs)
+	pub mean_hops: f32,
+	// longest shortest path (sampled in large graphs)
+	pub diameter: u32,
+	cost: f32
+}
+
+impl Score {
+	pub fn print(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		writeln!(out, "components: {}, connected pairs: {:.1}%, mean hops: {:.2}, diameter: {}",
+			self.components, 100.0 * self.connected, self.mean_hops, self.diameter)
+	}
+}
+
+pub struct Placement {
+	// positions of the new nodes
+	pub positions: Vec<[f32; 3]>,
+	pub before: Score,
+	pub after: Score
+}
+
+struct Layout<'a> {
+	// existing nodes (undirected)
+	neighbors: Vec<Vec<ID>>,
+	positions: Vec<Option<[f32; 3]>>,
+	sources: Vec<ID>,
+	in_range: &'a dyn Fn(&[f32; 3], &[f32; 3]) -> bool
+}
+
+impl<'a> Layout<'a> {
+	fn new(graph: &Graph, locations: &Locations, in_range: &'a dyn Fn(&[f32; 3], &[f32; 3]) -> bool) -> Self {
+		let node_count = graph.node_count();
+		let mut neighbors = vec![vec![]; node_count];
+		for link in graph.links() {
+			if !neighbors[link.from as usize].contains(&link.to) {
+				neighbors[link.from as usize].push(link.to);
+			}
+			if !neighbors[link.to as usize].contains(&link.from) {
+				neighbors[link.to as usize].push(link.from);
+			}
+		}
+
+		let positions = (0..node_count as ID)
+			.map(|id| locations.get_position(id).cloned())
+			.collect();
+
+		let mut sources : Vec<ID> = (0..node_count as ID).collect();
+		if node_count > MAX_SOURCES {
+			with_rng(|rng| sources.shuffle(rng));
+			sources.truncate(MAX_SOURCES);
+		}
+
+		Self { neighbors, positions, sources, in_range }
+	}
+
+	// existing and new nodes in range of a position
+	fn nodes_in_range(&self, pos: &[f32; 3], placed: &[[f32; 3]], skip: Option<usize>) -> Vec<ID> {
+		let node_count = self.neighbors.len();
+		let mut ids = vec![];
+		for (id, p) in self.positions.iter().enumerate() {
+			if let Some(p) = p {
+				if (self.in_range)(pos, p) {
+					ids.push(id as ID);
+				}
+			}
+		}
+		for (i, p) in placed.iter().enumerate() {
+			if Some(i) != skip && (self.in_range)(pos, p) {
+				ids.push((node_count + i) as ID);
+			}
+		}
+		ids
+	}
+
+	fn score(&self, placed: &[[f32; 3]]) -> Score {
+		let node_count = self.neighbors.len();
+		let mut neighbors = self.neighbors.clone();
+		neighbors.resize(node_count + placed.len(), vec![]);
+		for (i, pos) in placed.iter().enumerate() {
+			let id = (node_count + i) as ID;
+			for other in self.nodes_in_range(pos, &placed[..i], None) {
+				neighbors[id as usize].push(other);
+				neighbors[other as usize].push(id);
+			}
+		}
+
+		// components, only existing nodes are counted
+		let mut component = vec![usize::MAX; neighbors.len()];
+		let mut sizes = vec![];
+		for start in 0..node_count {
+			if component[start] != usize::MAX {
+				continue;
+			}
+			let c = sizes.len();
+			let mut size = 0;
+			let mut stack = vec![start];
+			component[start] = c;
+			while let Some(v) = stack.pop() {
+				if v < node_count {
+					size += 1;
+				}
+				for n in &neighbors[v] {
+					if component[*n as usize] == usize::MAX {
+						component[*n as usize] = c;
+						stack.push(*n as usize);
+					}
+				}
+			}
+			sizes.push(size);
+		}
+
+		let pairs = (node_count * node_count.max(1).saturating_sub(1)) as f32;
+		let connected_pairs : usize = sizes.iter().map(|s| s * (s - 1)).sum();
+		let connected = if pairs > 0.0 { connected_pairs as f32 / pairs } else { 1.0 };
+
+		// path lengths from the sources to the other existing nodes
+		let mut hops = vec![u32::MAX; neighbors.len()];
+		let mut queue = VecDeque::new();
+		let mut hops_sum = 0u64;
+		let mut path_count = 0u64;
+		let mut diameter = 0;
+		for source in &self.sources {
+			for h in hops.iter_mut() {
+				*h = u32::MAX;
+			}
+			hops[*source as usize] = 0;
+			queue.push_back(*source as usize);
+			while let Some(v) = queue.pop_front() {
+				if v < node_count && v != *source as usize {
+					hops_sum += hops[v] as u64;
+					path_count += 1;
+					diameter = diameter.max(hops[v]);
+				}
+				for n in &neighbors[v] {
+					if hops[*n as usize] == u32::MAX {
+						hops[*n as usize] = hops[v] + 1;
+						queue.push_back(*n as usize);
+					}
+				}
+			}
+		}
+		let mean_hops = if path_count > 0 { hops_sum as f32 / path_count as f32 } else { 0.0 };
+
+		Score {
+			components: sizes.len(),
+			connected,
+			mean_hops,
+			diameter,
+			// losing all pairs costs as much as a path over all nodes
+			cost: (1.0 - connected) * node_count as f32 + mean_hops
+		}
+	}
+
+	fn candidates(&self, range: f32) -> Vec<[f32; 3]> {
+		let known : Vec<&[f32; 3]> = self.positions.iter().filter_map(|p| p.as_ref()).collect();
+		let mut min = [f32::INFINITY; 2];
+		let mut max = [f32::NEG_INFINITY; 2];
+		for p in &known {
+			for i in 0..2 {
+				min[i] = min[i].min(p[i] - range / 2.0);
+				max[i] = max[i].max(p[i] + range / 2.0);
+			}
+		}
+
+		let mut candidates = vec![];
+		let step = ((max[0] - min[0]).max(max[1] - min[1]) / GRID_SIZE as f32).max(range / 4.0);
+		let mut x = min[0];
+		while x <= max[0] {
+			let mut y = min[1];
+			while y <= max[1] {
+				candidates.push([x, y, 0.0]);
+				y += step;
+			}
+			x += step;
+		}
+
+		// between each node and the closest node of another component
+		let component = self.component_ids();
+		for (a, p) in self.positions.iter().enumerate() {
+			let p = match p {
+				Some(p) => p,
+				None => continue
+			};
+			let mut closest : Option<(f32, &[f32; 3])> = None;
+			for (b, q) in self.positions.iter().enumerate() {
+				if let Some(q) = q {
+					if component[a] != component[b] {
+						let d = (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2);
+						if closest.is_none_or(|(c, _)| d < c) {
+							closest = Some((d, q));
+						}
+					}
+				}
+			}
+			if let Some((_, q)) = closest {
+				candidates.push([(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0, (p[2] + q[2]) / 2.0]);
+			}
+		}
+
+		candidates
+	}
+
+	fn component_ids(&self) -> Vec<usize> {
+		let mut component = vec![usize::MAX; self.neighbors.len()];
+		let mut c = 0;
+		for start in 0..self.neighbors.len() {
+			if component[start] != usize::MAX {
+				continue;
+			}
+			let mut stack = vec![start];
+			component[start] = c;
+			while let Some(v) = stack.pop() {
+				for n in &self.neighbors[v] {
+					if component[*n as usize] == usize::MAX {
+						component[*n as usize] = c;
+						stack.push(*n as usize);
+					}
+				}
+			}
+			c += 1;
+		}
+		component
+	}
+
+	fn greedy(&self, count: usize, range: f32) -> Vec<[f32; 3]> {
+		let candidates = self.candidates(range);
+		let mut placed : Vec<[f32; 3]> = vec![];
+
+		for _ in 0..count {
+			let mut best : Option<(f32, [f32; 3])> = None;
+			for candidate in &candidates {
+				if is_cancelled() {
+					return placed;
+				}
+				// a relay without neighbors is useless
+				if self.nodes_in_range(candidate, &placed, None).is_empty() || placed.contains(candidate) {
+					continue;
+				}
+				placed.push(*candidate);
+				let cost = self.score(&placed).cost;
+				placed.pop();
+				if best.is_none_or(|(c, _)| cost < c) {
+					best = Some((cost, *candidate));
+				}
+			}
+			match best {
+				Some((_, pos)) => placed.push(pos),
+				None => break
+			}
+		}
+
+		placed
+	}
+
+	fn anneal(&self, mut placed: Vec<[f32; 3]>, range: f32) -> Vec<[f32; 3]> {
+		if placed.is_empty() {
+			return placed;
+		}
+
+		let moves = ANNEAL_MOVES * placed.len();
+		let mut cost = self.score(&placed).cost;
+		let mut best = (cost, placed.clone());
+		// in hops, cools down to a hundredth
+		let start_temperature = 1.0f32;
+		let cooling = (0.01f32).powf(1.0 / moves as f32);
+		let mut temperature = start_temperature;
+
+		for _ in 0..moves {
+			if is_cancelled() {
+				break;
+			}
+			let i = random_range(0, placed.len());
+			let old = placed[i];
+			// move further while hot
+			let distance = range * (0.1 + temperature / start_temperature) * random::<f32>();
+			let angle = 2.0 * std::f32::consts::PI * random::<f32>();
+			placed[i] = [old[0] + distance * angle.cos(), old[1] + distance * angle.sin(), old[2]];
+
+			let new_cost = if self.nodes_in_range(&placed[i], &placed, Some(i)).is_empty() {
+				f32::INFINITY
+			} else {
+				self.score(&placed).cost
+			};
+
+			let delta = new_cost - cost;
+			if delta < 0.0 || random::<f32>() < (-delta / temperature).exp() {
+				cost = new_cost;
+				if cost < best.0 {
+					best = (cost, placed.clone());
+				}
+			} else {
+				placed[i] = old;
+			}
+			temperature *= cooling;
+		}
+
+		best.1
+	}
+}
+
+// positions for up to count new nodes, in_range decides if two positions are linked
+pub fn optimize_placement(graph: &Graph, locations: &Locations, count: usize, range: f32, method: PlacementMethod,
+		in_range: &dyn Fn(&[f32; 3], &[f32; 3]) -> bool) -> Result<Placement, MyError> {
+	if !(0..graph.node_count() as ID).any(|id| locations.get_position(id).is_some()) {
+		return Err(MyError::new("No node positions".to_string()));
+	}
+	if range.is_nan() || range <= 0.0 {
+		return Err(MyError::new(format!("Invalid range: {}", range)));
+	}
+
+	let layout = Layout::new(graph, locations, in_range);
+	let before = layout.score(&[]);
+	let mut positions = layout.greedy(count, range);
+	if method == PlacementMethod::Anneal {
+		positions = layout.anneal(positions, range);
+	}
+	let after = layout.score(&positions);
+
+	Ok(Placement { positions, before, after })
+}
@@ -1,8 +1,26 @@
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Instant, Duration};
 use crate::utils::fmt_duration;
 
 
+// set by the cancel command to stop long running commands
+static CANCEL : AtomicBool = AtomicBool::new(false);
+
+pub fn request_cancel() {
+	CANCEL.store(true, Ordering::Relaxed);
+}
+
+pub fn clear_cancel() {
+	CANCEL.store(false, Ordering::Relaxed);
+}
+
+// checked inside of long loops
+pub fn is_cancelled() -> bool {
+	CANCEL.load(Ordering::Relaxed)
+}
+
+
 fn duration_to_f64(d: Duration) -> f64 {
 	d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9_f64
 }
@@ -16,6 +34,12 @@ pub struct Progress {
 	min_interval: Duration,
 }
 
+impl Default for Progress {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Progress {
 	pub fn new() -> Self {
 		Self {
@@ -52,14 +76,18 @@ impl Progress {
 		}
 
 		if count >= self.next_check {
-			print_meter(&self.label, 15, count, total, self.start_instant.elapsed());
+			print_meter(self.label, 15, count, total, self.start_instant.elapsed());
 
 			let passed_time = duration_to_f64(self.start_instant.elapsed());
 			let iters_per_sec = count as f64 / passed_time;
-			let next_check = count + (iters_per_sec * duration_to_f64(self.min_interval)) as usize;
+			let next_check = if iters_per_sec.is_finite() {
+				count + (iters_per_sec * duration_to_f64(self.min_interval)) as usize
+			} else {
+				count + 1
+			};
 
 			// clear progress line on finish
-			if next_check >= total && self.clear_on_finish {
+			if (next_check >= total || is_cancelled()) && self.clear_on_finish {
 				clear_line();
 			}
 
@@ -86,13 +114,48 @@ fn print_meter(label: &str, length: u8, count: usize, total: usize, passed: Dura
 
 	let passed_time = duration_to_f64(passed);
 	let iters_per_sec = count as f64 / passed_time;
-	let left_time = (total - count) as f64 / iters_per_sec;
-	let left = Duration::from_millis((left_time * 1000.0) as u64);
 
-	print!("[elapsed: {}, left: {}, {:.2} iter/s]",
-		   fmt_duration(passed),
-		   fmt_duration(left),
-		   iters_per_sec);
+	// no estimate before the first iteration is done
+	if count > 0 && iters_per_sec.is_finite() {
+		let left_time = total.saturating_sub(count) as f64 / iters_per_sec;
+		let left = Duration::from_millis((left_time * 1000.0) as u64);
+
+		print!("[elapsed: {}, left: {}, {:.2} iter/s]",
+			   fmt_duration(passed),
+			   fmt_duration(left),
+			   iters_per_sec);
+	} else {
+		print!("[elapsed: {}]", fmt_duration(passed));
+	}
 
 	print!("                ");
 }
+
+// shows the progress of reading a file, fails if cancelled
+pub struct ProgressReader<R: std::io::Read> {
+	inner: R,
+	total: usize,
+	count: usize,
+	progress: Option<Progress>
+}
+
+impl<R: std::io::Read> ProgressReader<R> {
+	pub fn new(inner: R, total: usize, show_progress: bool) -> Self {
+		let progress = if show_progress && total > 0 { Some(Progress::new()) } else { None };
+		Self { inner, total, count: 0, progress }
+	}
+}
+
+impl<R: std::io::Read> std::io::Read for ProgressReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if is_cancelled() {
+			return Err(std::io::Error::other("Import cancelled"));
+		}
+		let n = self.inner.read(buf)?;
+		self.count += n;
+		if let Some(progress) = self.progress.as_mut() {
+			progress.update(self.total, self.count.min(self.total));
+		}
+		Ok(n)
+	}
+}
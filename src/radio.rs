@@ -0,0 +1,133 @@
+
+use crate::utils::MyError;
+
+
+/*
+ * Radio propagation models for connect_in_range.
+ * The received power decides if a link exists
+ * and the signal margin is used as link quality.
+ * Distances are in km, powers in dBm.
+ */
+
+// signal margin (in dB) for a link of full quality
+const FULL_QUALITY_MARGIN : f32 = 20.0;
+// minimum link quality at the sensitivity limit
+const MIN_QUALITY : f32 = 0.1;
+
+#[derive(Clone, Copy)]
+enum Model {
+	// connect all nodes in range
+	Range,
+	FreeSpace,
+	LogDistance { exponent: f32 },
+	TwoRay { height: f32 }
+}
+
+#[derive(Clone)]
+pub struct Radio {
+	model: Model,
+	tx_power: f32,
+	sensitivity: f32,
+	frequency: f32
+}
+
+// free space path loss in dB (distance in km, frequency in MHz)
+fn free_space_loss(distance: f32, frequency: f32) -> f32 {
+	20.0 * distance.log10() + 20.0 * frequency.log10() + 32.44
+}
+
+impl Default for Radio {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Radio {
+	pub fn new() -> Self {
+		Self {
+			model: Model::Range,
+			tx_power: 20.0,
+			sensitivity: -90.0,
+			frequency: 2400.0
+		}
+	}
+
+	pub fn get_model(&self) -> String {
+		let params = format!("tx_power {}dBm, sensitivity {}dBm, frequency {}MHz",
+			self.tx_power, self.sensitivity, self.frequency);
+		match self.model {
+			Model::Range => "range".to_string(),
+			Model::FreeSpace => format!("free_space ({})", params),
+			Model::LogDistance { exponent } => format!("log_distance (exponent {}, {})", exponent, params),
+			Model::TwoRay { height } => format!("two_ray (height {}m, {})", height, params)
+		}
+	}
+
+	pub fn set_model(&mut self, name: &str, args: &[f32]) -> Result<(), MyError> {
+		fn arg(args: &[f32], i: usize) -> Result<f32, MyError> {
+			args.get(i).cloned().ok_or_else(|| MyError::new(format!("Missing radio model argument {}", i + 1)))
+		}
+
+		// optional frequency after the required arguments
+		let (model, n) = match name {
+			"range" => (Model::Range, 0),
+			"free_space" => (Model::FreeSpace, 2),
+			"log_distance" => (Model::LogDistance { exponent: arg(args, 0)? }, 3),
+			"two_ray" => (Model::TwoRay { height: arg(args, 0)? }, 3),
+			_ => {
+				return Err(MyError::new(format!("Unknown radio model: {}", name)));
+			}
+		};
+
+		if n > 0 {
+			self.tx_power = arg(args, n - 2)?;
+			self.sensitivity = arg(args, n - 1)?;
+			self.frequency = args.get(n).cloned().unwrap_or(2400.0);
+		}
+		self.model = model;
+
+		Ok(())
+	}
+
+	// path loss in dB
+	fn path_loss(&self, distance: f32) -> f32 {
+		// avoid infinite gain for close nodes (1m reference distance)
+		let distance = f32::max(distance, 0.001);
+
+		match self.model {
+			Model::Range => 0.0,
+			Model::FreeSpace => free_space_loss(distance, self.frequency),
+			Model::LogDistance { exponent } => {
+				free_space_loss(0.001, self.frequency) + 10.0 * exponent * (distance / 0.001).log10()
+			},
+			Model::TwoRay { height } => {
+				// wave length in m
+				let lambda = 299.792_45 / self.frequency;
+				let crossover = 4.0 * std::f32::consts::PI * height * height / lambda / 1000.0;
+				if distance < crossover {
+					free_space_loss(distance, self.frequency)
+				} else {
+					40.0 * (distance * 1000.0).log10() - 40.0 * height.log10()
+				}
+			}
+		}
+	}
+
+	// link quality (0..1) for nodes in a distance (in km), None if there is no link
+	pub fn link_quality(&self, distance: f32, range: f32) -> Option<f32> {
+		if distance > range {
+			return None;
+		}
+
+		if let Model::Range = self.model {
+			return Some(1.0);
+		}
+
+		let margin = self.tx_power - self.path_loss(distance) - self.sensitivity;
+		if margin < 0.0 {
+			None
+		} else {
+			Some(MIN_QUALITY + (1.0 - MIN_QUALITY) * f32::min(margin / FULL_QUALITY_MARGIN, 1.0))
+		}
+	}
+}
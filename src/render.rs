@@ -0,0 +1,200 @@
+
+use std::fmt::Write;
+use std::fs::File;
+
+use crate::energy::Energy;
+use crate::eval_paths::EvalPaths;
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::sim::RoutingAlgorithm;
+use crate::style::{format_color, ExportStyle, NodeMetric, Styling};
+use crate::utils::MyError;
+
+
+/*
+ * Static rendering of the graph to SVG or PNG for figures.
+ * Positions are projected onto the x/y plane (north is up),
+ * nodes without a position are left out. Colors and sizes
+ * follow the export style, nodes are colored by component
+ * if no node color is set.
+ */
+
+const MARGIN: f32 = 10.0;
+const LINK_COLOR: [u8; 3] = [160, 160, 160];
+const MARK_COLOR: [u8; 3] = [255, 0, 255];
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+
+// the graph in pixel coordinates
+pub struct Scene {
+	pub width: u32,
+	pub height: u32,
+	// (id, x, y, radius, color)
+	nodes: Vec<(ID, f32, f32, f32, [u8; 3])>,
+	// (x1, y1, x2, y2, width, color)
+	links: Vec<(f32, f32, f32, f32, f32, [u8; 3])>
+}
+
+impl Scene {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(graph: &Graph, locations: &Locations, meta: &Meta, algo: &dyn RoutingAlgorithm, energy: &Energy, test: &EvalPaths,
+			mark_links: Option<&Graph>, width: u32, style: &ExportStyle) -> Result<Self, MyError> {
+		let mut style = style.clone();
+		if style.node_color.is_none() {
+			style.node_color = Some(NodeMetric::Component);
+		}
+		if style.node_color == Some(NodeMetric::Load) && !test.has_load() {
+			return Err(MyError::new("No load, run test first".to_string()));
+		}
+		let styling = Styling::new(&style, graph, Some(meta), Some(algo), Some(energy), Some(test));
+
+		let node_count = graph.node_count();
+		let positions : Vec<Option<[f32; 3]>> = (0..node_count as ID)
+			.map(|id| locations.get_position(id).cloned())
+			.collect();
+
+		let mut min = [f32::INFINITY; 2];
+		let mut max = [f32::NEG_INFINITY; 2];
+		for pos in positions.iter().flatten() {
+			for i in 0..2 {
+				min[i] = min[i].min(pos[i]);
+				max[i] = max[i].max(pos[i]);
+			}
+		}
+		if min[0] > max[0] {
+			return Err(MyError::new("No node positions".to_string()));
+		}
+
+		let inner = (width as f32 - 2.0 * MARGIN).max(1.0);
+		let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+		let scale = inner / extent;
+		let height = ((max[1] - min[1]) * scale + 2.0 * MARGIN).ceil() as u32;
+		// north is up
+		let project = |pos: &[f32; 3]| (MARGIN + (pos[0] - min[0]) * scale, MARGIN + (max[1] - pos[1]) * scale);
+
+		let positioned = positions.iter().filter(|pos| pos.is_some()).count();
+		let radius = (width as f32 / (positioned as f32).sqrt() / 8.0).clamp(1.5, 6.0);
+
+		let mut nodes = vec![];
+		for (id, pos) in positions.iter().enumerate() {
+			let id = id as ID;
+			if let Some(pos) = pos {
+				let (x, y) = project(pos);
+				let color = styling.node_color(id).unwrap_or(LINK_COLOR);
+				nodes.push((id, x, y, radius * styling.node_size(id).unwrap_or(1.0), color));
+			}
+		}
+
+		let mut links = vec![];
+		let mut marked_links = vec![];
+		for link in graph.links() {
+			// bidirectional links are drawn once
+			if link.from > link.to && graph.has_link(link.to, link.from) {
+				continue;
+			}
+			if let (Some(a), Some(b)) = (&positions[link.from as usize], &positions[link.to as usize]) {
+				let marked = mark_links.is_some_and(|mark| mark.has_link(link.from, link.to) || mark.has_link(link.to, link.from));
+				let (x1, y1) = project(a);
+				let (x2, y2) = project(b);
+				if marked {
+					marked_links.push((x1, y1, x2, y2, 3.0, MARK_COLOR));
+				} else {
+					let color = styling.link_color(link.from, link.to).unwrap_or(LINK_COLOR);
+					links.push((x1, y1, x2, y2, styling.link_width(link.from, link.to).unwrap_or(1.0), color));
+				}
+			}
+		}
+		// marked links on top
+		links.extend(marked_links);
+
+		Ok(Self { width, height, nodes, links })
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn link_count(&self) -> usize {
+		self.links.len()
+	}
+
+	pub fn to_svg(&self) -> String {
+		let mut ret = String::new();
+
+		writeln!(&mut ret, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+		writeln!(&mut ret, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+			self.width, self.height, self.width, self.height).unwrap();
+		writeln!(&mut ret, "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>", format_color(&BACKGROUND)).unwrap();
+
+		writeln!(&mut ret, "<g stroke-linecap=\"round\">").unwrap();
+		for (x1, y1, x2, y2, width, color) in &self.links {
+			writeln!(&mut ret, "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{:.1}\"/>",
+				x1, y1, x2, y2, format_color(color), width).unwrap();
+		}
+		writeln!(&mut ret, "</g>").unwrap();
+
+		writeln!(&mut ret, "<g stroke=\"#000000\" stroke-width=\"0.5\">").unwrap();
+		for (id, x, y, radius, color) in &self.nodes {
+			writeln!(&mut ret, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\"><title>{}</title></circle>",
+				x, y, radius, format_color(color), id).unwrap();
+		}
+		writeln!(&mut ret, "</g>").unwrap();
+		writeln!(&mut ret, "</svg>").unwrap();
+
+		ret
+	}
+
+	// RGB pixels, rows from the top
+	fn rasterize(&self) -> Vec<u8> {
+		let (w, h) = (self.width as i64, self.height as i64);
+		let mut pixels = BACKGROUND.iter().cloned().cycle().take((w * h * 3) as usize).collect::<Vec<u8>>();
+
+		let fill_circle = |pixels: &mut Vec<u8>, cx: f32, cy: f32, r: f32, color: &[u8; 3]| {
+			for y in (cy - r).floor() as i64..=(cy + r).ceil() as i64 {
+				for x in (cx - r).floor() as i64..=(cx + r).ceil() as i64 {
+					if x >= 0 && y >= 0 && x < w && y < h {
+						let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+						if dx * dx + dy * dy <= r * r {
+							let i = ((y * w + x) * 3) as usize;
+							pixels[i..i + 3].copy_from_slice(color);
+						}
+					}
+				}
+			}
+		};
+
+		for (x1, y1, x2, y2, width, color) in &self.links {
+			let r = (width / 2.0).max(0.75);
+			// dots along the line
+			let steps = ((x2 - x1).abs().max((y2 - y1).abs()).ceil() as usize).max(1);
+			for s in 0..=steps {
+				let f = s as f32 / steps as f32;
+				fill_circle(&mut pixels, x1 + f * (x2 - x1), y1 + f * (y2 - y1), r, color);
+			}
+		}
+
+		for (_, x, y, radius, color) in &self.nodes {
+			fill_circle(&mut pixels, *x, *y, radius + 0.5, &[0, 0, 0]);
+			fill_circle(&mut pixels, *x, *y, *radius, color);
+		}
+
+		pixels
+	}
+
+	pub fn write_png(&self, path: &str) -> Result<(), MyError> {
+		let file = File::create(path)?;
+		let mut encoder = png::Encoder::new(file, self.width, self.height);
+		encoder.set_color(png::ColorType::Rgb);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder.write_header().map_err(|e| MyError::new(e.to_string()))?;
+		writer.write_image_data(&self.rasterize()).map_err(|e| MyError::new(e.to_string()))?;
+		Ok(())
+	}
+
+	pub fn write_svg(&self, path: &str) -> Result<(), MyError> {
+		use std::io::Write;
+		let mut file = File::create(path)?;
+		file.write_all(self.to_svg().as_bytes())?;
+		Ok(())
+	}
+}
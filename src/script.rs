@@ -0,0 +1,306 @@
+
+use std::collections::HashMap;
+
+use crate::utils::MyError;
+
+
+/*
+ * Script interpreter for the run command.
+ * Statements are separated by newlines or ';'.
+ *
+ * let <name> = <expression>
+ * repeat <expression> { <statements> }
+ * <command>  ($name and ${name} are replaced by the variable value)
+ *
+ * Expressions support numbers, variables, + - * / % and parentheses.
+ */
+
+enum Statement {
+	Let(usize, String, String),
+	Repeat(usize, String, Vec<Statement>),
+	Command(usize, String)
+}
+
+enum Piece {
+	Line(usize, String),
+	Open(usize, String),
+	Close(usize)
+}
+
+// split script into statements, block starts and block ends
+fn split_pieces(text: &str) -> Vec<Piece> {
+	let mut pieces = vec![];
+	let mut current = String::new();
+	let mut line = 1;
+	let mut comment = false;
+	// inside ${name}
+	let mut variable = false;
+
+	fn flush(pieces: &mut Vec<Piece>, current: &mut String, line: usize) {
+		let s = current.trim();
+		if !s.is_empty() {
+			pieces.push(Piece::Line(line, s.to_string()));
+		}
+		current.clear();
+	}
+
+	for c in text.chars() {
+		if comment {
+			if c == '\n' {
+				comment = false;
+				line += 1;
+			}
+			continue;
+		}
+
+		match c {
+			'\n' => {
+				flush(&mut pieces, &mut current, line);
+				line += 1;
+			},
+			';' => {
+				flush(&mut pieces, &mut current, line);
+			},
+			'{' if current.ends_with('$') => {
+				variable = true;
+				current.push(c);
+			},
+			'}' if variable => {
+				variable = false;
+				current.push(c);
+			},
+			'{' => {
+				pieces.push(Piece::Open(line, current.trim().to_string()));
+				current.clear();
+			},
+			'}' => {
+				flush(&mut pieces, &mut current, line);
+				pieces.push(Piece::Close(line));
+			},
+			'#' if current.trim().is_empty() => {
+				comment = true;
+			},
+			_ => {
+				current.push(c);
+			}
+		}
+	}
+	flush(&mut pieces, &mut current, line);
+
+	pieces
+}
+
+fn parse_block(iter: &mut std::vec::IntoIter<Piece>, nested: bool) -> Result<Vec<Statement>, MyError> {
+	let mut statements = vec![];
+
+	while let Some(piece) = iter.next() {
+		match piece {
+			Piece::Line(line, s) => {
+				if let Some(rest) = s.strip_prefix("let ") {
+					if let Some((name, expr)) = rest.split_once('=') {
+						statements.push(Statement::Let(line, name.trim().to_string(), expr.trim().to_string()));
+					} else {
+						return Err(MyError::new(format!("{}: expected let <name> = <expression>", line)));
+					}
+				} else {
+					statements.push(Statement::Command(line, s));
+				}
+			},
+			Piece::Open(line, s) => {
+				if let Some(count) = s.strip_prefix("repeat ") {
+					let body = parse_block(iter, true)?;
+					statements.push(Statement::Repeat(line, count.trim().to_string(), body));
+				} else {
+					return Err(MyError::new(format!("{}: expected repeat <count> {{", line)));
+				}
+			},
+			Piece::Close(line) => {
+				if nested {
+					return Ok(statements);
+				}
+				return Err(MyError::new(format!("{}: unexpected }}", line)));
+			}
+		}
+	}
+
+	if nested {
+		Err(MyError::new("Missing }".to_string()))
+	} else {
+		Ok(statements)
+	}
+}
+
+fn fmt_value(value: f64) -> String {
+	if value.fract() == 0.0 && value.abs() < 1e15 {
+		format!("{}", value as i64)
+	} else {
+		format!("{}", value)
+	}
+}
+
+struct ExprParser<'a> {
+	chars: Vec<char>,
+	pos: usize,
+	vars: &'a HashMap<String, f64>
+}
+
+impl<'a> ExprParser<'a> {
+	fn peek(&mut self) -> Option<char> {
+		while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+			self.pos += 1;
+		}
+		self.chars.get(self.pos).cloned()
+	}
+
+	fn expr(&mut self) -> Result<f64, String> {
+		let mut value = self.term()?;
+		while let Some(c) = self.peek() {
+			match c {
+				'+' => { self.pos += 1; value += self.term()?; },
+				'-' => { self.pos += 1; value -= self.term()?; },
+				_ => break
+			}
+		}
+		Ok(value)
+	}
+
+	fn term(&mut self) -> Result<f64, String> {
+		let mut value = self.factor()?;
+		while let Some(c) = self.peek() {
+			match c {
+				'*' => { self.pos += 1; value *= self.factor()?; },
+				'/' => { self.pos += 1; value /= self.factor()?; },
+				'%' => { self.pos += 1; value %= self.factor()?; },
+				_ => break
+			}
+		}
+		Ok(value)
+	}
+
+	fn factor(&mut self) -> Result<f64, String> {
+		match self.peek() {
+			Some('-') => {
+				self.pos += 1;
+				Ok(-self.factor()?)
+			},
+			Some('(') => {
+				self.pos += 1;
+				let value = self.expr()?;
+				if self.peek() != Some(')') {
+					return Err("missing )".to_string());
+				}
+				self.pos += 1;
+				Ok(value)
+			},
+			Some(c) if c.is_ascii_digit() || c == '.' => {
+				let start = self.pos;
+				while self.pos < self.chars.len() && (self.chars[self.pos].is_ascii_digit() || self.chars[self.pos] == '.') {
+					self.pos += 1;
+				}
+				let s : String = self.chars[start..self.pos].iter().collect();
+				s.parse::<f64>().map_err(|_| format!("invalid number: {}", s))
+			},
+			Some(c) if c.is_alphabetic() || c == '_' => {
+				let start = self.pos;
+				while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_') {
+					self.pos += 1;
+				}
+				let name : String = self.chars[start..self.pos].iter().collect();
+				self.vars.get(&name).cloned().ok_or_else(|| format!("unknown variable: {}", name))
+			},
+			Some(c) => Err(format!("unexpected character: {}", c)),
+			None => Err("unexpected end of expression".to_string())
+		}
+	}
+}
+
+pub struct Script {
+	vars: HashMap<String, f64>
+}
+
+impl Default for Script {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Script {
+	pub fn new() -> Self {
+		Self { vars: HashMap::new() }
+	}
+
+	pub fn eval(&self, expr: &str) -> Result<f64, String> {
+		let mut parser = ExprParser { chars: expr.chars().collect(), pos: 0, vars: &self.vars };
+		let value = parser.expr()?;
+		if let Some(c) = parser.peek() {
+			return Err(format!("unexpected character: {}", c));
+		}
+		Ok(value)
+	}
+
+	// replace $name and ${name} by variable values
+	pub fn substitute(&self, line: &str) -> Result<String, String> {
+		let mut ret = String::new();
+		let mut chars = line.chars().peekable();
+
+		while let Some(c) = chars.next() {
+			if c != '$' {
+				ret.push(c);
+				continue;
+			}
+
+			let braces = chars.peek() == Some(&'{');
+			if braces {
+				chars.next();
+			}
+
+			let mut name = String::new();
+			while let Some(&c) = chars.peek() {
+				if c.is_alphanumeric() || c == '_' {
+					name.push(c);
+					chars.next();
+				} else {
+					break;
+				}
+			}
+
+			if braces && chars.next() != Some('}') {
+				return Err("missing }".to_string());
+			}
+
+			match self.vars.get(&name) {
+				Some(value) => ret.push_str(&fmt_value(*value)),
+				None => return Err(format!("unknown variable: {}", name))
+			}
+		}
+
+		Ok(ret)
+	}
+
+	pub fn run(&mut self, text: &str, handler: &mut dyn FnMut(&str) -> Result<(), MyError>) -> Result<(), MyError> {
+		let statements = parse_block(&mut split_pieces(text).into_iter(), false)?;
+		self.execute(&statements, handler)
+	}
+
+	fn execute(&mut self, statements: &[Statement], handler: &mut dyn FnMut(&str) -> Result<(), MyError>) -> Result<(), MyError> {
+		for statement in statements {
+			match statement {
+				Statement::Let(line, name, expr) => {
+					let value = self.eval(expr).map_err(|e| MyError::new(format!("{}: {}", line, e)))?;
+					self.vars.insert(name.clone(), value);
+				},
+				Statement::Repeat(line, count, body) => {
+					let count = self.eval(count).map_err(|e| MyError::new(format!("{}: {}", line, e)))?;
+					for _ in 0..(count.max(0.0) as u64) {
+						self.execute(body, handler)?;
+					}
+				},
+				Statement::Command(line, cmd) => {
+					let cmd = self.substitute(cmd).map_err(|e| MyError::new(format!("{}: {}", line, e)))?;
+					handler(&cmd).map_err(|e| MyError::new(format!("{}: {}", line, e)))?;
+				}
+			}
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,202 @@
+
+use std::collections::HashMap;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::utils::{map_tokens, MyError};
+
+
+/*
+ * Named node selections. A selection is an expression that is evaluated
+ * whenever it is used, e.g. "degree>3 & x<10 | @other".
+ * Terms are "<attribute><op><value>" with the operators <, <=, >, >=, = and !=,
+ * "meta.<key>=<value>" (or !=) for imported metadata where key and value may
+ * contain the wildcards * and ?, "@<name>" for another selection or "all".
+ * A term can be negated with "!".
+ * "&" binds stronger than "|".
+ */
+#[derive(Clone)]
+pub struct Selections {
+	data: HashMap<String, String>
+}
+
+// maximum depth of selections that reference other selections
+const MAX_DEPTH : usize = 8;
+
+const ATTRIBUTES : [&str; 5] = ["id", "degree", "x", "y", "z"];
+
+impl Default for Selections {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Selections {
+	pub fn new() -> Self {
+		Self { data: HashMap::new() }
+	}
+
+	pub fn is_valid_name(name: &str) -> bool {
+		!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+	}
+
+	pub fn define(&mut self, name: &str, expr: &str) -> Result<(), MyError> {
+		if !Self::is_valid_name(name) {
+			return Err(MyError::new(format!("Invalid selection name: {}", name)));
+		}
+		self.data.insert(name.to_string(), expr.to_string());
+		Ok(())
+	}
+
+	pub fn remove(&mut self, name: &str) -> bool {
+		self.data.remove(name).is_some()
+	}
+
+	// all selections sorted by name
+	pub fn list(&self) -> Vec<(&String, &String)> {
+		let mut list : Vec<(&String, &String)> = self.data.iter().collect();
+		list.sort();
+		list
+	}
+
+	// node ids of a selection
+	pub fn resolve(&self, name: &str, graph: &Graph, locations: &Locations, meta: &Meta) -> Result<Vec<ID>, MyError> {
+		self.resolve_depth(name, graph, locations, meta, 0)
+	}
+
+	fn resolve_depth(&self, name: &str, graph: &Graph, locations: &Locations, meta: &Meta, depth: usize) -> Result<Vec<ID>, MyError> {
+		if depth > MAX_DEPTH {
+			return Err(MyError::new(format!("Selections nested too deep: @{}", name)));
+		}
+		match self.data.get(name) {
+			Some(expr) => self.evaluate_depth(expr, graph, locations, meta, depth + 1),
+			None => Err(MyError::new(format!("Unknown selection: @{}", name)))
+		}
+	}
+
+	// node ids matching an expression
+	pub fn evaluate(&self, expr: &str, graph: &Graph, locations: &Locations, meta: &Meta) -> Result<Vec<ID>, MyError> {
+		self.evaluate_depth(expr, graph, locations, meta, 0)
+	}
+
+	fn evaluate_depth(&self, expr: &str, graph: &Graph, locations: &Locations, meta: &Meta, depth: usize) -> Result<Vec<ID>, MyError> {
+		let node_count = graph.node_count();
+		let mut selected = vec![false; node_count];
+
+		for any in expr.split('|') {
+			let mut matches = vec![true; node_count];
+			for term in any.split('&') {
+				let term = term.trim();
+				let (negate, term) = match term.strip_prefix('!') {
+					Some(term) => (true, term.trim()),
+					None => (false, term)
+				};
+
+				let term_matches = if term == "all" {
+					vec![true; node_count]
+				} else if let Some(name) = term.strip_prefix('@') {
+					let mut m = vec![false; node_count];
+					for id in self.resolve_depth(name, graph, locations, meta, depth)? {
+						if let Some(entry) = m.get_mut(id as usize) {
+							*entry = true;
+						}
+					}
+					m
+				} else if let Some(term) = term.strip_prefix("meta.") {
+					let (key, equal, value) = parse_meta_term(term)?;
+					(0..node_count as ID).map(|id| meta.matches(id, key, value) == equal).collect()
+				} else {
+					let (attr, op, value) = parse_term(term)?;
+					(0..node_count as ID).map(|id| {
+						node_attribute(graph, locations, id, attr).is_some_and(|v| compare(v, op, value))
+					}).collect()
+				};
+
+				for (m, t) in matches.iter_mut().zip(term_matches) {
+					*m = *m && (t != negate);
+				}
+			}
+
+			for (s, m) in selected.iter_mut().zip(matches) {
+				*s = *s || m;
+			}
+		}
+
+		Ok((0..node_count as ID).filter(|id| selected[*id as usize]).collect())
+	}
+
+	// replace @<name> by the node ids of the selection in the comma separated
+	// node lists at the given token positions, the rest of the input is kept
+	pub fn expand(&self, input: &str, positions: &[usize], graph: &Graph, locations: &Locations, meta: &Meta) -> Result<String, MyError> {
+		if positions.is_empty() || !input.contains('@') {
+			return Ok(input.to_string());
+		}
+
+		map_tokens(input, positions, |token| {
+			let mut items = vec![];
+			for item in token.split(',') {
+				if let Some(name) = item.strip_prefix('@') {
+					let ids = self.resolve(name, graph, locations, meta)?;
+					if ids.is_empty() {
+						return Err(MyError::new(format!("Selection is empty: {}", item)));
+					}
+					items.extend(ids.iter().map(|id| id.to_string()));
+				} else {
+					items.push(item.to_string());
+				}
+			}
+			Ok(items.join(","))
+		})
+	}
+}
+
+fn parse_term(term: &str) -> Result<(&str, &str, f32), MyError> {
+	for op in &["<=", ">=", "!=", "<", ">", "="] {
+		if let Some(pos) = term.find(op) {
+			let attr = term[..pos].trim();
+			if !ATTRIBUTES.contains(&attr) {
+				return Err(MyError::new(format!("Unknown attribute: {} (use {})", attr, ATTRIBUTES.join(", "))));
+			}
+			let value = term[pos + op.len()..].trim();
+			let value = value.parse::<f32>()
+				.map_err(|_| MyError::new(format!("Invalid value in selection: {}", term)))?;
+			return Ok((attr, op, value));
+		}
+	}
+	Err(MyError::new(format!("Invalid selection term: {}", term)))
+}
+
+// "<key>=<value>" or "<key>!=<value>"
+fn parse_meta_term(term: &str) -> Result<(&str, bool, &str), MyError> {
+	if let Some(pos) = term.find("!=") {
+		Ok((term[..pos].trim(), false, term[pos + 2..].trim()))
+	} else if let Some(pos) = term.find('=') {
+		Ok((term[..pos].trim(), true, term[pos + 1..].trim()))
+	} else {
+		Err(MyError::new(format!("Invalid metadata term: meta.{} (use meta.<key>=<value>)", term)))
+	}
+}
+
+// numeric node attribute (positions in km)
+fn node_attribute(graph: &Graph, locations: &Locations, id: ID, attr: &str) -> Option<f32> {
+	match attr {
+		"id" => Some(id as f32),
+		"degree" => Some(graph.get_node_degree(id) as f32),
+		"x" => locations.get_position(id).map(|pos| pos[0]),
+		"y" => locations.get_position(id).map(|pos| pos[1]),
+		"z" => locations.get_position(id).map(|pos| pos[2]),
+		_ => None
+	}
+}
+
+fn compare(a: f32, op: &str, b: f32) -> bool {
+	match op {
+		"<=" => a <= b,
+		">=" => a >= b,
+		"!=" => a != b,
+		"<" => a < b,
+		">" => a > b,
+		_ => a == b
+	}
+}
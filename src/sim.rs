@@ -1,17 +1,33 @@
 
-use std::time::Duration;
-use std::collections::HashMap;
-use std::f32;
+use std::cell::Cell;
 use serde_json::Value;
 
 use crate::graph::{Graph, Link, ID};
+use crate::algorithms::create_algorithm;
 use crate::algorithms::random_routing::RandomRouting;
 use crate::eval_paths::EvalPaths;
+use crate::events::EventSim;
 use crate::debug_path::DebugPath;
-use crate::utils::{get_str, get_u64, print_unknown_key};
+use crate::utils::{get_str, get_u64, print_unknown_key, random, MyError};
 use crate::movements::Movements;
 use crate::locations::Locations;
 use crate::meta::Meta;
+use crate::node_ids::NodeIds;
+use crate::timeline::Timeline;
+use crate::ws::WsClients;
+use crate::radio::Radio;
+use crate::obstacles::Obstacles;
+use crate::churn::Churn;
+use crate::link_model::LinkModel;
+use crate::channels::Interference;
+use crate::energy::Energy;
+use crate::mst::Mst;
+use crate::metrics::Metrics;
+use crate::selection::Selections;
+use crate::history::{History, Snapshot};
+use crate::style::ExportStyle;
+use crate::workspace::Workspaces;
+use crate::progress::{is_cancelled, Progress};
 
 
 // default distance, too small confuses d3.js
@@ -21,15 +37,87 @@ pub struct GlobalState {
 	pub graph: Graph,
 	pub locations: Locations,
 	pub movements: Movements,
+	pub radio: Radio,
+	pub obstacles: Obstacles,
+	pub churn: Churn,
+	// time varying link loss
+	pub link_model: LinkModel,
+	// interference of links on the same channel
+	pub interference: Interference,
+	pub energy: Energy,
+	// nodes with internet uplink
+	pub gateways: Vec<ID>,
+	pub overhead: Overhead,
+	pub mst: Mst,
+	pub selections: Selections,
+	// undo/redo of destructive graph commands
+	pub history: History,
+	// named graphs that are not the current one
+	pub workspaces: Workspaces,
+	// threads used by test
+	pub threads: usize,
 	pub meta: Meta,
-	pub algorithm: Box<RoutingAlgorithm>,
+	// stable ids that survive node removal
+	pub node_ids: NodeIds,
+	pub algorithm: Box<dyn RoutingAlgorithm>,
+	// name used to select the algorithm
+	pub algorithm_name: String,
 	pub test: EvalPaths,
+	pub events: EventSim,
 	pub debug_path: DebugPath,
 	pub sim_steps: u32,
 	pub abort_simulation: bool,
 	pub show_progress: bool,
+	// command results as JSON objects instead of text
+	pub json_output: bool,
 	pub cmd_address: String,
-	pub export_path: String
+	pub export_path: String,
+	// directory for per step exports
+	pub record_dir: Option<String>,
+	// per step measurements
+	pub metrics: Metrics,
+	// graph snapshots played back during the simulation
+	pub timeline: Timeline,
+	// file to append test results to
+	pub test_log: Option<String>,
+	// WebSocket clients for live graph updates
+	pub ws_clients: WsClients,
+	// links marked by the last command, kept until the graph changes
+	pub marked_links: Option<Graph>,
+	// node/link colors and sizes of the exports bound to metrics
+	pub export_style: ExportStyle
+}
+
+/*
+ * Copy of the state a command run on the side (e.g. sweep) may change.
+ * The algorithm is kept with its settings, its state is put back if it
+ * supports save_state and reset otherwise.
+ */
+#[derive(Clone)]
+pub struct SavedState {
+	graph: Graph,
+	locations: Locations,
+	movements: Movements,
+	radio: Radio,
+	obstacles: Obstacles,
+	churn: Churn,
+	link_model: LinkModel,
+	interference: Interference,
+	energy: Energy,
+	gateways: Vec<ID>,
+	overhead: Overhead,
+	mst: Mst,
+	selections: Selections,
+	meta: Meta,
+	node_ids: NodeIds,
+	algorithm_name: String,
+	algorithm_state: Option<Value>,
+	test: EvalPaths,
+	events: EventSim,
+	sim_steps: u32,
+	timeline: Timeline,
+	marked_links: Option<Graph>,
+	export_style: ExportStyle
 }
 
 impl GlobalState {
@@ -38,15 +126,39 @@ impl GlobalState {
 			graph: Graph::new(),
 			locations: Locations::new(),
 			movements: Movements::new(),
+			radio: Radio::new(),
+			obstacles: Obstacles::new(),
+			churn: Churn::new(),
+			link_model: LinkModel::new(),
+			interference: Interference::new(),
+			energy: Energy::new(),
+			gateways: vec![],
+			overhead: Overhead::new(),
+			mst: Mst::new(),
+			selections: Selections::new(),
+			history: History::new(),
+			workspaces: Workspaces::new(),
+			threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
 			meta: Meta::new(),
+			node_ids: NodeIds::new(),
 			algorithm: Box::new(RandomRouting::new()),
+			algorithm_name: "random".to_string(),
 			test: EvalPaths::new(),
+			events: EventSim::new(),
 			debug_path: DebugPath::new(),
 			sim_steps: 0,
 			abort_simulation: false,
 			show_progress: false,
+			json_output: false,
 			export_path: "graph.json".to_string(),
-			cmd_address: cmd_address.to_string()
+			cmd_address: cmd_address.to_string(),
+			record_dir: None,
+			metrics: Metrics::new(),
+			timeline: Timeline::new(),
+			test_log: None,
+			ws_clients: WsClients::new(),
+			marked_links: None,
+			export_style: ExportStyle::new()
 		}
 	}
 
@@ -55,6 +167,167 @@ impl GlobalState {
 		self.locations.remove_node(id);
 		self.movements.remove_node(id);
 		self.meta.remove_node(id);
+		self.node_ids.remove_node(id);
+
+		// the ids of later nodes are shifted
+		self.gateways.retain(|gateway| *gateway != id);
+		for gateway in &mut self.gateways {
+			if *gateway > id {
+				*gateway -= 1;
+			}
+		}
+	}
+
+	// only keep the given nodes, node ids[i] becomes node i
+	pub fn crop(&mut self, ids: &[ID]) {
+		let mut ids = ids.to_vec();
+		ids.sort_unstable();
+		ids.dedup();
+		ids.retain(|id| (*id as usize) < self.graph.node_count());
+
+		for id in (0..self.graph.node_count() as ID).rev() {
+			if ids.binary_search(&id).is_err() {
+				self.movements.remove_node(id);
+			}
+		}
+
+		self.graph = self.graph.subgraph(&ids);
+		self.locations = self.locations.subgraph(&ids);
+		self.meta = self.meta.subgraph(&ids);
+		self.node_ids = self.node_ids.subgraph(&ids);
+		self.gateways = self.gateways.iter()
+			.filter_map(|gateway| ids.binary_search(gateway).ok().map(|i| i as ID))
+			.collect();
+	}
+
+	// append another graph, node i becomes node_count + i, positions are moved by offset (in km)
+	pub fn merge(&mut self, graph: &Graph, locations: &Locations, meta: &Meta, offset: [f32; 3]) {
+		let base = self.graph.node_count() as ID;
+		let mut links = self.graph.links().to_vec();
+		for link in graph.links() {
+			let mut link = link.clone();
+			link.from += base;
+			link.to += base;
+			links.push(link);
+		}
+		self.graph = Graph::from_links(base as usize + graph.node_count(), links);
+
+		for id in 0..graph.node_count() as ID {
+			if let Some(pos) = locations.get_position(id) {
+				self.locations.insert(base + id, [pos[0] + offset[0], pos[1] + offset[1], pos[2] + offset[2]]);
+			}
+			if let Some(object) = meta.get_object(id) {
+				self.meta.insert(base + id, Value::Object(object).to_string());
+			}
+		}
+	}
+
+	fn snapshot(&self, command: &str) -> Snapshot {
+		Snapshot {
+			command: command.to_string(),
+			graph: self.graph.clone(),
+			locations: self.locations.clone(),
+			meta: self.meta.clone(),
+			node_ids: self.node_ids.clone(),
+			gateways: self.gateways.clone()
+		}
+	}
+
+	fn restore(&mut self, snapshot: Snapshot) {
+		self.graph = snapshot.graph;
+		self.locations = snapshot.locations;
+		self.meta = snapshot.meta;
+		self.node_ids = snapshot.node_ids;
+		self.gateways = snapshot.gateways;
+	}
+
+	// remember the state before a destructive command
+	pub fn checkpoint(&mut self, command: &str) {
+		let snapshot = self.snapshot(command);
+		self.history.push(snapshot);
+	}
+
+	// returns the command that was undone
+	pub fn undo(&mut self) -> Option<String> {
+		let current = self.snapshot("");
+		let snapshot = self.history.undo(current)?;
+		let command = snapshot.command.clone();
+		self.restore(snapshot);
+		Some(command)
+	}
+
+	// returns the command that was redone
+	pub fn redo(&mut self) -> Option<String> {
+		let current = self.snapshot("");
+		let snapshot = self.history.redo(current)?;
+		let command = snapshot.command.clone();
+		self.restore(snapshot);
+		Some(command)
+	}
+
+	pub fn save(&self) -> SavedState {
+		SavedState {
+			graph: self.graph.clone(),
+			locations: self.locations.clone(),
+			movements: self.movements.clone(),
+			radio: self.radio.clone(),
+			obstacles: self.obstacles.clone(),
+			churn: self.churn.clone(),
+			link_model: self.link_model.clone(),
+			interference: self.interference.clone(),
+			energy: self.energy.clone(),
+			gateways: self.gateways.clone(),
+			overhead: self.overhead.clone(),
+			mst: self.mst.clone(),
+			selections: self.selections.clone(),
+			meta: self.meta.clone(),
+			node_ids: self.node_ids.clone(),
+			algorithm_name: self.algorithm_name.clone(),
+			algorithm_state: self.algorithm.save_state(),
+			test: self.test.clone(),
+			events: self.events.clone(),
+			sim_steps: self.sim_steps,
+			timeline: self.timeline.clone(),
+			marked_links: self.marked_links.clone(),
+			export_style: self.export_style.clone()
+		}
+	}
+
+	pub fn restore_saved(&mut self, saved: SavedState) {
+		self.graph = saved.graph;
+		self.locations = saved.locations;
+		self.movements = saved.movements;
+		self.radio = saved.radio;
+		self.obstacles = saved.obstacles;
+		self.churn = saved.churn;
+		self.link_model = saved.link_model;
+		self.interference = saved.interference;
+		self.energy = saved.energy;
+		self.gateways = saved.gateways;
+		self.overhead = saved.overhead;
+		self.mst = saved.mst;
+		self.selections = saved.selections;
+		self.meta = saved.meta;
+		self.node_ids = saved.node_ids;
+		self.test = saved.test;
+		self.events = saved.events;
+		self.sim_steps = saved.sim_steps;
+		self.timeline = saved.timeline;
+		self.marked_links = saved.marked_links;
+		self.export_style = saved.export_style;
+
+		if self.algorithm_name != saved.algorithm_name {
+			if let Some(algorithm) = create_algorithm(&saved.algorithm_name) {
+				self.algorithm = algorithm;
+				self.algorithm_name = saved.algorithm_name;
+			}
+		}
+		self.algorithm.reset(self.graph.node_count());
+		if let Some(state) = saved.algorithm_state {
+			if self.algorithm.load_state(&state).is_err() {
+				self.algorithm.reset(self.graph.node_count());
+			}
+		}
 	}
 
 	pub fn clear(&mut self) {
@@ -62,13 +335,15 @@ impl GlobalState {
 		self.locations.clear();
 		self.movements.clear();
 		self.meta.clear();
+		self.node_ids.clear();
+		self.timeline.clear();
 	}
 
 	pub fn get_mean_link_distance(&self) -> (f32, f32) {
 		let mut distances = Vec::new();
 		let mut distance_sum = 0.0;
 
-		for link in &self.graph.links {
+		for link in self.graph.links() {
 			if let Some(distance) = self.locations.pos_distance(link.from, link.to) {
 				distance_sum += distance;
 				distances.push(distance);
@@ -86,24 +361,49 @@ impl GlobalState {
 		(mean, variance)
 	}
 
-	pub fn connect_in_range(&mut self, range: f32) {
-		let node_count = self.graph.node_count();
-
+	// returns false if cancelled, only some nodes are connected then
+	pub fn connect_in_range(&mut self, range: f32) -> bool {
 		// remove all links
 		self.graph.clear_links();
 
+		self.connect_new_in_range(0, range)
+	}
+
+	// connect the nodes from id first on with all nodes in range, links between older nodes are kept
+	pub fn connect_new_in_range(&mut self, first: ID, range: f32) -> bool {
+		let node_count = self.graph.node_count();
+		let mut progress = Progress::new();
+
 		for i in 0..node_count as ID {
+			if is_cancelled() {
+				return false;
+			}
+
+			if self.show_progress {
+				progress.update(node_count, i as usize);
+			}
+
 			for j in 0..node_count as ID {
-				if i == j {
+				if i == j || (i < first && j < first) {
 					continue;
 				}
 				if let Some(distance) = self.locations.pos_distance(i, j) {
-					if distance <= range {
-						self.graph.connect(i, j);
+					if let Some(quality) = self.radio.link_quality(distance, range) {
+						let a = self.locations.get_position(i).unwrap();
+						let b = self.locations.get_position(j).unwrap();
+						if !self.obstacles.is_blocked(a, b) {
+							self.graph.add_link(i, j, (quality * u16::MAX as f32) as u16);
+						}
 					}
 				}
 			}
 		}
+
+		if self.show_progress {
+			progress.update(node_count, node_count);
+		}
+
+		true
 	}
 
 	pub fn add_line(&mut self, count: u32, close: bool) {
@@ -116,8 +416,8 @@ impl GlobalState {
 
 		for i in 0..count {
 			let pos = if close {
-				let r = NODE_SPACING * (count as f32) / (2.0 * f32::consts::PI);
-				let a = 2.0 * (i as f32) * f32::consts::PI / (count as f32);
+				let r = NODE_SPACING * (count as f32) / (2.0 * std::f32::consts::PI);
+				let a = 2.0 * (i as f32) * std::f32::consts::PI / (count as f32);
 				[r * a.sin(), r * a.cos(), 0.0]
 			} else {
 				[(i as f32) * NODE_SPACING, 1.1 * (i % 2) as f32, 0.0]
@@ -130,7 +430,7 @@ impl GlobalState {
 		}
 
 		if close && (count > 2) {
-			self.graph.connect(offset + 0, offset + count - 1);
+			self.graph.connect(offset, offset + count - 1);
 		}
 	}
 
@@ -144,7 +444,7 @@ impl GlobalState {
 			//if i > 0 {
 				// Connect node with random previous node
 				loop {
-					let j = rand::random::<ID>() % i;
+					let j = random::<ID>() % i;
 					if i != j && !self.graph.has_link((offset + i) as ID, (offset + j) as ID) {
 						self.graph.connect((offset + i) as ID, (offset + j) as ID);
 						break;
@@ -157,8 +457,8 @@ impl GlobalState {
 		if count > 2 {
 			for _ in 0..std::cmp::min(intra, (count * (count - 1)) / 2 - (count + 1)) {
 				loop {
-					let i = rand::random::<ID>() % count;
-					let j = rand::random::<ID>() % count;
+					let i = random::<ID>() % count;
+					let j = random::<ID>() % count;
 					if i != j && !self.graph.has_link((offset + i) as ID, (offset + j) as ID) {
 						self.graph.connect((offset + i) as ID, (offset + j) as ID);
 						break;
@@ -168,7 +468,7 @@ impl GlobalState {
 		}
 /*
 		println!("add_tree:");
-		for link in &self.graph.links {
+		for link in self.graph.links() {
 			println!("link {} => {} ({})", link.from, link.to, link.quality);
 		}
 */
@@ -185,7 +485,7 @@ impl GlobalState {
 		self.locations.insert(offset, [0.0, 0.0, 0.0]);
 
 		for i in 0..count {
-			let a = 2.0 * (i as f32) * f32::consts::PI / (count as f32);
+			let a = 2.0 * (i as f32) * std::f32::consts::PI / (count as f32);
 			self.locations.insert(offset + i + 1, [
 				NODE_SPACING * a.cos(),
 				NODE_SPACING * a.sin(),
@@ -248,8 +548,272 @@ impl GlobalState {
 		}
 	}
 
+	// Add lattice of triangles, every node has up to six neighbors
+	pub fn add_lattice_tri(&mut self, x_count: u32, y_count: u32) {
+		if x_count < 1 || y_count < 1 {
+			return;
+		}
+
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(x_count * y_count);
+
+		let row_height = NODE_SPACING * 3f32.sqrt() / 2.0;
+		for y in 0..y_count {
+			for x in 0..x_count {
+				// shift every second row by half a node distance
+				let shift = if y % 2 == 1 { NODE_SPACING / 2.0 } else { 0.0 };
+				self.locations.insert(offset + y * x_count + x, [
+					(x as f32) * NODE_SPACING + shift,
+					(y as f32) * row_height,
+					0.0
+				]);
+			}
+		}
+
+		let id = |x: u32, y: u32| offset + y * x_count + x;
+		for y in 0..y_count {
+			for x in 0..x_count {
+				if x + 1 < x_count {
+					self.graph.connect(id(x, y), id(x + 1, y));
+				}
+				if y + 1 < y_count {
+					self.graph.connect(id(x, y), id(x, y + 1));
+					// diagonal neighbor in the next row
+					if y % 2 == 1 && x + 1 < x_count {
+						self.graph.connect(id(x, y), id(x + 1, y + 1));
+					}
+					if y % 2 == 0 && x > 0 {
+						self.graph.connect(id(x, y), id(x - 1, y + 1));
+					}
+				}
+			}
+		}
+	}
+
+	// Add lattice of hexagons (honeycomb), every node has up to three neighbors
+	pub fn add_lattice_hex(&mut self, x_count: u32, y_count: u32) {
+		if x_count < 1 || y_count < 1 {
+			return;
+		}
+
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(x_count * y_count);
+
+		// rows of zigzag lines, nodes alternate between lower and upper position
+		let is_upper = |x: u32, y: u32| (x + y) % 2 == 1;
+		for y in 0..y_count {
+			for x in 0..x_count {
+				let shift = if is_upper(x, y) { NODE_SPACING / 2.0 } else { 0.0 };
+				self.locations.insert(offset + y * x_count + x, [
+					(x as f32) * NODE_SPACING * 3f32.sqrt() / 2.0,
+					(y as f32) * NODE_SPACING * 1.5 + shift,
+					0.0
+				]);
+			}
+		}
+
+		let id = |x: u32, y: u32| offset + y * x_count + x;
+		for y in 0..y_count {
+			for x in 0..x_count {
+				if x + 1 < x_count {
+					self.graph.connect(id(x, y), id(x + 1, y));
+				}
+				// connect upper node to the lower node of the next row
+				if y + 1 < y_count && is_upper(x, y) {
+					self.graph.connect(id(x, y), id(x, y + 1));
+				}
+			}
+		}
+	}
+
+	// Add cubic lattice, every node has up to six neighbors
+	pub fn add_lattice3d(&mut self, x_count: u32, y_count: u32, z_count: u32) {
+		if x_count < 1 || y_count < 1 || z_count < 1 {
+			return;
+		}
+
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(x_count * y_count * z_count);
+
+		let id = |x: u32, y: u32, z: u32| offset + (z * y_count + y) * x_count + x;
+		for z in 0..z_count {
+			for y in 0..y_count {
+				for x in 0..x_count {
+					self.locations.insert(id(x, y, z), [
+						(x as f32) * NODE_SPACING,
+						(y as f32) * NODE_SPACING,
+						(z as f32) * NODE_SPACING
+					]);
+
+					if x + 1 < x_count {
+						self.graph.connect(id(x, y, z), id(x + 1, y, z));
+					}
+					if y + 1 < y_count {
+						self.graph.connect(id(x, y, z), id(x, y + 1, z));
+					}
+					if z + 1 < z_count {
+						self.graph.connect(id(x, y, z), id(x, y, z + 1));
+					}
+				}
+			}
+		}
+	}
+
+	// Add lattice of squares with wrap-around links, placed on a torus surface
+	pub fn add_torus(&mut self, x_count: u32, y_count: u32) {
+		if x_count < 1 || y_count < 1 {
+			return;
+		}
+
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(x_count * y_count);
+
+		// tube radius and distance from tube center to torus center
+		let r = NODE_SPACING * (y_count as f32) / (2.0 * std::f32::consts::PI);
+		let big_r = f32::max(NODE_SPACING * (x_count as f32) / (2.0 * std::f32::consts::PI), 2.0 * r);
+
+		let id = |x: u32, y: u32| offset + y * x_count + x;
+		for y in 0..y_count {
+			for x in 0..x_count {
+				let a = 2.0 * (x as f32) * std::f32::consts::PI / (x_count as f32);
+				let b = 2.0 * (y as f32) * std::f32::consts::PI / (y_count as f32);
+				self.locations.insert(id(x, y), [
+					(big_r + r * b.cos()) * a.cos(),
+					(big_r + r * b.cos()) * a.sin(),
+					r * b.sin()
+				]);
+
+				// connect to next node, the last node wraps around
+				if x_count > 1 {
+					self.graph.connect(id(x, y), id((x + 1) % x_count, y));
+				}
+				if y_count > 1 {
+					self.graph.connect(id(x, y), id(x, (y + 1) % y_count));
+				}
+			}
+		}
+	}
+
+	// place nodes randomly in a square, the area grows with the node count
+	fn add_random_nodes(&mut self, count: u32) -> u32 {
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(count);
+
+		let width = NODE_SPACING * (count as f32).sqrt();
+		for i in 0..count {
+			self.locations.insert(offset + i, [
+				random::<f32>() * width,
+				random::<f32>() * width,
+				0.0
+			]);
+		}
+
+		offset
+	}
+
+	// Random geometric graph: connect all nodes within radius (in km)
+	pub fn add_rgg(&mut self, count: u32, radius: f32) {
+		let offset = self.add_random_nodes(count);
+
+		for i in 0..count {
+			for j in (i + 1)..count {
+				if let Some(distance) = self.locations.pos_distance(offset + i, offset + j) {
+					if distance <= radius {
+						self.graph.connect(offset + i, offset + j);
+					}
+				}
+			}
+		}
+	}
+
+	// Erdős–Rényi graph: connect every pair of nodes with probability p
+	pub fn add_erdos(&mut self, count: u32, p: f32) {
+		let offset = self.add_random_nodes(count);
+
+		for i in 0..count {
+			for j in (i + 1)..count {
+				if random::<f32>() < p {
+					self.graph.connect(offset + i, offset + j);
+				}
+			}
+		}
+	}
+
+	// Barabási–Albert graph: every new node connects to m nodes, preferring nodes with high degree
+	pub fn add_barabasi(&mut self, count: u32, m: u32) {
+		if count < 1 || m < 1 {
+			return;
+		}
+
+		let offset = self.add_random_nodes(count);
+		// every node appears once per link, for selection by degree
+		let mut targets : Vec<ID> = vec![];
+
+		// start with a fully connected core
+		let core = u32::min(m + 1, count);
+		for i in 0..core {
+			for j in (i + 1)..core {
+				self.graph.connect(offset + i, offset + j);
+				targets.push(offset + i);
+				targets.push(offset + j);
+			}
+		}
+
+		for i in core..count {
+			let mut chosen : Vec<ID> = vec![];
+			while chosen.len() < m as usize {
+				let target = targets[random::<usize>() % targets.len()];
+				if !chosen.contains(&target) {
+					chosen.push(target);
+				}
+			}
+
+			for target in chosen {
+				self.graph.connect(offset + i, target);
+				targets.push(offset + i);
+				targets.push(target);
+			}
+		}
+	}
+
+	// Watts–Strogatz graph: ring with k nearest neighbors, every link is rewired with probability p
+	pub fn add_smallworld(&mut self, count: u32, k: u32, p: f32) {
+		if count < 1 {
+			return;
+		}
+
+		let offset = self.graph.node_count() as u32;
+		self.graph.add_nodes(count);
+
+		let r = NODE_SPACING * (count as f32) / (2.0 * std::f32::consts::PI);
+		for i in 0..count {
+			let a = 2.0 * (i as f32) * std::f32::consts::PI / (count as f32);
+			self.locations.insert(offset + i, [r * a.sin(), r * a.cos(), 0.0]);
+		}
+
+		let k = u32::min(k, count - 1);
+		for i in 0..count {
+			for j in 1..=(k / 2) {
+				let mut target = (i + j) % count;
+				if random::<f32>() < p {
+					// rewire to a random node
+					for _ in 0..count {
+						let t = random::<u32>() % count;
+						if t != i && !self.graph.has_link(offset + i, offset + t) {
+							target = t;
+							break;
+						}
+					}
+				}
+				if target != i && !self.graph.has_link(offset + i, offset + target) {
+					self.graph.connect(offset + i, offset + target);
+				}
+			}
+		}
+	}
+
 	// move out
-	pub fn graph_to_json(&self, graph: &Graph, ret: &mut String) -> Result<(), std::fmt::Error>
+	pub fn graph_to_json(&self, _graph: &Graph, ret: &mut String) -> Result<(), std::fmt::Error>
 	{
 		use std::fmt::Write;
 
@@ -269,11 +833,7 @@ impl GlobalState {
 					id.to_string()
 				};
 
-				let client_count = if let Some(clients) = get_u64(&v, "clients") {
-					clients
-				} else {
-					0
-				};
+				let client_count = get_u64(&v, "clients").unwrap_or_default();
 
 				if comma {
 					write!(ret, ",")?;
@@ -287,6 +847,39 @@ impl GlobalState {
 	}
 }
 
+// Control messages sent by the routing algorithm over all simulation steps
+#[derive(Clone)]
+pub struct Overhead {
+	pub steps: u64,
+	pub messages: u64,
+	pub bytes: u64,
+	pub max_step_messages: u64
+}
+
+impl Default for Overhead {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Overhead {
+	pub fn new() -> Self {
+		Self { steps: 0, messages: 0, bytes: 0, max_step_messages: 0 }
+	}
+
+	pub fn clear(&mut self) {
+		*self = Self::new();
+	}
+
+	pub fn add_step(&mut self, io: &Io) {
+		let (messages, bytes) = io.sent();
+		self.steps += 1;
+		self.messages += messages;
+		self.bytes += bytes;
+		self.max_step_messages = u64::max(self.max_step_messages, messages);
+	}
+}
+
 pub struct TestPacket {
 	// One hop transmitter and receiver address
 	pub transmitter: ID,
@@ -303,12 +896,12 @@ impl TestPacket {
 	}
 }
 
-pub trait RoutingAlgorithm : Send {
-	fn get_node(&self, _id: ID, _key: &str, _out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+pub trait RoutingAlgorithm : Send + Sync {
+	fn get_node(&self, _id: ID, _key: &str, _out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		Ok(())
 	}
 
-	fn get(&self, key: &str, _out: &mut std::fmt::Write) -> Result<(), std::fmt::Error> {
+	fn get(&self, key: &str, _out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
 		print_unknown_key(key);
 		Ok(())
 	}
@@ -318,10 +911,41 @@ pub trait RoutingAlgorithm : Send {
 		Ok(())
 	}
 
-	fn remove_node(&mut self, id: ID) {
+	fn set_node(&mut self, _id: ID, key: &str, _value: &str) -> Result<(), std::fmt::Error> {
+		print_unknown_key(key);
+		Ok(())
+	}
+
+	// internal state for save_state, None if not supported
+	fn save_state(&self) -> Option<Value> {
+		None
+	}
+
+	// restore the internal state after reset
+	fn load_state(&mut self, _state: &Value) -> Result<(), MyError> {
+		Ok(())
+	}
+
+	fn remove_node(&mut self, _id: ID) {
 		println!("not implemented");
 	}
 
+	// Routing is stable after the last step, None if the algorithm
+	// does not know and convergence is detected from routing decisions
+	fn is_converged(&self) -> Option<bool> {
+		None
+	}
+
+	// Size of the routing state of a node as (entries, bytes)
+	fn state_size(&self, _id: ID) -> Option<(usize, usize)> {
+		None
+	}
+
+	// Called when a node joins the network again
+	// as a new node and has to forget its state
+	fn reset_node(&mut self, _id: ID) {
+	}
+
 	// Called to initialize the states or
 	// when the number of nodes changes
 	fn reset(&mut self, len: usize);
@@ -330,38 +954,76 @@ pub trait RoutingAlgorithm : Send {
 	// link how to combine links with stats?
 	fn step(&mut self, io: &mut Io);
 
+	// Distance estimate between two nodes (e.g. of coordinates),
+	// None if the algorithm has none. Used for source routing.
+	fn distance(&self, _from: ID, _to: ID) -> Option<f32> {
+		None
+	}
+
 	// Get next hop for test packet
 	fn route(&self, _packet: &TestPacket) -> Option<ID> {
 		None
 	}
-}
 
-fn is_smaller(d: &Duration, secs: u64, millis: u32) -> bool {
-	let d_secs = d.as_secs();
-
-	if d_secs < secs {
- 		true
- 	} else if d_secs > secs {
- 		false
-	} else {
-		d.subsec_millis() < millis
+	// Get all candidate next hops for test packet, preferred first.
+	// Multipath algorithms return several, the default is the next hop of route.
+	fn route_multi(&self, packet: &TestPacket) -> Vec<ID> {
+		self.route(packet).into_iter().collect()
 	}
 }
 
 pub struct Io<'a> {
 	graph: &'a Graph,
+	locations: Option<&'a Locations>,
+	// control messages and bytes sent by the algorithm
+	messages: Cell<u64>,
+	bytes: Cell<u64>
 	//time?
 }
 
 impl<'a> Io<'a> {
-	pub fn new(graph: &'a Graph) -> Self {
+	pub fn new(graph: &'a Graph, locations: Option<&'a Locations>) -> Self {
 		Io {
-			graph: graph
+			graph,
+			locations,
+			messages: Cell::new(0),
+			bytes: Cell::new(0)
 		}
 	}
 
-	pub fn link_iter(&self) -> IoIterator {
-		IoIterator::new(&self)
+	// Account for control messages sent by the routing algorithm
+	pub fn send(&self, messages: u64, bytes: u64) {
+		self.messages.set(self.messages.get() + messages);
+		self.bytes.set(self.bytes.get() + bytes);
+	}
+
+	// Messages and bytes sent so far
+	pub fn sent(&self) -> (u64, u64) {
+		(self.messages.get(), self.bytes.get())
+	}
+
+	// Geo position of a node (if known)
+	pub fn position(&self, id: ID) -> Option<[f32; 3]> {
+		self.locations.and_then(|locations| locations.get_position(id).cloned())
+	}
+
+	pub fn locations(&self) -> Option<&'a Locations> {
+		self.locations
+	}
+
+	// Graph with only the links between the given nodes (same node ids),
+	// for an algorithm that runs on a part of the network
+	pub fn subgraph(&self, members: &[bool]) -> Graph {
+		let is_member = |id: ID| members.get(id as usize).cloned().unwrap_or(false);
+		let links = self.graph.links().iter()
+			.filter(|link| is_member(link.from) && is_member(link.to))
+			.cloned()
+			.collect();
+		Graph::from_links(self.graph.node_count(), links)
+	}
+
+	pub fn link_iter(&self) -> IoIterator<'_> {
+		IoIterator::new(self)
 	}
 
 	pub fn nodes_count(&self) -> usize {
@@ -372,6 +1034,22 @@ impl<'a> Io<'a> {
 		self.graph.get_neighbors(id)
 	}
 
+	// Links can be unidirectional
+	pub fn has_link(&self, from: ID, to: ID) -> bool {
+		self.graph.has_link(from, to)
+	}
+
+	pub fn get_link(&self, from: ID, to: ID) -> Option<&Link> {
+		self.node_links(from).iter().find(|link| link.to == to)
+	}
+
+	// ETX of a link with the delivery ratios of both directions
+	pub fn etx(&self, from: ID, to: ID) -> Option<f32> {
+		let forward = self.get_link(from, to)?;
+		let reverse = self.get_link(to, from)?;
+		Some(1.0 / (forward.delivery_ratio() * reverse.delivery_ratio()))
+	}
+
 /*
 	pub fn all_links(&self) -> &[Link] {
 		self.graph.links.as_slice()
@@ -387,7 +1065,7 @@ pub struct IoIterator<'a> {
 impl<'a> IoIterator<'a> {
 	fn new(io: &'a Io) -> Self { 
 		Self {
-			io: io,
+			io,
 			idx: 0,
 		}
 	}
@@ -401,7 +1079,7 @@ impl<'a> Iterator for IoIterator<'a> {
 	fn next(&mut self) -> Option<Self::Item> {
 		let graph = &self.io.graph;
 		if self.idx < graph.link_count() {
-			let link = &graph.links[self.idx];
+			let link = &graph.links()[self.idx];
 			self.idx += 1;
 			Some((link.from, link.to))
 		} else {
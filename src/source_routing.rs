@@ -0,0 +1,159 @@
+
+use std::mem::size_of;
+use std::sync::Mutex;
+
+use crate::graph::{Graph, ID};
+use crate::sim::{RoutingAlgorithm, TestPacket};
+
+
+/*
+* Source routing on top of any routing algorithm.
+* The source computes the whole route and puts the hop list into the
+* packet header, nodes on the way only follow the list. Algorithms with
+* distance estimates (e.g. coordinates) are used greedily at the source:
+* the route continues to the unvisited neighbor with the smallest
+* estimated distance to the destination. For other algorithms the source
+* follows the next hops of the algorithm and cuts out loops.
+*/
+
+// hop list from the source (excluded) to the destination
+pub fn source_route(graph: &Graph, algorithm: &dyn RoutingAlgorithm, source: ID, destination: ID) -> Option<Vec<ID>> {
+	if algorithm.distance(source, destination).is_some() {
+		greedy_route(graph, algorithm, source, destination)
+	} else {
+		next_hop_route(graph, algorithm, source, destination)
+	}
+}
+
+fn greedy_route(graph: &Graph, algorithm: &dyn RoutingAlgorithm, source: ID, destination: ID) -> Option<Vec<ID>> {
+	let mut visited = vec![false; graph.node_count()];
+	let mut current = source;
+	let mut route = vec![];
+	visited[source as usize] = true;
+
+	while current != destination {
+		let mut best : Option<(f32, ID)> = None;
+		for link in graph.get_neighbors(current) {
+			if visited[link.to as usize] {
+				continue;
+			}
+			let distance = if link.to == destination {
+				0.0
+			} else {
+				algorithm.distance(link.to, destination).unwrap_or(f32::INFINITY)
+			};
+			if best.is_none_or(|(d, _)| distance < d) {
+				best = Some((distance, link.to));
+			}
+		}
+		let (_, next) = best?;
+		visited[next as usize] = true;
+		route.push(next);
+		current = next;
+	}
+
+	Some(route)
+}
+
+fn next_hop_route(graph: &Graph, algorithm: &dyn RoutingAlgorithm, source: ID, destination: ID) -> Option<Vec<ID>> {
+	let mut packet = TestPacket::new(source, source, source, destination);
+	let mut path = vec![source];
+
+	for _ in 0..(2 * graph.node_count()) {
+		let next = algorithm.route(&packet)?;
+		if !graph.has_link(packet.receiver, next) {
+			return None;
+		}
+		// cut out the loop
+		if let Some(pos) = path.iter().position(|id| *id == next) {
+			path.truncate(pos + 1);
+		} else {
+			path.push(next);
+		}
+		if next == destination {
+			path.remove(0);
+			return Some(path);
+		}
+		packet.transmitter = packet.receiver;
+		packet.receiver = next;
+	}
+
+	None
+}
+
+// header sizes of the routes computed during a test
+pub struct HeaderStats {
+	// hops of every source route and if it was computed from distance estimates
+	hops: Mutex<Vec<(usize, bool)>>
+}
+
+impl Default for HeaderStats {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl HeaderStats {
+	pub fn new() -> Self {
+		Self { hops: Mutex::new(vec![]) }
+	}
+
+	// next hop of the packet on its source route
+	pub fn route(&self, graph: &Graph, algorithm: &dyn RoutingAlgorithm, packet: &TestPacket) -> Option<ID> {
+		let route = source_route(graph, algorithm, packet.source, packet.destination)?;
+		if packet.receiver == packet.source {
+			let estimated = algorithm.distance(packet.source, packet.destination).is_some();
+			self.hops.lock().unwrap().push((route.len(), estimated));
+			return route.first().cloned();
+		}
+		let pos = route.iter().position(|id| *id == packet.receiver)?;
+		route.get(pos + 1).cloned()
+	}
+
+	// (routes, from estimates, sorted hops)
+	fn hops(&self) -> (usize, usize, Vec<usize>) {
+		let routes = self.hops.lock().unwrap();
+		let estimated = routes.iter().filter(|(_, estimated)| *estimated).count();
+		let mut hops : Vec<usize> = routes.iter().map(|(hops, _)| *hops).collect();
+		hops.sort_unstable();
+		(routes.len(), estimated, hops)
+	}
+
+	pub fn to_json(&self, node_count: usize) -> serde_json::Value {
+		let (routes, estimated, hops) = self.hops();
+		let mean = hops.iter().sum::<usize>() as f32 / hops.len().max(1) as f32;
+		serde_json::json!({
+			"routes": routes,
+			"estimated": estimated,
+			"header_hops_percentiles": percentiles(&hops),
+			"header_bytes_mean": mean * size_of::<ID>() as f32,
+			"hops_per_log2_n": mean / (node_count.max(2) as f32).log2()
+		})
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write, node_count: usize) -> Result<(), std::fmt::Error> {
+		let (routes, estimated, hops) = self.hops();
+		if routes == 0 {
+			return writeln!(out, "source routes: none");
+		}
+		let p = percentiles(&hops);
+		let mean = hops.iter().sum::<usize>() as f32 / hops.len() as f32;
+		let bytes = mean * size_of::<ID>() as f32;
+		// hops relative to log2 of the node count, to compare network sizes
+		let log_n = (node_count.max(2) as f32).log2();
+
+		writeln!(out, "source routes: {}, from distance estimates: {}, from next hops: {}",
+			routes, estimated, routes - estimated)?;
+		writeln!(out, "header hops p50: {}, p90: {}, p99: {}, max: {}, mean bytes: {:.1}, hops per log2(n): {:.2}",
+			p[0], p[1], p[2], p[3], bytes, mean / log_n)
+	}
+}
+
+// p50, p90, p99 and max of sorted values
+fn percentiles(sorted: &[usize]) -> [usize; 4] {
+	if sorted.is_empty() {
+		return [0; 4];
+	}
+	let at = |p: usize| sorted[((sorted.len() - 1) * p) / 100];
+	[at(50), at(90), at(99), sorted[sorted.len() - 1]]
+}
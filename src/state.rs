@@ -0,0 +1,174 @@
+
+use std::fs::File;
+use std::io::Write;
+use serde_json::{json, Value};
+
+use crate::graph::{Graph, Link, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::node_ids::NodeIds;
+use crate::selection::Selections;
+use crate::algorithms::create_algorithm;
+use crate::sim::GlobalState;
+use crate::utils::{get_array, get_str, get_u64, get_seed, set_seed, MyError};
+
+
+/*
+ * Snapshots of the simulator state to checkpoint and resume experiments.
+ * The graph, locations, metadata, stable node ids, gateways,
+ * selections, the selected algorithm with its internal state (if
+ * supported), the step counter and the random seed are stored as JSON.
+ */
+
+const STATE_VERSION : u64 = 1;
+
+fn link_to_json(link: &Link) -> Value {
+	json!([link.from, link.to, link.quality(), link.cost(), link.bandwidth(), link.latency(), link.loss(), link.channel()])
+}
+
+fn link_from_json(value: &Value) -> Option<Link> {
+	let get = |i: usize| value.get(i).and_then(Value::as_f64);
+	let mut link = Link::new(get(0)? as ID, get(1)? as ID, get(2)? as u16);
+	link.set_attributes(get(3)? as u16, get(4)? as f32, get(5)? as f32, get(6)? as f32);
+	// older states have no channel
+	link.set_channel(get(7).unwrap_or(0.0) as u8);
+	Some(link)
+}
+
+pub fn save_state(sim: &GlobalState, path: &str) -> Result<(), MyError> {
+	let links : Vec<Value> = sim.graph.links().iter().map(link_to_json).collect();
+
+	let mut locations = serde_json::Map::new();
+	for (id, pos) in &sim.locations.data {
+		locations.insert(id.to_string(), json!(&pos[..]));
+	}
+
+	let mut meta = serde_json::Map::new();
+	for (id, data) in &sim.meta.data {
+		meta.insert(id.to_string(), Value::String(data.clone()));
+	}
+
+	let mut selections = serde_json::Map::new();
+	for (name, expr) in sim.selections.list() {
+		selections.insert(name.clone(), Value::String(expr.clone()));
+	}
+
+	let state = json!({
+		"version": STATE_VERSION,
+		"node_count": sim.graph.node_count(),
+		"links": links,
+		"locations": locations,
+		"meta": meta,
+		"node_ids": sim.node_ids.ids(),
+		"next_node_id": sim.node_ids.next(),
+		"gateways": sim.gateways,
+		"selections": selections,
+		"sim_steps": sim.sim_steps,
+		"seed": get_seed(),
+		"algorithm": sim.algorithm_name,
+		"algorithm_state": sim.algorithm.save_state()
+	});
+
+	File::create(path)
+		.and_then(|mut file| file.write_all(state.to_string().as_bytes()))
+		.map_err(|e| MyError::new(format!("{}: {}", path, e)))
+}
+
+pub fn load_state(sim: &mut GlobalState, path: &str) -> Result<(), MyError> {
+	let data = std::fs::read_to_string(path)
+		.map_err(|e| MyError::new(format!("{}: {}", path, e)))?;
+	let state : Value = serde_json::from_str(&data)
+		.map_err(|e| MyError::new(format!("{}: {}", path, e)))?;
+
+	let version = get_u64(&state, "version").unwrap_or(0);
+	if version != STATE_VERSION {
+		return Err(MyError::new(format!("Unsupported state version: {}", version)));
+	}
+
+	let node_count = get_u64(&state, "node_count")
+		.ok_or_else(|| MyError::new("node_count missing".to_string()))? as usize;
+
+	let mut links = vec![];
+	for value in get_array(&state, "links").map_or(&[][..], |v| v.as_slice()) {
+		match link_from_json(value) {
+			Some(link) if (link.from as usize) < node_count && (link.to as usize) < node_count => links.push(link),
+			_ => return Err(MyError::new(format!("Invalid link: {}", value)))
+		}
+	}
+
+	let mut locations = Locations::new();
+	if let Some(Value::Object(obj)) = state.get("locations") {
+		for (id, pos) in obj {
+			let get = |i: usize| pos.get(i).and_then(Value::as_f64).unwrap_or(0.0) as f32;
+			if let Ok(id) = id.parse::<ID>() {
+				locations.insert(id, [get(0), get(1), get(2)]);
+			}
+		}
+	}
+
+	let mut meta = Meta::new();
+	if let Some(Value::Object(obj)) = state.get("meta") {
+		for (id, data) in obj {
+			if let (Ok(id), Some(data)) = (id.parse::<ID>(), data.as_str()) {
+				meta.insert(id, data.to_string());
+			}
+		}
+	}
+
+	// states without stable ids get the node ids
+	let ids : Vec<ID> = get_array(&state, "node_ids").map_or(vec![], |ids| {
+		ids.iter().filter_map(Value::as_u64).map(|id| id as ID).collect()
+	});
+	let node_ids = if ids.len() == node_count {
+		NodeIds::from_ids(ids, get_u64(&state, "next_node_id").unwrap_or(0) as ID)
+	} else {
+		let mut node_ids = NodeIds::new();
+		node_ids.update(node_count);
+		node_ids
+	};
+
+	let mut selections = Selections::new();
+	if let Some(Value::Object(obj)) = state.get("selections") {
+		for (name, expr) in obj {
+			selections.define(name, expr.as_str().unwrap_or(""))?;
+		}
+	}
+
+	let gateways : Vec<ID> = get_array(&state, "gateways").map_or(vec![], |ids| {
+		ids.iter().filter_map(Value::as_u64).map(|id| id as ID).collect()
+	});
+
+	let algorithm_name = get_str(&state, "algorithm").unwrap_or("random").to_string();
+	let mut algorithm = create_algorithm(&algorithm_name)
+		.ok_or_else(|| MyError::new(format!("Unknown algorithm: {}", algorithm_name)))?;
+	algorithm.reset(node_count);
+	if let Some(algorithm_state) = state.get("algorithm_state").filter(|v| !v.is_null()) {
+		algorithm.load_state(algorithm_state)?;
+	}
+
+	// everything is valid, replace the current state
+	sim.clear();
+	sim.graph = Graph::from_links(node_count, links);
+	sim.locations = locations;
+	sim.meta = meta;
+	sim.node_ids = node_ids;
+	sim.selections = selections;
+	sim.gateways = gateways;
+	sim.algorithm = algorithm;
+	sim.algorithm_name = algorithm_name;
+	sim.sim_steps = get_u64(&state, "sim_steps").unwrap_or(0) as u32;
+
+	// the generator starts over from the seed
+	if let Some(seed) = get_u64(&state, "seed") {
+		set_seed(Some(seed));
+	}
+
+	sim.test.clear();
+	sim.churn.clear();
+	sim.energy.reset(node_count);
+	sim.overhead.clear();
+	sim.mst.clear();
+	sim.history.clear();
+
+	Ok(())
+}
@@ -1,5 +1,4 @@
 
-use crate::utils::CircularVec;
 
 
 #[derive(Clone, PartialEq)]
@@ -12,6 +11,12 @@ pub struct TestResult {
 	pub received_test_packets: u32,
 }
 
+impl Default for TestResult {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl TestResult {
 	pub fn new() -> Self {
 		Self {
@@ -49,6 +54,6 @@ impl TestResult {
 		write!(&mut ret, "\"received_test_packets\": {},", self.received_test_packets).unwrap();
 		write!(&mut ret, "}}").unwrap();
 
-		return ret;
+		ret
 	}
 }
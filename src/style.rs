@@ -0,0 +1,276 @@
+
+use std::collections::HashMap;
+
+use crate::energy::Energy;
+use crate::eval_paths::EvalPaths;
+use crate::graph::{Graph, ID};
+use crate::meta::Meta;
+use crate::sim::RoutingAlgorithm;
+use crate::utils::MyError;
+
+
+/*
+ * Node color/size and link color/width of the exports bound to metrics,
+ * e.g. export_style node_color=load link_width=etx. Numeric metrics are
+ * scaled between their minimum and maximum, components and clusters
+ * get colors from a palette.
+ */
+
+// colors of components and clusters
+const PALETTE: [[u8; 3]; 10] = [
+	[31, 119, 180], [255, 127, 14], [44, 160, 44], [214, 39, 40], [148, 103, 189],
+	[140, 86, 75], [227, 119, 194], [127, 127, 127], [188, 189, 34], [23, 190, 207]
+];
+// node size factor (1 is the default size)
+const MIN_NODE_SIZE: f32 = 0.5;
+const MAX_NODE_SIZE: f32 = 2.0;
+// link width in pixels
+const MIN_LINK_WIDTH: f32 = 1.0;
+const MAX_LINK_WIDTH: f32 = 5.0;
+// larger ETX values (e.g. infinite for lost links) are cut
+const MAX_ETX: f32 = 10.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NodeMetric {
+	Component,
+	Degree,
+	Load,
+	Cluster,
+	Energy,
+	Algorithm
+}
+
+impl NodeMetric {
+	pub fn parse(name: &str) -> Result<Self, MyError> {
+		match name {
+			"component" => Ok(NodeMetric::Component),
+			"degree" => Ok(NodeMetric::Degree),
+			"load" => Ok(NodeMetric::Load),
+			"cluster" => Ok(NodeMetric::Cluster),
+			"energy" => Ok(NodeMetric::Energy),
+			"algorithm" => Ok(NodeMetric::Algorithm),
+			_ => Err(MyError::new(format!("Unknown node metric: {} (use component, degree, load, cluster, energy or algorithm)", name)))
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			NodeMetric::Component => "component",
+			NodeMetric::Degree => "degree",
+			NodeMetric::Load => "load",
+			NodeMetric::Cluster => "cluster",
+			NodeMetric::Energy => "energy",
+			NodeMetric::Algorithm => "algorithm"
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LinkMetric {
+	Quality,
+	Etx,
+	Load,
+	Cost,
+	Bandwidth,
+	Latency,
+	Loss
+}
+
+impl LinkMetric {
+	pub fn parse(name: &str) -> Result<Self, MyError> {
+		match name {
+			"quality" => Ok(LinkMetric::Quality),
+			"etx" => Ok(LinkMetric::Etx),
+			"load" => Ok(LinkMetric::Load),
+			"cost" => Ok(LinkMetric::Cost),
+			"bandwidth" => Ok(LinkMetric::Bandwidth),
+			"latency" => Ok(LinkMetric::Latency),
+			"loss" => Ok(LinkMetric::Loss),
+			_ => Err(MyError::new(format!("Unknown link metric: {} (use quality, etx, load, cost, bandwidth, latency or loss)", name)))
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			LinkMetric::Quality => "quality",
+			LinkMetric::Etx => "etx",
+			LinkMetric::Load => "load",
+			LinkMetric::Cost => "cost",
+			LinkMetric::Bandwidth => "bandwidth",
+			LinkMetric::Latency => "latency",
+			LinkMetric::Loss => "loss"
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct ExportStyle {
+	pub node_color: Option<NodeMetric>,
+	pub node_size: Option<NodeMetric>,
+	pub link_color: Option<LinkMetric>,
+	pub link_width: Option<LinkMetric>
+}
+
+impl Default for ExportStyle {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ExportStyle {
+	pub fn new() -> Self {
+		Self {
+			node_color: None,
+			node_size: None,
+			link_color: None,
+			link_width: None
+		}
+	}
+
+	pub fn clear(&mut self) {
+		*self = Self::new();
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.node_color.is_none() && self.node_size.is_none() && self.link_color.is_none() && self.link_width.is_none()
+	}
+
+	// set from <key>=<metric>, the metric none removes the binding
+	pub fn set(&mut self, assignment: &str) -> Result<(), MyError> {
+		let (key, value) = assignment.split_once('=')
+			.ok_or_else(|| MyError::new(format!("Expected <key>=<metric>: {}", assignment)))?;
+		let none = value == "none";
+		match key {
+			"node_color" => self.node_color = if none { None } else { Some(NodeMetric::parse(value)?) },
+			"node_size" => self.node_size = if none { None } else { Some(NodeMetric::parse(value)?) },
+			"link_color" => self.link_color = if none { None } else { Some(LinkMetric::parse(value)?) },
+			"link_width" => self.link_width = if none { None } else { Some(LinkMetric::parse(value)?) },
+			_ => return Err(MyError::new(format!("Unknown style key: {} (use node_color, node_size, link_color or link_width)", key)))
+		}
+		Ok(())
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
+		let node = |metric: Option<NodeMetric>| metric.map_or("none", |m| m.name());
+		let link = |metric: Option<LinkMetric>| metric.map_or("none", |m| m.name());
+		writeln!(out, "node_color={} node_size={} link_color={} link_width={}",
+			node(self.node_color), node(self.node_size), link(self.link_color), link(self.link_width))
+	}
+}
+
+// blue (0) to red (1)
+pub fn gradient(f: f32) -> [u8; 3] {
+	let f = f.clamp(0.0, 1.0);
+	[(255.0 * f) as u8, (80.0 * (1.0 - (2.0 * f - 1.0).abs())) as u8, (255.0 * (1.0 - f)) as u8]
+}
+
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+	let hex = color.trim_start_matches('#');
+	if hex.len() != 6 {
+		return None;
+	}
+	let value = u32::from_str_radix(hex, 16).ok()?;
+	Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub fn format_color(color: &[u8; 3]) -> String {
+	format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// values scaled to 0..1 between the minimum and maximum
+fn normalize(values: &[Option<f32>]) -> Vec<Option<f32>> {
+	let min = values.iter().flatten().cloned().fold(f32::INFINITY, f32::min);
+	let max = values.iter().flatten().cloned().fold(f32::NEG_INFINITY, f32::max);
+	values.iter().map(|value| value.map(|v| if max > min { (v - min) / (max - min) } else { 0.0 })).collect()
+}
+
+// colors and sizes of the nodes and links for one export
+pub struct Styling {
+	node_colors: Vec<Option<[u8; 3]>>,
+	node_sizes: Vec<Option<f32>>,
+	link_colors: HashMap<(ID, ID), [u8; 3]>,
+	link_widths: HashMap<(ID, ID), f32>
+}
+
+impl Styling {
+	pub fn new(style: &ExportStyle, graph: &Graph, meta: Option<&Meta>, algo: Option<&dyn RoutingAlgorithm>,
+			energy: Option<&Energy>, test: Option<&EvalPaths>) -> Self {
+		let test = test.filter(|test| test.has_load());
+		let node_count = graph.node_count();
+		let components = graph.get_component_ids();
+		let mut algo_color = String::new();
+
+		let node_values = |metric: NodeMetric| -> Vec<Option<f32>> {
+			(0..node_count as ID).map(|id| match metric {
+				NodeMetric::Component => Some(components[id as usize] as f32),
+				NodeMetric::Degree => Some(graph.get_neighbors(id).len() as f32),
+				NodeMetric::Load => test.map(|test| test.node_load(id) as f32),
+				NodeMetric::Cluster => meta.and_then(|meta| meta.get_value(id, "cluster")).and_then(|c| c.as_f64()).map(|c| c as f32),
+				NodeMetric::Energy => energy.and_then(|energy| energy.get_level(id)),
+				NodeMetric::Algorithm => None
+			}).collect()
+		};
+
+		let node_colors = match style.node_color {
+			Some(NodeMetric::Algorithm) => (0..node_count as ID).map(|id| {
+				algo_color.clear();
+				let _ = algo.map(|algo| algo.get_node(id, "color", &mut algo_color));
+				parse_color(&algo_color)
+			}).collect(),
+			Some(metric @ NodeMetric::Component) | Some(metric @ NodeMetric::Cluster) => node_values(metric).iter()
+				.map(|value| value.map(|v| PALETTE[v as usize % PALETTE.len()])).collect(),
+			Some(metric) => normalize(&node_values(metric)).iter().map(|value| value.map(gradient)).collect(),
+			None => vec![]
+		};
+
+		let node_sizes = match style.node_size {
+			Some(metric) => normalize(&node_values(metric)).iter()
+				.map(|value| value.map(|v| MIN_NODE_SIZE + v * (MAX_NODE_SIZE - MIN_NODE_SIZE))).collect(),
+			None => vec![]
+		};
+
+		let link_values = |metric: LinkMetric| -> Vec<((ID, ID), Option<f32>)> {
+			graph.links().iter().map(|link| ((link.from, link.to), match metric {
+				LinkMetric::Quality => Some(link.quality() as f32 / u16::MAX as f32),
+				LinkMetric::Etx => Some(link.etx().min(MAX_ETX)),
+				LinkMetric::Load => test.map(|test| (test.link_load(link.from, link.to) + test.link_load(link.to, link.from)) as f32),
+				LinkMetric::Cost => Some(link.cost() as f32),
+				LinkMetric::Bandwidth => Some(link.bandwidth()),
+				LinkMetric::Latency => Some(link.latency()),
+				LinkMetric::Loss => Some(link.loss())
+			})).collect()
+		};
+
+		let scaled = |metric: Option<LinkMetric>, f: &dyn Fn(f32) -> f32| -> Vec<((ID, ID), f32)> {
+			match metric {
+				Some(metric) => {
+					let values = link_values(metric);
+					let normalized = normalize(&values.iter().map(|(_, v)| *v).collect::<Vec<_>>());
+					values.iter().zip(normalized).filter_map(|((key, _), v)| Some((*key, f(v?)))).collect()
+				},
+				None => vec![]
+			}
+		};
+
+		let link_widths = scaled(style.link_width, &|v| MIN_LINK_WIDTH + v * (MAX_LINK_WIDTH - MIN_LINK_WIDTH)).into_iter().collect();
+		let link_colors = scaled(style.link_color, &|v| v).into_iter().map(|(key, v)| (key, gradient(v))).collect();
+
+		Self { node_colors, node_sizes, link_colors, link_widths }
+	}
+
+	pub fn node_color(&self, id: ID) -> Option<[u8; 3]> {
+		self.node_colors.get(id as usize).cloned().flatten()
+	}
+
+	pub fn node_size(&self, id: ID) -> Option<f32> {
+		self.node_sizes.get(id as usize).cloned().flatten()
+	}
+
+	pub fn link_color(&self, from: ID, to: ID) -> Option<[u8; 3]> {
+		self.link_colors.get(&(from, to)).cloned()
+	}
+
+	pub fn link_width(&self, from: ID, to: ID) -> Option<f32> {
+		self.link_widths.get(&(from, to)).cloned()
+	}
+}
@@ -0,0 +1,197 @@
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::graph::{Graph, Link, ID};
+use crate::graph_diff::node_keys;
+use crate::importer::import_file;
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::utils::{wildcard_match, MyError};
+
+
+/*
+ * Playback of time ordered graph snapshots, e.g. hourly dumps of
+ * a real mesh. All snapshots are loaded at the start and their nodes
+ * are matched by name (see graph_diff), so every node keeps its id
+ * for the whole timeline. Nodes missing in a snapshot have no links.
+ * The topology advances to the next snapshot every few simulation steps.
+ */
+
+// file types that can be imported
+const EXTENSIONS : [&str; 3] = ["json", "graphml", "gexf"];
+
+#[derive(Clone)]
+struct Snapshot {
+	path: String,
+	links: Vec<Link>,
+	positions: Vec<(ID, [f32; 3])>
+}
+
+#[derive(Clone)]
+pub struct Timeline {
+	snapshots: Vec<Snapshot>,
+	// simulation steps per snapshot
+	steps: u32,
+	// simulation step of the first snapshot
+	start_step: u32,
+	current: usize
+}
+
+// files in a directory or matching a pattern in the file name, sorted by name
+fn list_files(source: &str) -> Result<Vec<String>, MyError> {
+	let path = Path::new(source);
+	let (dir, pattern) = if path.is_dir() {
+		(path, "*")
+	} else {
+		let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+		let pattern = path.file_name().and_then(|n| n.to_str()).unwrap_or("*");
+		(dir, pattern)
+	};
+
+	let mut files = vec![];
+	for entry in std::fs::read_dir(dir).map_err(|e| MyError::new(format!("{}: {}", dir.display(), e)))? {
+		let path = entry?.path();
+		let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+		let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+		if path.is_file() && EXTENSIONS.contains(&extension) && wildcard_match(pattern, name) {
+			files.push(path.to_string_lossy().to_string());
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+impl Default for Timeline {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Timeline {
+	pub fn new() -> Self {
+		Self {
+			snapshots: vec![],
+			steps: 1,
+			start_step: 0,
+			current: 0
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		!self.snapshots.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.snapshots.clear();
+		self.current = 0;
+	}
+
+	/*
+	 * Load all snapshots and replace the graph by the first one.
+	 * The node ids are assigned in the order the nodes appear.
+	 */
+	#[allow(clippy::too_many_arguments)]
+	pub fn load(&mut self, source: &str, steps: u32, start_step: u32, graph: &mut Graph, locations: &mut Locations,
+			meta: &mut Meta, show_progress: bool) -> Result<(), MyError> {
+		let files = list_files(source)?;
+		if files.is_empty() {
+			return Err(MyError::new(format!("No snapshots found: {}", source)));
+		}
+
+		let mut ids = HashMap::<String, ID>::new();
+		let mut union_meta = Meta::new();
+		let mut snapshots = vec![];
+
+		for path in files {
+			let mut snapshot_graph = Graph::new();
+			let mut snapshot_locations = Locations::new();
+			let mut snapshot_meta = Meta::new();
+			import_file(&mut snapshot_graph, Some(&mut snapshot_locations), Some(&mut snapshot_meta), &path, show_progress)?;
+
+			// snapshot node id => timeline node id
+			let mut map = vec![];
+			for (i, key) in node_keys(&snapshot_graph, &snapshot_meta).into_iter().enumerate() {
+				let next = ids.len() as ID;
+				let id = *ids.entry(key).or_insert(next);
+				if id == next {
+					if let Some(data) = snapshot_meta.data.get(&(i as ID)) {
+						union_meta.insert(id, data.clone());
+					}
+				}
+				map.push(id);
+			}
+
+			let links = snapshot_graph.links().iter().map(|link| {
+				let mut link = link.clone();
+				link.from = map[link.from as usize];
+				link.to = map[link.to as usize];
+				link
+			}).collect();
+
+			let positions = map.iter().enumerate().filter_map(|(i, id)| {
+				snapshot_locations.get_position(i as ID).map(|pos| (*id, *pos))
+			}).collect();
+
+			snapshots.push(Snapshot { path, links, positions });
+		}
+
+		self.snapshots = snapshots;
+		self.steps = steps.max(1);
+		self.start_step = start_step;
+		self.current = 0;
+
+		graph.clear();
+		graph.add_nodes(ids.len() as u32);
+		locations.clear();
+		*meta = union_meta;
+		self.apply(graph, locations);
+
+		Ok(())
+	}
+
+	fn apply(&self, graph: &mut Graph, locations: &mut Locations) {
+		if let Some(snapshot) = self.snapshots.get(self.current) {
+			*graph = Graph::from_links(graph.node_count(), snapshot.links.clone());
+			for (id, pos) in &snapshot.positions {
+				locations.insert(*id, *pos);
+			}
+		}
+	}
+
+	// called before every simulation step, true if the topology changed
+	pub fn step(&mut self, sim_step: u32, graph: &mut Graph, locations: &mut Locations) -> bool {
+		if self.snapshots.is_empty() {
+			return false;
+		}
+
+		// the last snapshot stays
+		let index = ((sim_step.saturating_sub(self.start_step) / self.steps) as usize).min(self.snapshots.len() - 1);
+		if index == self.current {
+			return false;
+		}
+
+		self.current = index;
+		self.apply(graph, locations);
+		true
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write, sim_step: u32) -> Result<(), MyError> {
+		match self.snapshots.get(self.current) {
+			Some(snapshot) => {
+				write!(out, "snapshot {}/{}: {}, links: {}", self.current + 1, self.snapshots.len(),
+					snapshot.path, snapshot.links.len())?;
+				if self.current + 1 < self.snapshots.len() {
+					let next = self.start_step + (self.current as u32 + 1) * self.steps;
+					writeln!(out, ", next in {} steps", next.saturating_sub(sim_step))?;
+				} else {
+					writeln!(out, ", last snapshot")?;
+				}
+			},
+			None => {
+				writeln!(out, "no timeline loaded")?;
+			}
+		}
+		Ok(())
+	}
+}
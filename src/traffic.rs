@@ -0,0 +1,151 @@
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use rand::Rng;
+
+use crate::graph::ID;
+use crate::utils::{random_range, MyError};
+
+
+/*
+ * Distribution of source/destination pairs for route tests.
+ */
+#[derive(Clone)]
+pub enum Traffic {
+	// random pairs
+	Uniform,
+	// between random nodes and random gateways (both directions)
+	Gateway(Vec<ID>),
+	// a fraction of all packets go to the hotspot nodes
+	Hotspot(Vec<ID>, f32),
+	// weighted pairs from a file
+	Matrix(Vec<(ID, ID, f32)>, f32)
+}
+
+impl Traffic {
+	// parse arguments of the traffic command
+	pub fn parse(args: &[&str], node_count: usize) -> Result<Self, MyError> {
+		let arg = |i: usize| -> Result<&str, MyError> {
+			args.get(i).cloned().ok_or_else(|| MyError::new(format!("Missing traffic argument {}", i)))
+		};
+
+		match arg(0)? {
+			"uniform" => Ok(Traffic::Uniform),
+			"gateway" => {
+				let ids = parse_id_list(arg(1)?)?;
+				Ok(Traffic::Gateway(ids))
+			},
+			"hotspot" => {
+				let fraction = arg(1)?.parse::<f32>()
+					.map_err(|_| MyError::new(format!("Invalid fraction: {}", args[1])))?;
+				let count = match args.get(2) {
+					Some(s) => s.parse::<usize>().map_err(|_| MyError::new(format!("Invalid count: {}", s)))?,
+					None => 1
+				};
+				if node_count == 0 || count == 0 {
+					return Err(MyError::new("No nodes for hotspots".to_string()));
+				}
+				// hotspots are picked once, so every test uses the same
+				let ids = (0..count).map(|_| random_range(0, node_count) as ID).collect();
+				Ok(Traffic::Hotspot(ids, fraction))
+			},
+			"file" => Self::load(arg(1)?),
+			name => Err(MyError::new(format!("Unknown traffic pattern: {}", name)))
+		}
+	}
+
+	// lines of "<source> <destination> [<weight>]", # starts a comment
+	fn load(path: &str) -> Result<Self, MyError> {
+		let file = File::open(path)?;
+		let mut pairs = vec![];
+		let mut total = 0.0;
+
+		for (i, line) in BufReader::new(file).lines().enumerate() {
+			let line = line?;
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			let tokens : Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',')
+				.filter(|s| !s.is_empty()).collect();
+			let invalid = || MyError::new(format!("Invalid traffic line {}: {}", i + 1, line));
+			if tokens.len() < 2 {
+				return Err(invalid());
+			}
+			let source = tokens[0].parse::<ID>().map_err(|_| invalid())?;
+			let destination = tokens[1].parse::<ID>().map_err(|_| invalid())?;
+			let weight = match tokens.get(2) {
+				Some(s) => s.parse::<f32>().map_err(|_| invalid())?,
+				None => 1.0
+			};
+			if weight > 0.0 {
+				total += weight;
+				pairs.push((source, destination, weight));
+			}
+		}
+
+		if pairs.is_empty() {
+			return Err(MyError::new(format!("No traffic in {}", path)));
+		}
+
+		Ok(Traffic::Matrix(pairs, total))
+	}
+
+	pub fn name(&self) -> String {
+		match self {
+			Traffic::Uniform => "uniform".to_string(),
+			Traffic::Gateway(ids) => format!("gateway ({} gateways)", ids.len()),
+			Traffic::Hotspot(ids, fraction) => format!("hotspot ({} hotspots, fraction {})", ids.len(), fraction),
+			Traffic::Matrix(pairs, _) => format!("file ({} pairs)", pairs.len())
+		}
+	}
+
+	// draw a source and destination, ids might be out of range
+	pub fn sample<R: Rng>(&self, rng: &mut R, node_count: usize) -> (ID, ID) {
+		let random = |rng: &mut R| rng.gen_range(0, node_count) as ID;
+
+		match self {
+			Traffic::Uniform => (random(rng), random(rng)),
+			Traffic::Gateway(ids) => {
+				let node = random(rng);
+				let gateway = ids[rng.gen_range(0, ids.len())];
+				if rng.gen::<bool>() {
+					(node, gateway)
+				} else {
+					(gateway, node)
+				}
+			},
+			Traffic::Hotspot(ids, fraction) => {
+				let source = random(rng);
+				if rng.gen::<f32>() < *fraction {
+					(source, ids[rng.gen_range(0, ids.len())])
+				} else {
+					(source, random(rng))
+				}
+			},
+			Traffic::Matrix(pairs, total) => {
+				let mut r = rng.gen::<f32>() * total;
+				for (source, destination, weight) in pairs {
+					if r < *weight {
+						return (*source, *destination);
+					}
+					r -= weight;
+				}
+				let last = pairs[pairs.len() - 1];
+				(last.0, last.1)
+			}
+		}
+	}
+}
+
+// comma separated list of node ids
+pub fn parse_id_list(s: &str) -> Result<Vec<ID>, MyError> {
+	let ids = s.split(',').filter(|s| !s.is_empty())
+		.map(|s| s.trim().parse::<ID>().map_err(|_| MyError::new(format!("Invalid node id: {}", s))))
+		.collect::<Result<Vec<ID>, MyError>>()?;
+	if ids.is_empty() {
+		return Err(MyError::new("Empty node id list".to_string()));
+	}
+	Ok(ids)
+}
@@ -2,17 +2,20 @@
 use serde_json::Value;
 
 use std::fmt;
-use std::usize;
-use std::f32;
 use std::fs::File;
 use std::io::prelude::*;
 use std::ops::{AddAssign, DivAssign, Index, Add, Mul, Div, Sub, Deref};
 use std::time::Duration;
 use std::default::Default;
+use std::sync::Mutex;
 use rand;
+use rand::{FromEntropy, Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::distributions::{Distribution, Standard};
+use rand::distributions::uniform::SampleUniform;
 
 
-pub const RAD2DEG : f32 = 360.0 / (2.0 * f32::consts::PI);
+pub const RAD2DEG : f32 = 360.0 / (2.0 * std::f32::consts::PI);
 
 pub const DEG2KM : f32 = 40076.0 / 360.0;
 
@@ -23,7 +26,7 @@ pub struct MyError {
 
 impl MyError {
 	pub fn new(msg: String) -> Self {
-		Self { msg: msg }
+		Self { msg }
 	}
 }
 
@@ -51,6 +54,44 @@ impl fmt::Display for MyError {
     }
 }
 
+/*
+ * All random numbers of the simulator come from one generator.
+ * With a seed set, runs are reproducible (tests with more
+ * than one thread excepted, they share the generator).
+ */
+struct SharedRng {
+	seed: Option<u64>,
+	rng: Option<StdRng>
+}
+
+static RNG : Mutex<SharedRng> = Mutex::new(SharedRng { seed: None, rng: None });
+
+// seed the generator or use a random seed
+pub fn set_seed(seed: Option<u64>) {
+	let mut shared = RNG.lock().unwrap();
+	shared.seed = seed;
+	shared.rng = seed.map(StdRng::seed_from_u64);
+}
+
+pub fn get_seed() -> Option<u64> {
+	RNG.lock().unwrap().seed
+}
+
+// do not call random functions inside of f, the generator is locked
+pub fn with_rng<T, F: FnOnce(&mut StdRng) -> T>(f: F) -> T {
+	let mut shared = RNG.lock().unwrap();
+	f(shared.rng.get_or_insert_with(StdRng::from_entropy))
+}
+
+pub fn random<T>() -> T where Standard: Distribution<T> {
+	with_rng(|rng| rng.gen())
+}
+
+// random value in [low, high)
+pub fn random_range<T: SampleUniform>(low: T, high: T) -> T {
+	with_rng(|rng| rng.gen_range(low, high))
+}
+
 pub fn print_unknown_key(key: &str) {
 	println!("unknown key: {}", key);
 }
@@ -74,7 +115,7 @@ pub fn get_u64(value: &Value, key: &str) -> Option<u64> {
 pub fn read_file(path: &str) -> Option<Vec<u8>> {
 	if let Ok(mut file) = File::open(path) {
 		let mut data = Vec::new();
-		if let Ok(_) = file.read_to_end(&mut data) {
+		if file.read_to_end(&mut data).is_ok() {
 			return Some(data);
 		}
 	}
@@ -155,7 +196,57 @@ pub fn get_mimetype(filename: &str) -> &'static str {
 		}
 	}
 
-	return "text/plain";
+	"text/plain"
+}
+
+// match text against a pattern where '*' matches any sequence and '?' any single character
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+	let p : Vec<char> = pattern.chars().collect();
+	let t : Vec<char> = text.chars().collect();
+	let (mut i, mut j) = (0, 0);
+	// position of the last '*' in the pattern and the text position it matched up to
+	let mut star = None;
+
+	while j < t.len() {
+		if i < p.len() && (p[i] == '?' || p[i] == t[j]) {
+			i += 1;
+			j += 1;
+		} else if i < p.len() && p[i] == '*' {
+			star = Some((i, j));
+			i += 1;
+		} else if let Some((si, sj)) = star {
+			// let the last '*' match one more character
+			i = si + 1;
+			j = sj + 1;
+			star = Some((si, sj + 1));
+		} else {
+			return false;
+		}
+	}
+
+	p[i..].iter().all(|c| *c == '*')
+}
+
+// replace the whitespace separated tokens at the given positions by f(token),
+// the other tokens and the whitespace are kept as they are
+pub fn map_tokens(input: &str, positions: &[usize], mut f: impl FnMut(&str) -> Result<String, MyError>) -> Result<String, MyError> {
+	let mut ret = String::new();
+	let mut rest = input;
+	let mut i = 0;
+	while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+		ret.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+		if positions.contains(&i) {
+			ret.push_str(&f(&rest[..end])?);
+		} else {
+			ret.push_str(&rest[..end]);
+		}
+		rest = &rest[end..];
+		i += 1;
+	}
+	ret.push_str(rest);
+	Ok(ret)
 }
 
 pub fn index_two_mut<T>(vec: &mut Vec<T>, n: usize, m: usize) -> (&mut T, &mut T) {
@@ -219,9 +310,9 @@ impl Vec3 {
 	// random around in the box of (0, 0, 0)
 	pub fn random_in_area(r: f32) -> Vec3 {
 		Vec3::new(
-			(2.0 * rand::random::<f32>() - 1.0) * r,
-			(2.0 * rand::random::<f32>() - 1.0) * r,
-			(2.0 * rand::random::<f32>() - 1.0) * r,
+			(2.0 * random::<f32>() - 1.0) * r,
+			(2.0 * random::<f32>() - 1.0) * r,
+			(2.0 * random::<f32>() - 1.0) * r,
 		)
 	}
 
@@ -407,6 +498,12 @@ pub struct CircularVec<T : Default + Copy> {
 	data: [T; 20],
 }
 
+impl<T : Default + Copy> Default for CircularVec<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl<T : Default + Copy> CircularVec<T> {
 	pub fn new() -> Self {
 		Self {
@@ -417,7 +514,7 @@ impl<T : Default + Copy> CircularVec<T> {
 	}
 
 	pub fn push(&mut self, item: T) {
-		if self.data.len() > 0 {
+		if !self.data.is_empty() {
 			self.data[self.idx] = item;
 			if self.len < self.data.len() {
 				self.len += 1;
@@ -429,6 +526,10 @@ impl<T : Default + Copy> CircularVec<T> {
 	pub fn len(&self) -> usize {
 		self.len
 	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
 }
 
 impl<T : Default + Copy> Index<usize> for CircularVec<T> {
@@ -442,7 +543,7 @@ impl<T : Default + Copy> Index<usize> for CircularVec<T> {
 impl<T : Default + Copy> Deref for CircularVec<T> {
     type Target = [T];
 
-    fn deref<'a>(&'a self) -> &'a [T] { &self.data[0..self.len] }
+    fn deref(&self) -> &[T] { &self.data[0..self.len] }
 }
 
 /*
@@ -0,0 +1,223 @@
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::graph::{Graph, ID};
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::utils::MyError;
+
+
+/*
+ * Checks of the graph invariants, e.g. for imported community data.
+ * Self-loops, duplicate and asymmetric links, positions and metadata
+ * of nodes that do not exist and invalid entries can be fixed. Nodes
+ * without position and inconsistent metadata keys are only reported.
+ */
+
+// entries listed per problem
+const MAX_LISTED: usize = 10;
+// metadata keys used by at least this share of the nodes are expected on all nodes
+const COMMON_KEY_SHARE: f32 = 0.5;
+
+pub struct Validation {
+	pub self_loops: Vec<(ID, ID)>,
+	pub duplicate_links: Vec<(ID, ID)>,
+	// links without a link back, not checked for directed graphs
+	pub asymmetric_links: Vec<(ID, ID)>,
+	pub missing_positions: Vec<ID>,
+	pub stray_positions: Vec<ID>,
+	pub invalid_positions: Vec<ID>,
+	pub stray_metadata: Vec<ID>,
+	// metadata that is not a JSON object
+	pub invalid_metadata: Vec<ID>,
+	// common keys and the nodes without them
+	pub missing_keys: Vec<(String, Vec<ID>)>,
+	// keys with values of different types
+	pub mixed_keys: Vec<(String, Vec<&'static str>)>,
+	node_count: usize
+}
+
+fn type_name(value: &Value) -> &'static str {
+	match value {
+		Value::Null => "null",
+		Value::Bool(_) => "bool",
+		Value::Number(_) => "number",
+		Value::String(_) => "string",
+		Value::Array(_) => "array",
+		Value::Object(_) => "object"
+	}
+}
+
+fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+	items.sort_unstable();
+	items
+}
+
+impl Validation {
+	pub fn new(graph: &Graph, locations: &Locations, meta: &Meta, directed: bool) -> Self {
+		let node_count = graph.node_count();
+
+		let mut self_loops = vec![];
+		let mut duplicate_links = vec![];
+		let mut asymmetric_links = vec![];
+		let mut seen = HashSet::new();
+		for link in graph.links() {
+			if link.from == link.to {
+				self_loops.push((link.from, link.to));
+			} else if !seen.insert((link.from, link.to)) {
+				duplicate_links.push((link.from, link.to));
+			} else if !directed && !graph.has_link(link.to, link.from) {
+				asymmetric_links.push((link.from, link.to));
+			}
+		}
+
+		let missing_positions = if locations.data.is_empty() {
+			// a graph without any positions is fine
+			vec![]
+		} else {
+			(0..node_count as ID).filter(|id| !locations.data.contains_key(id)).collect()
+		};
+		let stray_positions = sorted(locations.data.keys().filter(|id| **id as usize >= node_count).cloned().collect());
+		let invalid_positions = sorted(locations.data.iter()
+			.filter(|(id, pos)| (**id as usize) < node_count && !Locations::is_valid(pos))
+			.map(|(id, _)| *id).collect());
+
+		let stray_metadata = sorted(meta.data.keys().filter(|id| **id as usize >= node_count).cloned().collect());
+		let mut invalid_metadata = vec![];
+		let mut objects = vec![];
+		for id in 0..node_count as ID {
+			if meta.data.contains_key(&id) {
+				match meta.get_object(id) {
+					Some(obj) => objects.push((id, obj)),
+					None => invalid_metadata.push(id)
+				}
+			}
+		}
+
+		// key => (count, types)
+		let mut keys = HashMap::<&str, (usize, Vec<&'static str>)>::new();
+		for (_, obj) in &objects {
+			for (key, value) in obj {
+				let entry = keys.entry(key.as_str()).or_insert((0, vec![]));
+				entry.0 += 1;
+				let t = type_name(value);
+				if t != "null" && !entry.1.contains(&t) {
+					entry.1.push(t);
+				}
+			}
+		}
+
+		let mut missing_keys = vec![];
+		let mut mixed_keys = vec![];
+		for (key, (count, types)) in &keys {
+			if *count < objects.len() && *count as f32 >= COMMON_KEY_SHARE * objects.len() as f32 {
+				let ids = objects.iter().filter(|(_, obj)| !obj.contains_key(*key)).map(|(id, _)| *id).collect();
+				missing_keys.push((key.to_string(), ids));
+			}
+			if types.len() > 1 {
+				mixed_keys.push((key.to_string(), sorted(types.clone())));
+			}
+		}
+		missing_keys.sort_by(|a, b| a.0.cmp(&b.0));
+		mixed_keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+		Self {
+			self_loops, duplicate_links, asymmetric_links,
+			missing_positions, stray_positions, invalid_positions,
+			stray_metadata, invalid_metadata, missing_keys, mixed_keys,
+			node_count
+		}
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.fixable_count() == 0 && self.missing_positions.is_empty()
+			&& self.missing_keys.is_empty() && self.mixed_keys.is_empty()
+	}
+
+	pub fn fixable_count(&self) -> usize {
+		self.self_loops.len() + self.duplicate_links.len() + self.asymmetric_links.len()
+			+ self.stray_positions.len() + self.invalid_positions.len()
+			+ self.stray_metadata.len() + self.invalid_metadata.len()
+	}
+
+	// remove self-loops, duplicates, stray and invalid entries, add missing links back
+	pub fn fix(&self, graph: &mut Graph, locations: &mut Locations, meta: &mut Meta) {
+		let mut links = graph.links().to_vec();
+		links.retain(|link| link.from != link.to);
+		for (from, to) in &self.asymmetric_links {
+			if let Some(link) = graph.get_link(*from, *to) {
+				let mut back = link.clone();
+				back.from = *to;
+				back.to = *from;
+				links.push(back);
+			}
+		}
+		// duplicates are removed when the links are sorted
+		*graph = Graph::from_links(self.node_count, links);
+
+		for id in self.stray_positions.iter().chain(&self.invalid_positions) {
+			locations.data.remove(id);
+		}
+		for id in self.stray_metadata.iter().chain(&self.invalid_metadata) {
+			meta.data.remove(id);
+		}
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write) -> Result<(), MyError> {
+		if self.is_valid() {
+			writeln!(out, "no problems")?;
+			return Ok(());
+		}
+
+		fn list<T>(out: &mut dyn std::fmt::Write, title: &str, items: &[T], fmt: impl Fn(&T) -> String) -> Result<(), MyError> {
+			if items.is_empty() {
+				return Ok(());
+			}
+			let mut entries : Vec<String> = items.iter().take(MAX_LISTED).map(fmt).collect();
+			if items.len() > MAX_LISTED {
+				entries.push(format!("({} more)", items.len() - MAX_LISTED));
+			}
+			writeln!(out, "{} ({}): {}", title, items.len(), entries.join(", "))?;
+			Ok(())
+		}
+
+		let link = |(from, to): &(ID, ID)| format!("{} > {}", from, to);
+		list(out, "self-loops", &self.self_loops, link)?;
+		list(out, "duplicate links", &self.duplicate_links, link)?;
+		list(out, "asymmetric links", &self.asymmetric_links, link)?;
+		list(out, "nodes without position", &self.missing_positions, ID::to_string)?;
+		list(out, "positions of missing nodes", &self.stray_positions, ID::to_string)?;
+		list(out, "invalid positions", &self.invalid_positions, ID::to_string)?;
+		list(out, "metadata of missing nodes", &self.stray_metadata, ID::to_string)?;
+		list(out, "invalid metadata", &self.invalid_metadata, ID::to_string)?;
+		for (key, ids) in &self.missing_keys {
+			list(out, &format!("nodes without metadata key {}", key), ids, ID::to_string)?;
+		}
+		for (key, types) in &self.mixed_keys {
+			writeln!(out, "metadata key {} has mixed types: {}", key, types.join(", "))?;
+		}
+		Ok(())
+	}
+
+	pub fn to_json(&self) -> Value {
+		serde_json::json!({
+			"valid": self.is_valid(),
+			"self_loops": self.self_loops,
+			"duplicate_links": self.duplicate_links,
+			"asymmetric_links": self.asymmetric_links,
+			"missing_positions": self.missing_positions,
+			"stray_positions": self.stray_positions,
+			"invalid_positions": self.invalid_positions,
+			"stray_metadata": self.stray_metadata,
+			"invalid_metadata": self.invalid_metadata,
+			"missing_keys": self.missing_keys.iter().map(|(key, ids)| {
+				serde_json::json!({ "key": key, "nodes": ids })
+			}).collect::<Vec<_>>(),
+			"mixed_keys": self.mixed_keys.iter().map(|(key, types)| {
+				serde_json::json!({ "key": key, "types": types })
+			}).collect::<Vec<_>>()
+		})
+	}
+}
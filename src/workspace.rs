@@ -0,0 +1,209 @@
+
+use std::mem::replace;
+
+use crate::algorithms::create_algorithm;
+use crate::graph::{Graph, ID};
+use crate::history::History;
+use crate::locations::Locations;
+use crate::meta::Meta;
+use crate::movements::Movements;
+use crate::mst::Mst;
+use crate::node_ids::NodeIds;
+use crate::selection::Selections;
+use crate::sim::{GlobalState, RoutingAlgorithm};
+use crate::timeline::Timeline;
+use crate::utils::MyError;
+
+
+/*
+ * Named workspaces, each with its own graph, locations, metadata,
+ * routing algorithm (with its state) and undo history. The current
+ * workspace lives in the GlobalState, the others are kept here.
+ * Settings like the radio model, link model and test parameters
+ * are shared by all workspaces.
+ */
+
+struct Workspace {
+	name: String,
+	graph: Graph,
+	locations: Locations,
+	movements: Movements,
+	meta: Meta,
+	node_ids: NodeIds,
+	gateways: Vec<ID>,
+	selections: Selections,
+	history: History,
+	mst: Mst,
+	timeline: Timeline,
+	algorithm: Box<dyn RoutingAlgorithm>,
+	algorithm_name: String,
+	sim_steps: u32
+}
+
+impl Workspace {
+	// move the workspace out of the global state, leaves an empty one with the given algorithm
+	fn take(sim: &mut GlobalState, name: &str, algorithm_name: &str, algorithm: Box<dyn RoutingAlgorithm>) -> Self {
+		let history_limit = sim.history.limit();
+		let mut history = History::new();
+		history.set_limit(history_limit);
+
+		Self {
+			name: name.to_string(),
+			graph: std::mem::take(&mut sim.graph),
+			locations: std::mem::take(&mut sim.locations),
+			movements: std::mem::take(&mut sim.movements),
+			meta: std::mem::take(&mut sim.meta),
+			node_ids: std::mem::take(&mut sim.node_ids),
+			gateways: std::mem::take(&mut sim.gateways),
+			selections: std::mem::take(&mut sim.selections),
+			history: replace(&mut sim.history, history),
+			mst: std::mem::take(&mut sim.mst),
+			timeline: std::mem::take(&mut sim.timeline),
+			algorithm: replace(&mut sim.algorithm, algorithm),
+			algorithm_name: replace(&mut sim.algorithm_name, algorithm_name.to_string()),
+			sim_steps: replace(&mut sim.sim_steps, 0)
+		}
+	}
+
+	// make the workspace the current one
+	fn put(self, sim: &mut GlobalState) {
+		sim.graph = self.graph;
+		sim.locations = self.locations;
+		sim.movements = self.movements;
+		sim.meta = self.meta;
+		sim.node_ids = self.node_ids;
+		sim.gateways = self.gateways;
+		sim.selections = self.selections;
+		sim.history = self.history;
+		sim.mst = self.mst;
+		sim.timeline = self.timeline;
+		sim.algorithm = self.algorithm;
+		sim.algorithm_name = self.algorithm_name;
+		sim.sim_steps = self.sim_steps;
+	}
+}
+
+pub struct Workspaces {
+	// name of the workspace in the global state
+	current: String,
+	others: Vec<Workspace>
+}
+
+// new instance of the algorithm, random routing for unknown names
+fn new_algorithm(name: &str) -> (String, Box<dyn RoutingAlgorithm>) {
+	match create_algorithm(name) {
+		Some(algorithm) => (name.to_string(), algorithm),
+		None => ("random".to_string(), create_algorithm("random").unwrap())
+	}
+}
+
+impl Default for Workspaces {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Workspaces {
+	pub fn new() -> Self {
+		Self {
+			current: "default".to_string(),
+			others: vec![]
+		}
+	}
+
+	pub fn current(&self) -> &str {
+		&self.current
+	}
+
+	fn check_new_name(&self, name: &str) -> Result<(), MyError> {
+		if name == self.current || self.others.iter().any(|ws| ws.name == name) {
+			return Err(MyError::new(format!("Workspace exists already: {}", name)));
+		}
+		Ok(())
+	}
+
+	// switch to a new empty workspace with a new instance of the current algorithm
+	pub fn create(sim: &mut GlobalState, name: &str) -> Result<(), MyError> {
+		sim.workspaces.check_new_name(name)?;
+		let (algorithm_name, algorithm) = new_algorithm(&sim.algorithm_name);
+		let current = sim.workspaces.current.clone();
+		let workspace = Workspace::take(sim, &current, &algorithm_name, algorithm);
+		sim.workspaces.others.push(workspace);
+		sim.workspaces.current = name.to_string();
+		Ok(())
+	}
+
+	// switch to a copy of the current workspace (the algorithm state is copied if supported)
+	pub fn copy(sim: &mut GlobalState, name: &str) -> Result<(), MyError> {
+		sim.workspaces.check_new_name(name)?;
+		let (algorithm_name, mut algorithm) = new_algorithm(&sim.algorithm_name);
+		algorithm.reset(sim.graph.node_count());
+		if let Some(state) = sim.algorithm.save_state() {
+			algorithm.load_state(&state)?;
+		}
+
+		let mut history = History::new();
+		history.set_limit(sim.history.limit());
+
+		let copy = Workspace {
+			name: name.to_string(),
+			graph: sim.graph.clone(),
+			locations: sim.locations.clone(),
+			movements: Movements::new(),
+			meta: sim.meta.clone(),
+			node_ids: sim.node_ids.clone(),
+			gateways: sim.gateways.clone(),
+			selections: sim.selections.clone(),
+			history,
+			mst: Mst::new(),
+			timeline: Timeline::new(),
+			algorithm,
+			algorithm_name,
+			sim_steps: sim.sim_steps
+		};
+
+		let current = sim.workspaces.current.clone();
+		let workspace = Workspace::take(sim, &current, "random", create_algorithm("random").unwrap());
+		sim.workspaces.others.push(workspace);
+		copy.put(sim);
+		sim.workspaces.current = name.to_string();
+		Ok(())
+	}
+
+	pub fn switch(sim: &mut GlobalState, name: &str) -> Result<(), MyError> {
+		if name == sim.workspaces.current {
+			return Ok(());
+		}
+		let index = sim.workspaces.others.iter().position(|ws| ws.name == name)
+			.ok_or_else(|| MyError::new(format!("Unknown workspace: {}", name)))?;
+		let target = sim.workspaces.others.remove(index);
+		let current = sim.workspaces.current.clone();
+		let workspace = Workspace::take(sim, &current, "random", create_algorithm("random").unwrap());
+		sim.workspaces.others.push(workspace);
+		target.put(sim);
+		sim.workspaces.current = name.to_string();
+		Ok(())
+	}
+
+	pub fn remove(&mut self, name: &str) -> Result<(), MyError> {
+		if name == self.current {
+			return Err(MyError::new("Cannot remove the current workspace.".to_string()));
+		}
+		let index = self.others.iter().position(|ws| ws.name == name)
+			.ok_or_else(|| MyError::new(format!("Unknown workspace: {}", name)))?;
+		self.others.remove(index);
+		Ok(())
+	}
+
+	pub fn print(&self, out: &mut dyn std::fmt::Write, sim: &GlobalState) -> Result<(), MyError> {
+		writeln!(out, "* {}: nodes: {}, links: {}, algorithm: {}",
+			self.current, sim.graph.node_count(), sim.graph.link_count(), sim.algorithm_name)?;
+		let mut others : Vec<&Workspace> = self.others.iter().collect();
+		others.sort_by(|a, b| a.name.cmp(&b.name));
+		for ws in others {
+			writeln!(out, "  {}: nodes: {}, links: {}, algorithm: {}",
+				ws.name, ws.graph.node_count(), ws.graph.link_count(), ws.algorithm_name)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,89 @@
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tungstenite::{accept, Message, WebSocket};
+
+use crate::exporter::export_json;
+use crate::sim::GlobalState;
+
+
+/*
+ * WebSocket clients that get a JSON graph snapshot
+ * (same format as the export file) whenever the graph changes.
+ */
+pub struct WsClients {
+	clients: Vec<WebSocket<TcpStream>>,
+	// last snapshot that was sent
+	last: String
+}
+
+impl Default for WsClients {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl WsClients {
+	pub fn new() -> Self {
+		Self { clients: vec![], last: String::new() }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.clients.is_empty()
+	}
+
+	fn add(&mut self, mut client: WebSocket<TcpStream>, snapshot: String) {
+		if client.send(Message::text(snapshot.clone())).is_ok() {
+			self.clients.push(client);
+			self.last = snapshot;
+		}
+	}
+
+	// send snapshot to all clients, remove clients that fail
+	pub fn broadcast(&mut self, snapshot: String) {
+		if self.clients.is_empty() || snapshot == self.last {
+			return;
+		}
+
+		self.clients.retain_mut(|client| client.send(Message::text(snapshot.clone())).is_ok());
+		self.last = snapshot;
+	}
+}
+
+pub fn ws_loop(sim: Arc<Mutex<GlobalState>>, address: &str) {
+	match TcpListener::bind(address) {
+		Err(err) => {
+			println!("{}", err);
+		},
+		Ok(listener) => {
+			println!("Push graph updates on ws://{}", address);
+
+			for stream in listener.incoming() {
+				let stream = match stream {
+					Ok(stream) => stream,
+					Err(_) => continue
+				};
+
+				// slow clients must not block the simulation
+				let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+
+				let client = match accept(stream) {
+					Ok(client) => client,
+					Err(err) => {
+						println!("WebSocket handshake failed: {}", err);
+						continue;
+					}
+				};
+
+				if let Ok(mut sim) = sim.lock() {
+					let snapshot = export_json(&sim.graph, Some(&sim.locations), Some(&sim.meta), Some(&*sim.algorithm), Some(&sim.energy), Some(&sim.test), None, Some(&sim.export_style));
+					sim.ws_clients.add(client, snapshot);
+				} else {
+					break;
+				}
+			}
+		}
+	}
+}